@@ -9,6 +9,7 @@
 )]
 extern "system" {}
 
+use bstr::ByteSlice;
 use criterion::{criterion_group, criterion_main, BatchSize, Criterion};
 use mlua::prelude::*;
 
@@ -39,6 +40,27 @@ fn create_array(c: &mut Criterion) {
     });
 }
 
+fn create_sequence(c: &mut Criterion) {
+    c.bench_function("create sequence 10000", |b| {
+        b.iter_batched_ref(
+            Lua::new,
+            |lua| {
+                lua.create_sequence_from(1..=10_000).unwrap();
+            },
+            BatchSize::SmallInput,
+        );
+    });
+    c.bench_function("create sequence 10000 (table from)", |b| {
+        b.iter_batched_ref(
+            Lua::new,
+            |lua| {
+                lua.create_table_from((1..=10_000).map(|i| (i, i))).unwrap();
+            },
+            BatchSize::SmallInput,
+        );
+    });
+}
+
 fn create_string_table(c: &mut Criterion) {
     c.bench_function("create string table 10", |b| {
         b.iter_batched_ref(
@@ -55,6 +77,28 @@ fn create_string_table(c: &mut Criterion) {
     });
 }
 
+fn intern_strings(c: &mut Criterion) {
+    let long = "an identifier long enough not to be interned by Lua itself";
+
+    c.bench_function("create long string 100", move |b| {
+        let lua = Lua::new();
+        b.iter(|| {
+            for _ in 0..100 {
+                lua.create_string(long).unwrap();
+            }
+        });
+    });
+
+    c.bench_function("intern long string 100", move |b| {
+        let lua = Lua::new();
+        b.iter(|| {
+            for _ in 0..100 {
+                lua.intern_string(long).unwrap();
+            }
+        });
+    });
+}
+
 fn call_add_function(c: &mut Criterion) {
     c.bench_function("call add function 3 10", |b| {
         b.iter_batched_ref(
@@ -86,6 +130,38 @@ fn call_add_function(c: &mut Criterion) {
     });
 }
 
+fn call_add_function_unprotected(c: &mut Criterion) {
+    c.bench_function("call add function unprotected 3 10", |b| {
+        b.iter_batched_ref(
+            || {
+                let lua = Lua::new();
+                let f = {
+                    let f: LuaFunction = lua
+                        .load(
+                            r#"
+                                function(a, b, c)
+                                    return a + b + c
+                                end
+                            "#,
+                        )
+                        .eval()
+                        .unwrap();
+                    lua.create_registry_value(f).unwrap()
+                };
+                (lua, f)
+            },
+            |(lua, f)| {
+                let add_function: LuaFunction = lua.registry_value(f).unwrap();
+                for i in 0..10 {
+                    let _result: i64 =
+                        unsafe { add_function.call_unprotected((i, i + 1, i + 2)).unwrap() };
+                }
+            },
+            BatchSize::SmallInput,
+        );
+    });
+}
+
 fn call_add_callback(c: &mut Criterion) {
     c.bench_function("call callback add 2 10", |b| {
         b.iter_batched_ref(
@@ -158,6 +234,103 @@ fn call_append_callback(c: &mut Criterion) {
     });
 }
 
+fn call_string_arg_callback(c: &mut Criterion) {
+    fn bench(
+        c: &mut Criterion,
+        name: &str,
+        arg_len: usize,
+        callback: for<'lua> fn(&'lua Lua, LuaMultiValue<'lua>) -> LuaResult<usize>,
+    ) {
+        c.bench_function(name, move |b| {
+            b.iter_batched_ref(
+                || {
+                    let lua = Lua::new();
+                    let f = {
+                        let c: LuaFunction = lua.create_function(callback).unwrap();
+                        lua.globals().set("callback", c).unwrap();
+                        lua.globals().set("arg", "x".repeat(arg_len)).unwrap();
+                        let f: LuaFunction = lua
+                            .load(
+                                r#"
+                                function()
+                                    for _ = 1,10 do
+                                        callback(arg)
+                                    end
+                                end
+                            "#,
+                            )
+                            .eval()
+                            .unwrap();
+                        lua.create_registry_value(f).unwrap()
+                    };
+                    (lua, f)
+                },
+                |(lua, f)| {
+                    let entry_function: LuaFunction = lua.registry_value(f).unwrap();
+                    entry_function.call::<_, ()>(()).unwrap();
+                },
+                BatchSize::SmallInput,
+            );
+        });
+    }
+
+    fn string_arg<'lua>(lua: &'lua Lua, args: LuaMultiValue<'lua>) -> LuaResult<usize> {
+        Ok(lua.unpack_multi::<String>(args)?.len())
+    }
+
+    fn borrowed_str_arg<'lua>(lua: &'lua Lua, args: LuaMultiValue<'lua>) -> LuaResult<usize> {
+        Ok(lua.unpack_multi::<LuaBorrowedStr>(args)?.len())
+    }
+
+    bench(c, "call callback String arg 32B 10", 32, string_arg);
+    bench(
+        c,
+        "call callback BorrowedStr arg 32B 10",
+        32,
+        borrowed_str_arg,
+    );
+    bench(c, "call callback String arg 4KB 10", 4096, string_arg);
+    bench(
+        c,
+        "call callback BorrowedStr arg 4KB 10",
+        4096,
+        borrowed_str_arg,
+    );
+}
+
+fn call_return_bytes_callback(c: &mut Criterion) {
+    static BLOB: [u8; 1 << 20] = [b'x'; 1 << 20];
+
+    fn bench(
+        c: &mut Criterion,
+        name: &str,
+        callback: for<'lua> fn(&'lua Lua, ()) -> LuaResult<LuaValue<'lua>>,
+    ) {
+        c.bench_function(name, move |b| {
+            b.iter_batched_ref(
+                || {
+                    let lua = Lua::new();
+                    let f = lua.create_function(callback).unwrap();
+                    let f = lua.create_registry_value(f).unwrap();
+                    (lua, f)
+                },
+                |(lua, f)| {
+                    let f: LuaFunction = lua.registry_value(f).unwrap();
+                    f.call::<_, ()>(()).unwrap();
+                },
+                BatchSize::SmallInput,
+            );
+        });
+    }
+
+    bench(c, "call callback return static BStr 1MB", |lua, ()| {
+        BLOB.as_bstr().to_lua(lua)
+    });
+    bench(c, "call callback return created string 1MB", |lua, ()| {
+        lua.create_string(&BLOB[..])?.to_lua(lua)
+    });
+}
+
 fn create_registry_values(c: &mut Criterion) {
     c.bench_function("create registry 10", |b| {
         b.iter_batched_ref(
@@ -189,6 +362,66 @@ fn create_userdata(c: &mut Criterion) {
             BatchSize::SmallInput,
         );
     });
+
+    c.bench_function("create userdata 100000", |b| {
+        let lua = Lua::new();
+        b.iter(|| {
+            for i in 0..100_000 {
+                lua.create_userdata(UserData(i)).unwrap();
+            }
+            lua.gc_collect().unwrap();
+        });
+    });
+}
+
+fn call_userdata_method(c: &mut Criterion) {
+    fn add_methods<'lua, T, M: LuaUserDataMethods<'lua, T>>(methods: &mut M) {
+        methods.add_method("get", |_, _, ()| Ok(1));
+        methods.add_meta_method(LuaMetaMethod::Index, |_, _, _: LuaString| Ok(LuaNil));
+        methods.set_index_priority(LuaIndexPriority::HandlerFirst);
+    }
+
+    struct Uncached;
+    impl LuaUserData for Uncached {
+        fn add_methods<'lua, M: LuaUserDataMethods<'lua, Self>>(methods: &mut M) {
+            add_methods(methods);
+        }
+    }
+
+    struct Cached;
+    impl LuaUserData for Cached {
+        fn add_methods<'lua, M: LuaUserDataMethods<'lua, Self>>(methods: &mut M) {
+            add_methods(methods);
+            methods.enable_method_cache();
+        }
+    }
+
+    fn bench<T: 'static + LuaUserData + Send>(c: &mut Criterion, name: &str, ud: fn() -> T) {
+        c.bench_function(name, move |b| {
+            let lua = Lua::new();
+            let f: LuaFunction = lua
+                .load(
+                    r#"
+                    function(obj)
+                        for i = 1,1000 do
+                            obj:get()
+                        end
+                    end
+                "#,
+                )
+                .eval()
+                .unwrap();
+            let obj = lua.create_userdata(ud()).unwrap();
+            b.iter(|| f.call::<_, ()>(obj.clone()).unwrap());
+        });
+    }
+
+    bench(c, "call userdata method 1000 (handler first)", || Uncached);
+    bench(
+        c,
+        "call userdata method 1000 (handler first, cached)",
+        || Cached,
+    );
 }
 
 criterion_group! {
@@ -199,12 +432,18 @@ criterion_group! {
     targets =
         create_table,
         create_array,
+        create_sequence,
         create_string_table,
+        intern_strings,
         call_add_function,
+        call_add_function_unprotected,
         call_add_callback,
         call_append_callback,
+        call_string_arg_callback,
+        call_return_bytes_callback,
         create_registry_values,
         create_userdata,
+        call_userdata_method,
 }
 
 criterion_main!(benches);
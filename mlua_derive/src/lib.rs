@@ -2,8 +2,12 @@ extern crate proc_macro;
 
 use proc_macro::TokenStream;
 use proc_macro2::{Ident, Span};
-use quote::quote_spanned;
-use syn::{parse_macro_input, spanned::Spanned, AttributeArgs, Error, ItemFn};
+use quote::{quote, quote_spanned};
+use syn::{
+    parse_macro_input, spanned::Spanned, Attribute, AttributeArgs, Data, DeriveInput, Error,
+    Fields, FnArg, ImplItem, ItemFn, ItemImpl, Lit, LitStr, Meta, MetaNameValue, NestedMeta,
+    ReturnType, Signature, Type, Visibility,
+};
 
 #[proc_macro_attribute]
 pub fn lua_module(attr: TokenStream, item: TokenStream) -> TokenStream {
@@ -33,3 +37,350 @@ pub fn lua_module(attr: TokenStream, item: TokenStream) -> TokenStream {
 
     wrapped.into()
 }
+
+/// Implements `mlua::UserData` for a type by registering the `pub` functions of an `impl` block.
+///
+/// Methods taking `&self` are registered with `add_method`, methods taking `&mut self` with
+/// `add_method_mut` and associated functions with `add_function`. The Lua name is the Rust name,
+/// unless overridden with `#[lua(name = "...")]`. A function marked with
+/// `#[lua(meta = "ToString")]` is registered as the corresponding `mlua::MetaMethod` instead.
+///
+/// Arguments are converted with `FromLua` and must be owned types. If the first argument after
+/// `self` is a `&Lua`, the Lua state is passed in. Functions returning a type named `Result` are
+/// expected to return `mlua::Result`, any other return value is converted with `ToLuaMulti`.
+///
+/// ```ignore
+/// struct Counter(i64);
+///
+/// #[mlua_derive::methods]
+/// impl Counter {
+///     pub fn new(start: i64) -> Counter {
+///         Counter(start)
+///     }
+///
+///     pub fn get(&self) -> i64 {
+///         self.0
+///     }
+///
+///     #[lua(name = "inc")]
+///     pub fn increment(&mut self, by: i64) {
+///         self.0 += by;
+///     }
+///
+///     #[lua(meta = "ToString")]
+///     pub fn describe(&self) -> String {
+///         format!("Counter({})", self.0)
+///     }
+/// }
+/// ```
+#[proc_macro_attribute]
+pub fn methods(attr: TokenStream, item: TokenStream) -> TokenStream {
+    let args = parse_macro_input!(attr as AttributeArgs);
+    let mut item = parse_macro_input!(item as ItemImpl);
+
+    if !args.is_empty() {
+        let err = Error::new(Span::call_site(), "the number of arguments must be zero")
+            .to_compile_error();
+        return err.into();
+    }
+
+    match methods_impl(&mut item) {
+        Ok(user_data) => {
+            let wrapped = quote! {
+                #item
+                #user_data
+            };
+            wrapped.into()
+        }
+        Err(err) => err.to_compile_error().into(),
+    }
+}
+
+fn methods_impl(item: &mut ItemImpl) -> Result<proc_macro2::TokenStream, Error> {
+    if let Some((_, path, _)) = &item.trait_ {
+        return Err(Error::new(
+            path.span(),
+            "`methods` cannot be used on trait implementations",
+        ));
+    }
+
+    let mut registrations = Vec::new();
+    for impl_item in &mut item.items {
+        let method = match impl_item {
+            ImplItem::Method(method) => method,
+            _ => continue,
+        };
+
+        let options = take_lua_options(&mut method.attrs)?;
+        if let Visibility::Public(_) = method.vis {
+        } else {
+            if options.is_some() {
+                return Err(Error::new(
+                    method.sig.span(),
+                    "only `pub` functions can be registered",
+                ));
+            }
+            continue;
+        }
+        registrations.push(registration(&method.sig, options.unwrap_or_default())?);
+    }
+
+    let self_ty = &item.self_ty;
+    let (impl_generics, _, where_clause) = item.generics.split_for_impl();
+    Ok(quote! {
+        impl #impl_generics mlua::UserData for #self_ty #where_clause {
+            fn add_methods<'lua, M: mlua::UserDataMethods<'lua, Self>>(methods: &mut M) {
+                #(#registrations)*
+            }
+        }
+    })
+}
+
+/// Implements `mlua::VariantName` for an enum, returning the name of the active variant.
+///
+/// Combined with `UserDataMethods::add_variant_name_method`, this exposes a `variant_name()`
+/// method to Lua so scripts can branch on the variant of a tagged union.
+///
+/// ```ignore
+/// #[derive(mlua::VariantName)]
+/// enum Shape {
+///     Circle { radius: f64 },
+///     Rect(f64, f64),
+///     Empty,
+/// }
+///
+/// impl mlua::UserData for Shape {
+///     fn add_methods<'lua, M: mlua::UserDataMethods<'lua, Self>>(methods: &mut M) {
+///         methods.add_variant_name_method();
+///     }
+/// }
+/// ```
+#[proc_macro_derive(VariantName)]
+pub fn variant_name(item: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(item as DeriveInput);
+
+    let data = match &input.data {
+        Data::Enum(data) => data,
+        _ => {
+            let err = Error::new(
+                input.ident.span(),
+                "`VariantName` can only be derived for enums",
+            )
+            .to_compile_error();
+            return err.into();
+        }
+    };
+
+    let arms = data.variants.iter().map(|variant| {
+        let ident = &variant.ident;
+        let name = LitStr::new(&ident.to_string(), ident.span());
+        let pattern = match variant.fields {
+            Fields::Named(_) => quote!(Self::#ident { .. }),
+            Fields::Unnamed(_) => quote!(Self::#ident(..)),
+            Fields::Unit => quote!(Self::#ident),
+        };
+        quote!(#pattern => #name,)
+    });
+
+    let ident = &input.ident;
+    let (impl_generics, ty_generics, where_clause) = input.generics.split_for_impl();
+    let wrapped = quote! {
+        impl #impl_generics mlua::VariantName for #ident #ty_generics #where_clause {
+            fn variant_name(&self) -> &'static str {
+                match *self {
+                    #(#arms)*
+                }
+            }
+        }
+    };
+
+    wrapped.into()
+}
+
+#[derive(Default)]
+struct LuaOptions {
+    name: Option<LitStr>,
+    meta: Option<LitStr>,
+}
+
+// Extracts and removes the `#[lua(...)]` attributes of a function.
+fn take_lua_options(attrs: &mut Vec<Attribute>) -> Result<Option<LuaOptions>, Error> {
+    let mut options = None;
+    let mut result = Ok(());
+    attrs.retain(|attr| {
+        if !attr.path.is_ident("lua") {
+            return true;
+        }
+        let opts = options.get_or_insert_with(LuaOptions::default);
+        if let Err(err) = parse_lua_options(attr, opts) {
+            result = Err(err);
+        }
+        false
+    });
+    result.map(|_| options)
+}
+
+fn parse_lua_options(attr: &Attribute, options: &mut LuaOptions) -> Result<(), Error> {
+    let list = match attr.parse_meta()? {
+        Meta::List(list) => list,
+        meta => return Err(Error::new(meta.span(), "expected `#[lua(...)]`")),
+    };
+    for nested in list.nested {
+        match nested {
+            NestedMeta::Meta(Meta::NameValue(MetaNameValue {
+                path,
+                lit: Lit::Str(lit),
+                ..
+            })) => {
+                if path.is_ident("name") {
+                    options.name = Some(lit);
+                } else if path.is_ident("meta") {
+                    options.meta = Some(lit);
+                } else {
+                    return Err(Error::new(
+                        path.span(),
+                        "unknown option, expected `name` or `meta`",
+                    ));
+                }
+            }
+            nested => {
+                return Err(Error::new(
+                    nested.span(),
+                    "expected `name = \"...\"` or `meta = \"...\"`",
+                ))
+            }
+        }
+    }
+    if options.name.is_some() && options.meta.is_some() {
+        return Err(Error::new(
+            attr.span(),
+            "`name` and `meta` cannot be used together",
+        ));
+    }
+    Ok(())
+}
+
+fn registration(sig: &Signature, options: LuaOptions) -> Result<proc_macro2::TokenStream, Error> {
+    let span = sig.span();
+    if !sig.generics.params.is_empty() {
+        return Err(Error::new(
+            sig.generics.span(),
+            "generic functions cannot be registered",
+        ));
+    }
+
+    let mut inputs = sig.inputs.iter().peekable();
+    let receiver = match inputs.peek() {
+        Some(FnArg::Receiver(receiver)) => {
+            if receiver.reference.is_none() {
+                return Err(Error::new(
+                    receiver.span(),
+                    "methods taking `self` by value cannot be registered",
+                ));
+            }
+            let mutable = receiver.mutability.is_some();
+            inputs.next();
+            Some(mutable)
+        }
+        _ => None,
+    };
+
+    let mut pass_lua = false;
+    if let Some(FnArg::Typed(arg)) = inputs.peek() {
+        if is_lua_ref(&arg.ty) {
+            pass_lua = true;
+            inputs.next();
+        }
+    }
+
+    let mut arg_names = Vec::new();
+    let mut arg_types = Vec::new();
+    for (i, input) in inputs.enumerate() {
+        match input {
+            FnArg::Typed(arg) => {
+                arg_names.push(Ident::new(&format!("arg{}", i), Span::call_site()));
+                arg_types.push(&arg.ty);
+            }
+            FnArg::Receiver(receiver) => {
+                return Err(Error::new(receiver.span(), "unexpected receiver"))
+            }
+        }
+    }
+
+    let ident = &sig.ident;
+    let lua_arg = if pass_lua { quote!(lua,) } else { quote!() };
+    let call = match receiver {
+        Some(_) => quote!(Self::#ident(this, #lua_arg #(#arg_names),*)),
+        None => quote!(Self::#ident(#lua_arg #(#arg_names),*)),
+    };
+    let body = if returns_result(&sig.output) {
+        call
+    } else {
+        quote!(Ok(#call))
+    };
+
+    let (add, key) = match options.meta {
+        Some(meta) => {
+            let meta = Ident::new(&meta.value(), meta.span());
+            let add = match receiver {
+                Some(false) => quote!(add_meta_method),
+                Some(true) => quote!(add_meta_method_mut),
+                None => quote!(add_meta_function),
+            };
+            (add, quote!(mlua::MetaMethod::#meta))
+        }
+        None => {
+            let name = options
+                .name
+                .unwrap_or_else(|| LitStr::new(&ident.to_string(), ident.span()));
+            let add = match receiver {
+                Some(false) => quote!(add_method),
+                Some(true) => quote!(add_method_mut),
+                None => quote!(add_function),
+            };
+            (add, quote!(#name))
+        }
+    };
+
+    let lua = if pass_lua { quote!(lua) } else { quote!(_) };
+    let this = if receiver.is_some() {
+        quote!(this,)
+    } else {
+        quote!()
+    };
+    Ok(quote_spanned! { span =>
+        methods.#add(#key, |#lua, #this (#(#arg_names,)*): (#(#arg_types,)*)| #body);
+    })
+}
+
+// Checks whether the type is `&Lua` (or `&mlua::Lua`).
+fn is_lua_ref(ty: &Type) -> bool {
+    match ty {
+        Type::Reference(reference) if reference.mutability.is_none() => match &*reference.elem {
+            Type::Path(path) => path
+                .path
+                .segments
+                .last()
+                .map(|segment| segment.ident == "Lua")
+                .unwrap_or(false),
+            _ => false,
+        },
+        _ => false,
+    }
+}
+
+// Checks whether the function returns a type named `Result`.
+fn returns_result(output: &ReturnType) -> bool {
+    match output {
+        ReturnType::Type(_, ty) => match &**ty {
+            Type::Path(path) => path
+                .path
+                .segments
+                .last()
+                .map(|segment| segment.ident == "Result")
+                .unwrap_or(false),
+            _ => false,
+        },
+        ReturnType::Default => false,
+    }
+}
@@ -0,0 +1,145 @@
+use crate::error::{Error, Result};
+use crate::lua::Lua;
+use crate::string::String;
+use crate::types::Integer;
+use crate::userdata::{AnyUserData, MetaMethod, UserData, UserDataMethods};
+
+/// A growable byte buffer that Lua scripts can append to and read from.
+///
+/// Building binary data in Lua by concatenating strings creates a new string for every step. A
+/// `Buffer` keeps its contents in a Rust `Vec<u8>` instead, so appending is cheap. It is created
+/// with [`Lua::create_buffer`] (or converted like any other [`UserData`]) and exposes the following
+/// methods to Lua:
+///
+/// * `buf:append(s)` appends the bytes of the string `s` (numbers are converted to strings).
+/// * `buf:len()` or `#buf` returns the number of bytes in the buffer.
+/// * `buf:read(pos [, n])` returns `n` bytes starting at the 1-based position `pos`, or all bytes
+///   from `pos` to the end if `n` is omitted. Reading outside of the buffer raises an error.
+/// * `buf:to_string()` or `tostring(buf)` returns the whole contents as a Lua string.
+/// * `buf:clear()` removes all bytes from the buffer.
+///
+/// The contents are allocated by Rust, not by Lua, so they are not counted against a memory limit
+/// set with [`Lua::set_memory_limit`] or by a sandbox. A script with access to a buffer can grow it
+/// without bound; do not hand buffers to untrusted code that must stay within a memory budget.
+///
+/// # Examples
+///
+/// ```
+/// # use mlua::{Buffer, Lua, Result};
+/// # fn main() -> Result<()> {
+/// # let lua = Lua::new();
+/// let buf = lua.create_buffer(64)?;
+/// lua.globals().set("buf", buf.clone())?;
+///
+/// lua.load(
+///     r#"
+///         for i = 1, 3 do
+///             buf:append("line " .. i .. "\n")
+///         end
+///         assert(buf:read(1, 6) == "line 1")
+///     "#,
+/// )
+/// .exec()?;
+///
+/// assert_eq!(buf.borrow::<Buffer>()?.as_bytes(), b"line 1\nline 2\nline 3\n");
+/// # Ok(())
+/// # }
+/// ```
+///
+/// [`Lua::create_buffer`]: struct.Lua.html#method.create_buffer
+/// [`Lua::set_memory_limit`]: struct.Lua.html#method.set_memory_limit
+/// [`UserData`]: trait.UserData.html
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct Buffer(Vec<u8>);
+
+impl Buffer {
+    /// Creates an empty buffer.
+    pub fn new() -> Buffer {
+        Buffer(Vec::new())
+    }
+
+    /// Creates an empty buffer with space for at least `capacity` bytes.
+    pub fn with_capacity(capacity: usize) -> Buffer {
+        Buffer(Vec::with_capacity(capacity))
+    }
+
+    /// Returns the contents of the buffer.
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.0
+    }
+
+    /// Consumes the buffer, returning its contents.
+    pub fn into_bytes(self) -> Vec<u8> {
+        self.0
+    }
+
+    // Returns the `n` bytes starting at the 1-based position `pos`, or the bytes from `pos` to the
+    // end of the buffer.
+    fn read(&self, pos: Integer, n: Option<Integer>) -> Result<&[u8]> {
+        let len = self.0.len() as Integer;
+        let out_of_bounds = || {
+            let n = n.map_or_else(|| "to end".to_string(), |n| n.to_string());
+            Error::RuntimeError(format!(
+                "buffer read out of bounds (position {}, length {}, buffer size {})",
+                pos, n, len
+            ))
+        };
+
+        // Both `start` and `remaining` are within `0..=len` after these checks
+        let start = match pos.checked_sub(1) {
+            Some(start) if start >= 0 && start <= len => start,
+            _ => return Err(out_of_bounds()),
+        };
+        let remaining = len - start;
+        let n = n.unwrap_or(remaining);
+        if n < 0 || n > remaining {
+            return Err(out_of_bounds());
+        }
+        let start = start as usize;
+        Ok(&self.0[start..start + n as usize])
+    }
+}
+
+impl From<Vec<u8>> for Buffer {
+    fn from(bytes: Vec<u8>) -> Buffer {
+        Buffer(bytes)
+    }
+}
+
+impl UserData for Buffer {
+    fn add_methods<'lua, M: UserDataMethods<'lua, Self>>(methods: &mut M) {
+        methods.add_method_mut("append", |_, this, data: String| {
+            this.0.extend_from_slice(data.as_bytes());
+            Ok(())
+        });
+        methods.add_method("len", |_, this, ()| Ok(this.0.len()));
+        methods.add_method("read", |lua, this, (pos, n): (Integer, Option<Integer>)| {
+            lua.create_string(this.read(pos, n)?)
+        });
+        methods.add_method("to_string", |lua, this, ()| lua.create_string(&this.0));
+        methods.add_method_mut("clear", |_, this, ()| {
+            this.0.clear();
+            Ok(())
+        });
+
+        methods.add_meta_method(MetaMethod::Len, |_, this, ()| Ok(this.0.len()));
+        methods.add_meta_method(MetaMethod::ToString, |lua, this, ()| {
+            lua.create_string(&this.0)
+        });
+    }
+
+    fn name() -> Option<&'static str> {
+        Some("Buffer")
+    }
+}
+
+impl Lua {
+    /// Creates an empty [`Buffer`] userdata with space for at least `capacity` bytes.
+    ///
+    /// The contents of the buffer are not counted against the Lua memory limit.
+    ///
+    /// [`Buffer`]: struct.Buffer.html
+    pub fn create_buffer<'lua>(&'lua self, capacity: usize) -> Result<AnyUserData<'lua>> {
+        self.create_userdata(Buffer::with_capacity(capacity))
+    }
+}
@@ -0,0 +1,382 @@
+use std::collections::{HashMap, HashSet};
+use std::os::raw::c_void;
+use std::string::String as StdString;
+
+use crate::error::{Error, Result};
+use crate::lua::Lua;
+use crate::table::Table;
+use crate::value::Value;
+
+const MAGIC: &[u8] = b"MLV\x01";
+
+const TAG_NIL: u8 = 0;
+const TAG_FALSE: u8 = 1;
+const TAG_TRUE: u8 = 2;
+const TAG_INTEGER: u8 = 3;
+const TAG_NUMBER: u8 = 4;
+const TAG_STRING: u8 = 5;
+const TAG_TABLE: u8 = 6;
+const TAG_TABLE_REF: u8 = 7;
+const TAG_END: u8 = 0xff;
+
+// Maximum nesting depth of tables, to avoid overflowing the stack on deeply nested or hostile input
+const MAX_DEPTH: usize = 200;
+
+impl Lua {
+    /// Encodes a Lua value to bytes, which can be turned back into a value with [`decode_value`].
+    ///
+    /// The format is self-describing and supports `nil`, booleans, integers and floats (the
+    /// distinction is preserved), binary strings and tables. Tables referenced more than once,
+    /// including recursive tables, are encoded once and shared again after decoding. Metatables
+    /// are not encoded.
+    ///
+    /// Returns a [`FromLuaConversionError`] for functions, userdata, threads and light userdata,
+    /// and for tables nested more than 200 levels deep.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use mlua::{Lua, Result, Table, Value};
+    /// # fn main() -> Result<()> {
+    /// # let lua = Lua::new();
+    /// let table: Table = lua.load("{ name = 'mlua', version = 2, ratio = 0.5 }").eval()?;
+    ///
+    /// let bytes = lua.encode_value(&Value::Table(table))?;
+    /// let table: Table = lua.unpack(lua.decode_value(&bytes)?)?;
+    /// assert_eq!(table.get::<_, String>("name")?, "mlua");
+    /// assert_eq!(table.get::<_, i64>("version")?, 2);
+    /// # Ok(())
+    /// # }
+    /// ```
+    ///
+    /// [`decode_value`]: #method.decode_value
+    /// [`FromLuaConversionError`]: enum.Error.html#variant.FromLuaConversionError
+    pub fn encode_value<'lua>(&'lua self, value: &Value<'lua>) -> Result<Vec<u8>> {
+        let mut encoder = Encoder::new(false, HashSet::new());
+        encoder.encode(value)?;
+        Ok(encoder.output)
+    }
+
+    /// Decodes a Lua value from bytes produced by [`encode_value`].
+    ///
+    /// Returns a [`ToLuaConversionError`] if the input is malformed or has tables nested more than
+    /// 200 levels deep, so untrusted input can be decoded safely.
+    ///
+    /// [`encode_value`]: #method.encode_value
+    /// [`ToLuaConversionError`]: enum.Error.html#variant.ToLuaConversionError
+    pub fn decode_value<'lua>(&'lua self, bytes: &[u8]) -> Result<Value<'lua>> {
+        if !bytes.starts_with(MAGIC) {
+            return Err(decode_error("invalid header"));
+        }
+        let mut decoder = Decoder {
+            lua: self,
+            input: &bytes[MAGIC.len()..],
+            tables: Vec::new(),
+            depth: 0,
+        };
+        let value = decoder.decode()?;
+        if !decoder.input.is_empty() {
+            return Err(decode_error("trailing bytes"));
+        }
+        Ok(value)
+    }
+
+    /// Saves the data stored in global variables, to be restored later with [`restore_data`].
+    ///
+    /// Every global holding data that [`encode_value`] supports is saved, including nested tables,
+    /// which may reference each other or be recursive. Functions, userdata, threads and light
+    /// userdata are skipped, as are table entries whose key or value is one of them. Loaded
+    /// modules, which include the standard libraries, and references to the globals table itself
+    /// are skipped too, so that restoring a snapshot does not replace them.
+    ///
+    /// Use [`snapshot_data_with`] to return an error for unsupported values instead.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use mlua::{Lua, Result};
+    /// # fn main() -> Result<()> {
+    /// let lua = Lua::new();
+    /// lua.load("player = { name = 'Ann', level = 3 }; function greet() end").exec()?;
+    /// let snapshot = lua.snapshot_data()?;
+    ///
+    /// let fresh = Lua::new();
+    /// fresh.restore_data(&snapshot)?;
+    /// assert_eq!(fresh.load("player.name .. player.level").eval::<String>()?, "Ann3");
+    /// assert!(fresh.load("greet == nil").eval::<bool>()?);
+    /// # Ok(())
+    /// # }
+    /// ```
+    ///
+    /// [`restore_data`]: #method.restore_data
+    /// [`encode_value`]: #method.encode_value
+    /// [`snapshot_data_with`]: #method.snapshot_data_with
+    pub fn snapshot_data(&self) -> Result<Vec<u8>> {
+        self.snapshot_data_with(true)
+    }
+
+    /// Saves the data stored in global variables, like [`snapshot_data`].
+    ///
+    /// If `skip_unsupported` is `false`, a [`FromLuaConversionError`] is returned when a saved
+    /// table contains an unsupported value, including references to loaded modules or the globals
+    /// table. Globals which do not hold data, such as the functions of the base library, are always
+    /// skipped.
+    ///
+    /// [`snapshot_data`]: #method.snapshot_data
+    /// [`FromLuaConversionError`]: enum.Error.html#variant.FromLuaConversionError
+    pub fn snapshot_data_with(&self, skip_unsupported: bool) -> Result<Vec<u8>> {
+        let globals = self.globals();
+
+        let mut excluded = HashSet::new();
+        excluded.insert(globals.0.to_pointer());
+        if let Ok(Value::Table(loaded)) = self.raw_registry_value(b"_LOADED") {
+            for pair in loaded.pairs::<Value, Value>() {
+                if let (_, Value::Table(module)) = pair? {
+                    excluded.insert(module.0.to_pointer());
+                }
+            }
+        }
+
+        let mut encoder = Encoder::new(skip_unsupported, excluded);
+        encoder.tables.insert(globals.0.to_pointer(), 0);
+        encoder.output.push(TAG_TABLE);
+        for pair in globals.pairs::<Value, Value>() {
+            let (key, value) = pair?;
+            if encoder.is_supported(&key) && encoder.is_supported(&value) {
+                encoder.encode_entry(&key, &value)?;
+            }
+        }
+        encoder.output.push(TAG_END);
+        Ok(encoder.output)
+    }
+
+    /// Restores global variables from a snapshot produced by [`snapshot_data`].
+    ///
+    /// Each saved global is assigned, replacing any existing value. Globals which are not part of
+    /// the snapshot are left untouched.
+    ///
+    /// Returns a [`ToLuaConversionError`] if the input is malformed.
+    ///
+    /// [`snapshot_data`]: #method.snapshot_data
+    /// [`ToLuaConversionError`]: enum.Error.html#variant.ToLuaConversionError
+    pub fn restore_data(&self, bytes: &[u8]) -> Result<()> {
+        let data = match self.decode_value(bytes)? {
+            Value::Table(data) => data,
+            _ => return Err(decode_error("not a snapshot")),
+        };
+        let globals = self.globals();
+        for pair in data.pairs::<Value, Value>() {
+            let (key, value) = pair?;
+            globals.set(key, value)?;
+        }
+        Ok(())
+    }
+}
+
+struct Encoder {
+    output: Vec<u8>,
+    // Ids of already encoded tables, by their pointer
+    tables: HashMap<*const c_void, u64>,
+    // Skip unsupported table entries instead of returning an error
+    skip_unsupported: bool,
+    // Tables which are treated as unsupported values
+    excluded: HashSet<*const c_void>,
+    // Number of tables currently being encoded
+    depth: usize,
+}
+
+impl Encoder {
+    fn new(skip_unsupported: bool, excluded: HashSet<*const c_void>) -> Encoder {
+        Encoder {
+            output: MAGIC.to_vec(),
+            tables: HashMap::new(),
+            skip_unsupported,
+            excluded,
+            depth: 0,
+        }
+    }
+
+    fn is_excluded(&self, value: &Value) -> bool {
+        match *value {
+            Value::Table(ref t) => self.excluded.contains(&t.0.to_pointer()),
+            _ => false,
+        }
+    }
+
+    fn is_supported(&self, value: &Value) -> bool {
+        match *value {
+            Value::Nil
+            | Value::Boolean(_)
+            | Value::Integer(_)
+            | Value::Number(_)
+            | Value::String(_) => true,
+            Value::Table(_) => !self.is_excluded(value),
+            _ => false,
+        }
+    }
+
+    fn encode_entry(&mut self, key: &Value, value: &Value) -> Result<()> {
+        if self.skip_unsupported && !(self.is_supported(key) && self.is_supported(value)) {
+            return Ok(());
+        }
+        self.encode(key)?;
+        self.encode(value)
+    }
+
+    // `Integer` and `Number` are not `i64` and `f64` with every Lua version
+    #[allow(clippy::unnecessary_cast)]
+    fn encode(&mut self, value: &Value) -> Result<()> {
+        match *value {
+            Value::Nil => self.output.push(TAG_NIL),
+            Value::Boolean(false) => self.output.push(TAG_FALSE),
+            Value::Boolean(true) => self.output.push(TAG_TRUE),
+            Value::Integer(i) => {
+                self.output.push(TAG_INTEGER);
+                self.output.extend_from_slice(&(i as i64).to_le_bytes());
+            }
+            Value::Number(n) => {
+                self.output.push(TAG_NUMBER);
+                self.output
+                    .extend_from_slice(&(n as f64).to_bits().to_le_bytes());
+            }
+            Value::String(ref s) => {
+                let bytes = s.as_bytes();
+                self.output.push(TAG_STRING);
+                self.output
+                    .extend_from_slice(&(bytes.len() as u64).to_le_bytes());
+                self.output.extend_from_slice(bytes);
+            }
+            Value::Table(ref table) if !self.is_excluded(value) => self.encode_table(table)?,
+            ref value => {
+                return Err(Error::FromLuaConversionError {
+                    from: value.type_name(),
+                    to: "encoded value",
+                    message: Some(StdString::from("unsupported type")),
+                })
+            }
+        }
+        Ok(())
+    }
+
+    fn encode_table(&mut self, table: &Table) -> Result<()> {
+        let ptr = table.0.to_pointer();
+        if let Some(&id) = self.tables.get(&ptr) {
+            self.output.push(TAG_TABLE_REF);
+            self.output.extend_from_slice(&id.to_le_bytes());
+            return Ok(());
+        }
+        if self.depth >= MAX_DEPTH {
+            return Err(Error::FromLuaConversionError {
+                from: "table",
+                to: "encoded value",
+                message: Some(StdString::from("table nesting is too deep")),
+            });
+        }
+        let id = self.tables.len() as u64;
+        self.tables.insert(ptr, id);
+
+        self.depth += 1;
+        self.output.push(TAG_TABLE);
+        for pair in table.clone().pairs::<Value, Value>() {
+            let (key, value) = pair?;
+            self.encode_entry(&key, &value)?;
+        }
+        self.output.push(TAG_END);
+        self.depth -= 1;
+        Ok(())
+    }
+}
+
+struct Decoder<'lua, 'a> {
+    lua: &'lua Lua,
+    input: &'a [u8],
+    tables: Vec<Table<'lua>>,
+    // Number of tables currently being decoded
+    depth: usize,
+}
+
+impl<'lua, 'a> Decoder<'lua, 'a> {
+    fn decode(&mut self) -> Result<Value<'lua>> {
+        let tag = self.read_tag()?;
+        self.decode_tagged(tag)
+    }
+
+    fn decode_tagged(&mut self, tag: u8) -> Result<Value<'lua>> {
+        Ok(match tag {
+            TAG_NIL => Value::Nil,
+            TAG_FALSE => Value::Boolean(false),
+            TAG_TRUE => Value::Boolean(true),
+            TAG_INTEGER => Value::Integer(i64::from_le_bytes(self.read_array()?) as _),
+            TAG_NUMBER => {
+                Value::Number(f64::from_bits(u64::from_le_bytes(self.read_array()?)) as _)
+            }
+            TAG_STRING => {
+                let len = u64::from_le_bytes(self.read_array()?);
+                if len > self.input.len() as u64 {
+                    return Err(decode_error("unexpected end of input"));
+                }
+                let (bytes, rest) = self.input.split_at(len as usize);
+                self.input = rest;
+                Value::String(self.lua.create_string(bytes)?)
+            }
+            TAG_TABLE => {
+                if self.depth >= MAX_DEPTH {
+                    return Err(decode_error("table nesting is too deep"));
+                }
+                let table = self.lua.create_table()?;
+                self.tables.push(table.clone());
+                self.depth += 1;
+                loop {
+                    let tag = self.read_tag()?;
+                    if tag == TAG_END {
+                        break;
+                    }
+                    let key = self.decode_tagged(tag)?;
+                    let value = self.decode()?;
+                    if let Value::Nil = key {
+                        return Err(decode_error("nil table key"));
+                    }
+                    table.raw_set(key, value)?;
+                }
+                self.depth -= 1;
+                Value::Table(table)
+            }
+            TAG_TABLE_REF => {
+                let id = u64::from_le_bytes(self.read_array()?);
+                match self.tables.get(id as usize) {
+                    Some(table) => Value::Table(table.clone()),
+                    None => return Err(decode_error("invalid table reference")),
+                }
+            }
+            _ => return Err(decode_error("invalid tag")),
+        })
+    }
+
+    fn read_tag(&mut self) -> Result<u8> {
+        match self.input.split_first() {
+            Some((&tag, rest)) => {
+                self.input = rest;
+                Ok(tag)
+            }
+            None => Err(decode_error("unexpected end of input")),
+        }
+    }
+
+    fn read_array(&mut self) -> Result<[u8; 8]> {
+        if self.input.len() < 8 {
+            return Err(decode_error("unexpected end of input"));
+        }
+        let mut array = [0; 8];
+        array.copy_from_slice(&self.input[..8]);
+        self.input = &self.input[8..];
+        Ok(array)
+    }
+}
+
+fn decode_error(message: &str) -> Error {
+    Error::ToLuaConversionError {
+        from: "encoded value",
+        to: "value",
+        message: Some(StdString::from(message)),
+    }
+}
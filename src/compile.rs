@@ -0,0 +1,128 @@
+use std::os::raw::{c_int, c_void};
+use std::slice;
+use std::string::String as StdString;
+use std::thread::{self, JoinHandle};
+
+use crate::error::{Error, Result};
+use crate::ffi;
+use crate::function::Function;
+use crate::lua::{ChunkMode, Lua};
+use crate::stdlib::StdLib;
+use crate::types::RegistryKey;
+use crate::util::{assert_stack, StackGuard};
+
+/// A Lua chunk being compiled on a background thread.
+///
+/// This struct is created by the [`Lua::compile_on_thread`] method.
+///
+/// [`Lua::compile_on_thread`]: struct.Lua.html#method.compile_on_thread
+#[derive(Debug)]
+pub struct CompileHandle {
+    name: StdString,
+    thread: JoinHandle<Result<Vec<u8>>>,
+}
+
+impl CompileHandle {
+    /// Returns true if the compilation has finished, so that [`join`] will not block.
+    ///
+    /// This can be polled from an event loop or an async executor to avoid blocking on large
+    /// scripts.
+    ///
+    /// [`join`]: #method.join
+    pub fn is_finished(&self) -> bool {
+        self.thread.is_finished()
+    }
+
+    /// Waits for the compilation to finish and loads the compiled chunk into `lua`.
+    ///
+    /// The chunk is loaded as a function with the globals of `lua` as its environment, just like
+    /// [`Chunk::into_function`] would do, and stored in the registry. Syntax errors in the source
+    /// are returned here as [`SyntaxError`].
+    ///
+    /// [`Chunk::into_function`]: struct.Chunk.html#method.into_function
+    /// [`SyntaxError`]: enum.Error.html#variant.SyntaxError
+    pub fn join(self, lua: &Lua) -> Result<RegistryKey> {
+        let name = self.name;
+        let bytecode = self
+            .thread
+            .join()
+            .map_err(|_| Error::RuntimeError(format!("compilation of '{}' panicked", name)))??;
+        let func = lua
+            .load(&bytecode)
+            .set_name(&name)?
+            .set_mode(ChunkMode::Binary)
+            .into_function()?;
+        lua.create_registry_value(func)
+    }
+}
+
+impl Lua {
+    /// Compiles Lua source code on a new OS thread.
+    ///
+    /// A `Lua` instance is `Send` but not `Sync`, so code can only be loaded into it from the
+    /// thread that currently owns it, and loading blocks that thread while the source is parsed.
+    /// For large scripts this can be noticeable, e.g. at startup. This function instead parses the
+    /// source into bytecode using a separate Lua state on a background thread. The returned
+    /// [`CompileHandle`] then loads the bytecode into a `Lua` instance, which is much faster than
+    /// parsing the source.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use mlua::{Function, Lua, Result};
+    /// # fn main() -> Result<()> {
+    /// let handle = Lua::compile_on_thread("return 1 + 2", "bundle");
+    ///
+    /// let lua = Lua::new();
+    /// // ... do other work while the script compiles
+    /// let key = handle.join(&lua)?;
+    /// let bundle: Function = lua.registry_value(&key)?;
+    /// assert_eq!(bundle.call::<_, i64>(())?, 3);
+    /// # Ok(())
+    /// # }
+    /// ```
+    ///
+    /// [`CompileHandle`]: struct.CompileHandle.html
+    pub fn compile_on_thread<S: Into<Vec<u8>>>(source: S, name: &str) -> CompileHandle {
+        let source = source.into();
+        let thread_name = name.to_owned();
+        let thread = thread::spawn(move || {
+            let lua = Lua::new_with(StdLib::NONE);
+            let func = lua.load(&source).set_name(&thread_name)?.into_function()?;
+            Ok(dump_function(&func))
+        });
+        CompileHandle {
+            name: name.to_owned(),
+            thread,
+        }
+    }
+}
+
+// Returns the bytecode of `func`, including debug information.
+fn dump_function(func: &Function) -> Vec<u8> {
+    unsafe extern "C" fn writer(
+        _: *mut ffi::lua_State,
+        p: *const c_void,
+        sz: usize,
+        ud: *mut c_void,
+    ) -> c_int {
+        let buf = &mut *(ud as *mut Vec<u8>);
+        buf.extend_from_slice(slice::from_raw_parts(p as *const u8, sz));
+        0
+    }
+
+    let lua = func.0.lua;
+    let mut bytecode = Vec::new();
+    unsafe {
+        let _sg = StackGuard::new(lua.state);
+        assert_stack(lua.state, 1);
+        lua.push_ref(&func.0);
+        ffi::lua_dump(
+            lua.state,
+            writer,
+            &mut bytecode as *mut Vec<u8> as *mut c_void,
+            0,
+        );
+    }
+    bytecode
+}
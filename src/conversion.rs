@@ -1,9 +1,17 @@
 use std::collections::{BTreeMap, HashMap};
-use std::ffi::{CStr, CString};
+use std::ffi::{CStr, CString, OsStr, OsString};
 use std::hash::{BuildHasher, Hash};
+use std::net::{AddrParseError, IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+use std::num::{
+    NonZeroI128, NonZeroI16, NonZeroI32, NonZeroI64, NonZeroI8, NonZeroIsize, NonZeroU128,
+    NonZeroU16, NonZeroU32, NonZeroU64, NonZeroU8, NonZeroUsize,
+};
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
 use std::string::String as StdString;
+use std::sync::Arc;
 
-use bstr::{BStr, BString};
+use bstr::{BStr, BString, ByteSlice};
 use num_traits::cast;
 
 use crate::error::{Error, Result};
@@ -216,6 +224,37 @@ impl<'lua> FromLua<'lua> for StdString {
     }
 }
 
+impl<'lua> ToLua<'lua> for char {
+    fn to_lua(self, lua: &'lua Lua) -> Result<Value<'lua>> {
+        let mut buf = [0; 4];
+        Ok(Value::String(
+            lua.create_string(self.encode_utf8(&mut buf))?,
+        ))
+    }
+}
+
+impl<'lua> FromLua<'lua> for char {
+    fn from_lua(value: Value<'lua>, lua: &'lua Lua) -> Result<Self> {
+        let ty = value.type_name();
+        let s = lua
+            .coerce_string(value)?
+            .ok_or_else(|| Error::FromLuaConversionError {
+                from: ty,
+                to: "char",
+                message: Some("expected string or number".to_string()),
+            })?;
+        let mut chars = s.to_str()?.chars();
+        match (chars.next(), chars.next()) {
+            (Some(c), None) => Ok(c),
+            _ => Err(Error::FromLuaConversionError {
+                from: ty,
+                to: "char",
+                message: Some("expected a string of exactly one character".to_string()),
+            }),
+        }
+    }
+}
+
 impl<'lua, 'a> ToLua<'lua> for &'a str {
     fn to_lua(self, lua: &'lua Lua) -> Result<Value<'lua>> {
         Ok(Value::String(lua.create_string(self)?))
@@ -269,7 +308,7 @@ impl<'lua> FromLua<'lua> for BString {
             lua.coerce_string(value)?
                 .ok_or_else(|| Error::FromLuaConversionError {
                     from: ty,
-                    to: "String",
+                    to: "BString",
                     message: Some("expected string or number".to_string()),
                 })?
                 .as_bytes()
@@ -278,12 +317,177 @@ impl<'lua> FromLua<'lua> for BString {
     }
 }
 
+// `Vec<u8>` and `Box<[u8]>` convert to tables like any other sequence, so `&BStr` is the way to
+// pass borrowed bytes (such as large static data) as a Lua string. The bytes are copied once into
+// the Lua string, without an intermediate allocation.
 impl<'lua, 'a> ToLua<'lua> for &BStr {
     fn to_lua(self, lua: &'lua Lua) -> Result<Value<'lua>> {
         Ok(Value::String(lua.create_string(&self)?))
     }
 }
 
+// Lua strings are byte strings, so OS strings and paths are converted as raw bytes. This is
+// lossless on Unix, while on Windows they must be valid UTF-8 to be converted.
+
+impl<'lua> ToLua<'lua> for OsString {
+    fn to_lua(self, lua: &'lua Lua) -> Result<Value<'lua>> {
+        self.as_os_str().to_lua(lua)
+    }
+}
+
+impl<'lua> FromLua<'lua> for OsString {
+    fn from_lua(value: Value<'lua>, lua: &'lua Lua) -> Result<Self> {
+        let ty = value.type_name();
+        let string = lua
+            .coerce_string(value)?
+            .ok_or_else(|| Error::FromLuaConversionError {
+                from: ty,
+                to: "OsString",
+                message: Some("expected string or number".to_string()),
+            })?;
+
+        match string.as_bytes().to_os_str() {
+            Ok(s) => Ok(s.to_os_string()),
+            Err(err) => Err(Error::FromLuaConversionError {
+                from: ty,
+                to: "OsString",
+                message: Some(err.to_string()),
+            }),
+        }
+    }
+}
+
+impl<'lua> ToLua<'lua> for &OsStr {
+    fn to_lua(self, lua: &'lua Lua) -> Result<Value<'lua>> {
+        match <[u8]>::from_os_str(self) {
+            Some(bytes) => Ok(Value::String(lua.create_string(bytes)?)),
+            None => Err(Error::ToLuaConversionError {
+                from: "OsStr",
+                to: "string",
+                message: Some("invalid utf-8 encoding".to_string()),
+            }),
+        }
+    }
+}
+
+impl<'lua> ToLua<'lua> for PathBuf {
+    fn to_lua(self, lua: &'lua Lua) -> Result<Value<'lua>> {
+        self.as_path().to_lua(lua)
+    }
+}
+
+impl<'lua> FromLua<'lua> for PathBuf {
+    fn from_lua(value: Value<'lua>, lua: &'lua Lua) -> Result<Self> {
+        let ty = value.type_name();
+        let string = lua
+            .coerce_string(value)?
+            .ok_or_else(|| Error::FromLuaConversionError {
+                from: ty,
+                to: "PathBuf",
+                message: Some("expected string or number".to_string()),
+            })?;
+
+        match string.as_bytes().to_path() {
+            Ok(path) => Ok(path.to_path_buf()),
+            Err(err) => Err(Error::FromLuaConversionError {
+                from: ty,
+                to: "PathBuf",
+                message: Some(err.to_string()),
+            }),
+        }
+    }
+}
+
+impl<'lua> ToLua<'lua> for &Path {
+    fn to_lua(self, lua: &'lua Lua) -> Result<Value<'lua>> {
+        match <[u8]>::from_path(self) {
+            Some(bytes) => Ok(Value::String(lua.create_string(bytes)?)),
+            None => Err(Error::ToLuaConversionError {
+                from: "Path",
+                to: "string",
+                message: Some("invalid utf-8 encoding".to_string()),
+            }),
+        }
+    }
+}
+
+// Network addresses are converted to and from their canonical string representation.
+
+macro_rules! lua_convert_addr {
+    ($x:ty) => {
+        impl<'lua> ToLua<'lua> for $x {
+            fn to_lua(self, lua: &'lua Lua) -> Result<Value<'lua>> {
+                Ok(Value::String(lua.create_string(&self.to_string())?))
+            }
+        }
+
+        impl<'lua> FromLua<'lua> for $x {
+            fn from_lua(value: Value<'lua>, _: &'lua Lua) -> Result<Self> {
+                let ty = value.type_name();
+                let string = match value {
+                    Value::String(s) => s,
+                    _ => {
+                        return Err(Error::FromLuaConversionError {
+                            from: ty,
+                            to: stringify!($x),
+                            message: Some("expected string".to_string()),
+                        })
+                    }
+                };
+
+                string.to_str()?.parse().map_err(|err: AddrParseError| {
+                    Error::FromLuaConversionError {
+                        from: ty,
+                        to: stringify!($x),
+                        message: Some(err.to_string()),
+                    }
+                })
+            }
+        }
+    };
+}
+
+lua_convert_addr!(IpAddr);
+lua_convert_addr!(Ipv4Addr);
+lua_convert_addr!(Ipv6Addr);
+lua_convert_addr!(SocketAddr);
+
+// UUIDs are converted to their hyphenated string representation, e.g.
+// `67e55044-10b1-426f-9247-bb680e5fe0c8`. Other formats accepted by `Uuid::parse_str` are accepted
+// when converting from Lua.
+
+#[cfg(feature = "uuid")]
+impl<'lua> ToLua<'lua> for uuid::Uuid {
+    fn to_lua(self, lua: &'lua Lua) -> Result<Value<'lua>> {
+        let mut buf = [0; uuid::adapter::Hyphenated::LENGTH];
+        let s = self.to_hyphenated().encode_lower(&mut buf);
+        Ok(Value::String(lua.create_string(s)?))
+    }
+}
+
+#[cfg(feature = "uuid")]
+impl<'lua> FromLua<'lua> for uuid::Uuid {
+    fn from_lua(value: Value<'lua>, _: &'lua Lua) -> Result<Self> {
+        let ty = value.type_name();
+        let string = match value {
+            Value::String(s) => s,
+            _ => {
+                return Err(Error::FromLuaConversionError {
+                    from: ty,
+                    to: "Uuid",
+                    message: Some("expected string".to_string()),
+                })
+            }
+        };
+
+        uuid::Uuid::parse_str(string.to_str()?).map_err(|err| Error::FromLuaConversionError {
+            from: ty,
+            to: "Uuid",
+            message: Some(err.to_string()),
+        })
+    }
+}
+
 macro_rules! lua_convert_int {
     ($x:ty) => {
         impl<'lua> ToLua<'lua> for $x {
@@ -341,6 +545,42 @@ lua_convert_int!(u128);
 lua_convert_int!(isize);
 lua_convert_int!(usize);
 
+macro_rules! lua_convert_nonzero {
+    ($x:ty, $int:ty) => {
+        impl<'lua> ToLua<'lua> for $x {
+            fn to_lua(self, lua: &'lua Lua) -> Result<Value<'lua>> {
+                self.get().to_lua(lua)
+            }
+        }
+
+        impl<'lua> FromLua<'lua> for $x {
+            fn from_lua(value: Value<'lua>, lua: &'lua Lua) -> Result<Self> {
+                let ty = value.type_name();
+                <$x>::new(<$int>::from_lua(value, lua)?).ok_or_else(|| {
+                    Error::FromLuaConversionError {
+                        from: ty,
+                        to: stringify!($x),
+                        message: Some("expected non-zero".to_owned()),
+                    }
+                })
+            }
+        }
+    };
+}
+
+lua_convert_nonzero!(NonZeroI8, i8);
+lua_convert_nonzero!(NonZeroU8, u8);
+lua_convert_nonzero!(NonZeroI16, i16);
+lua_convert_nonzero!(NonZeroU16, u16);
+lua_convert_nonzero!(NonZeroI32, i32);
+lua_convert_nonzero!(NonZeroU32, u32);
+lua_convert_nonzero!(NonZeroI64, i64);
+lua_convert_nonzero!(NonZeroU64, u64);
+lua_convert_nonzero!(NonZeroI128, i128);
+lua_convert_nonzero!(NonZeroU128, u128);
+lua_convert_nonzero!(NonZeroIsize, isize);
+lua_convert_nonzero!(NonZeroUsize, usize);
+
 macro_rules! lua_convert_float {
     ($x:ty) => {
         impl<'lua> ToLua<'lua> for $x {
@@ -373,6 +613,60 @@ macro_rules! lua_convert_float {
 lua_convert_float!(f32);
 lua_convert_float!(f64);
 
+// A generic `Box<T>` conversion would conflict with the `UserData` impls above, since `Box` is a
+// fundamental type. Unsized contents are covered instead.
+impl<'lua> ToLua<'lua> for Box<str> {
+    fn to_lua(self, lua: &'lua Lua) -> Result<Value<'lua>> {
+        Ok(Value::String(lua.create_string(&*self)?))
+    }
+}
+
+impl<'lua> FromLua<'lua> for Box<str> {
+    fn from_lua(value: Value<'lua>, lua: &'lua Lua) -> Result<Self> {
+        Ok(StdString::from_lua(value, lua)?.into_boxed_str())
+    }
+}
+
+impl<'lua, T: ToLua<'lua>> ToLua<'lua> for Box<[T]> {
+    fn to_lua(self, lua: &'lua Lua) -> Result<Value<'lua>> {
+        Ok(Value::Table(lua.create_sequence_from(self.into_vec())?))
+    }
+}
+
+impl<'lua, T: FromLua<'lua>> FromLua<'lua> for Box<[T]> {
+    fn from_lua(value: Value<'lua>, lua: &'lua Lua) -> Result<Self> {
+        Ok(Vec::<T>::from_lua(value, lua)?.into_boxed_slice())
+    }
+}
+
+/// Shared pointers are converted by cloning the pointed-to value.
+impl<'lua, T: Clone + ToLua<'lua>> ToLua<'lua> for Rc<T> {
+    fn to_lua(self, lua: &'lua Lua) -> Result<Value<'lua>> {
+        T::clone(&self).to_lua(lua)
+    }
+}
+
+/// Always produces a new `Rc` that is not shared with any other pointer.
+impl<'lua, T: FromLua<'lua>> FromLua<'lua> for Rc<T> {
+    fn from_lua(value: Value<'lua>, lua: &'lua Lua) -> Result<Self> {
+        Ok(Rc::new(T::from_lua(value, lua)?))
+    }
+}
+
+/// Shared pointers are converted by cloning the pointed-to value.
+impl<'lua, T: Clone + ToLua<'lua>> ToLua<'lua> for Arc<T> {
+    fn to_lua(self, lua: &'lua Lua) -> Result<Value<'lua>> {
+        T::clone(&self).to_lua(lua)
+    }
+}
+
+/// Always produces a new `Arc` that is not shared with any other pointer.
+impl<'lua, T: FromLua<'lua>> FromLua<'lua> for Arc<T> {
+    fn from_lua(value: Value<'lua>, lua: &'lua Lua) -> Result<Self> {
+        Ok(Arc::new(T::from_lua(value, lua)?))
+    }
+}
+
 impl<'lua, T: ToLua<'lua>> ToLua<'lua> for Vec<T> {
     fn to_lua(self, lua: &'lua Lua) -> Result<Value<'lua>> {
         Ok(Value::Table(lua.create_sequence_from(self)?))
@@ -4,6 +4,8 @@ use std::result::Result as StdResult;
 use std::string::String as StdString;
 use std::sync::Arc;
 
+use crate::types::RegistryKey;
+
 /// Error type returned by `mlua` methods.
 #[derive(Debug, Clone)]
 pub enum Error {
@@ -23,6 +25,27 @@ pub enum Error {
     /// Among other things, this includes invoking operators on wrong types (such as calling or
     /// indexing a `nil` value).
     RuntimeError(StdString),
+    /// Lua runtime error raised with a value that is not a string or a number, such as a table
+    /// raised with `error({code = 42})`.
+    ///
+    /// The original error value is kept in the Lua registry and can be retrieved with
+    /// [`Lua::registry_value`]. When this error is returned from a Rust callback, the original
+    /// value is raised again in Lua.
+    ///
+    /// Once every copy of this error has been dropped, its registry slot is released the next time
+    /// Lua raises another such error (or [`Lua::create_error_object`] is called), or by
+    /// [`Lua::expire_registry_values`], so dropped errors do not pile up in the registry.
+    ///
+    /// [`Lua::create_error_object`]: struct.Lua.html#method.create_error_object
+    ///
+    /// [`Lua::registry_value`]: struct.Lua.html#method.registry_value
+    /// [`Lua::expire_registry_values`]: struct.Lua.html#method.expire_registry_values
+    RuntimeErrorValue {
+        /// A description of the error value.
+        message: StdString,
+        /// The registry key of the error value.
+        value: Arc<RegistryKey>,
+    },
     /// Lua memory error, aka `LUA_ERRMEM`
     ///
     /// The Lua VM returns this error when the allocator does not return the requested memory, aka
@@ -33,10 +56,32 @@ pub enum Error {
     /// The Lua VM returns this error when there is an error running a `__gc` metamethod.
     #[cfg(any(feature = "lua53", feature = "lua52"))]
     GarbageCollectorError(StdString),
+    /// Setting memory limit is not available.
+    ///
+    /// This error can only happen when the Lua state was not created by mlua (e.g. in a module)
+    /// or when using LuaJIT, which does not support custom allocators.
+    MemoryLimitNotAvailable,
+    /// Lua code ran longer than the deadline given to [`Lua::exec_with_timeout`].
+    ///
+    /// [`Lua::exec_with_timeout`]: struct.Lua.html#method.exec_with_timeout
+    Timeout,
+    /// Lua code executed more instructions than allowed by the instruction limit of a sandbox.
+    ///
+    /// See [`SandboxConfig::instruction_limit`].
+    ///
+    /// [`SandboxConfig::instruction_limit`]: struct.SandboxConfig.html#structfield.instruction_limit
+    InstructionLimitExceeded,
     /// A mutable callback has triggered Lua code that has called the same mutable callback again.
     ///
     /// This is an error because a mutable callback can only be borrowed mutably once.
     RecursiveMutCallback,
+    /// Too many Rust callbacks were running at the same time.
+    ///
+    /// Returned when calling a Rust callback would exceed the limit set with
+    /// [`Lua::set_call_depth_limit`].
+    ///
+    /// [`Lua::set_call_depth_limit`]: struct.Lua.html#method.set_call_depth_limit
+    RecursionLimitReached,
     /// Either a callback or a userdata method has been called, but the callback or userdata has
     /// been destructed.
     ///
@@ -70,6 +115,20 @@ pub enum Error {
         /// A string containing more detailed error information.
         message: Option<StdString>,
     },
+    /// An argument passed to a Rust callback could not be converted to the expected type.
+    ///
+    /// The message follows the style of Lua's own argument errors, e.g.
+    /// `bad argument #2 to 'foo' (i64 expected, got string)`.
+    BadArgument {
+        /// Name of the called function, if it could be determined.
+        to: Option<StdString>,
+        /// Position of the argument, starting from 1.
+        pos: usize,
+        /// Name of the argument, if provided when registering the function.
+        name: Option<StdString>,
+        /// Underlying conversion error.
+        cause: Arc<Error>,
+    },
     /// [`Thread::resume`] was called on an inactive coroutine.
     ///
     /// A coroutine is inactive if its main function has returned or if an error has occured inside
@@ -108,8 +167,26 @@ pub enum Error {
     /// [`AnyUserData`]: struct.AnyUserData.html
     /// [`UserData`]: trait.UserData.html
     UserDataBorrowMutError,
+    /// A metatable field that is managed by mlua was modified through [`UserDataMetatable`].
+    ///
+    /// The `__gc`, `__metatable` and `__index` fields are reserved, methods should be changed with
+    /// [`UserDataMetatable::set_method`] instead.
+    ///
+    /// [`UserDataMetatable`]: struct.UserDataMetatable.html
+    /// [`UserDataMetatable::set_method`]: struct.UserDataMetatable.html#method.set_method
+    MetaMethodRestricted(StdString),
     /// A `RegistryKey` produced from a different Lua state was used.
     MismatchedRegistryKey,
+    /// A value created by a different Lua state was used.
+    ///
+    /// Handles such as [`Table`] or [`Function`] belong to the Lua state that created them and can
+    /// not be passed to another one. Use [`Lua::ptr_eq`] to check whether two `Lua` instances
+    /// share the same state.
+    ///
+    /// [`Table`]: struct.Table.html
+    /// [`Function`]: struct.Function.html
+    /// [`Lua::ptr_eq`]: struct.Lua.html#method.ptr_eq
+    MismatchedLuaState,
     /// A Rust callback returned `Err`, raising the contained `Error` as a Lua error.
     CallbackError {
         /// Lua call stack backtrace.
@@ -135,6 +212,9 @@ impl fmt::Display for Error {
         match *self {
             Error::SyntaxError { ref message, .. } => write!(fmt, "syntax error: {}", message),
             Error::RuntimeError(ref msg) => write!(fmt, "runtime error: {}", msg),
+            Error::RuntimeErrorValue { ref message, .. } => {
+                write!(fmt, "runtime error: {}", message)
+            }
             Error::MemoryError(ref msg) => {
                 write!(fmt, "memory error: {}", msg)
             }
@@ -142,7 +222,11 @@ impl fmt::Display for Error {
             Error::GarbageCollectorError(ref msg) => {
                 write!(fmt, "garbage collector error: {}", msg)
             }
+            Error::MemoryLimitNotAvailable => write!(fmt, "setting memory limit is not available"),
+            Error::Timeout => write!(fmt, "script execution timed out"),
+            Error::InstructionLimitExceeded => write!(fmt, "instruction limit exceeded"),
             Error::RecursiveMutCallback => write!(fmt, "mutable callback called recursively"),
+            Error::RecursionLimitReached => write!(fmt, "callback recursion limit reached"),
             Error::CallbackDestructed => write!(
                 fmt,
                 "a destructed callback or destructed userdata method was called"
@@ -177,13 +261,37 @@ impl fmt::Display for Error {
                     Some(ref message) => write!(fmt, " ({})", message),
                 }
             }
+            Error::BadArgument {
+                ref to,
+                pos,
+                ref name,
+                ref cause,
+            } => {
+                match *name {
+                    Some(ref name) => write!(fmt, "bad argument '{}'", name)?,
+                    None => write!(fmt, "bad argument #{}", pos)?,
+                }
+                if let Some(ref to) = *to {
+                    write!(fmt, " to '{}'", to)?;
+                }
+                match **cause {
+                    Error::FromLuaConversionError { from, to, .. } => {
+                        write!(fmt, " ({} expected, got {})", to, from)
+                    }
+                    ref cause => write!(fmt, ": {}", cause),
+                }
+            }
             Error::CoroutineInactive => write!(fmt, "cannot resume inactive coroutine"),
             Error::UserDataTypeMismatch => write!(fmt, "userdata is not expected type"),
             Error::UserDataBorrowError => write!(fmt, "userdata already mutably borrowed"),
             Error::UserDataBorrowMutError => write!(fmt, "userdata already borrowed"),
+            Error::MetaMethodRestricted(ref field) => write!(fmt, "metamethod {} is restricted", field),
             Error::MismatchedRegistryKey => {
                 write!(fmt, "RegistryKey used from different Lua state")
             }
+            Error::MismatchedLuaState => {
+                write!(fmt, "value created by a different Lua state used")
+            }
             Error::CallbackError { ref traceback, ref cause } => {
                 write!(fmt, "callback error: {}: {}", cause, traceback)
             }
@@ -195,6 +303,7 @@ impl fmt::Display for Error {
 impl StdError for Error {
     fn source(&self) -> Option<&(dyn StdError + 'static)> {
         match *self {
+            Error::BadArgument { ref cause, .. } => Some(cause.as_ref()),
             Error::CallbackError { ref cause, .. } => Some(cause.as_ref()),
             Error::ExternalError(ref err) => Some(err.as_ref()),
             _ => None,
@@ -206,6 +315,186 @@ impl Error {
     pub fn external<T: Into<Box<dyn StdError + Send + Sync>>>(err: T) -> Error {
         Error::ExternalError(err.into().into())
     }
+
+    /// Returns the bare error message, without the decorations added by `Display`.
+    ///
+    /// For errors raised by Lua this is the message as given by Lua, without the kind prefix (such
+    /// as `runtime error:`) and without the stack traceback. For a [`CallbackError`] it is the
+    /// message of the error returned by the callback, and for an [`ExternalError`] it is the
+    /// `Display` output of the wrapped error. Other errors return their `Display` output.
+    ///
+    /// [`CallbackError`]: #variant.CallbackError
+    /// [`ExternalError`]: #variant.ExternalError
+    pub fn message(&self) -> StdString {
+        match *self {
+            Error::SyntaxError { ref message, .. }
+            | Error::RuntimeErrorValue { ref message, .. } => message.clone(),
+            Error::RuntimeError(ref msg) | Error::MemoryError(ref msg) => strip_traceback(msg),
+            #[cfg(any(feature = "lua53", feature = "lua52"))]
+            Error::GarbageCollectorError(ref msg) => strip_traceback(msg),
+            Error::CallbackError { ref cause, .. } => cause.message(),
+            Error::ExternalError(ref err) => err.to_string(),
+            ref err => err.to_string(),
+        }
+    }
+
+    /// Returns an iterator over this error and its causes.
+    ///
+    /// The first item is the error itself. It is followed by the cause of a [`CallbackError`] or
+    /// [`BadArgument`], or by the wrapped error of an [`ExternalError`] if that is an `Error`,
+    /// and so on down to the innermost error.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use mlua::{Error, Lua, Result};
+    /// # fn main() -> Result<()> {
+    /// # let lua = Lua::new();
+    /// let fail = lua.create_function(|_, ()| -> Result<()> {
+    ///     Err(Error::RuntimeError("boom".to_string()))
+    /// })?;
+    ///
+    /// let err = fail.call::<_, ()>(()).unwrap_err();
+    /// let messages = err.chain().map(Error::message).collect::<Vec<_>>();
+    /// assert_eq!(messages, vec!["boom", "boom"]);
+    /// assert!(matches!(err.chain().last(), Some(Error::RuntimeError(_))));
+    /// # Ok(())
+    /// # }
+    /// ```
+    ///
+    /// [`CallbackError`]: #variant.CallbackError
+    /// [`BadArgument`]: #variant.BadArgument
+    /// [`ExternalError`]: #variant.ExternalError
+    pub fn chain(&self) -> ErrorChain<'_> {
+        ErrorChain { next: Some(self) }
+    }
+
+    /// Returns the category of the error.
+    ///
+    /// This allows reacting to classes of errors without matching every variant or inspecting
+    /// error messages. A [`CallbackError`] is classified as [`ErrorKind::Callback`]; use
+    /// [`chain`] to classify the error returned by the callback instead.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use mlua::{ErrorKind, Lua, Result};
+    /// # fn main() -> Result<()> {
+    /// # let lua = Lua::new();
+    /// let err = lua.load("x = ").exec().unwrap_err();
+    /// assert_eq!(err.kind(), ErrorKind::Syntax);
+    ///
+    /// let err = lua.load("error('boom')").exec().unwrap_err();
+    /// assert_eq!(err.kind(), ErrorKind::Runtime);
+    /// # Ok(())
+    /// # }
+    /// ```
+    ///
+    /// [`CallbackError`]: #variant.CallbackError
+    /// [`ErrorKind::Callback`]: enum.ErrorKind.html#variant.Callback
+    /// [`chain`]: #method.chain
+    pub fn kind(&self) -> ErrorKind {
+        match *self {
+            Error::SyntaxError { .. } => ErrorKind::Syntax,
+            Error::RuntimeError(_) | Error::RuntimeErrorValue { .. } => ErrorKind::Runtime,
+            Error::MemoryError(_) => ErrorKind::Memory,
+            #[cfg(any(feature = "lua53", feature = "lua52"))]
+            Error::GarbageCollectorError(_) => ErrorKind::GarbageCollector,
+            Error::ToLuaConversionError { .. } => ErrorKind::ToLuaConversion,
+            Error::FromLuaConversionError { .. } => ErrorKind::FromLuaConversion,
+            Error::BadArgument { .. } => ErrorKind::BadArgument,
+            Error::UserDataTypeMismatch
+            | Error::UserDataBorrowError
+            | Error::UserDataBorrowMutError => ErrorKind::UserData,
+            Error::Timeout
+            | Error::InstructionLimitExceeded
+            | Error::RecursionLimitReached
+            | Error::StackError
+            | Error::BindError => ErrorKind::Limit,
+            Error::MemoryLimitNotAvailable
+            | Error::RecursiveMutCallback
+            | Error::CallbackDestructed
+            | Error::CoroutineInactive
+            | Error::MetaMethodRestricted(_)
+            | Error::MismatchedRegistryKey
+            | Error::MismatchedLuaState => ErrorKind::Usage,
+            Error::CallbackError { .. } => ErrorKind::Callback,
+            Error::ExternalError(_) => ErrorKind::External,
+        }
+    }
+}
+
+/// The category of an [`Error`], as returned by [`Error::kind`].
+///
+/// [`Error`]: enum.Error.html
+/// [`Error::kind`]: enum.Error.html#method.kind
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ErrorKind {
+    /// A [`SyntaxError`](enum.Error.html#variant.SyntaxError).
+    Syntax,
+    /// A [`RuntimeError`](enum.Error.html#variant.RuntimeError) or a
+    /// [`RuntimeErrorValue`](enum.Error.html#variant.RuntimeErrorValue).
+    Runtime,
+    /// A [`MemoryError`](enum.Error.html#variant.MemoryError).
+    Memory,
+    /// An error in a `__gc` metamethod. Only raised by Lua 5.2 and 5.3.
+    GarbageCollector,
+    /// A [`ToLuaConversionError`](enum.Error.html#variant.ToLuaConversionError).
+    ToLuaConversion,
+    /// A [`FromLuaConversionError`](enum.Error.html#variant.FromLuaConversionError).
+    FromLuaConversion,
+    /// A [`BadArgument`](enum.Error.html#variant.BadArgument) error.
+    BadArgument,
+    /// A failed [`AnyUserData`](struct.AnyUserData.html) borrow.
+    UserData,
+    /// A limit was exceeded: the execution timeout, the callback depth limit, the Lua stack size or
+    /// the number of bound arguments.
+    Limit,
+    /// The API was used in a way that is not supported, such as resuming a dead coroutine or
+    /// mixing values from different Lua states.
+    Usage,
+    /// A [`CallbackError`](enum.Error.html#variant.CallbackError).
+    Callback,
+    /// An [`ExternalError`](enum.Error.html#variant.ExternalError).
+    External,
+}
+
+/// An iterator over an [`Error`] and its causes.
+///
+/// This struct is created by the [`Error::chain`] method.
+///
+/// [`Error`]: enum.Error.html
+/// [`Error::chain`]: enum.Error.html#method.chain
+#[derive(Debug, Clone)]
+pub struct ErrorChain<'a> {
+    next: Option<&'a Error>,
+}
+
+impl<'a> Iterator for ErrorChain<'a> {
+    type Item = &'a Error;
+
+    fn next(&mut self) -> Option<&'a Error> {
+        let current = self.next?;
+        self.next = match *current {
+            Error::BadArgument { ref cause, .. } | Error::CallbackError { ref cause, .. } => {
+                Some(cause.as_ref())
+            }
+            Error::ExternalError(ref err) => {
+                let err: &(dyn StdError + 'static) = err.as_ref();
+                err.downcast_ref::<Error>()
+            }
+            _ => None,
+        };
+        Some(current)
+    }
+}
+
+// Removes the stack traceback that mlua appends to Lua error messages.
+fn strip_traceback(msg: &str) -> StdString {
+    match msg.find("\nstack traceback:") {
+        Some(pos) => msg[..pos].to_string(),
+        None => msg.to_string(),
+    }
 }
 
 pub trait ExternalError {
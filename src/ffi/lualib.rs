@@ -64,4 +64,18 @@ extern "C" {
     pub fn luaopen_ffi(L: *mut lua_State) -> c_int;
 
     pub fn luaL_openlibs(L: *mut lua_State);
+
+    // from `luajit.h`
+    #[cfg(feature = "luajit")]
+    pub fn luaJIT_setmode(L: *mut lua_State, idx: c_int, mode: c_int) -> c_int;
 }
+
+// `luaJIT_setmode` modes and flags
+#[cfg(feature = "luajit")]
+pub const LUAJIT_MODE_ENGINE: c_int = 0;
+#[cfg(feature = "luajit")]
+pub const LUAJIT_MODE_OFF: c_int = 0x0000;
+#[cfg(feature = "luajit")]
+pub const LUAJIT_MODE_ON: c_int = 0x0100;
+#[cfg(feature = "luajit")]
+pub const LUAJIT_MODE_FLUSH: c_int = 0x0200;
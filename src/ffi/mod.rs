@@ -193,7 +193,10 @@ pub use self::lualib::{luaopen_coroutine, luaopen_utf8};
 pub use self::lualib::{luaopen_bit32, luaopen_coroutine};
 
 #[cfg(feature = "luajit")]
-pub use self::lualib::{luaopen_bit, luaopen_ffi, luaopen_jit};
+pub use self::lualib::{
+    luaJIT_setmode, luaopen_bit, luaopen_ffi, luaopen_jit, LUAJIT_MODE_ENGINE, LUAJIT_MODE_FLUSH,
+    LUAJIT_MODE_OFF, LUAJIT_MODE_ON,
+};
 
 // constants from lua.h
 pub use self::lua::{
@@ -202,8 +205,8 @@ pub use self::lua::{
     LUA_HOOKCOUNT, LUA_HOOKLINE, LUA_HOOKRET, LUA_HOOKTAILCALL, LUA_MASKCALL, LUA_MASKCOUNT,
     LUA_MASKLINE, LUA_MASKRET, LUA_MINSTACK, LUA_MULTRET, LUA_OK, LUA_OPADD, LUA_OPDIV, LUA_OPEQ,
     LUA_OPLE, LUA_OPLT, LUA_OPMOD, LUA_OPMUL, LUA_OPPOW, LUA_OPSUB, LUA_OPUNM, LUA_REGISTRYINDEX,
-    LUA_TBOOLEAN, LUA_TFUNCTION, LUA_TLIGHTUSERDATA, LUA_TNIL, LUA_TNONE, LUA_TNUMBER, LUA_TSTRING,
-    LUA_TTABLE, LUA_TTHREAD, LUA_TUSERDATA, LUA_YIELD,
+    LUA_RELEASE, LUA_TBOOLEAN, LUA_TFUNCTION, LUA_TLIGHTUSERDATA, LUA_TNIL, LUA_TNONE, LUA_TNUMBER,
+    LUA_TSTRING, LUA_TTABLE, LUA_TTHREAD, LUA_TUSERDATA, LUA_YIELD,
 };
 
 #[cfg(feature = "lua53")]
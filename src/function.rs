@@ -1,13 +1,19 @@
+use std::ffi::CStr;
 use std::os::raw::c_int;
-use std::ptr;
+use std::string::String as StdString;
+use std::sync::Arc;
+use std::{mem, ptr};
 
 use crate::error::{Error, Result};
 use crate::ffi;
-use crate::types::LuaRef;
+use crate::hook::{Debug, HookMask};
+use crate::lua::Lua;
+use crate::thread::Thread;
+use crate::types::{HookCallback, LuaRef};
 use crate::util::{
     assert_stack, check_stack, error_traceback, pop_error, protect_lua_closure, StackGuard,
 };
-use crate::value::{FromLuaMulti, MultiValue, ToLuaMulti};
+use crate::value::{FromLua, FromLuaMulti, MultiValue, ToLua, ToLuaMulti, Value};
 
 /// Handle to an internal Lua function.
 #[derive(Clone, Debug)]
@@ -86,6 +92,117 @@ impl<'lua> Function<'lua> {
         R::from_lua_multi(results, lua)
     }
 
+    /// Calls the function like [`call`], also returning the results not consumed by `H`.
+    ///
+    /// [`call`] silently drops the results that don't fit the requested type. This method converts
+    /// the leading results to `H` and returns the remaining ones as a [`MultiValue`], which is
+    /// useful for functions returning a status followed by a variable number of values.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use mlua::{Function, Lua, Result};
+    /// # fn main() -> Result<()> {
+    /// # let lua = Lua::new();
+    /// let f: Function = lua.load("function() return true, 1, 2 end").eval()?;
+    ///
+    /// let (ok, rest) = f.call_with_rest::<_, bool>(())?;
+    /// assert!(ok);
+    /// assert_eq!(rest.len(), 2);
+    /// # Ok(())
+    /// # }
+    /// ```
+    ///
+    /// [`call`]: #method.call
+    /// [`MultiValue`]: struct.MultiValue.html
+    pub fn call_with_rest<A, H>(&self, args: A) -> Result<(H, MultiValue<'lua>)>
+    where
+        A: ToLuaMulti<'lua>,
+        H: FromLuaMulti<'lua>,
+    {
+        let results: MultiValue = self.call(args)?;
+        H::from_lua_multi_with_rest(results, self.0.lua)
+    }
+
+    /// Calls the function like [`call`], but without a protected call.
+    ///
+    /// This skips the `lua_pcall` frame and error handler that [`call`] sets up, which saves a
+    /// little time per call in hot loops. The call is made with `lua_call` directly.
+    ///
+    /// # Safety
+    ///
+    /// The called function must not raise an error, including memory errors and errors raised by
+    /// metamethods or by Rust callbacks it calls. An error raised outside of any protected call
+    /// invokes the Lua panic handler, which aborts the process. Inside a Rust callback it unwinds
+    /// through Rust frames using `longjmp`, which is undefined behavior.
+    ///
+    /// Errors converting `args` or the returned values are still returned normally.
+    ///
+    /// [`call`]: #method.call
+    pub unsafe fn call_unprotected<A, R>(&self, args: A) -> Result<R>
+    where
+        A: ToLuaMulti<'lua>,
+        R: FromLuaMulti<'lua>,
+    {
+        let lua = self.0.lua;
+
+        let args = args.to_lua_multi(lua)?;
+        let nargs = args.len() as c_int;
+
+        let results = {
+            let _sg = StackGuard::new(lua.state);
+            check_stack(lua.state, nargs + 2)?;
+
+            let stack_start = ffi::lua_gettop(lua.state);
+            lua.push_ref(&self.0);
+            for arg in args {
+                lua.push_value(arg)?;
+            }
+            ffi::lua_call(lua.state, nargs, ffi::LUA_MULTRET);
+            let nresults = ffi::lua_gettop(lua.state) - stack_start;
+            let mut results = MultiValue::new();
+            assert_stack(lua.state, 2);
+            for _ in 0..nresults {
+                results.push_front(lua.pop_value());
+            }
+            results
+        };
+        R::from_lua_multi(results, lua)
+    }
+
+    /// Calls the function, passing `args` as function arguments, and collects every returned value.
+    ///
+    /// This is useful when the number of values returned by the function is not known in advance.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use mlua::{Function, Lua, Result};
+    /// # fn main() -> Result<()> {
+    /// # let lua = Lua::new();
+    /// let triple: Function = lua.load(
+    ///     r#"
+    ///         function(a)
+    ///             return a, a * 2, a * 3
+    ///         end
+    /// "#).eval()?;
+    ///
+    /// let results = triple.call_multi(1)?;
+    /// assert_eq!(results.len(), 3);
+    ///
+    /// let results = results
+    ///     .into_iter()
+    ///     .map(|v| lua.unpack::<i64>(v))
+    ///     .collect::<Result<Vec<_>>>()?;
+    /// assert_eq!(results, vec![1, 2, 3]);
+    ///
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn call_multi<A: ToLuaMulti<'lua>>(&self, args: A) -> Result<Vec<Value<'lua>>> {
+        Ok(self.call::<_, MultiValue>(args)?.into_vec())
+    }
+
     /// Returns a function that, when called, calls `self`, passing `args` as the first set of
     /// arguments.
     ///
@@ -159,6 +276,268 @@ impl<'lua> Function<'lua> {
             Ok(Function(lua.pop_ref()))
         }
     }
+
+    /// Consumes the function, creating a new coroutine with it as the main function.
+    ///
+    /// Equivalent to [`Lua::create_thread`]. The returned [`Thread`] is resumed with
+    /// [`Thread::resume`], which converts the yielded (or returned) values to the requested type and
+    /// propagates errors raised inside the coroutine as `Err`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use mlua::{Function, Lua, Result};
+    /// # fn main() -> Result<()> {
+    /// # let lua = Lua::new();
+    /// let counter: Function = lua.load(r#"
+    ///     function(start)
+    ///         local n = coroutine.yield(start)
+    ///         return start + n
+    ///     end
+    /// "#).eval()?;
+    ///
+    /// let thread = counter.into_thread()?;
+    /// assert_eq!(thread.resume::<_, i64>(10)?, 10);
+    /// assert_eq!(thread.resume::<_, i64>(5)?, 15);
+    /// # Ok(())
+    /// # }
+    /// ```
+    ///
+    /// [`Lua::create_thread`]: struct.Lua.html#method.create_thread
+    /// [`Thread`]: struct.Thread.html
+    /// [`Thread::resume`]: struct.Thread.html#method.resume
+    pub fn into_thread(self) -> Result<Thread<'lua>> {
+        self.0.lua.create_thread(self)
+    }
+
+    /// Returns the first and last line of the function definition in its chunk.
+    ///
+    /// The main function of a chunk starts at line `0`. Returns `None` for functions which are not
+    /// defined in Lua, such as Rust callbacks.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use mlua::{Function, Lua, Result};
+    /// # fn main() -> Result<()> {
+    /// # let lua = Lua::new();
+    /// let f: Function = lua.load("\nfunction()\n    return 1\nend").set_name("=example")?.eval()?;
+    /// assert_eq!(f.line_defined(), Some((2, 4)));
+    /// assert_eq!(f.source().as_deref(), Some("=example"));
+    ///
+    /// let print: Function = lua.globals().get("print")?;
+    /// assert_eq!(print.line_defined(), None);
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn line_defined(&self) -> Option<(u32, u32)> {
+        let ar = self.source_info()?;
+        Some((ar.linedefined as u32, ar.lastlinedefined as u32))
+    }
+
+    /// Returns the source identifier of the chunk defining the function.
+    ///
+    /// This is the chunk name given to [`Chunk::set_name`], or the default name assigned by Lua.
+    /// Returns `None` for functions which are not defined in Lua, such as Rust callbacks.
+    ///
+    /// [`Chunk::set_name`]: struct.Chunk.html#method.set_name
+    pub fn source(&self) -> Option<StdString> {
+        let ar = self.source_info()?;
+        if ar.source.is_null() {
+            return None;
+        }
+        unsafe { Some(CStr::from_ptr(ar.source).to_string_lossy().into_owned()) }
+    }
+
+    /// Returns the name and value of the `n`-th upvalue of the function.
+    ///
+    /// Upvalues are numbered starting at `1`, in the order they are first referenced in the
+    /// function. Returns `None` if the function has no upvalue with this index. Upvalue names are
+    /// only available if the chunk was loaded with debug information; otherwise the name is a
+    /// placeholder chosen by Lua, such as `"(*no name)"`.
+    ///
+    /// The upvalues of C functions, which includes Rust callbacks, hold internal state and are not
+    /// accessible, so this always returns `None` for them.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use mlua::{Function, Lua, Result};
+    /// # fn main() -> Result<()> {
+    /// # let lua = Lua::new();
+    /// let counter: Function = lua.load(r#"
+    ///     local count = 0
+    ///     return function()
+    ///         count = count + 1
+    ///         return count
+    ///     end
+    /// "#).eval()?;
+    ///
+    /// counter.call::<_, ()>(())?;
+    /// assert_eq!(counter.get_upvalue::<i64>(1)?, Some(("count".to_string(), 1)));
+    ///
+    /// counter.set_upvalue(1, 41)?;
+    /// assert_eq!(counter.call::<_, i64>(())?, 42);
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn get_upvalue<V: FromLua<'lua>>(&self, n: usize) -> Result<Option<(StdString, V)>> {
+        let lua = self.0.lua;
+        let (name, value) = unsafe {
+            let _sg = StackGuard::new(lua.state);
+            assert_stack(lua.state, 2);
+
+            lua.push_ref(&self.0);
+            if ffi::lua_iscfunction(lua.state, -1) != 0 || n == 0 || n > c_int::MAX as usize {
+                return Ok(None);
+            }
+            let name = ffi::lua_getupvalue(lua.state, -1, n as c_int);
+            if name.is_null() {
+                return Ok(None);
+            }
+            let name = CStr::from_ptr(name).to_string_lossy().into_owned();
+            (name, lua.pop_value())
+        };
+        Ok(Some((name, V::from_lua(value, lua)?)))
+    }
+
+    /// Sets the value of the `n`-th upvalue of the function.
+    ///
+    /// Upvalues are shared between all closures that capture the same local variable, so the new
+    /// value is visible to all of them. Returns an error if the function has no upvalue with this
+    /// index, or if it is a C function (see [`get_upvalue`]).
+    ///
+    /// [`get_upvalue`]: #method.get_upvalue
+    pub fn set_upvalue<V: ToLua<'lua>>(&self, n: usize, value: V) -> Result<()> {
+        let lua = self.0.lua;
+        let value = value.to_lua(lua)?;
+        unsafe {
+            let _sg = StackGuard::new(lua.state);
+            assert_stack(lua.state, 2);
+
+            lua.push_ref(&self.0);
+            if ffi::lua_iscfunction(lua.state, -1) != 0 {
+                return Err(Error::RuntimeError(
+                    "cannot set upvalues of a C function".to_string(),
+                ));
+            }
+            lua.push_value(value)?;
+            if n == 0
+                || n > c_int::MAX as usize
+                || ffi::lua_setupvalue(lua.state, -2, n as c_int).is_null()
+            {
+                return Err(Error::RuntimeError(format!(
+                    "function has no upvalue with index {}",
+                    n
+                )));
+            }
+        }
+        Ok(())
+    }
+
+    /// Sets a breakpoint calling `callback` whenever this function is called.
+    ///
+    /// The callback receives the [`Debug`] information of the call and the arguments passed to the
+    /// function. For Lua functions these are the parameters and, on Lua 5.2 and 5.3, the variable
+    /// arguments. Returning an error from the callback aborts the call with this
+    /// error.
+    ///
+    /// The breakpoint is implemented with a [`HookMask::CALL`] hook, so it replaces any hook set
+    /// with [`Lua::set_hook`] and only one breakpoint can be active at a time. Only calls on the
+    /// main thread are detected. The breakpoint is removed when the returned [`Breakpoint`] is
+    /// dropped.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use mlua::{Function, Lua, Result};
+    /// # fn main() -> Result<()> {
+    /// # let lua = Lua::new();
+    /// lua.load("function greet(name) return 'hello ' .. name end").exec()?;
+    /// let greet: Function = lua.globals().get("greet")?;
+    ///
+    /// let breakpoint = greet.set_breakpoint(|lua, _, args| {
+    ///     let name: String = lua.unpack_multi(args)?;
+    ///     println!("greet called with {}", name);
+    ///     Ok(())
+    /// })?;
+    /// lua.load("greet('world')").exec()?;
+    /// drop(breakpoint);
+    /// # Ok(())
+    /// # }
+    /// ```
+    ///
+    /// [`Debug`]: struct.Debug.html
+    /// [`HookMask::CALL`]: struct.HookMask.html#associatedconstant.CALL
+    /// [`Lua::set_hook`]: struct.Lua.html#method.set_hook
+    /// [`Breakpoint`]: struct.Breakpoint.html
+    pub fn set_breakpoint<F>(&self, mut callback: F) -> Result<Breakpoint<'lua>>
+    where
+        F: 'static + Send + for<'cb> FnMut(&'cb Lua, Debug, MultiValue<'cb>) -> Result<()>,
+    {
+        let lua = self.0.lua;
+        let function = self.0.to_pointer() as usize;
+        lua.set_hook(HookMask::CALL, 0, move |lua, debug| {
+            if debug.function_pointer() as usize != function {
+                return Ok(());
+            }
+
+            let mut args = MultiValue::new();
+            unsafe {
+                // Hook callbacks run inside `callback_error`, which keeps a preallocated userdata
+                // at the bottom of the stack
+                let nargs = debug.push_arguments(1)?;
+                assert_stack(lua.state, 2);
+                for _ in 0..nargs {
+                    args.push_front(lua.pop_value());
+                }
+            }
+            callback(lua, debug, args)
+        })?;
+
+        Ok(Breakpoint {
+            lua,
+            hook: mlua_expect!(lua.hook_callback(), "breakpoint hook is not set"),
+        })
+    }
+
+    // Returns the `S` debug information of a Lua function, or `None` for C functions
+    fn source_info(&self) -> Option<ffi::lua_Debug> {
+        let lua = self.0.lua;
+        unsafe {
+            let _sg = StackGuard::new(lua.state);
+            assert_stack(lua.state, 1);
+
+            let mut ar: ffi::lua_Debug = mem::zeroed();
+            lua.push_ref(&self.0);
+            if ffi::lua_getinfo(lua.state, cstr!(">S"), &mut ar) == 0 || ar.linedefined < 0 {
+                return None;
+            }
+            Some(ar)
+        }
+    }
+}
+
+/// Handle to a breakpoint set with [`Function::set_breakpoint`].
+///
+/// The breakpoint is removed when the handle is dropped, unless the hook has been replaced in the
+/// meantime.
+///
+/// [`Function::set_breakpoint`]: struct.Function.html#method.set_breakpoint
+#[must_use = "the breakpoint is removed when the handle is dropped"]
+pub struct Breakpoint<'lua> {
+    lua: &'lua Lua,
+    hook: HookCallback,
+}
+
+impl<'lua> Drop for Breakpoint<'lua> {
+    fn drop(&mut self) {
+        if let Some(hook) = self.lua.hook_callback() {
+            if Arc::ptr_eq(&hook, &self.hook) {
+                self.lua.remove_hook();
+            }
+        }
+    }
 }
 
 impl<'lua> PartialEq for Function<'lua> {
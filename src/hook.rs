@@ -0,0 +1,416 @@
+use std::cmp::Reverse;
+use std::collections::HashMap;
+use std::ffi::CStr;
+use std::marker::PhantomData;
+use std::ops::{BitAnd, BitAndAssign, BitOr, BitOrAssign, BitXor, BitXorAssign};
+use std::os::raw::{c_char, c_int, c_void};
+use std::string::String as StdString;
+use std::time::{Duration, Instant};
+
+use crate::error::Result;
+use crate::ffi::{self, lua_Debug, lua_State};
+use crate::util::check_stack;
+
+/// Contains information about the currently executing Lua code.
+///
+/// The `Debug` structure is provided as a parameter to the hook function set with
+/// [`Lua::set_hook`]. You may call the methods on this structure to retrieve information about the
+/// Lua code executing at the time that the hook function was called. Further information can be
+/// found in the [Lua 5.3 documentation][lua_doc].
+///
+/// [lua_doc]: https://www.lua.org/manual/5.3/manual.html#lua_Debug
+/// [`Lua::set_hook`]: struct.Lua.html#method.set_hook
+pub struct Debug<'a> {
+    ar: *mut lua_Debug,
+    state: *mut lua_State,
+    _phantom: PhantomData<&'a ()>,
+}
+
+impl<'a> Debug<'a> {
+    pub(crate) unsafe fn new(state: *mut lua_State, ar: *mut lua_Debug) -> Self {
+        Debug {
+            ar,
+            state,
+            _phantom: PhantomData,
+        }
+    }
+
+    /// Returns the specific event that triggered the hook.
+    pub fn event(&self) -> DebugEvent {
+        unsafe {
+            match (*self.ar).event {
+                ffi::LUA_HOOKCALL => DebugEvent::Call,
+                ffi::LUA_HOOKRET => DebugEvent::Ret,
+                ffi::LUA_HOOKTAILCALL => DebugEvent::TailCall,
+                ffi::LUA_HOOKLINE => DebugEvent::Line,
+                ffi::LUA_HOOKCOUNT => DebugEvent::Count,
+                event => DebugEvent::Unknown(event),
+            }
+        }
+    }
+
+    /// Corresponds to the `n` what mask.
+    pub fn names(&self) -> DebugNames<'a> {
+        unsafe {
+            mlua_assert!(
+                ffi::lua_getinfo(self.state, cstr!("n"), self.ar) != 0,
+                "lua_getinfo failed with `n`"
+            );
+            DebugNames {
+                name: ptr_to_str((*self.ar).name),
+                name_what: ptr_to_str((*self.ar).namewhat),
+            }
+        }
+    }
+
+    /// Corresponds to the `S` what mask.
+    pub fn source(&self) -> DebugSource<'a> {
+        unsafe {
+            mlua_assert!(
+                ffi::lua_getinfo(self.state, cstr!("S"), self.ar) != 0,
+                "lua_getinfo failed with `S`"
+            );
+            DebugSource {
+                source: ptr_to_str((*self.ar).source),
+                short_src: ptr_to_str((*self.ar).short_src.as_ptr()),
+                line_defined: (*self.ar).linedefined,
+                last_line_defined: (*self.ar).lastlinedefined,
+                what: ptr_to_str((*self.ar).what),
+            }
+        }
+    }
+
+    /// Corresponds to the `l` what mask. Returns the current line.
+    pub fn curr_line(&self) -> i32 {
+        unsafe {
+            mlua_assert!(
+                ffi::lua_getinfo(self.state, cstr!("l"), self.ar) != 0,
+                "lua_getinfo failed with `l`"
+            );
+            (*self.ar).currentline
+        }
+    }
+
+    /// Corresponds to the `t` what mask. Returns true if the hook is in a function tail call, false
+    /// otherwise.
+    #[cfg(any(feature = "lua53", feature = "lua52"))]
+    pub fn is_tail_call(&self) -> bool {
+        unsafe {
+            mlua_assert!(
+                ffi::lua_getinfo(self.state, cstr!("t"), self.ar) != 0,
+                "lua_getinfo failed with `t`"
+            );
+            (*self.ar).istailcall != 0
+        }
+    }
+
+    // Corresponds to the `f` what mask, returns the address of the running function.
+    pub(crate) fn function_pointer(&self) -> *const c_void {
+        unsafe {
+            mlua_assert!(
+                ffi::lua_getinfo(self.state, cstr!("f"), self.ar) != 0,
+                "lua_getinfo failed with `f`"
+            );
+            let ptr = ffi::lua_topointer(self.state, -1);
+            ffi::lua_pop(self.state, 1);
+            ptr
+        }
+    }
+
+    // Pushes the arguments of the function being called onto the stack and returns their number.
+    // Must only be called from a call hook. `shift` is the number of values placed at the bottom of
+    // the stack frame while the hook runs.
+    pub(crate) unsafe fn push_arguments(&self, shift: c_int) -> Result<c_int> {
+        if self.source().what == Some(&b"C"[..]) {
+            let nargs = ffi::lua_gettop(self.state) - shift;
+            check_stack(self.state, nargs)?;
+            for i in 1..=nargs {
+                ffi::lua_pushvalue(self.state, i + shift);
+            }
+            return Ok(nargs);
+        }
+
+        // Only the parameters are active local variables when a Lua function is entered
+        check_stack(self.state, 1)?;
+        let mut nparams = 0;
+        loop {
+            let name = ffi::lua_getlocal(self.state, self.ar, nparams + 1);
+            if name.is_null() {
+                break;
+            }
+            ffi::lua_pop(self.state, 1);
+            if *name == b'(' as c_char {
+                break;
+            }
+            nparams += 1;
+        }
+
+        // On Lua 5.2+ stack indices of vararg functions start below the extra arguments, which are
+        // followed by a copy of the parameters
+        #[allow(unused_mut)]
+        let mut nvarargs = 0;
+        #[allow(unused_mut)]
+        let mut params_start = 0;
+        #[cfg(any(feature = "lua53", feature = "lua52"))]
+        {
+            while !ffi::lua_getlocal(self.state, self.ar, -(nvarargs + 1)).is_null() {
+                ffi::lua_pop(self.state, 1);
+                nvarargs += 1;
+            }
+            if self.stack().is_vararg {
+                params_start = nparams + nvarargs;
+            }
+        }
+
+        check_stack(self.state, nparams + nvarargs)?;
+        for i in 1..=nparams {
+            ffi::lua_pushvalue(self.state, params_start + i + shift);
+        }
+        for i in 1..=nvarargs {
+            ffi::lua_pushvalue(self.state, nparams + i + shift);
+        }
+        Ok(nparams + nvarargs)
+    }
+
+    /// Corresponds to the `u` what mask.
+    pub fn stack(&self) -> DebugStack {
+        unsafe {
+            mlua_assert!(
+                ffi::lua_getinfo(self.state, cstr!("u"), self.ar) != 0,
+                "lua_getinfo failed with `u`"
+            );
+            DebugStack {
+                num_ups: (*self.ar).nups as i32,
+                #[cfg(any(feature = "lua53", feature = "lua52"))]
+                num_params: (*self.ar).nparams as i32,
+                #[cfg(any(feature = "lua53", feature = "lua52"))]
+                is_vararg: (*self.ar).isvararg != 0,
+            }
+        }
+    }
+}
+
+/// The event that triggered a hook.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DebugEvent {
+    /// A function was called.
+    Call,
+    /// A function was called as a tail call.
+    ///
+    /// In Lua 5.1 and LuaJIT this event is reported as `TailRet` and signals the simulated
+    /// return from a function that did a tail call.
+    TailCall,
+    /// A function is about to return.
+    Ret,
+    /// The interpreter is about to start the execution of a new line of code.
+    Line,
+    /// The interpreter executed the number of instructions given in [`Lua::set_hook`].
+    ///
+    /// [`Lua::set_hook`]: struct.Lua.html#method.set_hook
+    Count,
+    /// An event code not recognized by mlua.
+    Unknown(c_int),
+}
+
+/// Information about the name of the function that triggered a hook.
+#[derive(Clone, Debug)]
+pub struct DebugNames<'a> {
+    pub name: Option<&'a [u8]>,
+    pub name_what: Option<&'a [u8]>,
+}
+
+/// Information about the source of the function that triggered a hook.
+#[derive(Clone, Debug)]
+pub struct DebugSource<'a> {
+    pub source: Option<&'a [u8]>,
+    pub short_src: Option<&'a [u8]>,
+    pub line_defined: i32,
+    pub last_line_defined: i32,
+    pub what: Option<&'a [u8]>,
+}
+
+/// Information about the upvalues and parameters of the function that triggered a hook.
+#[derive(Copy, Clone, Debug)]
+pub struct DebugStack {
+    pub num_ups: i32,
+    #[cfg(any(feature = "lua53", feature = "lua52"))]
+    pub num_params: i32,
+    #[cfg(any(feature = "lua53", feature = "lua52"))]
+    pub is_vararg: bool,
+}
+
+/// Call statistics of a function, collected by [`Lua::profile`].
+///
+/// [`Lua::profile`]: struct.Lua.html#method.profile
+#[derive(Clone, Debug)]
+pub struct FunctionProfile {
+    /// Name of the function at its first call, if known.
+    pub name: Option<StdString>,
+    /// Short source of the function, e.g. the chunk name or `[C]`.
+    pub source: Option<StdString>,
+    /// Line where the function definition starts.
+    pub line_defined: i32,
+    /// Number of calls.
+    pub calls: u64,
+    /// Total time spent in the function, including the functions it called.
+    pub total_time: Duration,
+}
+
+// Data collected by the profiling hook. Functions are keyed by their address.
+#[derive(Default)]
+pub(crate) struct ProfileData {
+    pub(crate) active: bool,
+    frames: Vec<(usize, Instant)>,
+    functions: HashMap<usize, FunctionProfile>,
+}
+
+impl ProfileData {
+    pub(crate) fn on_event(&mut self, debug: &Debug) {
+        let now = Instant::now();
+        match debug.event() {
+            DebugEvent::Call => self.enter(debug, now),
+            DebugEvent::TailCall => {
+                // The calling function is replaced and no return event will be fired for it
+                if let Some((ptr, _)) = self.frames.last().cloned() {
+                    self.leave(ptr, now);
+                }
+                self.enter(debug, now);
+            }
+            DebugEvent::Ret => {
+                let ptr = debug.function_pointer() as usize;
+                self.leave(ptr, now);
+            }
+            _ => {}
+        }
+    }
+
+    pub(crate) fn results(&self) -> Vec<FunctionProfile> {
+        let mut results = self.functions.values().cloned().collect::<Vec<_>>();
+        results.sort_by_key(|p| Reverse(p.total_time));
+        results
+    }
+
+    fn enter(&mut self, debug: &Debug, now: Instant) {
+        let ptr = debug.function_pointer() as usize;
+        let profile = self.functions.entry(ptr).or_insert_with(|| {
+            let source = debug.source();
+            FunctionProfile {
+                name: debug
+                    .names()
+                    .name
+                    .map(|s| StdString::from_utf8_lossy(s).into_owned()),
+                source: source
+                    .short_src
+                    .map(|s| StdString::from_utf8_lossy(s).into_owned()),
+                line_defined: source.line_defined,
+                calls: 0,
+                total_time: Duration::default(),
+            }
+        });
+        profile.calls += 1;
+        self.frames.push((ptr, now));
+    }
+
+    fn leave(&mut self, ptr: usize, now: Instant) {
+        // Frames above the returning function were unwound by an error
+        if let Some(pos) = self.frames.iter().rposition(|&(p, _)| p == ptr) {
+            let (_, start) = self.frames[pos];
+            self.frames.truncate(pos);
+            // Count recursive calls only once
+            if !self.frames.iter().any(|&(p, _)| p == ptr) {
+                if let Some(profile) = self.functions.get_mut(&ptr) {
+                    profile.total_time += now - start;
+                }
+            }
+        }
+    }
+}
+
+/// Flags describing the set of events that trigger a hook set with [`Lua::set_hook`].
+///
+/// [`Lua::set_hook`]: struct.Lua.html#method.set_hook
+#[derive(Copy, Clone, Debug, Eq, Ord, PartialEq, PartialOrd)]
+pub struct HookMask(u32);
+
+impl HookMask {
+    /// Called when the interpreter calls a function, just after Lua enters the new function.
+    pub const CALL: HookMask = HookMask(ffi::LUA_MASKCALL as u32);
+    /// Called when the interpreter returns from a function, just before Lua leaves the function.
+    pub const RET: HookMask = HookMask(ffi::LUA_MASKRET as u32);
+    /// Called when the interpreter is about to start the execution of a new line of code, or when
+    /// it jumps back in the code (even to the same line).
+    pub const LINE: HookMask = HookMask(ffi::LUA_MASKLINE as u32);
+    /// Called after the interpreter executes every `count` instructions.
+    pub const COUNT: HookMask = HookMask(ffi::LUA_MASKCOUNT as u32);
+
+    /// No events.
+    pub const NONE: HookMask = HookMask(0);
+    /// Every event: `CALL | RET | LINE | COUNT`.
+    pub const ALL: HookMask = HookMask(
+        (ffi::LUA_MASKCALL | ffi::LUA_MASKRET | ffi::LUA_MASKLINE | ffi::LUA_MASKCOUNT) as u32,
+    );
+
+    /// Returns `true` if every event in `mask` is also in `self`.
+    ///
+    /// Any mask contains [`NONE`].
+    ///
+    /// [`NONE`]: #associatedconstant.NONE
+    pub fn contains(self, mask: Self) -> bool {
+        self.0 & mask.0 == mask.0
+    }
+
+    pub(crate) fn bits(self) -> c_int {
+        self.0 as c_int
+    }
+
+    pub(crate) fn from_bits(bits: c_int) -> Self {
+        HookMask(bits as u32) & HookMask::ALL
+    }
+}
+
+impl BitAnd for HookMask {
+    type Output = Self;
+    fn bitand(self, rhs: Self) -> Self::Output {
+        HookMask(self.0 & rhs.0)
+    }
+}
+
+impl BitAndAssign for HookMask {
+    fn bitand_assign(&mut self, rhs: Self) {
+        *self = HookMask(self.0 & rhs.0)
+    }
+}
+
+impl BitOr for HookMask {
+    type Output = Self;
+    fn bitor(self, rhs: Self) -> Self::Output {
+        HookMask(self.0 | rhs.0)
+    }
+}
+
+impl BitOrAssign for HookMask {
+    fn bitor_assign(&mut self, rhs: Self) {
+        *self = HookMask(self.0 | rhs.0)
+    }
+}
+
+impl BitXor for HookMask {
+    type Output = Self;
+    fn bitxor(self, rhs: Self) -> Self::Output {
+        HookMask(self.0 ^ rhs.0)
+    }
+}
+
+impl BitXorAssign for HookMask {
+    fn bitxor_assign(&mut self, rhs: Self) {
+        *self = HookMask(self.0 ^ rhs.0)
+    }
+}
+
+unsafe fn ptr_to_str<'a>(input: *const c_char) -> Option<&'a [u8]> {
+    if input.is_null() {
+        None
+    } else {
+        Some(CStr::from_ptr(input).to_bytes())
+    }
+}
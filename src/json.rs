@@ -0,0 +1,170 @@
+use std::string::String as StdString;
+
+use serde_json::{Map, Number as JsonNumber, Value as JsonValue};
+
+use crate::error::{Error, Result};
+use crate::lua::Lua;
+use crate::table::Table;
+use crate::types::Integer;
+use crate::value::Value;
+
+impl Lua {
+    /// Converts a [`serde_json::Value`] to a Lua value.
+    ///
+    /// Objects and arrays are converted to tables (arrays become sequences starting at index 1),
+    /// `null` is converted to `nil`. Numbers that fit into an [`Integer`] are converted to
+    /// integers, other numbers to floats.
+    ///
+    /// `null` elements of an array are converted to [`Value::NULL`] instead, so that they don't
+    /// leave holes in the resulting sequence. `null` members of an object are dropped.
+    ///
+    /// Requires `feature = "json"`
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use mlua::{Lua, Result, Table};
+    /// # fn main() -> Result<()> {
+    /// # let lua = Lua::new();
+    /// let json = serde_json::json!({ "name": "mlua", "tags": ["lua", "rust"] });
+    ///
+    /// let table: Table = lua.unpack(lua.from_json_value(&json)?)?;
+    /// assert_eq!(table.get::<_, String>("name")?, "mlua");
+    /// assert_eq!(table.get::<_, Table>("tags")?.raw_len(), 2);
+    /// # Ok(())
+    /// # }
+    /// ```
+    ///
+    /// [`serde_json::Value`]: https://docs.rs/serde_json/1.0/serde_json/enum.Value.html
+    /// [`Integer`]: type.Integer.html
+    /// [`Value::NULL`]: enum.Value.html#associatedconstant.NULL
+    pub fn from_json_value<'lua>(&'lua self, json: &JsonValue) -> Result<Value<'lua>> {
+        Ok(match json {
+            JsonValue::Null => Value::Nil,
+            JsonValue::Bool(b) => Value::Boolean(*b),
+            JsonValue::Number(n) => match n.as_i64() {
+                Some(i) => Value::Integer(i as Integer),
+                None => Value::Number(n.as_f64().unwrap_or(0.0)),
+            },
+            JsonValue::String(s) => Value::String(self.create_string(s)?),
+            JsonValue::Array(array) => {
+                let table = self.create_table()?;
+                for (i, v) in array.iter().enumerate() {
+                    let v = match v {
+                        JsonValue::Null => Value::NULL,
+                        v => self.from_json_value(v)?,
+                    };
+                    table.raw_set(i as Integer + 1, v)?;
+                }
+                Value::Table(table)
+            }
+            JsonValue::Object(object) => {
+                let table = self.create_table()?;
+                for (k, v) in object {
+                    table.raw_set(k.as_str(), self.from_json_value(v)?)?;
+                }
+                Value::Table(table)
+            }
+        })
+    }
+
+    /// Converts a Lua value to a [`serde_json::Value`].
+    ///
+    /// Tables that are non-empty sequences are converted to arrays, other tables to objects. Object
+    /// keys must be strings or numbers (numbers are converted to strings). `nil` and
+    /// [`Value::NULL`] are converted to `null`.
+    ///
+    /// Returns a [`FromLuaConversionError`] for values that have no JSON representation, such as
+    /// functions, userdata, non UTF-8 strings, non-finite floats, and recursive tables. Sequences
+    /// with holes (`nil` values before the border) and tables with keys that convert to the same
+    /// object key, such as `1` and `"1"`, are rejected as well, instead of losing entries.
+    ///
+    /// Requires `feature = "json"`
+    ///
+    /// [`serde_json::Value`]: https://docs.rs/serde_json/1.0/serde_json/enum.Value.html
+    /// [`FromLuaConversionError`]: enum.Error.html#variant.FromLuaConversionError
+    /// [`Value::NULL`]: enum.Value.html#associatedconstant.NULL
+    pub fn to_json_value<'lua>(&'lua self, value: Value<'lua>) -> Result<JsonValue> {
+        to_json_value(value, &mut Vec::new())
+    }
+}
+
+fn to_json_value<'lua>(value: Value<'lua>, visited: &mut Vec<Table<'lua>>) -> Result<JsonValue> {
+    Ok(match value {
+        Value::Nil => JsonValue::Null,
+        ref value if value.is_null() => JsonValue::Null,
+        Value::Boolean(b) => JsonValue::Bool(b),
+        Value::Integer(i) => JsonValue::Number(JsonNumber::from(i)),
+        Value::Number(n) => match JsonNumber::from_f64(n) {
+            Some(n) => JsonValue::Number(n),
+            None => return Err(conversion_error("number", "non-finite number")),
+        },
+        Value::String(s) => JsonValue::String(s.to_str()?.to_owned()),
+        Value::Table(table) => {
+            if visited.contains(&table) {
+                return Err(conversion_error("table", "recursive table"));
+            }
+            visited.push(table.clone());
+
+            let len = table.raw_len();
+            let mut is_sequence = len > 0;
+            let mut entries = Vec::new();
+            for pair in table.clone().pairs::<Value, Value>() {
+                let (key, value) = pair?;
+                match key {
+                    Value::Integer(i) if i >= 1 && i <= len => {}
+                    _ => is_sequence = false,
+                }
+                entries.push((key, value));
+            }
+
+            let json = if is_sequence {
+                // Every key is in `1..=len`, so a missing key is a hole in the sequence
+                if entries.len() as Integer != len {
+                    return Err(conversion_error("table", "sequence with holes"));
+                }
+                let mut array = Vec::with_capacity(len as usize);
+                for i in 1..=len {
+                    array.push(to_json_value(table.raw_get(i)?, visited)?);
+                }
+                JsonValue::Array(array)
+            } else {
+                let mut object = Map::new();
+                for (key, value) in entries {
+                    let key = match key {
+                        Value::String(s) => s.to_str()?.to_owned(),
+                        Value::Integer(i) => i.to_string(),
+                        Value::Number(n) => n.to_string(),
+                        key => {
+                            return Err(Error::FromLuaConversionError {
+                                from: key.type_name(),
+                                to: "JSON object key",
+                                message: None,
+                            })
+                        }
+                    };
+                    if object.contains_key(&key) {
+                        return Err(conversion_error(
+                            "table",
+                            &format!("duplicate object key \"{}\"", key),
+                        ));
+                    }
+                    object.insert(key, to_json_value(value, visited)?);
+                }
+                JsonValue::Object(object)
+            };
+
+            visited.pop();
+            json
+        }
+        value => return Err(conversion_error(value.type_name(), "unsupported type")),
+    })
+}
+
+fn conversion_error(from: &'static str, message: &str) -> Error {
+    Error::FromLuaConversionError {
+        from,
+        to: "JSON value",
+        message: Some(StdString::from(message)),
+    }
+}
@@ -43,12 +43,20 @@
 #[macro_use]
 mod macros;
 
+mod buffer;
+mod codec;
+mod compile;
 mod conversion;
 mod error;
 mod ffi;
 mod function;
+mod hook;
+#[cfg(feature = "json")]
+mod json;
 mod lua;
 mod multi;
+mod rate_limiter;
+mod sandbox;
 mod scope;
 mod stdlib;
 mod string;
@@ -59,19 +67,34 @@ mod userdata;
 mod util;
 mod value;
 
-pub use crate::ffi::lua_State;
+pub use crate::ffi::{lua_CFunction, lua_State};
 
-pub use crate::error::{Error, ExternalError, ExternalResult, Result};
-pub use crate::function::Function;
-pub use crate::lua::{Chunk, Lua};
-pub use crate::multi::Variadic;
+pub use crate::buffer::Buffer;
+pub use crate::compile::CompileHandle;
+pub use crate::error::{Error, ErrorChain, ErrorKind, ExternalError, ExternalResult, Result};
+pub use crate::function::{Breakpoint, Function};
+pub use crate::hook::{
+    Debug, DebugEvent, DebugNames, DebugSource, DebugStack, FunctionProfile, HookMask,
+};
+pub use crate::lua::{Chunk, ChunkMode, Lua, StaticUserDataMethods, NAMESPACED_REGISTRY_PREFIX};
+pub use crate::multi::{AsTable, Variadic};
+pub use crate::rate_limiter::RateLimiter;
+pub use crate::sandbox::SandboxConfig;
 pub use crate::scope::Scope;
 pub use crate::stdlib::StdLib;
-pub use crate::string::String;
-pub use crate::table::{Table, TablePairs, TableSequence};
-pub use crate::thread::{Thread, ThreadStatus};
-pub use crate::types::{Integer, LightUserData, Number, RegistryKey};
-pub use crate::userdata::{AnyUserData, MetaMethod, UserData, UserDataMethods};
-pub use crate::value::{FromLua, FromLuaMulti, MultiValue, Nil, ToLua, ToLuaMulti, Value};
+pub use crate::string::{BorrowedStr, String};
+pub use crate::table::{Table, TableKeys, TablePairs, TableSequence, TableValues};
+pub use crate::thread::{Thread, ThreadIterator, ThreadStatus};
+pub use crate::types::{Integer, LightUserData, Number, RegistryKey, Version};
+pub use crate::userdata::{
+    AnyUserData, IndexPriority, MetaMethod, UserData, UserDataMetatable, UserDataMethods,
+    VariantName,
+};
+pub use crate::value::{
+    FromLua, FromLuaMulti, MultiValue, Nil, NumberFormat, NumberKind, ToLua, ToLuaMulti, Value,
+};
+
+#[cfg(feature = "macros")]
+pub use mlua_derive::{lua_module, methods, VariantName};
 
 pub mod prelude;
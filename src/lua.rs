@@ -1,32 +1,81 @@
+#[cfg(not(feature = "luajit"))]
+use std::alloc::{self, Layout};
 use std::any::TypeId;
 use std::cell::{RefCell, UnsafeCell};
 use std::collections::HashMap;
-use std::ffi::CString;
+use std::ffi::{CStr, CString};
+use std::hash::{BuildHasherDefault, Hasher};
 use std::marker::PhantomData;
 use std::os::raw::{c_char, c_int, c_void};
+use std::string::String as StdString;
 use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
 use std::{mem, ptr, str};
 
 use crate::error::{Error, Result};
 use crate::ffi;
 use crate::function::Function;
+use crate::hook::{Debug, DebugEvent, FunctionProfile, HookMask, ProfileData};
+use crate::sandbox::SandboxConfig;
 use crate::scope::Scope;
 use crate::stdlib::StdLib;
 use crate::string::String;
 use crate::table::Table;
 use crate::thread::Thread;
-use crate::types::{Callback, Integer, LightUserData, LuaRef, Number, RegistryKey};
-use crate::userdata::{AnyUserData, MetaMethod, UserData, UserDataMethods};
+use crate::types::{
+    Callback, HookCallback, IndexTableCallback, Integer, LightUserData, LuaRef,
+    MetatableProtectionCallback, Number, RegistryKey, ThreadBound, Version,
+};
+use crate::userdata::{
+    AnyUserData, IndexPriority, MetaMethod, UserData, UserDataMetatable, UserDataMethods,
+};
 #[cfg(any(feature = "lua51", feature = "luajit"))]
 use crate::util::set_main_state;
 use crate::util::{
-    assert_stack, callback_error, check_stack, get_main_state, get_userdata, get_wrapped_error,
-    init_error_registry, init_userdata_metatable, pop_error, protect_lua, protect_lua_closure,
-    push_string, push_userdata, push_wrapped_error, userdata_destructor, StackGuard,
+    assert_stack, callback_error, check_stack, enable_userdata_method_cache, get_main_state,
+    get_userdata, get_wrapped_error, init_error_registry, init_userdata_metatable,
+    name_bad_argument, pop_error, protect_lua, protect_lua_closure, push_string, push_userdata,
+    push_wrapped_error, to_string, userdata_destructor, StackGuard,
 };
 use crate::value::{FromLua, FromLuaMulti, MultiValue, Nil, ToLua, ToLuaMulti, Value};
 
+/// Prefix prepended to the names passed to [`Lua::set_namespaced_registry_value`] and friends.
+///
+/// Values set through these methods are stored in the Lua registry under this prefix, so user names
+/// never clash with the keys used by Lua itself or by C modules. mlua's own registry entries use
+/// light userdata keys and are unreachable by name. Lua code or C modules can still access the
+/// values by looking up the prefixed string key in the registry.
+///
+/// [`Lua::set_namespaced_registry_value`]: struct.Lua.html#method.set_namespaced_registry_value
+pub const NAMESPACED_REGISTRY_PREFIX: &str = "__mlua_named.";
+
 /// Top level Lua struct which holds the Lua state itself.
+///
+/// Every `Lua` instance owns a completely independent Lua state: globals, the registry, registered
+/// userdata types, hooks and memory limits are never shared between instances, and mlua keeps no
+/// process-global mutable state. `Lua` is `Send` but not `Sync`, so an application that needs Lua
+/// on several OS threads (for example a server with a worker pool) should create one `Lua` per
+/// thread, or move a `Lua` to the thread that uses it.
+///
+/// ```
+/// # use mlua::{Lua, Result};
+/// # fn main() -> Result<()> {
+/// let workers = (0..4)
+///     .map(|i| {
+///         std::thread::spawn(move || -> Result<i64> {
+///             let lua = Lua::new();
+///             lua.globals().set("id", i)?;
+///             lua.load("id * 10").eval()
+///         })
+///     })
+///     .collect::<Vec<_>>();
+///
+/// for (i, worker) in workers.into_iter().enumerate() {
+///     assert_eq!(worker.join().unwrap()?, i as i64 * 10);
+/// }
+/// # Ok(())
+/// # }
+/// ```
 pub struct Lua {
     pub(crate) state: *mut ffi::lua_State,
     main_state: *mut ffi::lua_State,
@@ -38,13 +87,64 @@ pub struct Lua {
 
 // Data associated with the lua_State.
 struct ExtraData {
-    registered_userdata: HashMap<TypeId, c_int>,
+    registered_userdata: HashMap<TypeId, c_int, BuildHasherDefault<TypeIdHasher>>,
     registry_unref_list: Arc<Mutex<Option<Vec<c_int>>>>,
 
     ref_thread: *mut ffi::lua_State,
     ref_stack_size: c_int,
     ref_stack_max: c_int,
     ref_free: Vec<c_int>,
+
+    hook_callback: Option<HookCallback>,
+    profile_data: Option<Arc<Mutex<ProfileData>>>,
+    // Functions compiled by `load_cached`, by chunk name
+    chunk_cache: HashMap<StdString, CachedChunk>,
+    // Strings returned by `intern_string`
+    string_cache: HashMap<StdString, RegistryKey>,
+    // Read-only view of the globals, used as the environment of chunks loaded after `sandbox`
+    // froze the globals
+    sandbox_env: Option<RegistryKey>,
+    // Allocator state, set only if the Lua state was created by mlua.
+    mem_info: *mut MemoryInfo,
+    // Number of Rust callbacks currently running and the limit set by `set_call_depth_limit`
+    call_depth: usize,
+    call_depth_limit: usize,
+}
+
+// `TypeId`s are already hashes, so there is no need to hash them again when looking up userdata
+// metatables, which happens every time a userdata is created.
+#[derive(Default)]
+struct TypeIdHasher(u64);
+
+impl Hasher for TypeIdHasher {
+    fn finish(&self) -> u64 {
+        self.0
+    }
+
+    fn write(&mut self, bytes: &[u8]) {
+        for &b in bytes {
+            self.0 = self.0.rotate_left(8) ^ u64::from(b);
+        }
+    }
+
+    fn write_u64(&mut self, i: u64) {
+        self.0 ^= i;
+    }
+
+    fn write_u128(&mut self, i: u128) {
+        self.0 ^= (i as u64) ^ ((i >> 64) as u64);
+    }
+}
+
+// A function compiled by `load_cached`, along with the source it was compiled from
+struct CachedChunk {
+    source: StdString,
+    function: RegistryKey,
+}
+
+struct MemoryInfo {
+    used_memory: isize,
+    memory_limit: isize,
 }
 
 unsafe impl Send for Lua {}
@@ -61,6 +161,9 @@ impl Drop for Lua {
                 );
                 *mlua_expect!(extra.registry_unref_list.lock(), "unref list poisoned") = None;
                 ffi::lua_close(self.state);
+                if !extra.mem_info.is_null() {
+                    drop(Box::from_raw(extra.mem_info));
+                }
             }
         }
     }
@@ -68,6 +171,13 @@ impl Drop for Lua {
 
 impl Lua {
     /// Creates a new Lua state and loads standard library without the `debug` library.
+    ///
+    /// Features that inspect the Lua stack, such as error tracebacks, hooks set with
+    /// [`set_hook`] and [`caller_source`], use the C API directly and do not require the `debug`
+    /// library to be loaded. Only Lua code using the `debug` table itself needs it.
+    ///
+    /// [`set_hook`]: #method.set_hook
+    /// [`caller_source`]: #method.caller_source
     pub fn new() -> Lua {
         Self::new_with(StdLib::ALL_NO_DEBUG)
     }
@@ -79,6 +189,15 @@ impl Lua {
     /// [`StdLib`]: struct.StdLib.html
     pub fn new_with(libs: StdLib) -> Lua {
         unsafe {
+            #[cfg(not(feature = "luajit"))]
+            let mem_info = Box::into_raw(Box::new(MemoryInfo {
+                used_memory: 0,
+                memory_limit: 0,
+            }));
+            #[cfg(not(feature = "luajit"))]
+            let state = ffi::lua_newstate(allocator, mem_info as *mut c_void);
+            // LuaJIT on 64 bit platforms does not support custom allocators
+            #[cfg(feature = "luajit")]
             let state = ffi::luaL_newstate();
 
             ffi::luaL_requiref(state, cstr!("_G"), ffi::luaopen_base, 1);
@@ -86,6 +205,10 @@ impl Lua {
 
             let mut lua = Lua::init_from_ptr(state);
             lua.ephemeral = false;
+            #[cfg(not(feature = "luajit"))]
+            {
+                lua.extra.borrow_mut().mem_info = mem_info;
+            }
 
             mlua_expect!(
                 protect_lua_closure(lua.main_state, 0, 0, |state| {
@@ -111,6 +234,225 @@ impl Lua {
         }
     }
 
+    /// Registers `opener` as the loader of the module `name` in `package.preload`.
+    ///
+    /// A subsequent `require(name)` from Lua calls `opener` with the module name and returns its
+    /// result (which is cached in `package.loaded` as for any other module).
+    ///
+    /// Requires the `package` standard library to be loaded.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use mlua::{Lua, Result};
+    /// # fn main() -> Result<()> {
+    /// let lua = Lua::new();
+    /// let opener = lua.create_function(|lua, _: String| {
+    ///     let module = lua.create_table()?;
+    ///     module.set("answer", 42)?;
+    ///     Ok(module)
+    /// })?;
+    /// lua.preload_module("foo", opener)?;
+    ///
+    /// assert_eq!(lua.load(r#"require("foo").answer"#).eval::<i32>()?, 42);
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn preload_module<'lua>(&'lua self, name: &str, opener: Function<'lua>) -> Result<()> {
+        let preload: Table = self.package()?.get("preload")?;
+        preload.set(name, opener)
+    }
+
+    /// Registers a raw C module opener (a `luaopen_*` function) as the loader of the module `name`
+    /// in `package.preload`.
+    ///
+    /// This allows existing C extensions linked into the program to be loaded with `require(name)`
+    /// alongside Rust modules. See [`preload_module`] for details.
+    ///
+    /// # Safety
+    ///
+    /// `opener` must be a valid Lua C function compiled against the same Lua version and ABI as
+    /// mlua is using. The function pointer is stored in the Lua state without any lifetime
+    /// tracking, so the code it points to (e.g. a dynamically loaded library) must stay loaded for
+    /// as long as this `Lua` instance is alive.
+    ///
+    /// [`preload_module`]: #method.preload_module
+    pub unsafe fn register_c_module(&self, name: &str, opener: ffi::lua_CFunction) -> Result<()> {
+        let opener = {
+            let _sg = StackGuard::new(self.state);
+            assert_stack(self.state, 1);
+            protect_lua_closure(self.state, 0, 1, |state| {
+                ffi::lua_pushcfunction(state, opener);
+            })?;
+            Function(self.pop_ref())
+        };
+        self.preload_module(name, opener)
+    }
+
+    /// Builds a module table and stores it in `package.loaded[name]`, so that `require(name)`
+    /// returns it.
+    ///
+    /// `build` receives a fresh empty table to fill with the functions and constants of the module.
+    /// Unlike [`preload_module`], the module is built immediately rather than on the first
+    /// `require`. The module table is also returned, replacing any module already loaded under
+    /// `name`.
+    ///
+    /// Requires the `package` standard library to be loaded.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use mlua::{Lua, Result};
+    /// # fn main() -> Result<()> {
+    /// let lua = Lua::new();
+    /// lua.register_module("mathx", |lua, module| {
+    ///     module.set("double", lua.create_function(|_, x: i64| Ok(x * 2))?)?;
+    ///     module.set("answer", 42)
+    /// })?;
+    ///
+    /// assert_eq!(lua.load(r#"require("mathx").double(21)"#).eval::<i64>()?, 42);
+    /// # Ok(())
+    /// # }
+    /// ```
+    ///
+    /// [`preload_module`]: #method.preload_module
+    pub fn register_module<'lua, F>(&'lua self, name: &str, build: F) -> Result<Table<'lua>>
+    where
+        F: FnOnce(&'lua Lua, &Table<'lua>) -> Result<()>,
+    {
+        let loaded: Table = self.package()?.get("loaded")?;
+        let module = self.create_table()?;
+        build(self, &module)?;
+        loaded.set(name, module.clone())?;
+        Ok(module)
+    }
+
+    /// Removes the global function `name` and releases the resources held by it.
+    ///
+    /// The global is set to `nil` and a full garbage collection is run, so that a Rust callback
+    /// which is no longer referenced is collected right away. This drops the closure together with
+    /// everything it captured, and registry values whose [`RegistryKey`] was dropped are expired.
+    /// A function that is still referenced elsewhere, for example stored in a table, keeps working
+    /// and is only released once the last reference is gone.
+    ///
+    /// Returns `true` if a function was removed, `false` if there is no such global, and an error if
+    /// the global is not a function.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use mlua::{Lua, Result};
+    /// # use std::sync::Arc;
+    /// # fn main() -> Result<()> {
+    /// # let lua = Lua::new();
+    /// let config = Arc::new("config".to_string());
+    /// let captured = config.clone();
+    /// lua.globals().set(
+    ///     "config",
+    ///     lua.create_function(move |_, ()| Ok(captured.to_string()))?,
+    /// )?;
+    /// assert_eq!(Arc::strong_count(&config), 2);
+    ///
+    /// assert!(lua.unregister_function("config")?);
+    /// assert_eq!(Arc::strong_count(&config), 1);
+    /// # Ok(())
+    /// # }
+    /// ```
+    ///
+    /// [`RegistryKey`]: struct.RegistryKey.html
+    pub fn unregister_function(&self, name: &str) -> Result<bool> {
+        self.remove_function(self.globals(), name)
+    }
+
+    /// Removes the function `name` from the module `module` loaded in `package.loaded`, and
+    /// releases the resources held by it.
+    ///
+    /// This works like [`unregister_function`] for functions of modules, such as those created with
+    /// [`register_module`]. Returns `false` if the module is not loaded or has no field `name`.
+    ///
+    /// Requires the `package` standard library to be loaded.
+    ///
+    /// [`unregister_function`]: #method.unregister_function
+    /// [`register_module`]: #method.register_module
+    pub fn unregister_module_function(&self, module: &str, name: &str) -> Result<bool> {
+        let loaded: Table = self.package()?.get("loaded")?;
+        match loaded.get::<_, Option<Table>>(module)? {
+            Some(module) => self.remove_function(module, name),
+            None => Ok(false),
+        }
+    }
+
+    fn remove_function<'lua>(&'lua self, table: Table<'lua>, name: &str) -> Result<bool> {
+        if table.get::<_, Option<Function>>(name)?.is_none() {
+            return Ok(false);
+        }
+        table.set(name, Nil)?;
+        drop(table);
+
+        self.gc_collect()?;
+        self.expire_registry_values();
+        Ok(true)
+    }
+
+    /// Adds a Rust module searcher in front of the existing ones used by `require`.
+    ///
+    /// The searcher is called with the module name. Returning `Ok(Some(loader))` supplies the
+    /// function that loads the module, `require` then calls it and caches the result in
+    /// `package.loaded` like for any other module. Returning `Ok(None)` makes `require` fall
+    /// through to the next searcher.
+    ///
+    /// This allows, for example, loading modules from memory instead of the filesystem.
+    ///
+    /// Module names that are not valid UTF-8 are not passed to the searcher, `require` falls
+    /// through to the next searcher for them.
+    ///
+    /// Requires the `package` standard library to be loaded.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use mlua::{Lua, Result};
+    /// # fn main() -> Result<()> {
+    /// let lua = Lua::new();
+    /// lua.set_module_searcher(|lua, name| {
+    ///     if name == "virtual" {
+    ///         return Ok(Some(lua.load("return { answer = 42 }").into_function()?));
+    ///     }
+    ///     Ok(None)
+    /// })?;
+    ///
+    /// assert_eq!(lua.load(r#"require("virtual").answer"#).eval::<i32>()?, 42);
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn set_module_searcher<F>(&self, searcher: F) -> Result<()>
+    where
+        F: 'static + Send + for<'lua> Fn(&'lua Lua, &str) -> Result<Option<Function<'lua>>>,
+    {
+        let searcher = self.create_function(move |lua, name: String| {
+            let loader = match name.to_str() {
+                Ok(name) => searcher(lua, name)?,
+                Err(_) => None,
+            };
+            match loader {
+                Some(loader) => Ok(Value::Function(loader)),
+                None => {
+                    let message = format!(
+                        "\n\tno module '{}' in Rust searcher",
+                        StdString::from_utf8_lossy(name.as_bytes())
+                    );
+                    Ok(Value::String(lua.create_string(&message)?))
+                }
+            }
+        })?;
+
+        #[cfg(any(feature = "lua53", feature = "lua52"))]
+        let searchers: Table = self.package()?.get("searchers")?;
+        #[cfg(any(feature = "lua51", feature = "luajit"))]
+        let searchers: Table = self.package()?.get("loaders")?;
+        searchers.raw_insert(1, searcher)
+    }
+
     /// Constructs a new Lua instance from the existing state.
     pub unsafe fn init_from_ptr(state: *mut ffi::lua_State) -> Lua {
         #[cfg(any(feature = "lua53", feature = "lua52"))]
@@ -176,15 +518,46 @@ impl Lua {
         // Create ExtraData
 
         let extra = Arc::new(RefCell::new(ExtraData {
-            registered_userdata: HashMap::new(),
+            registered_userdata: HashMap::default(),
             registry_unref_list: Arc::new(Mutex::new(Some(Vec::new()))),
             ref_thread,
             // We need 1 extra stack space to move values in and out of the ref stack.
             ref_stack_size: ffi::LUA_MINSTACK - 1,
             ref_stack_max: 0,
             ref_free: Vec::new(),
+            hook_callback: None,
+            profile_data: None,
+            chunk_cache: HashMap::new(),
+            string_cache: HashMap::new(),
+            sandbox_env: None,
+            mem_info: ptr::null_mut(),
+            call_depth: 0,
+            call_depth_limit: 0,
         }));
 
+        // Place ExtraData in the registry, so it can be reached from callbacks that do not have it
+        // as an upvalue (hooks and error handling).
+
+        mlua_expect!(
+            (|| -> Result<()> {
+                ffi::lua_pushlightuserdata(
+                    main_state,
+                    &EXTRA_REGISTRY_KEY as *const u8 as *mut c_void,
+                );
+                push_userdata::<Arc<RefCell<ExtraData>>>(main_state, extra.clone())?;
+                ffi::lua_pushlightuserdata(
+                    main_state,
+                    &FUNCTION_EXTRA_METATABLE_REGISTRY_KEY as *const u8 as *mut c_void,
+                );
+                ffi::lua_rawget(main_state, ffi::LUA_REGISTRYINDEX);
+                ffi::lua_setmetatable(main_state, -2);
+                protect_lua_closure(main_state, 2, 0, |state| {
+                    ffi::lua_rawset(state, ffi::LUA_REGISTRYINDEX);
+                })
+            })(),
+            "Error during Lua construction"
+        );
+
         mlua_debug_assert!(
             ffi::lua_gettop(main_state) == main_state_top,
             "stack leak during creation"
@@ -212,6 +585,114 @@ impl Lua {
         unsafe { self.push_value(cb.call(())?).map(|_| 1) }
     }
 
+    /// Returns true if both `Lua` instances refer to the same Lua state.
+    ///
+    /// The `&Lua` passed to callbacks shares the state of the `Lua` that created the callback, even
+    /// when called from a coroutine. Values such as tables can be freely passed between instances
+    /// for which this returns true, while passing them to any other instance fails with
+    /// [`Error::MismatchedLuaState`].
+    ///
+    /// [`Error::MismatchedLuaState`]: enum.Error.html#variant.MismatchedLuaState
+    pub fn ptr_eq(&self, other: &Lua) -> bool {
+        self.main_state == other.main_state
+    }
+
+    /// Returns the amount of memory (in bytes) currently used inside this Lua state.
+    pub fn used_memory(&self) -> usize {
+        unsafe {
+            let mem_info = self.extra.borrow().mem_info;
+            if mem_info.is_null() {
+                // Get data from the Lua GC
+                let used_kbytes = ffi::lua_gc(self.main_state, ffi::LUA_GCCOUNT, 0);
+                let used_kbytes_rem = ffi::lua_gc(self.main_state, ffi::LUA_GCCOUNTB, 0);
+                return (used_kbytes as usize) * 1024 + (used_kbytes_rem as usize);
+            }
+            (*mem_info).used_memory as usize
+        }
+    }
+
+    /// Sets a memory limit (in bytes) on this Lua state.
+    ///
+    /// Once the limit is reached, any further allocation fails and Lua raises a
+    /// [`MemoryError`]. A limit of `0` removes the restriction. Returns the previous limit.
+    ///
+    /// Returns [`MemoryLimitNotAvailable`] if the Lua state was not created by mlua or when
+    /// using LuaJIT.
+    ///
+    /// [`MemoryError`]: enum.Error.html#variant.MemoryError
+    /// [`MemoryLimitNotAvailable`]: enum.Error.html#variant.MemoryLimitNotAvailable
+    pub fn set_memory_limit(&self, memory_limit: usize) -> Result<usize> {
+        unsafe {
+            let mem_info = self.extra.borrow().mem_info;
+            if mem_info.is_null() {
+                return Err(Error::MemoryLimitNotAvailable);
+            }
+            let prev_limit = (*mem_info).memory_limit as usize;
+            (*mem_info).memory_limit = memory_limit as isize;
+            Ok(prev_limit)
+        }
+    }
+
+    /// Sets the maximum number of nested Rust callback calls.
+    ///
+    /// Every Rust callback called from Lua adds native frames to the C stack, so deeply recursive
+    /// code going through callbacks, such as an `__index` metamethod which indexes its own table,
+    /// can exhaust it regardless of the size of the Lua stack. Once `limit` callbacks are running
+    /// at the same time, calling another one fails with [`RecursionLimitReached`] instead.
+    ///
+    /// The depth is shared by all threads of the Lua state. A limit of `0` (the default) removes
+    /// the restriction. Returns the previous limit.
+    ///
+    /// [`RecursionLimitReached`]: enum.Error.html#variant.RecursionLimitReached
+    pub fn set_call_depth_limit(&self, limit: usize) -> usize {
+        let mut extra = self.extra.borrow_mut();
+        mem::replace(&mut extra.call_depth_limit, limit)
+    }
+
+    /// Returns the number of values currently on the Lua stack of the running thread.
+    ///
+    /// Inside a callback this includes the arguments of the callback. This is mostly useful for
+    /// diagnosing stack leaks in code that manipulates many values at once.
+    pub fn stack_top(&self) -> i32 {
+        unsafe { ffi::lua_gettop(self.state) as i32 }
+    }
+
+    /// Ensures that the Lua stack of the running thread has space for at least `n` extra values.
+    ///
+    /// Returns [`StackError`] if the stack can not grow that large, or if `n` is negative.
+    ///
+    /// [`StackError`]: enum.Error.html#variant.StackError
+    pub fn check_stack(&self, n: i32) -> Result<()> {
+        if n < 0 {
+            return Err(Error::StackError);
+        }
+        unsafe { check_stack(self.state, n as c_int) }
+    }
+
+    /// Returns the version of the Lua runtime this state is running on.
+    ///
+    /// The runtime is selected with a cargo feature at compile time, this allows code which supports
+    /// several runtimes to adapt to their semantic differences, such as integer support, without
+    /// repeating the feature checks.
+    pub fn version(&self) -> Version {
+        #[cfg(feature = "lua53")]
+        return Version::Lua53;
+        #[cfg(feature = "lua52")]
+        return Version::Lua52;
+        #[cfg(feature = "lua51")]
+        return Version::Lua51;
+        #[cfg(feature = "luajit")]
+        return Version::LuaJIT;
+    }
+
+    /// Returns the release string of the Lua runtime, including the patch version.
+    ///
+    /// This is the `LUA_RELEASE` constant of the Lua headers, e.g. `"Lua 5.3.6"`. For LuaJIT it
+    /// is the release of the implemented Lua version.
+    pub fn version_release(&self) -> &'static str {
+        ffi::LUA_RELEASE
+    }
+
     /// Returns true if the garbage collector is currently running automatically.
     #[cfg(any(feature = "lua53", feature = "lua52"))]
     pub fn gc_is_running(&self) -> bool {
@@ -236,6 +717,19 @@ impl Lua {
     ///
     /// It may be necessary to call this function twice to collect all currently unreachable
     /// objects.  Once to finish the current gc cycle, and once to start and finish the next cycle.
+    ///
+    /// # Reentrancy
+    ///
+    /// This and the other `gc_*` methods may be called from callbacks, including while a userdata
+    /// is borrowed by a method. Borrowed userdata are always reachable, so they are never finalized
+    /// during the collection. Finalizers (`__gc` metamethods) of other values may run and call
+    /// back into Rust; if they access a userdata which is already borrowed, they get a
+    /// [`UserDataBorrowError`] or [`UserDataBorrowMutError`] instead of aliasing the borrow. With
+    /// Lua 5.2 and 5.3, an error raised by a finalizer is returned as a [`GarbageCollectorError`].
+    ///
+    /// [`UserDataBorrowError`]: enum.Error.html#variant.UserDataBorrowError
+    /// [`UserDataBorrowMutError`]: enum.Error.html#variant.UserDataBorrowMutError
+    /// [`GarbageCollectorError`]: enum.Error.html#variant.GarbageCollectorError
     pub fn gc_collect(&self) -> Result<()> {
         unsafe {
             protect_lua_closure(self.main_state, 0, 0, |state| {
@@ -246,7 +740,10 @@ impl Lua {
 
     /// Steps the garbage collector one indivisible step.
     ///
-    /// Returns true if this has finished a collection cycle.
+    /// Returns true if this has finished a collection cycle. Refer to [`gc_collect`] for the
+    /// reentrancy guarantees.
+    ///
+    /// [`gc_collect`]: #method.gc_collect
     pub fn gc_step(&self) -> Result<bool> {
         self.gc_step_kbytes(0)
     }
@@ -283,96 +780,885 @@ impl Lua {
         unsafe { ffi::lua_gc(self.main_state, ffi::LUA_GCSETSTEPMUL, step_multiplier) }
     }
 
-    /// Returns Lua source code as a `Chunk` builder type.
+    /// Sets a 'hook' function that will be called as Lua code executes.
     ///
-    /// In order to actually compile or run the resulting code, you must call [`Chunk::exec`] or
-    /// similar on the returned builder.  Code is not even parsed until one of these methods is
-    /// called.
+    /// The `mask` selects which events trigger the hook and may combine several [`HookMask`]
+    /// flags, e.g. `HookMask::CALL | HookMask::RET | HookMask::LINE`. When `mask` contains
+    /// [`HookMask::COUNT`], the hook is also called after the interpreter executes every `count`
+    /// instructions; otherwise `count` is ignored.
     ///
-    /// [`Chunk::exec`]: struct.Chunk.html#method.exec
-    pub fn load<'lua, 'a, S>(&'lua self, source: &'a S) -> Chunk<'lua, 'a>
+    /// The callback receives a [`Debug`] record which can be used to find out which event
+    /// triggered the hook (see [`Debug::event`]) and to inspect the running function. Returning an
+    /// error from the callback raises it as a Lua error at the point where the hook was triggered.
+    ///
+    /// The hook is installed on the main Lua thread, coroutines created afterwards inherit it.
+    /// Setting a new hook replaces the previous one.
+    ///
+    /// # Reentrancy
+    ///
+    /// Lua disables hooks on a thread while a hook is running on it, so the callback may safely
+    /// call into Lua (load chunks, call functions, etc.) without triggering itself. Calling
+    /// `set_hook` or [`remove_hook`] from within the callback is allowed and takes effect for
+    /// subsequent events. If the callback resumes a coroutine that triggers the hook again, the
+    /// nested call fails with [`Error::RecursiveMutCallback`].
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use mlua::{DebugEvent, HookMask, Lua, Result};
+    /// # fn main() -> Result<()> {
+    /// let lua = Lua::new();
+    /// lua.set_hook(HookMask::CALL | HookMask::LINE, 0, |_lua, debug| {
+    ///     match debug.event() {
+    ///         DebugEvent::Call => println!("call"),
+    ///         DebugEvent::Line => println!("line {}", debug.curr_line()),
+    ///         _ => {}
+    ///     }
+    ///     Ok(())
+    /// })?;
+    ///
+    /// lua.load(
+    ///     r#"
+    ///         local x = 2 + 3
+    ///         local y = x * 63
+    ///         local z = string.len(x..", "..y)
+    ///     "#,
+    /// )
+    /// .exec()?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    ///
+    /// [`HookMask`]: struct.HookMask.html
+    /// [`HookMask::COUNT`]: struct.HookMask.html#associatedconstant.COUNT
+    /// [`Debug`]: struct.Debug.html
+    /// [`Debug::event`]: struct.Debug.html#method.event
+    /// [`remove_hook`]: #method.remove_hook
+    /// [`Error::RecursiveMutCallback`]: enum.Error.html#variant.RecursiveMutCallback
+    pub fn set_hook<F>(&self, mask: HookMask, count: usize, callback: F) -> Result<()>
     where
-        S: ?Sized + AsRef<[u8]>,
+        F: 'static + Send + FnMut(&Lua, Debug) -> Result<()>,
     {
-        Chunk {
-            lua: self,
-            source: source.as_ref(),
-            name: None,
-            env: None,
+        extern "C" fn hook_proc(state: *mut ffi::lua_State, ar: *mut ffi::lua_Debug) {
+            unsafe {
+                callback_error(state, |_| {
+                    check_stack(state, 2)?;
+
+                    ffi::lua_pushlightuserdata(
+                        state,
+                        &EXTRA_REGISTRY_KEY as *const u8 as *mut c_void,
+                    );
+                    ffi::lua_rawget(state, ffi::LUA_REGISTRYINDEX);
+                    let extra = (*get_userdata::<Arc<RefCell<ExtraData>>>(state, -1)).clone();
+                    ffi::lua_pop(state, 1);
+
+                    let lua = Lua {
+                        state,
+                        main_state: get_main_state(state),
+                        extra,
+                        ephemeral: true,
+                        _no_ref_unwind_safe: PhantomData,
+                    };
+
+                    let callback = match lua.extra.borrow().hook_callback.clone() {
+                        Some(callback) => callback,
+                        None => return Ok(()),
+                    };
+                    let mut callback = callback
+                        .try_borrow_mut()
+                        .map_err(|_| Error::RecursiveMutCallback)?;
+                    (*callback)(&lua, Debug::new(state, ar))
+                })
+            }
         }
-    }
 
-    fn load_chunk<'lua>(
-        &'lua self,
-        source: &[u8],
-        name: Option<&CString>,
-        env: Option<Value<'lua>>,
-    ) -> Result<Function<'lua>> {
         unsafe {
-            let _sg = StackGuard::new(self.state);
-            assert_stack(self.state, 1);
-
-            match if let Some(name) = name {
-                ffi::luaL_loadbufferx(
-                    self.state,
-                    source.as_ptr() as *const c_char,
-                    source.len(),
-                    name.as_ptr() as *const c_char,
-                    cstr!("t"),
-                )
-            } else {
-                ffi::luaL_loadbufferx(
-                    self.state,
-                    source.as_ptr() as *const c_char,
-                    source.len(),
-                    ptr::null(),
-                    cstr!("t"),
-                )
-            } {
-                ffi::LUA_OK => {
-                    if let Some(env) = env {
-                        self.push_value(env)?;
-                        #[cfg(any(feature = "lua53", feature = "lua52"))]
-                        ffi::lua_setupvalue(self.state, -2, 1);
-                        #[cfg(any(feature = "lua51", feature = "luajit"))]
-                        ffi::lua_setfenv(self.state, -2);
-                    }
-                    Ok(Function(self.pop_ref()))
-                }
-                err => Err(pop_error(self.state, err)),
-            }
+            self.extra.borrow_mut().hook_callback = Some(Arc::new(RefCell::new(callback)));
+            ffi::lua_sethook(self.main_state, hook_proc, mask.bits(), count as c_int);
         }
+        Ok(())
     }
 
-    /// Create and return an interned Lua string.  Lua strings can be arbitrary [u8] data including
-    /// embedded nulls, so in addition to `&str` and `&String`, you can also pass plain `&[u8]`
-    /// here.
-    pub fn create_string<S>(&self, s: &S) -> Result<String>
-    where
-        S: ?Sized + AsRef<[u8]>,
-    {
+    /// Removes any hook previously set by [`set_hook`].
+    ///
+    /// [`set_hook`]: #method.set_hook
+    pub fn remove_hook(&self) {
         unsafe {
-            let _sg = StackGuard::new(self.state);
-            assert_stack(self.state, 4);
-            push_string(self.state, s)?;
-            Ok(String(self.pop_ref()))
+            let hook = ffi::lua_gethook(self.main_state);
+            if let Some(hook) = hook {
+                ffi::lua_sethook(self.main_state, hook, 0, 0);
+            }
         }
+        self.extra.borrow_mut().hook_callback = None;
     }
 
-    /// Creates and returns a new table.
-    pub fn create_table(&self) -> Result<Table> {
-        unsafe {
-            let _sg = StackGuard::new(self.state);
-            assert_stack(self.state, 3);
-            unsafe extern "C" fn new_table(state: *mut ffi::lua_State) -> c_int {
-                ffi::lua_newtable(state);
-                1
+    // Returns the callback of the hook set with `set_hook`, if any.
+    pub(crate) fn hook_callback(&self) -> Option<HookCallback> {
+        self.extra.borrow().hook_callback.clone()
+    }
+
+    /// Starts or stops profiling of Lua function calls.
+    ///
+    /// When enabled, a [`HookMask::CALL`] and [`HookMask::RET`] hook counts calls and measures the
+    /// time spent in every function, both Lua and Rust/C functions. Enabling the profiler discards
+    /// previously collected data and replaces any hook set with [`set_hook`], disabling it removes
+    /// the hook, while keeping the collected data available through [`profile_results`].
+    ///
+    /// The hook runs on every function call and return, which can slow down call-heavy scripts
+    /// considerably. There is no overhead once profiling is disabled. Only the main thread is
+    /// profiled.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use mlua::{Lua, Result};
+    /// # fn main() -> Result<()> {
+    /// # let lua = Lua::new();
+    /// lua.profile(true)?;
+    /// lua.load(r#"
+    ///     local function square(x) return x * x end
+    ///     for i = 1, 10 do square(i) end
+    /// "#).exec()?;
+    /// lua.profile(false)?;
+    ///
+    /// let results = lua.profile_results();
+    /// let square = results.iter().find(|p| p.name.as_deref() == Some("square")).unwrap();
+    /// assert_eq!(square.calls, 10);
+    /// # Ok(())
+    /// # }
+    /// ```
+    ///
+    /// [`HookMask::CALL`]: struct.HookMask.html#associatedconstant.CALL
+    /// [`HookMask::RET`]: struct.HookMask.html#associatedconstant.RET
+    /// [`set_hook`]: #method.set_hook
+    /// [`profile_results`]: #method.profile_results
+    pub fn profile(&self, enable: bool) -> Result<()> {
+        if !enable {
+            let profile_data = self.extra.borrow().profile_data.clone();
+            if let Some(profile_data) = profile_data {
+                let mut profile_data = mlua_expect!(profile_data.lock(), "profile data poisoned");
+                if profile_data.active {
+                    profile_data.active = false;
+                    self.remove_hook();
+                }
+            }
+            return Ok(());
+        }
+
+        let profile_data = Arc::new(Mutex::new(ProfileData::default()));
+        let hook_data = profile_data.clone();
+        self.set_hook(HookMask::CALL | HookMask::RET, 0, move |_, debug| {
+            mlua_expect!(hook_data.lock(), "profile data poisoned").on_event(&debug);
+            Ok(())
+        })?;
+        mlua_expect!(profile_data.lock(), "profile data poisoned").active = true;
+        self.extra.borrow_mut().profile_data = Some(profile_data);
+        Ok(())
+    }
+
+    /// Returns the statistics collected by [`profile`], sorted by total time in descending order.
+    ///
+    /// [`profile`]: #method.profile
+    pub fn profile_results(&self) -> Vec<FunctionProfile> {
+        match self.extra.borrow().profile_data {
+            Some(ref profile_data) => {
+                mlua_expect!(profile_data.lock(), "profile data poisoned").results()
+            }
+            None => Vec::new(),
+        }
+    }
+
+    /// Executes a chunk, aborting it with [`Error::Timeout`] if it runs longer than `timeout`.
+    ///
+    /// The deadline is checked with a [`HookMask::COUNT`] hook every 1000 VM instructions, so a
+    /// script is interrupted shortly after the deadline passes, unless it is blocked inside a
+    /// single long-running Rust or C function. A hook set with [`set_hook`] (including the
+    /// instruction limit of a [`sandbox`]) keeps running while the chunk runs: the timeout hook
+    /// forwards its events to the previous callback, and if that callback counts instructions the
+    /// deadline is checked at its interval instead.
+    ///
+    /// With LuaJIT the JIT compiler is flushed and switched off while the chunk runs, since
+    /// compiled traces do not call hooks. It is switched on again afterwards, unless a hook was set
+    /// before the call.
+    ///
+    /// Note that Lua code can catch the timeout error with `pcall`, although the error is raised
+    /// again on the next check, and coroutines created before this call are not covered by the
+    /// deadline.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use mlua::{Error, Lua, Result};
+    /// # use std::time::Duration;
+    /// # fn main() -> Result<()> {
+    /// let lua = Lua::new();
+    /// let chunk = lua.load("while true do end");
+    /// match lua.exec_with_timeout(chunk, Duration::from_millis(10)) {
+    ///     Err(Error::Timeout) => {}
+    ///     r => panic!("expected a timeout, got {:?}", r),
+    /// }
+    /// # Ok(())
+    /// # }
+    /// ```
+    ///
+    /// [`Error::Timeout`]: enum.Error.html#variant.Timeout
+    /// [`HookMask::COUNT`]: struct.HookMask.html#associatedconstant.COUNT
+    /// [`set_hook`]: #method.set_hook
+    /// [`sandbox`]: #method.sandbox
+    pub fn exec_with_timeout<'lua, 'a>(
+        &'lua self,
+        chunk: Chunk<'lua, 'a>,
+        timeout: Duration,
+    ) -> Result<()> {
+        const CHECK_INTERVAL: usize = 1000;
+
+        let deadline = match Instant::now().checked_add(timeout) {
+            Some(deadline) => deadline,
+            None => return chunk.exec(),
+        };
+
+        let prev_callback = self.extra.borrow_mut().hook_callback.take();
+        let (prev_hook, prev_mask, prev_count) = unsafe {
+            (
+                ffi::lua_gethook(self.main_state),
+                ffi::lua_gethookmask(self.main_state),
+                ffi::lua_gethookcount(self.main_state),
+            )
+        };
+
+        // Keep the previous hook working by forwarding the events it asked for.
+        //
+        // Like every hook callback, the previous one is only reachable through this `Lua`, which
+        // is `Send` but not `Sync`, so it is never called from two threads at once.
+        struct ChainedHook(HookCallback, HookMask);
+        unsafe impl Send for ChainedHook {}
+
+        let chained = match prev_callback {
+            Some(ref callback) if prev_mask != 0 => Some(ChainedHook(
+                callback.clone(),
+                HookMask::from_bits(prev_mask),
+            )),
+            _ => None,
+        };
+        let (mask, count) = match chained {
+            Some(ChainedHook(_, mask)) if mask.contains(HookMask::COUNT) => {
+                (mask, prev_count as usize)
+            }
+            Some(ChainedHook(_, mask)) => (mask | HookMask::COUNT, CHECK_INTERVAL),
+            None => (HookMask::COUNT, CHECK_INTERVAL),
+        };
+
+        // Traces compiled by the JIT do not call hooks
+        #[cfg(feature = "luajit")]
+        unsafe {
+            ffi::luaJIT_setmode(
+                self.main_state,
+                0,
+                ffi::LUAJIT_MODE_ENGINE | ffi::LUAJIT_MODE_FLUSH,
+            );
+            ffi::luaJIT_setmode(
+                self.main_state,
+                0,
+                ffi::LUAJIT_MODE_ENGINE | ffi::LUAJIT_MODE_OFF,
+            );
+        }
+
+        let result = self
+            .set_hook(mask, count, move |lua, debug| {
+                let is_count = debug.event() == DebugEvent::Count;
+                if let Some(ChainedHook(ref callback, mask)) = chained {
+                    if !is_count || mask.contains(HookMask::COUNT) {
+                        let mut callback = callback
+                            .try_borrow_mut()
+                            .map_err(|_| Error::RecursiveMutCallback)?;
+                        (*callback)(lua, debug)?;
+                    }
+                }
+                if is_count && Instant::now() >= deadline {
+                    Err(Error::Timeout)
+                } else {
+                    Ok(())
+                }
+            })
+            .and_then(|_| chunk.exec());
+
+        unsafe {
+            match prev_hook {
+                Some(hook) => ffi::lua_sethook(self.main_state, hook, prev_mask, prev_count),
+                None => self.remove_hook(),
+            }
+        }
+        self.extra.borrow_mut().hook_callback = prev_callback;
+
+        // A previous hook needs the JIT compiler to stay off as well
+        #[cfg(feature = "luajit")]
+        unsafe {
+            if prev_mask == 0 {
+                ffi::luaJIT_setmode(
+                    self.main_state,
+                    0,
+                    ffi::LUAJIT_MODE_ENGINE | ffi::LUAJIT_MODE_ON,
+                );
+            }
+        }
+
+        if let Err(Error::CallbackError { ref cause, .. }) = result {
+            if let Error::Timeout = **cause {
+                return Err(Error::Timeout);
+            }
+        }
+        result
+    }
+
+    /// Applies a curated set of restrictions to this Lua state in one call.
+    ///
+    /// Depending on `config`, this removes unsafe standard libraries from the global environment,
+    /// sets memory and instruction limits, and freezes the globals table. See [`SandboxConfig`]
+    /// for the individual knobs and their defaults.
+    ///
+    /// Regardless of `config`, scripts can only load source code afterwards: Lua does not verify
+    /// bytecode, so `load` (and `loadstring`, where available) are replaced with versions that
+    /// reject precompiled chunks, and `string.dump` is removed.
+    ///
+    /// The sandbox should be applied after the host has loaded its own Lua code, since chunks loaded
+    /// afterwards can not define globals when the globals are frozen.
+    ///
+    /// Every setting is validated before the state is modified, so if an error is returned the
+    /// state is left unchanged. Setting a memory limit fails with [`MemoryLimitNotAvailable`] if
+    /// the state was not created by mlua, and setting an instruction limit fails if a hook is
+    /// already set.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use mlua::{Lua, Result, SandboxConfig};
+    /// # fn main() -> Result<()> {
+    /// let lua = Lua::new();
+    /// lua.sandbox(SandboxConfig::default())?;
+    ///
+    /// assert!(lua.load("os.exit()").exec().is_err());
+    /// assert!(lua.load("print = nil").exec().is_err());
+    /// # Ok(())
+    /// # }
+    /// ```
+    ///
+    /// [`SandboxConfig`]: struct.SandboxConfig.html
+    /// [`MemoryLimitNotAvailable`]: enum.Error.html#variant.MemoryLimitNotAvailable
+    pub fn sandbox(&self, config: SandboxConfig) -> Result<()> {
+        let globals = self.globals();
+
+        if config.memory_limit.is_some() && self.extra.borrow().mem_info.is_null() {
+            return Err(Error::MemoryLimitNotAvailable);
+        }
+        if config.instruction_limit.is_some()
+            && unsafe { ffi::lua_gethookmask(self.main_state) } != 0
+        {
+            return Err(Error::RuntimeError(
+                "cannot set an instruction limit, a hook is already set".to_string(),
+            ));
+        }
+
+        let mut libs: Vec<(StdLib, &[&str])> = vec![
+            (StdLib::TABLE, &["table"]),
+            (StdLib::IO, &["io", "dofile", "loadfile"]),
+            (StdLib::OS, &["os"]),
+            (StdLib::STRING, &["string"]),
+            (StdLib::MATH, &["math"]),
+            (StdLib::PACKAGE, &["package", "require"]),
+            (StdLib::DEBUG, &["debug"]),
+        ];
+        #[cfg(any(feature = "lua53", feature = "lua52"))]
+        libs.push((StdLib::COROUTINE, &["coroutine"]));
+        #[cfg(feature = "lua53")]
+        libs.push((StdLib::UTF8, &["utf8"]));
+        #[cfg(feature = "lua52")]
+        libs.push((StdLib::BIT, &["bit32"]));
+        #[cfg(feature = "luajit")]
+        {
+            libs.push((StdLib::BIT, &["bit"]));
+            libs.push((StdLib::JIT, &["jit"]));
+            libs.push((StdLib::FFI, &["ffi"]));
+        }
+
+        for (lib, names) in libs {
+            if config.remove_libs.contains(lib) {
+                self.remove_globals(names)?;
+            }
+        }
+
+        // Lua does not verify bytecode, and crafted bytecode can break out of the sandbox, so
+        // scripts may only load source code and can not dump functions.
+        let load = self.create_function(load_text)?;
+        for name in &["load", "loadstring"] {
+            if globals.raw_get::<_, Value>(*name)? != Nil {
+                globals.raw_set(*name, load.clone())?;
+            }
+        }
+        if let Value::Table(string) = globals.raw_get::<_, Value>("string")? {
+            string.raw_set("dump", Nil)?;
+        }
+
+        if let Some(memory_limit) = config.memory_limit {
+            self.set_memory_limit(memory_limit)?;
+        }
+
+        if let Some(instruction_limit) = config.instruction_limit {
+            // Traces compiled by the JIT do not call hooks
+            #[cfg(feature = "luajit")]
+            unsafe {
+                ffi::luaJIT_setmode(
+                    self.main_state,
+                    0,
+                    ffi::LUAJIT_MODE_ENGINE | ffi::LUAJIT_MODE_FLUSH,
+                );
+                ffi::luaJIT_setmode(
+                    self.main_state,
+                    0,
+                    ffi::LUAJIT_MODE_ENGINE | ffi::LUAJIT_MODE_OFF,
+                );
+            }
+
+            const INSTRUCTION_STEP: u64 = 1000;
+            let step = instruction_limit.clamp(1, INSTRUCTION_STEP);
+            let mut executed = 0;
+            self.set_hook(HookMask::COUNT, step as usize, move |_, _| {
+                executed += step;
+                if executed > instruction_limit {
+                    return Err(Error::InstructionLimitExceeded);
+                }
+                Ok(())
+            })?;
+        }
+
+        if config.freeze_globals {
+            // Chunks loaded from now on get a read-only view of the globals as their environment,
+            // which also hides the writable globals table behind `_G`, `rawset` and `getfenv`.
+            let env: Table = self
+                .load(
+                    r#"
+                    local globals = ...
+                    local error, getfenv, next, rawequal, rawset, setmetatable, tostring, type =
+                        error, getfenv, next, rawequal, rawset, setmetatable, tostring, type
+
+                    local env = {}
+                    local overrides = setmetatable({ _G = env }, { __index = globals })
+                    overrides.rawset = function(t, k, v)
+                        if rawequal(t, env) then
+                            error("attempt to modify read-only globals", 2)
+                        end
+                        return rawset(t, k, v)
+                    end
+                    if getfenv then
+                        overrides.getfenv = function(f)
+                            if f == nil then
+                                f = 1
+                            end
+                            if type(f) == "number" and f > 0 then
+                                f = f + 1
+                            end
+                            local fenv = getfenv(f)
+                            if rawequal(fenv, globals) then
+                                return env
+                            end
+                            return fenv
+                        end
+                    end
+
+                    return setmetatable(env, {
+                        __index = overrides,
+                        __newindex = function(_, k)
+                            error("attempt to modify read-only global '" .. tostring(k) .. "'", 2)
+                        end,
+                        __pairs = function()
+                            return function(_, k)
+                                local v
+                                k, v = next(globals, k)
+                                if rawequal(v, globals) then
+                                    v = env
+                                end
+                                return k, v
+                            end, env, nil
+                        end,
+                        __metatable = false,
+                    })
+                "#,
+                )
+                .set_name("=sandbox")?
+                .call(globals)?;
+            let env = self.create_registry_value(env)?;
+            self.extra.borrow_mut().sandbox_env = Some(env);
+        }
+
+        Ok(())
+    }
+
+    /// Removes the given globals, along with the modules of the same name in `package.loaded`.
+    ///
+    /// Removing the `package.loaded` entry as well makes sure a removed library can not be brought
+    /// back with `require` after it was loaded.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use mlua::{Lua, Result};
+    /// # fn main() -> Result<()> {
+    /// let lua = Lua::new();
+    /// lua.remove_globals(&["os", "io", "dofile"])?;
+    /// assert!(lua.load("os.exit()").exec().is_err());
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn remove_globals(&self, names: &[&str]) -> Result<()> {
+        let globals = self.globals();
+        let loaded = match globals.raw_get::<_, Value>("package")? {
+            Value::Table(package) => package.get::<_, Option<Table>>("loaded")?,
+            _ => None,
+        };
+        for name in names {
+            globals.raw_set(*name, Nil)?;
+            if let Some(loaded) = &loaded {
+                loaded.raw_set(*name, Nil)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Removes the LuaJIT `ffi` library and makes `require("ffi")` fail.
+    ///
+    /// The `ffi` library gives scripts unrestricted access to native memory and functions, so it
+    /// must not be reachable from untrusted code.
+    #[cfg(feature = "luajit")]
+    pub fn disable_ffi(&self) -> Result<()> {
+        self.remove_globals(&["ffi"])?;
+
+        let globals = self.globals();
+        if let Value::Table(package) = globals.raw_get::<_, Value>("package")? {
+            if let Some(preload) = package.get::<_, Option<Table>>("preload")? {
+                let disabled = self.create_function(|_, ()| -> Result<()> {
+                    Err(Error::RuntimeError(
+                        "the ffi library is disabled".to_string(),
+                    ))
+                })?;
+                preload.raw_set("ffi", disabled)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Returns Lua source code as a `Chunk` builder type.
+    ///
+    /// In order to actually compile or run the resulting code, you must call [`Chunk::exec`] or
+    /// similar on the returned builder.  Code is not even parsed until one of these methods is
+    /// called.
+    ///
+    /// [`Chunk::exec`]: struct.Chunk.html#method.exec
+    pub fn load<'lua, 'a, S>(&'lua self, source: &'a S) -> Chunk<'lua, 'a>
+    where
+        S: ?Sized + AsRef<[u8]>,
+    {
+        Chunk {
+            lua: self,
+            source: source.as_ref(),
+            name: None,
+            env: None,
+            mode: ChunkMode::default(),
+        }
+    }
+
+    /// Compiles a chunk of Lua code, reusing the result of a previous compilation of the same
+    /// source.
+    ///
+    /// One compiled function is cached per `name`, along with the source it was compiled from.
+    /// Calling this method again with the same `name` and an identical `source` returns the same
+    /// function without compiling the source again, while a changed source is compiled anew and
+    /// replaces the cached function for that name.
+    ///
+    /// Cached functions are kept alive in the registry until [`clear_chunk_cache`] is called, along
+    /// with anything they reference. Since the same function is returned on every hit, any state
+    /// kept in its upvalues is shared as well.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use mlua::{Lua, Result};
+    /// # fn main() -> Result<()> {
+    /// # let lua = Lua::new();
+    /// let template = "return ... * 2";
+    /// for i in 0..3 {
+    ///     // Compiled only once
+    ///     let f = lua.load_cached("template", template)?;
+    ///     assert_eq!(f.call::<_, i64>(i)?, i * 2);
+    /// }
+    /// lua.clear_chunk_cache();
+    /// # Ok(())
+    /// # }
+    /// ```
+    ///
+    /// [`clear_chunk_cache`]: #method.clear_chunk_cache
+    pub fn load_cached<'lua>(&'lua self, name: &str, source: &str) -> Result<Function<'lua>> {
+        let registry_id = match self.extra.borrow().chunk_cache.get(name) {
+            Some(cached) if cached.source == source => Some(cached.function.registry_id),
+            _ => None,
+        };
+        if let Some(registry_id) = registry_id {
+            return Ok(Function(unsafe { self.registry_ref(registry_id) }));
+        }
+
+        let function = self.load(source).set_name(name)?.into_function()?;
+        let cached = CachedChunk {
+            source: source.to_owned(),
+            function: self.create_registry_value(function.clone())?,
+        };
+        let replaced = self
+            .extra
+            .borrow_mut()
+            .chunk_cache
+            .insert(name.to_owned(), cached);
+        if replaced.is_some() {
+            drop(replaced);
+            self.expire_registry_values();
+        }
+        Ok(function)
+    }
+
+    /// Removes every function cached by [`load_cached`].
+    ///
+    /// [`load_cached`]: #method.load_cached
+    pub fn clear_chunk_cache(&self) {
+        let cache = mem::take(&mut self.extra.borrow_mut().chunk_cache);
+        drop(cache);
+        self.expire_registry_values();
+    }
+
+    fn load_chunk<'lua>(
+        &'lua self,
+        source: &[u8],
+        name: Option<&CString>,
+        env: Option<Value<'lua>>,
+        mode: ChunkMode,
+    ) -> Result<Function<'lua>> {
+        let env = match env {
+            Some(env) => Some(env),
+            None => self.sandbox_env().map(Value::Table),
+        };
+        unsafe {
+            let _sg = StackGuard::new(self.state);
+            assert_stack(self.state, 1);
+
+            match if let Some(name) = name {
+                ffi::luaL_loadbufferx(
+                    self.state,
+                    source.as_ptr() as *const c_char,
+                    source.len(),
+                    name.as_ptr() as *const c_char,
+                    mode.as_cstr(),
+                )
+            } else {
+                ffi::luaL_loadbufferx(
+                    self.state,
+                    source.as_ptr() as *const c_char,
+                    source.len(),
+                    ptr::null(),
+                    mode.as_cstr(),
+                )
+            } {
+                ffi::LUA_OK => {
+                    if let Some(env) = env {
+                        self.push_value(env)?;
+                        #[cfg(any(feature = "lua53", feature = "lua52"))]
+                        ffi::lua_setupvalue(self.state, -2, 1);
+                        #[cfg(any(feature = "lua51", feature = "luajit"))]
+                        ffi::lua_setfenv(self.state, -2);
+                    }
+                    Ok(Function(self.pop_ref()))
+                }
+                err => Err(pop_error(self.state, err)),
+            }
+        }
+    }
+
+    /// Create and return an interned Lua string.  Lua strings can be arbitrary [u8] data including
+    /// embedded nulls, so in addition to `&str` and `&String`, you can also pass plain `&[u8]`
+    /// here.
+    pub fn create_string<S>(&self, s: &S) -> Result<String>
+    where
+        S: ?Sized + AsRef<[u8]>,
+    {
+        unsafe {
+            let _sg = StackGuard::new(self.state);
+            assert_stack(self.state, 4);
+            push_string(self.state, s)?;
+            Ok(String(self.pop_ref()))
+        }
+    }
+
+    /// Returns a cached Lua string with the contents of `s`, creating it on first use.
+    ///
+    /// Every call with the same contents returns a handle to the same Lua string object, which is
+    /// kept alive in the registry until [`clear_interned_strings`] is called.
+    ///
+    /// The cache is not bounded: every distinct string passed here stays in it, and keeps its
+    /// registry slot, until [`clear_interned_strings`] is called.
+    ///
+    /// Lua itself already interns short strings (up to 40 bytes in Lua 5.3), so for those this only
+    /// saves hashing the string on the Lua side and may well be slower than [`create_string`], as a
+    /// Rust-side lookup and a registry access are needed instead. Interning helps when the same long
+    /// strings are created over and over, since Lua allocates a fresh object for each of them. It
+    /// hurts when most strings are used only once: they are never collected while cached.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use mlua::{Lua, Result};
+    /// # fn main() -> Result<()> {
+    /// # let lua = Lua::new();
+    /// let key = "a rather long identifier that Lua would not intern on its own";
+    /// let a = lua.intern_string(key)?;
+    /// let b = lua.intern_string(key)?;
+    /// assert_eq!(a.as_bytes().as_ptr(), b.as_bytes().as_ptr());
+    /// lua.clear_interned_strings();
+    /// # Ok(())
+    /// # }
+    /// ```
+    ///
+    /// [`clear_interned_strings`]: #method.clear_interned_strings
+    /// [`create_string`]: #method.create_string
+    pub fn intern_string<'lua>(&'lua self, s: &str) -> Result<String<'lua>> {
+        let registry_id = self
+            .extra
+            .borrow()
+            .string_cache
+            .get(s)
+            .map(|key| key.registry_id);
+        if let Some(registry_id) = registry_id {
+            return Ok(String(unsafe { self.registry_ref(registry_id) }));
+        }
+
+        let string = self.create_string(s)?;
+        let registry_key = self.create_registry_value(string.clone())?;
+        self.extra
+            .borrow_mut()
+            .string_cache
+            .insert(s.to_owned(), registry_key);
+        Ok(string)
+    }
+
+    /// Removes every string cached by [`intern_string`].
+    ///
+    /// [`intern_string`]: #method.intern_string
+    pub fn clear_interned_strings(&self) {
+        let cache = mem::take(&mut self.extra.borrow_mut().string_cache);
+        drop(cache);
+        self.expire_registry_values();
+    }
+
+    /// Formats `args` according to `fmt` by calling Lua's `string.format`.
+    ///
+    /// The result is exactly what a script would get from `string.format(fmt, ...)`, including
+    /// the formatting of floats and the quoting done by `%q`. Errors raised by `string.format`, such
+    /// as an invalid format specifier or a bad argument, are returned as is.
+    ///
+    /// Requires the `string` standard library to be loaded. The function is looked up in the global
+    /// `string` table on each call, so a replaced `string.format` is used as well.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use mlua::{Lua, Result};
+    /// # fn main() -> Result<()> {
+    /// # let lua = Lua::new();
+    /// let s = lua.string_format("%s has %d items", ("cart", 3))?;
+    /// assert_eq!(s, "cart has 3 items");
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn string_format<'lua, A: ToLuaMulti<'lua>>(
+        &'lua self,
+        fmt: &str,
+        args: A,
+    ) -> Result<String<'lua>> {
+        let format: Function = match self.globals().get::<_, Value>("string")? {
+            Value::Table(string) => string.get("format")?,
+            _ => {
+                return Err(Error::RuntimeError(
+                    "string library is not loaded".to_string(),
+                ))
+            }
+        };
+        let mut args = args.to_lua_multi(self)?;
+        args.push_front(Value::String(self.create_string(fmt)?));
+        format.call(args)
+    }
+
+    /// Wraps a raw pointer as a Lua light userdata.
+    ///
+    /// This does not allocate and can not fail. Refer to [`LightUserData`] for the caveats of
+    /// holding raw pointers in Lua.
+    ///
+    /// [`LightUserData`]: struct.LightUserData.html
+    pub fn create_light_userdata<T>(&self, ptr: *mut T) -> LightUserData {
+        LightUserData(ptr as *mut c_void)
+    }
+
+    /// Creates and returns a new table.
+    pub fn create_table(&self) -> Result<Table> {
+        unsafe {
+            let _sg = StackGuard::new(self.state);
+            assert_stack(self.state, 3);
+            unsafe extern "C" fn new_table(state: *mut ffi::lua_State) -> c_int {
+                ffi::lua_newtable(state);
+                1
             }
             protect_lua(self.state, 0, new_table)?;
             Ok(Table(self.pop_ref()))
         }
     }
 
+    /// Creates and returns a new table with the given metatable.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use mlua::{Lua, Result, Table};
+    /// # fn main() -> Result<()> {
+    /// # let lua = Lua::new();
+    /// let class: Table = lua.load("{ greet = function(self) return 'hi ' .. self.name end }").eval()?;
+    /// let class_mt = lua.create_table_from(vec![("__index", class)])?;
+    ///
+    /// let object = lua.create_table_from_with_metatable(vec![("name", "mlua")], class_mt)?;
+    /// lua.globals().set("object", object)?;
+    /// assert_eq!(lua.load("object:greet()").eval::<String>()?, "hi mlua");
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn create_table_with_metatable<'lua>(
+        &'lua self,
+        metatable: Table<'lua>,
+    ) -> Result<Table<'lua>> {
+        let table = self.create_table()?;
+        table.set_metatable(Some(metatable));
+        Ok(table)
+    }
+
+    /// Creates a table with the given metatable and fills it with values from an iterator.
+    ///
+    /// The values are set before the metatable is attached, so metamethods such as `__newindex`
+    /// are not invoked.
+    pub fn create_table_from_with_metatable<'lua, K, V, I>(
+        &'lua self,
+        cont: I,
+        metatable: Table<'lua>,
+    ) -> Result<Table<'lua>>
+    where
+        K: ToLua<'lua>,
+        V: ToLua<'lua>,
+        I: IntoIterator<Item = (K, V)>,
+    {
+        let table = self.create_table_from(cont)?;
+        table.set_metatable(Some(metatable));
+        Ok(table)
+    }
+
     /// Creates a table and fills it with values from an iterator.
     pub fn create_table_from<'lua, K, V, I>(&'lua self, cont: I) -> Result<Table<'lua>>
     where
@@ -405,117 +1691,503 @@ impl Lua {
         }
     }
 
-    /// Creates a table from an iterator of values, using `1..` as the keys.
-    pub fn create_sequence_from<'lua, T, I>(&'lua self, cont: I) -> Result<Table<'lua>>
+    /// Creates a table from an iterator of values, using `1..` as the keys.
+    ///
+    /// The array part of the table is preallocated using the lower bound of the iterator's
+    /// `size_hint`, so converting a `Vec` or another sized collection does not need to resize the
+    /// table while it is filled.
+    pub fn create_sequence_from<'lua, T, I>(&'lua self, cont: I) -> Result<Table<'lua>>
+    where
+        T: ToLua<'lua>,
+        I: IntoIterator<Item = T>,
+    {
+        let iter = cont.into_iter();
+        let narr = iter.size_hint().0.min(c_int::MAX as usize) as c_int;
+        unsafe {
+            let _sg = StackGuard::new(self.state);
+            // `Lua` instance assumes that on any callback, the Lua stack has at least LUA_MINSTACK
+            // slots available to avoid panics.
+            check_stack(self.state, 4 + ffi::LUA_MINSTACK)?;
+
+            protect_lua_closure(self.state, 0, 1, |state| {
+                ffi::lua_createtable(state, narr, 0);
+            })?;
+
+            for (i, v) in iter.enumerate() {
+                self.push_value(v.to_lua(self)?)?;
+                let i = (i + 1) as Integer;
+                protect_lua_closure(self.state, 2, 1, |state| {
+                    ffi::lua_rawseti(state, -2, i);
+                })?;
+            }
+            Ok(Table(self.pop_ref()))
+        }
+    }
+
+    /// Wraps a Rust function or closure, creating a callable Lua function handle to it.
+    ///
+    /// The function's return value is always a `Result`: If the function returns `Err`, the error
+    /// is raised as a Lua error, which can be caught using `(x)pcall` or bubble up to the Rust code
+    /// that invoked the Lua code. This allows using the `?` operator to propagate errors through
+    /// intermediate Lua code.
+    ///
+    /// If the function returns `Ok`, the contained value will be converted to one or more Lua
+    /// values. For details on Rust-to-Lua conversions, refer to the [`ToLua`] and [`ToLuaMulti`]
+    /// traits.
+    ///
+    /// Rust functions can not be suspended, so a coroutine can not yield while it is running one,
+    /// for example from a Lua function called back through [`Function::call`]. Lua checks this
+    /// before yielding on every supported version and raises an "attempt to yield across a C-call
+    /// boundary" error instead, which is returned as `Err` like any other error. Values should be
+    /// passed back to the coroutine by returning them from the Rust function instead.
+    ///
+    /// # Examples
+    ///
+    /// Create a function which prints its argument:
+    ///
+    /// ```
+    /// # use mlua::{Lua, Result};
+    /// # fn main() -> Result<()> {
+    /// # let lua = Lua::new();
+    /// let greet = lua.create_function(|_, name: String| {
+    ///     println!("Hello, {}!", name);
+    ///     Ok(())
+    /// });
+    /// # let _ = greet;    // used
+    /// # Ok(())
+    /// # }
+    /// ```
+    ///
+    /// Use tuples to accept multiple arguments:
+    ///
+    /// ```
+    /// # use mlua::{Lua, Result};
+    /// # fn main() -> Result<()> {
+    /// # let lua = Lua::new();
+    /// let print_person = lua.create_function(|_, (name, age): (String, u8)| {
+    ///     println!("{} is {} years old!", name, age);
+    ///     Ok(())
+    /// });
+    /// # let _ = print_person;    // used
+    /// # Ok(())
+    /// # }
+    /// ```
+    ///
+    /// [`ToLua`]: trait.ToLua.html
+    /// [`ToLuaMulti`]: trait.ToLuaMulti.html
+    /// [`Function::call`]: struct.Function.html#method.call
+    pub fn create_function<'lua, 'callback, A, R, F>(&'lua self, func: F) -> Result<Function<'lua>>
+    where
+        A: FromLuaMulti<'callback>,
+        R: ToLuaMulti<'callback>,
+        F: 'static + Send + Fn(&'callback Lua, A) -> Result<R>,
+    {
+        self.create_callback(Box::new(move |lua, args| {
+            func(lua, A::from_lua_args(args, 1, None, lua)?)?.to_lua_multi(lua)
+        }))
+    }
+
+    /// Wraps a Rust mutable closure, creating a callable Lua function handle to it.
+    ///
+    /// This is a version of [`create_function`] that accepts a FnMut argument.  Refer to
+    /// [`create_function`] for more information about the implementation.
+    ///
+    /// [`create_function`]: #method.create_function
+    pub fn create_function_mut<'lua, 'callback, A, R, F>(
+        &'lua self,
+        func: F,
+    ) -> Result<Function<'lua>>
+    where
+        A: FromLuaMulti<'callback>,
+        R: ToLuaMulti<'callback>,
+        F: 'static + Send + FnMut(&'callback Lua, A) -> Result<R>,
+    {
+        let func = RefCell::new(func);
+        self.create_function(move |lua, args| {
+            (&mut *func
+                .try_borrow_mut()
+                .map_err(|_| Error::RecursiveMutCallback)?)(lua, args)
+        })
+    }
+
+    /// Wraps a Rust closure that is not `Send`, creating a callable Lua function handle to it.
+    ///
+    /// This is a version of [`create_function`] for callbacks that capture single-threaded data
+    /// such as `Rc` or `RefCell`. Since `Lua` itself is `Send`, this ties the callback to the
+    /// thread calling this method:
+    ///
+    /// * Calling the function while the `Lua` instance is used from another thread returns an
+    ///   error instead of running the callback.
+    /// * If the `Lua` instance is dropped on another thread, the callback (and everything it
+    ///   captured) is leaked instead of being dropped.
+    ///
+    /// The `Lua` instance can still be moved to other threads, the callback just only works on
+    /// the thread that created it.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use mlua::{Lua, Result};
+    /// # use std::cell::Cell;
+    /// # use std::rc::Rc;
+    /// # fn main() -> Result<()> {
+    /// # let lua = Lua::new();
+    /// let count = Rc::new(Cell::new(0));
+    /// let counter = count.clone();
+    /// let increment = lua.create_function_local(move |_, ()| {
+    ///     counter.set(counter.get() + 1);
+    ///     Ok(())
+    /// })?;
+    ///
+    /// increment.call::<_, ()>(())?;
+    /// assert_eq!(count.get(), 1);
+    /// # Ok(())
+    /// # }
+    /// ```
+    ///
+    /// [`create_function`]: #method.create_function
+    pub fn create_function_local<'lua, 'callback, A, R, F>(
+        &'lua self,
+        func: F,
+    ) -> Result<Function<'lua>>
+    where
+        A: FromLuaMulti<'callback>,
+        R: ToLuaMulti<'callback>,
+        F: 'static + Fn(&'callback Lua, A) -> Result<R>,
+    {
+        let func = ThreadBound::new(func);
+        self.create_callback(Box::new(move |lua, args| {
+            let func = func.get().ok_or_else(|| {
+                Error::RuntimeError(
+                    "function created with create_function_local called from another thread"
+                        .to_string(),
+                )
+            })?;
+            func(lua, A::from_lua_args(args, 1, None, lua)?)?.to_lua_multi(lua)
+        }))
+    }
+
+    /// Wraps a Lua function into a new thread (or coroutine).
+    ///
+    /// Equivalent to `coroutine.create`.
+    pub fn create_thread<'lua>(&'lua self, func: Function<'lua>) -> Result<Thread<'lua>> {
+        unsafe {
+            let _sg = StackGuard::new(self.state);
+            assert_stack(self.state, 2);
+
+            let thread_state =
+                protect_lua_closure(self.state, 0, 1, |state| ffi::lua_newthread(state))?;
+            self.push_ref(&func.0);
+            ffi::lua_xmove(self.state, thread_state, 1);
+
+            Ok(Thread(self.pop_ref()))
+        }
+    }
+
+    /// Wraps a Lua function into a new thread (or coroutine), giving it a name.
+    ///
+    /// The name is returned by [`Thread::name`] and included in the traceback of errors raised
+    /// inside the thread, which helps to tell apart coroutines in systems running many of them. It
+    /// does not keep the thread alive.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use mlua::{Function, Lua, Result};
+    /// # fn main() -> Result<()> {
+    /// # let lua = Lua::new();
+    /// let worker: Function = lua.load("function() coroutine.yield() end").eval()?;
+    /// let thread = lua.create_thread_with_name(worker, "worker-1")?;
+    /// assert_eq!(thread.name().as_deref(), Some("worker-1"));
+    /// # Ok(())
+    /// # }
+    /// ```
+    ///
+    /// [`Thread::name`]: struct.Thread.html#method.name
+    pub fn create_thread_with_name<'lua>(
+        &'lua self,
+        func: Function<'lua>,
+        name: &str,
+    ) -> Result<Thread<'lua>> {
+        let thread = self.create_thread(func)?;
+        self.thread_names()?.raw_set(thread.clone(), name)?;
+        Ok(thread)
+    }
+
+    // Returns the table of thread names set by `create_thread_with_name`, creating it on first use.
+    // Threads are weak keys, so that naming a thread does not keep it alive.
+    pub(crate) fn thread_names<'lua>(&'lua self) -> Result<Table<'lua>> {
+        unsafe {
+            let _sg = StackGuard::new(self.state);
+            assert_stack(self.state, 1);
+
+            ffi::lua_pushlightuserdata(
+                self.state,
+                &THREAD_NAMES_REGISTRY_KEY as *const u8 as *mut c_void,
+            );
+            ffi::lua_rawget(self.state, ffi::LUA_REGISTRYINDEX);
+            if ffi::lua_isnil(self.state, -1) == 0 {
+                return Ok(Table(self.pop_ref()));
+            }
+        }
+
+        let names = self.create_table()?;
+        let metatable = self.create_table()?;
+        metatable.raw_set("__mode", "k")?;
+        names.set_metatable(Some(metatable));
+
+        unsafe {
+            let _sg = StackGuard::new(self.state);
+            assert_stack(self.state, 2);
+
+            ffi::lua_pushlightuserdata(
+                self.state,
+                &THREAD_NAMES_REGISTRY_KEY as *const u8 as *mut c_void,
+            );
+            self.push_ref(&names.0);
+            protect_lua_closure(self.state, 2, 0, |state| {
+                ffi::lua_rawset(state, ffi::LUA_REGISTRYINDEX)
+            })?;
+        }
+        Ok(names)
+    }
+
+    /// Create a Lua userdata object from a custom userdata type.
+    ///
+    /// The metatable of `T` is built from [`UserData::add_methods`] only once per Lua state, the
+    /// first time a value of type `T` is created, and is shared by all instances. Creating further
+    /// instances only looks up the cached metatable by `TypeId`, so there is no need to cache it
+    /// separately when creating many userdata values.
+    ///
+    /// [`UserData::add_methods`]: trait.UserData.html#method.add_methods
+    pub fn create_userdata<T>(&self, data: T) -> Result<AnyUserData>
+    where
+        T: 'static + Send + UserData,
+    {
+        unsafe { self.make_userdata(data) }
+    }
+
+    /// Registers the methods of a userdata type that does not implement [`UserData`].
+    ///
+    /// This is useful for types defined in other crates, on which `UserData` cannot be implemented
+    /// because of the orphan rule. `add_methods` is given a method registry that works the same way
+    /// as the one passed to [`UserData::add_methods`]. Values of a registered type are created with
+    /// [`create_registered_userdata`] and can be borrowed from [`AnyUserData`] as usual.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if a metatable for `T` has already been created, either by a previous call
+    /// to this method or because `T` implements `UserData` and a value of type `T` has already been
+    /// converted to Lua.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use mlua::{Lua, Result, UserDataMethods};
+    /// # fn main() -> Result<()> {
+    /// # let lua = Lua::new();
+    /// // `Duration` is a foreign type, `UserData` cannot be implemented on it
+    /// use std::time::Duration;
+    ///
+    /// lua.register_userdata_with::<Duration, _>(|methods| {
+    ///     methods.add_method("as_millis", |_, duration, ()| Ok(duration.as_millis() as u64));
+    /// })?;
+    ///
+    /// let duration = lua.create_registered_userdata(Duration::from_secs(2))?;
+    /// lua.globals().set("duration", duration)?;
+    /// assert_eq!(lua.load("duration:as_millis()").eval::<u64>()?, 2000);
+    /// # Ok(())
+    /// # }
+    /// ```
+    ///
+    /// [`UserData`]: trait.UserData.html
+    /// [`UserData::add_methods`]: trait.UserData.html#method.add_methods
+    /// [`create_registered_userdata`]: #method.create_registered_userdata
+    /// [`AnyUserData`]: struct.AnyUserData.html
+    pub fn register_userdata_with<'lua, T, F>(&'lua self, add_methods: F) -> Result<()>
+    where
+        T: 'static + Send,
+        F: FnOnce(&mut StaticUserDataMethods<'lua, T>),
+    {
+        if self.registered_userdata_metatable::<T>().is_some() {
+            return Err(Error::RuntimeError(format!(
+                "userdata type `{}` is already registered",
+                std::any::type_name::<T>()
+            )));
+        }
+
+        let mut methods = StaticUserDataMethods::default();
+        add_methods(&mut methods);
+        unsafe { self.register_userdata_metatable::<T>(methods, None)? };
+        Ok(())
+    }
+
+    /// Creates a Lua userdata object from a type registered with [`register_userdata_with`].
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if no metatable has been registered for `T`.
+    ///
+    /// [`register_userdata_with`]: #method.register_userdata_with
+    pub fn create_registered_userdata<'lua, T>(&'lua self, data: T) -> Result<AnyUserData<'lua>>
     where
-        T: ToLua<'lua>,
-        I: IntoIterator<Item = T>,
+        T: 'static + Send,
     {
-        self.create_table_from(cont.into_iter().enumerate().map(|(k, v)| (k + 1, v)))
+        match self.registered_userdata_metatable::<T>() {
+            Some(ud_index) => unsafe { self.make_userdata_with_metatable(data, ud_index) },
+            None => Err(Error::RuntimeError(format!(
+                "userdata type `{}` is not registered",
+                std::any::type_name::<T>()
+            ))),
+        }
     }
 
-    /// Wraps a Rust function or closure, creating a callable Lua function handle to it.
-    ///
-    /// The function's return value is always a `Result`: If the function returns `Err`, the error
-    /// is raised as a Lua error, which can be caught using `(x)pcall` or bubble up to the Rust code
-    /// that invoked the Lua code. This allows using the `?` operator to propagate errors through
-    /// intermediate Lua code.
+    /// Returns a handle to the metatable shared by all userdata of type `T`.
     ///
-    /// If the function returns `Ok`, the contained value will be converted to one or more Lua
-    /// values. For details on Rust-to-Lua conversions, refer to the [`ToLua`] and [`ToLuaMulti`]
-    /// traits.
+    /// All userdata created from the same type share a single metatable, so changes made through
+    /// the returned handle apply to existing and future instances at once. The metatable is
+    /// created from [`UserData::add_methods`] if no userdata of type `T` has been created yet.
     ///
     /// # Examples
     ///
-    /// Create a function which prints its argument:
-    ///
     /// ```
-    /// # use mlua::{Lua, Result};
+    /// # use mlua::{Lua, Result, UserData};
     /// # fn main() -> Result<()> {
-    /// # let lua = Lua::new();
-    /// let greet = lua.create_function(|_, name: String| {
-    ///     println!("Hello, {}!", name);
-    ///     Ok(())
-    /// });
-    /// # let _ = greet;    // used
+    /// struct Counter;
+    /// impl UserData for Counter {}
+    ///
+    /// let lua = Lua::new();
+    /// lua.globals().set("counter", lua.create_userdata(Counter)?)?;
+    ///
+    /// let metatable = lua.userdata_type_metatable::<Counter>()?;
+    /// metatable.set_method(
+    ///     "name",
+    ///     lua.create_function(|_, _: mlua::AnyUserData| Ok("counter"))?,
+    /// )?;
+    ///
+    /// assert_eq!(lua.load("counter:name()").eval::<String>()?, "counter");
     /// # Ok(())
     /// # }
     /// ```
     ///
-    /// Use tuples to accept multiple arguments:
+    /// [`UserData::add_methods`]: trait.UserData.html#method.add_methods
+    pub fn userdata_type_metatable<'lua, T>(&'lua self) -> Result<UserDataMetatable<'lua>>
+    where
+        T: 'static + UserData,
+    {
+        unsafe {
+            let table_id = self.userdata_metatable::<T>()?;
+
+            let _sg = StackGuard::new(self.state);
+            assert_stack(self.state, 1);
+            ffi::lua_rawgeti(
+                self.state,
+                ffi::LUA_REGISTRYINDEX,
+                table_id as ffi::lua_Integer,
+            );
+            Ok(UserDataMetatable(Table(self.pop_ref())))
+        }
+    }
+
+    /// Creates a Rust function with [`create_function`] and stores it in the global variable `name`.
+    ///
+    /// Use [`Table::set_function`] to store functions in a module table instead.
+    ///
+    /// # Examples
     ///
     /// ```
     /// # use mlua::{Lua, Result};
     /// # fn main() -> Result<()> {
     /// # let lua = Lua::new();
-    /// let print_person = lua.create_function(|_, (name, age): (String, u8)| {
-    ///     println!("{} is {} years old!", name, age);
-    ///     Ok(())
-    /// });
-    /// # let _ = print_person;    // used
+    /// lua.set_function("add", |_, (a, b): (i64, i64)| Ok(a + b))?;
+    /// assert_eq!(lua.load("add(1, 2)").eval::<i64>()?, 3);
     /// # Ok(())
     /// # }
     /// ```
     ///
-    /// [`ToLua`]: trait.ToLua.html
-    /// [`ToLuaMulti`]: trait.ToLuaMulti.html
-    pub fn create_function<'lua, 'callback, A, R, F>(&'lua self, func: F) -> Result<Function<'lua>>
+    /// [`create_function`]: #method.create_function
+    /// [`Table::set_function`]: struct.Table.html#method.set_function
+    pub fn set_function<'callback, A, R, F>(&self, name: &str, func: F) -> Result<()>
     where
         A: FromLuaMulti<'callback>,
         R: ToLuaMulti<'callback>,
         F: 'static + Send + Fn(&'callback Lua, A) -> Result<R>,
     {
-        self.create_callback(Box::new(move |lua, args| {
-            func(lua, A::from_lua_multi(args, lua)?)?.to_lua_multi(lua)
-        }))
+        self.globals().set_function(name, func)
     }
 
-    /// Wraps a Rust mutable closure, creating a callable Lua function handle to it.
+    /// Removes the global variable `name`.
     ///
-    /// This is a version of [`create_function`] that accepts a FnMut argument.  Refer to
-    /// [`create_function`] for more information about the implementation.
+    /// Equivalent to calling [`Table::remove`] on the [`globals`] table.
     ///
-    /// [`create_function`]: #method.create_function
-    pub fn create_function_mut<'lua, 'callback, A, R, F>(
-        &'lua self,
-        func: F,
-    ) -> Result<Function<'lua>>
-    where
-        A: FromLuaMulti<'callback>,
-        R: ToLuaMulti<'callback>,
-        F: 'static + Send + FnMut(&'callback Lua, A) -> Result<R>,
-    {
-        let func = RefCell::new(func);
-        self.create_function(move |lua, args| {
-            (&mut *func
-                .try_borrow_mut()
-                .map_err(|_| Error::RecursiveMutCallback)?)(lua, args)
-        })
+    /// [`Table::remove`]: struct.Table.html#method.remove
+    /// [`globals`]: #method.globals
+    pub fn remove_global(&self, name: &str) -> Result<()> {
+        self.globals().remove(name)
     }
 
-    /// Wraps a Lua function into a new thread (or coroutine).
+    /// Returns the sorted names of all global variables.
     ///
-    /// Equivalent to `coroutine.create`.
-    pub fn create_thread<'lua>(&'lua self, func: Function<'lua>) -> Result<Thread<'lua>> {
-        unsafe {
-            let _sg = StackGuard::new(self.state);
-            assert_stack(self.state, 2);
-
-            let thread_state =
-                protect_lua_closure(self.state, 0, 1, |state| ffi::lua_newthread(state))?;
-            self.push_ref(&func.0);
-            ffi::lua_xmove(self.state, thread_state, 1);
-
-            Ok(Thread(self.pop_ref()))
-        }
+    /// Only string keys of the globals table are returned. This is the same as calling
+    /// [`global_names_with`] with `inherited` set to `false`.
+    ///
+    /// [`global_names_with`]: #method.global_names_with
+    pub fn global_names(&self) -> Result<Vec<StdString>> {
+        self.global_names_with(false)
     }
 
-    /// Create a Lua userdata object from a custom userdata type.
-    pub fn create_userdata<T>(&self, data: T) -> Result<AnyUserData>
-    where
-        T: 'static + Send + UserData,
-    {
-        unsafe { self.make_userdata(data) }
+    /// Returns the sorted names of all global variables, optionally including inherited ones.
+    ///
+    /// If `inherited` is `true`, the keys of tables reachable through the `__index` field of the
+    /// globals table metatable (and their metatables in turn) are included as well, for example
+    /// when unknown globals fall back to another table. Only string keys are returned, names that
+    /// are not valid UTF-8 are skipped.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use mlua::{Lua, Result};
+    /// # fn main() -> Result<()> {
+    /// # let lua = Lua::new();
+    /// lua.globals().set("answer", 42)?;
+    ///
+    /// let names = lua.global_names()?;
+    /// assert!(names.iter().any(|n| n == "answer"));
+    /// assert!(names.iter().any(|n| n == "print"));
+    /// # Ok(())
+    /// # }
+    /// ```
+    ///
+    /// [`sandbox`]: #method.sandbox
+    pub fn global_names_with(&self, inherited: bool) -> Result<Vec<StdString>> {
+        let mut names = Vec::new();
+        let mut visited = Vec::new();
+        let mut next = Some(self.globals());
+        while let Some(table) = next.take() {
+            if visited.contains(&table) {
+                break;
+            }
+            for pair in table.clone().pairs::<Value, Value>() {
+                if let (Value::String(name), _) = pair? {
+                    if let Ok(name) = name.to_str() {
+                        names.push(name.to_owned());
+                    }
+                }
+            }
+            if inherited {
+                next = match table.get_metatable() {
+                    Some(mt) => match mt.raw_get::<_, Value>("__index")? {
+                        Value::Table(index) => Some(index),
+                        _ => None,
+                    },
+                    None => None,
+                };
+            }
+            visited.push(table);
+        }
+        names.sort();
+        names.dedup();
+        Ok(names)
     }
 
     /// Returns a handle to the global environment.
@@ -531,6 +2203,40 @@ impl Lua {
         }
     }
 
+    // Returns the read-only view of the globals given to chunks once `sandbox` froze the globals
+    fn sandbox_env(&self) -> Option<Table<'_>> {
+        let registry_id = self
+            .extra
+            .borrow()
+            .sandbox_env
+            .as_ref()
+            .map(|key| key.registry_id)?;
+        Some(Table(unsafe { self.registry_ref(registry_id) }))
+    }
+
+    // Returns a reference to the value in the given registry slot, without the ownership check
+    // done by `registry_value`. Only for keys held by mlua itself.
+    unsafe fn registry_ref(&self, registry_id: c_int) -> LuaRef<'_> {
+        let _sg = StackGuard::new(self.state);
+        assert_stack(self.state, 1);
+        ffi::lua_rawgeti(
+            self.state,
+            ffi::LUA_REGISTRYINDEX,
+            registry_id as ffi::lua_Integer,
+        );
+        self.pop_ref()
+    }
+
+    // Returns the `package` table, or an error if the package library is not loaded.
+    fn package<'lua>(&'lua self) -> Result<Table<'lua>> {
+        match self.globals().get::<_, Value>("package")? {
+            Value::Table(package) => Ok(package),
+            _ => Err(Error::RuntimeError(
+                "package library is not loaded".to_string(),
+            )),
+        }
+    }
+
     /// Returns a handle to the active `Thread`.  For calls to `Lua` this will be the main Lua thread,
     /// for parameters given to a callback, this will be whatever Lua thread called the callback.
     pub fn current_thread<'lua>(&'lua self) -> Thread<'lua> {
@@ -540,6 +2246,47 @@ impl Lua {
         }
     }
 
+    /// Returns the source chunk name and the current line of the Lua function that called the
+    /// currently running callback.
+    ///
+    /// Returns `None` if there is no caller (e.g. when used outside of a callback) or if the caller
+    /// has no line information, such as a C function.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use mlua::{Lua, Result};
+    /// # fn main() -> Result<()> {
+    /// # let lua = Lua::new();
+    /// let log = lua.create_function(|lua, msg: String| {
+    ///     if let Some((source, line)) = lua.caller_source() {
+    ///         println!("{}:{}: {}", source, line, msg);
+    ///     }
+    ///     Ok(())
+    /// })?;
+    /// lua.globals().set("log", log)?;
+    ///
+    /// lua.load(r#"log("hello")"#).set_name("example")?.exec()?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn caller_source(&self) -> Option<(StdString, u32)> {
+        unsafe {
+            let mut ar: ffi::lua_Debug = mem::zeroed();
+            if ffi::lua_getstack(self.state, 1, &mut ar) == 0 {
+                return None;
+            }
+            if ffi::lua_getinfo(self.state, cstr!("Sl"), &mut ar) == 0 {
+                return None;
+            }
+            if ar.currentline < 0 || ar.source.is_null() {
+                return None;
+            }
+            let source = CStr::from_ptr(ar.source).to_string_lossy().into_owned();
+            Some((source, ar.currentline as u32))
+        }
+    }
+
     /// Calls the given function with a `Scope` parameter, giving the function the ability to create
     /// userdata and callbacks from rust types that are !Send or non-'static.
     ///
@@ -645,34 +2392,179 @@ impl Lua {
         t.to_lua(self)
     }
 
-    /// Converts a `Value` instance into a value that implements `FromLua`.
-    pub fn unpack<'lua, T: FromLua<'lua>>(&'lua self, value: Value<'lua>) -> Result<T> {
+    /// Converts a `Value` instance into a value that implements `FromLua`.
+    pub fn unpack<'lua, T: FromLua<'lua>>(&'lua self, value: Value<'lua>) -> Result<T> {
+        T::from_lua(value, self)
+    }
+
+    /// Converts a value that implements `ToLuaMulti` into a `MultiValue` instance.
+    pub fn pack_multi<'lua, T: ToLuaMulti<'lua>>(&'lua self, t: T) -> Result<MultiValue<'lua>> {
+        t.to_lua_multi(self)
+    }
+
+    /// Converts a `MultiValue` instance into a value that implements `FromLuaMulti`.
+    pub fn unpack_multi<'lua, T: FromLuaMulti<'lua>>(
+        &'lua self,
+        value: MultiValue<'lua>,
+    ) -> Result<T> {
+        T::from_lua_multi(value, self)
+    }
+
+    /// Converts every value of `values` into `T`.
+    ///
+    /// Useful for results of variable arity, such as those of [`Function::call_multi`]. If a value
+    /// can not be converted, the conversion error message includes its index in `values`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use mlua::{Function, Lua, Result};
+    /// # fn main() -> Result<()> {
+    /// # let lua = Lua::new();
+    /// let f: Function = lua.load("function() return 1, 2, 3 end").eval()?;
+    /// let values: Vec<i64> = lua.convert_all(f.call_multi(())?)?;
+    /// assert_eq!(values, vec![1, 2, 3]);
+    /// # Ok(())
+    /// # }
+    /// ```
+    ///
+    /// [`Function::call_multi`]: struct.Function.html#method.call_multi
+    pub fn convert_all<'lua, T: FromLua<'lua>>(
+        &'lua self,
+        values: Vec<Value<'lua>>,
+    ) -> Result<Vec<T>> {
+        values
+            .into_iter()
+            .enumerate()
+            .map(|(i, value)| T::from_lua(value, self).map_err(|err| conversion_at_index(err, i)))
+            .collect()
+    }
+
+    /// Converts every element of `values` into a `Value`.
+    ///
+    /// This is the inverse of [`convert_all`]. If an element can not be converted, the conversion
+    /// error message includes its index in `values`.
+    ///
+    /// [`convert_all`]: #method.convert_all
+    pub fn to_values<'lua, T: ToLua<'lua>>(&'lua self, values: Vec<T>) -> Result<Vec<Value<'lua>>> {
+        values
+            .into_iter()
+            .enumerate()
+            .map(|(i, value)| {
+                value
+                    .to_lua(self)
+                    .map_err(|err| conversion_at_index(err, i))
+            })
+            .collect()
+    }
+
+    /// Set a value in the Lua registry based on a string name.
+    ///
+    /// This value will be available to rust from all `Lua` instances which share the same main
+    /// state.
+    ///
+    /// The name is used as is, so it shares the registry with Lua itself (such as `_LOADED`) and
+    /// with C modules. Use [`set_namespaced_registry_value`] for names which must not collide with
+    /// them.
+    ///
+    /// [`set_namespaced_registry_value`]: #method.set_namespaced_registry_value
+    pub fn set_named_registry_value<'lua, S, T>(&'lua self, name: &S, t: T) -> Result<()>
+    where
+        S: ?Sized + AsRef<[u8]>,
+        T: ToLua<'lua>,
+    {
+        self.set_raw_registry_value(name.as_ref(), t.to_lua(self)?)
+    }
+
+    /// Get a value from the Lua registry based on a string name.
+    ///
+    /// Any Lua instance which shares the underlying main state may call this method to
+    /// get a value previously set by [`set_named_registry_value`].
+    ///
+    /// [`set_named_registry_value`]: #method.set_named_registry_value
+    pub fn named_registry_value<'lua, S, T>(&'lua self, name: &S) -> Result<T>
+    where
+        S: ?Sized + AsRef<[u8]>,
+        T: FromLua<'lua>,
+    {
+        let value = self.raw_registry_value(name.as_ref())?;
+        T::from_lua(value, self)
+    }
+
+    /// Removes a named value in the Lua registry.
+    ///
+    /// Equivalent to calling [`set_named_registry_value`] with a value of Nil.
+    ///
+    /// [`set_named_registry_value`]: #method.set_named_registry_value
+    pub fn unset_named_registry_value<'lua, S>(&'lua self, name: &S) -> Result<()>
+    where
+        S: ?Sized + AsRef<[u8]>,
+    {
+        self.set_named_registry_value(name, Nil)
+    }
+
+    /// Set a value in the Lua registry based on a string name, like [`set_named_registry_value`],
+    /// but in a namespace of its own.
+    ///
+    /// The name is stored under the [`NAMESPACED_REGISTRY_PREFIX`], so it can never collide with
+    /// keys used by Lua itself (such as `_LOADED`), by C modules, or by this crate: the crate keeps
+    /// its internal state under light userdata keys, which can't be produced from a string.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use mlua::{Lua, Result};
+    /// # fn main() -> Result<()> {
+    /// let lua = Lua::new();
+    /// lua.set_namespaced_registry_value("_LOADED", 42)?;
+    /// assert_eq!(lua.namespaced_registry_value::<_, i32>("_LOADED")?, 42);
+    ///
+    /// // Lua's own `_LOADED` table is untouched
+    /// assert!(lua.load("require('string') == string").eval::<bool>()?);
+    /// # Ok(())
+    /// # }
+    /// ```
+    ///
+    /// [`set_named_registry_value`]: #method.set_named_registry_value
+    /// [`NAMESPACED_REGISTRY_PREFIX`]: constant.NAMESPACED_REGISTRY_PREFIX.html
+    pub fn set_namespaced_registry_value<'lua, S, T>(&'lua self, name: &S, t: T) -> Result<()>
+    where
+        S: ?Sized + AsRef<[u8]>,
+        T: ToLua<'lua>,
+    {
+        self.set_raw_registry_value(&namespaced_registry_key(name.as_ref()), t.to_lua(self)?)
+    }
+
+    /// Get a value from the Lua registry previously set by [`set_namespaced_registry_value`].
+    ///
+    /// [`set_namespaced_registry_value`]: #method.set_namespaced_registry_value
+    pub fn namespaced_registry_value<'lua, S, T>(&'lua self, name: &S) -> Result<T>
+    where
+        S: ?Sized + AsRef<[u8]>,
+        T: FromLua<'lua>,
+    {
+        let value = self.raw_registry_value(&namespaced_registry_key(name.as_ref()))?;
         T::from_lua(value, self)
     }
 
-    /// Converts a value that implements `ToLuaMulti` into a `MultiValue` instance.
-    pub fn pack_multi<'lua, T: ToLuaMulti<'lua>>(&'lua self, t: T) -> Result<MultiValue<'lua>> {
-        t.to_lua_multi(self)
-    }
-
-    /// Converts a `MultiValue` instance into a value that implements `FromLuaMulti`.
-    pub fn unpack_multi<'lua, T: FromLuaMulti<'lua>>(
-        &'lua self,
-        value: MultiValue<'lua>,
-    ) -> Result<T> {
-        T::from_lua_multi(value, self)
-    }
-
-    /// Set a value in the Lua registry based on a string name.
+    /// Removes a value set by [`set_namespaced_registry_value`] from the Lua registry.
     ///
-    /// This value will be available to rust from all `Lua` instances which share the same main
-    /// state.
-    pub fn set_named_registry_value<'lua, S, T>(&'lua self, name: &S, t: T) -> Result<()>
+    /// Equivalent to calling [`set_namespaced_registry_value`] with a value of Nil.
+    ///
+    /// [`set_namespaced_registry_value`]: #method.set_namespaced_registry_value
+    pub fn unset_namespaced_registry_value<S>(&self, name: &S) -> Result<()>
     where
         S: ?Sized + AsRef<[u8]>,
-        T: ToLua<'lua>,
     {
-        let t = t.to_lua(self)?;
+        self.set_namespaced_registry_value(name, Nil)
+    }
+
+    // Sets a registry value under the exact string key `name`.
+    pub(crate) fn set_raw_registry_value<'lua>(
+        &'lua self,
+        name: &[u8],
+        t: Value<'lua>,
+    ) -> Result<()> {
         unsafe {
             let _sg = StackGuard::new(self.state);
             assert_stack(self.state, 5);
@@ -688,18 +2580,10 @@ impl Lua {
         }
     }
 
-    /// Get a value from the Lua registry based on a string name.
-    ///
-    /// Any Lua instance which shares the underlying main state may call this method to
-    /// get a value previously set by [`set_named_registry_value`].
-    ///
-    /// [`set_named_registry_value`]: #method.set_named_registry_value
-    pub fn named_registry_value<'lua, S, T>(&'lua self, name: &S) -> Result<T>
-    where
-        S: ?Sized + AsRef<[u8]>,
-        T: FromLua<'lua>,
-    {
-        let value = unsafe {
+    // Gets the registry value stored under the exact string key `name`, such as Lua's own
+    // `_LOADED` table.
+    pub(crate) fn raw_registry_value<'lua>(&'lua self, name: &[u8]) -> Result<Value<'lua>> {
+        unsafe {
             let _sg = StackGuard::new(self.state);
             assert_stack(self.state, 4);
 
@@ -710,21 +2594,62 @@ impl Lua {
             }
             protect_lua(self.state, 1, get_registry)?;
 
-            self.pop_value()
-        };
-        T::from_lua(value, self)
+            Ok(self.pop_value())
+        }
     }
 
-    /// Removes a named value in the Lua registry.
+    /// Creates an [`Error`] that raises `value` as-is when returned from a Rust callback.
     ///
-    /// Equivalent to calling [`set_named_registry_value`] with a value of Nil.
+    /// This allows Rust functions to raise structured errors (tables, userdata) which Lua code can
+    /// inspect after catching them with `pcall`. The returned error is
+    /// [`Error::RuntimeErrorValue`], the message is a description of the value.
     ///
-    /// [`set_named_registry_value`]: #method.set_named_registry_value
-    pub fn unset_named_registry_value<'lua, S>(&'lua self, name: &S) -> Result<()>
-    where
-        S: ?Sized + AsRef<[u8]>,
-    {
-        self.set_named_registry_value(name, Nil)
+    /// # Examples
+    ///
+    /// ```
+    /// # use mlua::{Lua, Result};
+    /// # fn main() -> Result<()> {
+    /// # let lua = Lua::new();
+    /// let fail = lua.create_function(|lua, ()| -> Result<()> {
+    ///     let err = lua.create_table()?;
+    ///     err.set("code", 404)?;
+    ///     Err(lua.create_error_object(err)?)
+    /// })?;
+    /// lua.globals().set("fail", fail)?;
+    ///
+    /// let code: i64 = lua.load(r#"
+    ///     local ok, err = pcall(fail)
+    ///     return err.code
+    /// "#).eval()?;
+    /// assert_eq!(code, 404);
+    /// # Ok(())
+    /// # }
+    /// ```
+    ///
+    /// [`Error`]: enum.Error.html
+    /// [`Error::RuntimeErrorValue`]: enum.Error.html#variant.RuntimeErrorValue
+    pub fn create_error_object<'lua, T: ToLua<'lua>>(&'lua self, value: T) -> Result<Error> {
+        let value = value.to_lua(self)?;
+        unsafe {
+            let _sg = StackGuard::new(self.state);
+            assert_stack(self.state, 2);
+
+            self.push_value(value)?;
+            let message = to_string(self.state, -1).into_owned();
+            let unref_list = self.extra.borrow().registry_unref_list.clone();
+            expire_registry_values(self.state, &unref_list);
+            let registry_id = protect_lua_closure(self.state, 1, 0, |state| {
+                ffi::luaL_ref(state, ffi::LUA_REGISTRYINDEX)
+            })?;
+
+            Ok(Error::RuntimeErrorValue {
+                message,
+                value: Arc::new(RegistryKey {
+                    registry_id,
+                    unref_list,
+                }),
+            })
+        }
     }
 
     /// Place a value in the Lua registry with an auto-generated key.
@@ -816,19 +2741,67 @@ impl Lua {
     /// by `Lua::remove_registry_value`.
     pub fn expire_registry_values(&self) {
         unsafe {
-            let unref_list = mem::replace(
-                &mut *mlua_expect!(
-                    self.extra.borrow().registry_unref_list.lock(),
-                    "unref list poisoned"
-                ),
-                Some(Vec::new()),
-            );
-            for id in mlua_expect!(unref_list, "unref list not set") {
-                ffi::luaL_unref(self.state, ffi::LUA_REGISTRYINDEX, id);
+            let unref_list = self.extra.borrow().registry_unref_list.clone();
+            expire_registry_values(self.state, &unref_list);
+        }
+    }
+
+    /// Returns the number of occupied reference slots in the Lua registry.
+    ///
+    /// This counts the values stored with [`create_registry_value`], as well as slots used
+    /// internally by Lua and mlua, such as userdata metatables. Values whose `RegistryKey` was
+    /// dropped keep their slot until [`expire_registry_values`] is called.
+    ///
+    /// This is intended for tests which check that code does not leak registry values, by
+    /// comparing the count before and after running it.
+    ///
+    /// [`create_registry_value`]: #method.create_registry_value
+    /// [`expire_registry_values`]: #method.expire_registry_values
+    pub fn registry_slot_count(&self) -> usize {
+        unsafe {
+            let _sg = StackGuard::new(self.state);
+            assert_stack(self.state, 3);
+
+            let mut count = 0;
+            ffi::lua_pushnil(self.state);
+            while ffi::lua_next(self.state, ffi::LUA_REGISTRYINDEX) != 0 {
+                ffi::lua_pop(self.state, 1);
+                if ffi::lua_type(self.state, -1) == ffi::LUA_TNUMBER
+                    && ffi::lua_tointeger(self.state, -1) > 0
+                {
+                    count += 1;
+                }
             }
+
+            // Freed slots form a list starting at index 0, each holding the next free index. The last
+            // one holds `nil` and so was not counted.
+            ffi::lua_rawgeti(self.state, ffi::LUA_REGISTRYINDEX, 0);
+            let mut free = ffi::lua_tointeger(self.state, -1);
+            ffi::lua_pop(self.state, 1);
+            while free > 0 {
+                ffi::lua_rawgeti(self.state, ffi::LUA_REGISTRYINDEX, free);
+                if ffi::lua_isnil(self.state, -1) != 0 {
+                    break;
+                }
+                count -= 1;
+                free = ffi::lua_tointeger(self.state, -1);
+                ffi::lua_pop(self.state, 1);
+            }
+
+            count
         }
     }
 
+    /// Returns the number of live handles to Lua values, such as `Table` or `Function`.
+    ///
+    /// Every handle holds a reference to its value, which is released when the handle is dropped.
+    /// This is intended for tests which check that code does not leak handles, by comparing the
+    /// count before and after running it.
+    pub fn active_ref_count(&self) -> usize {
+        let extra = self.extra.borrow();
+        extra.ref_stack_max as usize - extra.ref_free.len()
+    }
+
     // Uses 2 stack spaces, does not call checkstack
     pub(crate) unsafe fn push_value(&self, value: Value) -> Result<()> {
         match value {
@@ -853,23 +2826,23 @@ impl Lua {
             }
 
             Value::String(s) => {
-                self.push_ref(&s.0);
+                self.push_ref_checked(&s.0)?;
             }
 
             Value::Table(t) => {
-                self.push_ref(&t.0);
+                self.push_ref_checked(&t.0)?;
             }
 
             Value::Function(f) => {
-                self.push_ref(&f.0);
+                self.push_ref_checked(&f.0)?;
             }
 
             Value::Thread(t) => {
-                self.push_ref(&t.0);
+                self.push_ref_checked(&t.0)?;
             }
 
             Value::UserData(ud) => {
-                self.push_ref(&ud.0);
+                self.push_ref_checked(&ud.0)?;
             }
 
             Value::Error(e) => {
@@ -947,6 +2920,16 @@ impl Lua {
         ffi::lua_xmove(extra.ref_thread, self.state, 1);
     }
 
+    // Like `push_ref`, but returns `MismatchedLuaState` instead of panicking if the reference was
+    // created by a different main Lua state. Used for values coming from user code.
+    pub(crate) unsafe fn push_ref_checked<'lua>(&'lua self, lref: &LuaRef<'lua>) -> Result<()> {
+        if lref.lua.main_state != self.main_state {
+            return Err(Error::MismatchedLuaState);
+        }
+        self.push_ref(lref);
+        Ok(())
+    }
+
     // Pops the topmost element of the stack and stores a reference to it.  This pins the object,
     // preventing garbage collection until the returned `LuaRef` is dropped.
     //
@@ -982,21 +2965,31 @@ impl Lua {
     }
 
     pub(crate) unsafe fn userdata_metatable<T: 'static + UserData>(&self) -> Result<c_int> {
-        if let Some(table_id) = self
-            .extra
+        if let Some(table_id) = self.registered_userdata_metatable::<T>() {
+            return Ok(table_id);
+        }
+
+        let mut methods = StaticUserDataMethods::default();
+        T::add_methods(&mut methods);
+        self.register_userdata_metatable::<T>(methods, T::name())
+    }
+
+    pub(crate) fn registered_userdata_metatable<T: 'static>(&self) -> Option<c_int> {
+        self.extra
             .borrow()
             .registered_userdata
             .get(&TypeId::of::<T>())
-        {
-            return Ok(*table_id);
-        }
+            .cloned()
+    }
 
+    unsafe fn register_userdata_metatable<'lua, T: 'static>(
+        &'lua self,
+        methods: StaticUserDataMethods<'lua, T>,
+        name: Option<&str>,
+    ) -> Result<c_int> {
         let _sg = StackGuard::new(self.state);
         assert_stack(self.state, 8);
 
-        let mut methods = StaticUserDataMethods::default();
-        T::add_methods(&mut methods);
-
         protect_lua_closure(self.state, 0, 1, |state| {
             ffi::lua_newtable(state);
         })?;
@@ -1009,8 +3002,19 @@ impl Lua {
             })?;
         }
 
-        if methods.methods.is_empty() {
-            init_userdata_metatable::<RefCell<T>>(self.state, -1, None)?;
+        // With `HandlerFirst`, the methods table is always created so that methods added later keep
+        // the priority
+        if methods.methods.is_empty()
+            && methods.index_table.is_none()
+            && methods.index_priority == IndexPriority::MethodsFirst
+        {
+            init_userdata_metatable::<RefCell<T>>(
+                self.state,
+                -1,
+                None,
+                methods.index_priority,
+                name,
+            )?;
         } else {
             protect_lua_closure(self.state, 0, 1, |state| {
                 ffi::lua_newtable(state);
@@ -1022,11 +3026,27 @@ impl Lua {
                     ffi::lua_rawset(state, -3);
                 })?;
             }
+            if let Some(index_table) = methods.index_table {
+                self.set_methods_index_table(index_table(self)?)?;
+            }
 
-            init_userdata_metatable::<RefCell<T>>(self.state, -2, Some(-1))?;
+            init_userdata_metatable::<RefCell<T>>(
+                self.state,
+                -2,
+                Some(-1),
+                methods.index_priority,
+                name,
+            )?;
+            if methods.method_cache {
+                enable_userdata_method_cache(self.state, -2)?;
+            }
             ffi::lua_pop(self.state, 1);
         }
 
+        if let Some(protection) = methods.metatable_protection {
+            self.set_metatable_protection(protection(self)?)?;
+        }
+
         let id = protect_lua_closure(self.state, 1, 0, |state| {
             ffi::luaL_ref(state, ffi::LUA_REGISTRYINDEX)
         })?;
@@ -1039,6 +3059,42 @@ impl Lua {
         Ok(id)
     }
 
+    // Sets the `__metatable` field of the userdata metatable at the top of the stack, unless
+    // `value` is nil which keeps the default protection.
+    // Uses 4 stack spaces, does not call checkstack
+    pub(crate) unsafe fn set_metatable_protection<'lua>(
+        &'lua self,
+        value: Value<'lua>,
+    ) -> Result<()> {
+        if let Nil = value {
+            return Ok(());
+        }
+        push_string(self.state, "__metatable")?;
+        self.push_value(value)?;
+        protect_lua_closure(self.state, 3, 1, |state| {
+            ffi::lua_rawset(state, -3);
+        })
+    }
+
+    // Sets the metatable of the methods table at the top of the stack so that missing methods are
+    // looked up in the given table.
+    // Uses 3 stack spaces, does not call checkstack
+    pub(crate) unsafe fn set_methods_index_table<'lua>(
+        &'lua self,
+        index_table: Table<'lua>,
+    ) -> Result<()> {
+        protect_lua_closure(self.state, 0, 1, |state| {
+            ffi::lua_createtable(state, 0, 1);
+        })?;
+        push_string(self.state, "__index")?;
+        self.push_ref(&index_table.0);
+        protect_lua_closure(self.state, 3, 1, |state| {
+            ffi::lua_rawset(state, -3);
+        })?;
+        ffi::lua_setmetatable(self.state, -2);
+        Ok(())
+    }
+
     // Creates a Function out of a Callback containing a 'static Fn.  This is safe ONLY because the
     // Fn is 'static, otherwise it could capture 'callback arguments improperly.  Without ATCs, we
     // cannot easily deal with the "correct" callback type of:
@@ -1083,7 +3139,8 @@ impl Lua {
 
                 let func = get_userdata::<Callback>(state, ffi::lua_upvalueindex(1));
 
-                let results = (*func)(&lua, args)?;
+                let _depth_guard = CallDepthGuard::new(&lua.extra)?;
+                let results = (*func)(&lua, args).map_err(|err| name_bad_argument(state, err))?;
                 let nresults = results.len() as c_int;
 
                 check_stack(state, nresults)?;
@@ -1128,10 +3185,18 @@ impl Lua {
     where
         T: 'static + UserData,
     {
+        let ud_index = self.userdata_metatable::<T>()?;
+        self.make_userdata_with_metatable(data, ud_index)
+    }
+
+    unsafe fn make_userdata_with_metatable<'lua, T: 'static>(
+        &'lua self,
+        data: T,
+        ud_index: c_int,
+    ) -> Result<AnyUserData<'lua>> {
         let _sg = StackGuard::new(self.state);
         assert_stack(self.state, 4);
 
-        let ud_index = self.userdata_metatable::<T>()?;
         push_userdata::<RefCell<T>>(self.state, RefCell::new(data))?;
 
         ffi::lua_rawgeti(
@@ -1154,6 +3219,31 @@ pub struct Chunk<'lua, 'a> {
     source: &'a [u8],
     name: Option<CString>,
     env: Option<Value<'lua>>,
+    mode: ChunkMode,
+}
+
+/// Kind of chunks accepted by [`Chunk::set_mode`].
+///
+/// [`Chunk::set_mode`]: struct.Chunk.html#method.set_mode
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Default)]
+pub enum ChunkMode {
+    /// Only Lua source code is accepted.
+    #[default]
+    Text,
+    /// Only precompiled bytecode, as produced by `string.dump` or `luac`, is accepted.
+    Binary,
+    /// Both source code and bytecode are accepted.
+    TextOrBinary,
+}
+
+impl ChunkMode {
+    fn as_cstr(self) -> *const c_char {
+        match self {
+            ChunkMode::Text => cstr!("t"),
+            ChunkMode::Binary => cstr!("b"),
+            ChunkMode::TextOrBinary => cstr!("bt"),
+        }
+    }
 }
 
 impl<'lua, 'a> Chunk<'lua, 'a> {
@@ -1185,6 +3275,34 @@ impl<'lua, 'a> Chunk<'lua, 'a> {
         Ok(self)
     }
 
+    /// Sets whether the chunk may be source code, precompiled bytecode or either.
+    ///
+    /// Defaults to [`ChunkMode::Text`]. Loading a chunk of the wrong kind fails with a syntax
+    /// error, so a loader restricted to source code can not be fed bytecode, which Lua does not
+    /// verify and which can crash the process when malformed.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use mlua::{ChunkMode, Lua, Result, String};
+    /// # fn main() -> Result<()> {
+    /// # let lua = Lua::new();
+    /// let bytecode: String = lua.load("string.dump(function() return 42 end)").eval()?;
+    /// let bytecode = bytecode.as_bytes();
+    /// assert!(lua.load(bytecode).exec().is_err());
+    ///
+    /// let answer: i64 = lua.load(bytecode).set_mode(ChunkMode::Binary).call(())?;
+    /// assert_eq!(answer, 42);
+    /// # Ok(())
+    /// # }
+    /// ```
+    ///
+    /// [`ChunkMode::Text`]: enum.ChunkMode.html#variant.Text
+    pub fn set_mode(mut self, mode: ChunkMode) -> Chunk<'lua, 'a> {
+        self.mode = mode;
+        self
+    }
+
     /// Execute this chunk of code.
     ///
     /// This is equivalent to calling the chunk function with no arguments and no return values.
@@ -1204,10 +3322,12 @@ impl<'lua, 'a> Chunk<'lua, 'a> {
         // actual lua repl does.
         let mut expression_source = b"return ".to_vec();
         expression_source.extend(self.source);
-        if let Ok(function) =
-            self.lua
-                .load_chunk(&expression_source, self.name.as_ref(), self.env.clone())
-        {
+        if let Ok(function) = self.lua.load_chunk(
+            &expression_source,
+            self.name.as_ref(),
+            self.env.clone(),
+            self.mode,
+        ) {
             function.call(())
         } else {
             self.call(())
@@ -1223,10 +3343,25 @@ impl<'lua, 'a> Chunk<'lua, 'a> {
 
     /// Load this chunk into a regular `Function`.
     ///
-    /// This simply compiles the chunk without actually executing it.
+    /// This simply compiles the chunk without actually executing it. The returned function can be
+    /// called any number of times without recompiling the source.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use mlua::{Lua, Result};
+    /// # fn main() -> Result<()> {
+    /// # let lua = Lua::new();
+    /// let greet = lua.load(r#"return "Hello, " .. ...  .. "!""#).into_function()?;
+    ///
+    /// assert_eq!(greet.call::<_, String>("world")?, "Hello, world!");
+    /// assert_eq!(greet.call::<_, String>("Lua")?, "Hello, Lua!");
+    /// # Ok(())
+    /// # }
+    /// ```
     pub fn into_function(self) -> Result<Function<'lua>> {
         self.lua
-            .load_chunk(self.source, self.name.as_ref(), self.env)
+            .load_chunk(self.source, self.name.as_ref(), self.env, self.mode)
     }
 }
 
@@ -1325,6 +3460,105 @@ unsafe fn load_from_std_lib(state: *mut ffi::lua_State, libs: StdLib) {
     }
 }
 
+// Adds the index of the failed element to a conversion error of `convert_all` or `to_values`.
+fn conversion_at_index(err: Error, index: usize) -> Error {
+    let with_index = |message: Option<StdString>| match message {
+        Some(message) => Some(format!("at index {}: {}", index, message)),
+        None => Some(format!("at index {}", index)),
+    };
+    match err {
+        Error::FromLuaConversionError { from, to, message } => Error::FromLuaConversionError {
+            from,
+            to,
+            message: with_index(message),
+        },
+        Error::ToLuaConversionError { from, to, message } => Error::ToLuaConversionError {
+            from,
+            to,
+            message: with_index(message),
+        },
+        err => err,
+    }
+}
+
+// Counts a running Rust callback in `ExtraData::call_depth` for as long as it is alive.
+struct CallDepthGuard(Arc<RefCell<ExtraData>>);
+
+impl CallDepthGuard {
+    fn new(extra: &Arc<RefCell<ExtraData>>) -> Result<CallDepthGuard> {
+        let mut data = extra.borrow_mut();
+        if data.call_depth_limit != 0 && data.call_depth >= data.call_depth_limit {
+            return Err(Error::RecursionLimitReached);
+        }
+        data.call_depth += 1;
+        Ok(CallDepthGuard(extra.clone()))
+    }
+}
+
+impl Drop for CallDepthGuard {
+    fn drop(&mut self) {
+        self.0.borrow_mut().call_depth -= 1;
+    }
+}
+
+// Builds the registry key used for a user name in the namespaced registry value methods.
+fn namespaced_registry_key(name: &[u8]) -> Vec<u8> {
+    let mut key = Vec::with_capacity(NAMESPACED_REGISTRY_PREFIX.len() + name.len());
+    key.extend_from_slice(NAMESPACED_REGISTRY_PREFIX.as_bytes());
+    key.extend_from_slice(name);
+    key
+}
+
+// Minimum alignment guaranteed by the system allocator, which Lua relies on.
+#[cfg(not(feature = "luajit"))]
+const SYS_MIN_ALIGN: usize = mem::size_of::<usize>() * 2;
+
+#[cfg(not(feature = "luajit"))]
+unsafe extern "C" fn allocator(
+    extra_data: *mut c_void,
+    ptr: *mut c_void,
+    osize: usize,
+    nsize: usize,
+) -> *mut c_void {
+    let mem_info = &mut *(extra_data as *mut MemoryInfo);
+
+    // When `ptr` is NULL, `osize` encodes the kind of object being allocated
+    let osize = if ptr.is_null() { 0 } else { osize };
+
+    if nsize == 0 {
+        // Free memory
+        if !ptr.is_null() {
+            let layout = Layout::from_size_align_unchecked(osize, SYS_MIN_ALIGN);
+            alloc::dealloc(ptr as *mut u8, layout);
+            mem_info.used_memory -= osize as isize;
+        }
+        return ptr::null_mut();
+    }
+
+    // Do not allocate more than the memory limit, shrinking is always allowed
+    let mem_diff = nsize as isize - osize as isize;
+    let new_used_memory = mem_info.used_memory + mem_diff;
+    if mem_diff > 0 && mem_info.memory_limit > 0 && new_used_memory > mem_info.memory_limit {
+        return ptr::null_mut();
+    }
+
+    let new_layout = match Layout::from_size_align(nsize, SYS_MIN_ALIGN) {
+        Ok(layout) => layout,
+        Err(_) => return ptr::null_mut(),
+    };
+    let new_ptr = if ptr.is_null() {
+        alloc::alloc(new_layout)
+    } else {
+        let old_layout = Layout::from_size_align_unchecked(osize, SYS_MIN_ALIGN);
+        alloc::realloc(ptr as *mut u8, old_layout, nsize)
+    };
+
+    if !new_ptr.is_null() {
+        mem_info.used_memory = new_used_memory;
+    }
+    new_ptr as *mut c_void
+}
+
 unsafe fn ref_stack_pop(extra: &mut ExtraData) -> c_int {
     if let Some(free) = extra.ref_free.pop() {
         ffi::lua_replace(extra.ref_thread, free);
@@ -1343,26 +3577,155 @@ unsafe fn ref_stack_pop(extra: &mut ExtraData) -> c_int {
     }
 }
 
+// Replacement for the `load` and `loadstring` functions in a sandbox, which only accepts source
+// code. The arguments are the same as for `load` in Lua 5.3, except that `mode` is ignored.
+fn load_text<'lua>(
+    lua: &'lua Lua,
+    args: MultiValue<'lua>,
+) -> Result<(Option<Function<'lua>>, Option<StdString>)> {
+    let has_env = args.len() >= 4;
+    let (chunk, name, _, env) = lua.unpack_multi::<(Value, Option<String>, Value, Value)>(args)?;
+    let source = match chunk {
+        Value::String(s) => s.as_bytes().to_vec(),
+        Value::Function(reader) => {
+            let mut source = Vec::new();
+            while let Some(piece) = reader.call::<_, Option<String>>(())? {
+                if piece.as_bytes().is_empty() {
+                    break;
+                }
+                source.extend_from_slice(piece.as_bytes());
+            }
+            source
+        }
+        value => {
+            return Err(Error::FromLuaConversionError {
+                from: value.type_name(),
+                to: "chunk",
+                message: Some("expected string or function".to_string()),
+            })
+        }
+    };
+
+    let mut chunk = lua.load(&source).set_mode(ChunkMode::Text);
+    chunk = match name {
+        Some(name) => chunk.set_name(name.as_bytes())?,
+        None => chunk.set_name("=(load)")?,
+    };
+    if has_env {
+        chunk = chunk.set_environment(env)?;
+    }
+    match chunk.into_function() {
+        Ok(function) => Ok((Some(function), None)),
+        Err(Error::SyntaxError { message, .. }) => Ok((None, Some(message))),
+        Err(err) => Err(err),
+    }
+}
+
+// Pops the error value on top of the stack and places it in the registry.
+// Returns `None` (and leaves the stack untouched) if the value could not be stored.
+// Uses 2 stack spaces, does not call checkstack
+pub(crate) unsafe fn pop_error_value(state: *mut ffi::lua_State) -> Option<RegistryKey> {
+    ffi::lua_pushlightuserdata(state, &EXTRA_REGISTRY_KEY as *const u8 as *mut c_void);
+    if ffi::lua_rawget(state, ffi::LUA_REGISTRYINDEX) != ffi::LUA_TUSERDATA {
+        ffi::lua_pop(state, 1);
+        return None;
+    }
+    let extra = get_userdata::<Arc<RefCell<ExtraData>>>(state, -1);
+    let unref_list = match (*extra).try_borrow() {
+        Ok(extra) => extra.registry_unref_list.clone(),
+        Err(_) => {
+            ffi::lua_pop(state, 1);
+            return None;
+        }
+    };
+    ffi::lua_pop(state, 1);
+
+    // Errors are easily created in a loop and dropped without a thought, so free the slots of
+    // the ones already dropped instead of waiting for `Lua::expire_registry_values`.
+    expire_registry_values(state, &unref_list);
+    let registry_id = protect_lua_closure(state, 1, 0, |state| {
+        ffi::luaL_ref(state, ffi::LUA_REGISTRYINDEX)
+    })
+    .ok()?;
+    Some(RegistryKey {
+        registry_id,
+        unref_list,
+    })
+}
+
+// Frees the registry slots of every `RegistryKey` dropped so far.
+// Uses 1 stack space, does not call checkstack
+unsafe fn expire_registry_values(
+    state: *mut ffi::lua_State,
+    unref_list: &Mutex<Option<Vec<c_int>>>,
+) {
+    let unref_list = mem::replace(
+        &mut *mlua_expect!(unref_list.lock(), "unref list poisoned"),
+        Some(Vec::new()),
+    );
+    for id in mlua_expect!(unref_list, "unref list not set") {
+        ffi::luaL_unref(state, ffi::LUA_REGISTRYINDEX, id);
+    }
+}
+
+// Pushes the error value stored in the registry onto the stack, if the key belongs to this state.
+// Uses 2 stack spaces, does not call checkstack
+pub(crate) unsafe fn push_error_value(state: *mut ffi::lua_State, key: &RegistryKey) -> bool {
+    ffi::lua_pushlightuserdata(state, &EXTRA_REGISTRY_KEY as *const u8 as *mut c_void);
+    if ffi::lua_rawget(state, ffi::LUA_REGISTRYINDEX) != ffi::LUA_TUSERDATA {
+        ffi::lua_pop(state, 1);
+        return false;
+    }
+    let extra = get_userdata::<Arc<RefCell<ExtraData>>>(state, -1);
+    let owned = match (*extra).try_borrow() {
+        Ok(extra) => Arc::ptr_eq(&key.unref_list, &extra.registry_unref_list),
+        Err(_) => false,
+    };
+    ffi::lua_pop(state, 1);
+
+    if owned {
+        ffi::lua_rawgeti(
+            state,
+            ffi::LUA_REGISTRYINDEX,
+            key.registry_id as ffi::lua_Integer,
+        );
+    }
+    owned
+}
+
 static FUNCTION_CALLBACK_METATABLE_REGISTRY_KEY: u8 = 0;
 static FUNCTION_EXTRA_METATABLE_REGISTRY_KEY: u8 = 0;
+static EXTRA_REGISTRY_KEY: u8 = 0;
+static THREAD_NAMES_REGISTRY_KEY: u8 = 0;
 
-struct StaticUserDataMethods<'lua, T: 'static + UserData> {
+/// Method registry for types registered with [`Lua::register_userdata_with`].
+///
+/// [`Lua::register_userdata_with`]: struct.Lua.html#method.register_userdata_with
+pub struct StaticUserDataMethods<'lua, T: 'static> {
     methods: Vec<(Vec<u8>, Callback<'lua, 'static>)>,
     meta_methods: Vec<(MetaMethod, Callback<'lua, 'static>)>,
+    index_table: Option<IndexTableCallback<'lua>>,
+    index_priority: IndexPriority,
+    method_cache: bool,
+    metatable_protection: Option<MetatableProtectionCallback<'lua>>,
     _type: PhantomData<T>,
 }
 
-impl<'lua, T: 'static + UserData> Default for StaticUserDataMethods<'lua, T> {
+impl<'lua, T: 'static> Default for StaticUserDataMethods<'lua, T> {
     fn default() -> StaticUserDataMethods<'lua, T> {
         StaticUserDataMethods {
             methods: Vec::new(),
             meta_methods: Vec::new(),
+            index_table: None,
+            index_priority: IndexPriority::default(),
+            method_cache: false,
+            metatable_protection: None,
             _type: PhantomData,
         }
     }
 }
 
-impl<'lua, T: 'static + UserData> UserDataMethods<'lua, T> for StaticUserDataMethods<'lua, T> {
+impl<'lua, T: 'static> UserDataMethods<'lua, T> for StaticUserDataMethods<'lua, T> {
     fn add_method<S, A, R, M>(&mut self, name: &S, method: M)
     where
         S: ?Sized + AsRef<[u8]>,
@@ -1413,16 +3776,26 @@ impl<'lua, T: 'static + UserData> UserDataMethods<'lua, T> for StaticUserDataMet
         R: ToLuaMulti<'lua>,
         M: 'static + Send + Fn(&'lua Lua, &T, A) -> Result<R>,
     {
-        self.meta_methods.push((meta, Self::box_method(method)));
+        self.meta_methods.push((
+            meta,
+            Self::box_method(move |lua, data: &T, args: A| {
+                meta.validate_result(lua, method(lua, data, args)?.to_lua_multi(lua)?)
+            }),
+        ));
     }
 
-    fn add_meta_method_mut<A, R, M>(&mut self, meta: MetaMethod, method: M)
+    fn add_meta_method_mut<A, R, M>(&mut self, meta: MetaMethod, mut method: M)
     where
         A: FromLuaMulti<'lua>,
         R: ToLuaMulti<'lua>,
         M: 'static + Send + FnMut(&'lua Lua, &mut T, A) -> Result<R>,
     {
-        self.meta_methods.push((meta, Self::box_method_mut(method)));
+        self.meta_methods.push((
+            meta,
+            Self::box_method_mut(move |lua, data: &mut T, args: A| {
+                meta.validate_result(lua, method(lua, data, args)?.to_lua_multi(lua)?)
+            }),
+        ));
     }
 
     fn add_meta_function<A, R, F>(&mut self, meta: MetaMethod, function: F)
@@ -1431,21 +3804,52 @@ impl<'lua, T: 'static + UserData> UserDataMethods<'lua, T> for StaticUserDataMet
         R: ToLuaMulti<'lua>,
         F: 'static + Send + Fn(&'lua Lua, A) -> Result<R>,
     {
-        self.meta_methods.push((meta, Self::box_function(function)));
+        self.meta_methods.push((
+            meta,
+            Self::box_function(move |lua, args: A| {
+                meta.validate_result(lua, function(lua, args)?.to_lua_multi(lua)?)
+            }),
+        ));
     }
 
-    fn add_meta_function_mut<A, R, F>(&mut self, meta: MetaMethod, function: F)
+    fn add_meta_function_mut<A, R, F>(&mut self, meta: MetaMethod, mut function: F)
     where
         A: FromLuaMulti<'lua>,
         R: ToLuaMulti<'lua>,
         F: 'static + Send + FnMut(&'lua Lua, A) -> Result<R>,
     {
-        self.meta_methods
-            .push((meta, Self::box_function_mut(function)));
+        self.meta_methods.push((
+            meta,
+            Self::box_function_mut(move |lua, args: A| {
+                meta.validate_result(lua, function(lua, args)?.to_lua_multi(lua)?)
+            }),
+        ));
+    }
+
+    fn set_index_table_with<F>(&mut self, f: F)
+    where
+        F: 'lua + FnOnce(&'lua Lua) -> Result<Table<'lua>>,
+    {
+        self.index_table = Some(Box::new(f));
+    }
+
+    fn set_index_priority(&mut self, priority: IndexPriority) {
+        self.index_priority = priority;
+    }
+
+    fn enable_method_cache(&mut self) {
+        self.method_cache = true;
+    }
+
+    fn set_metatable_protection<V>(&mut self, value: V)
+    where
+        V: 'lua + ToLua<'lua>,
+    {
+        self.metatable_protection = Some(Box::new(move |lua| value.to_lua(lua)));
     }
 }
 
-impl<'lua, T: 'static + UserData> StaticUserDataMethods<'lua, T> {
+impl<'lua, T: 'static> StaticUserDataMethods<'lua, T> {
     fn box_method<A, R, M>(method: M) -> Callback<'lua, 'static>
     where
         A: FromLuaMulti<'lua>,
@@ -1456,7 +3860,7 @@ impl<'lua, T: 'static + UserData> StaticUserDataMethods<'lua, T> {
             if let Some(front) = args.pop_front() {
                 let userdata = AnyUserData::from_lua(front, lua)?;
                 let userdata = userdata.borrow::<T>()?;
-                method(lua, &userdata, A::from_lua_multi(args, lua)?)?.to_lua_multi(lua)
+                method(lua, &userdata, A::from_lua_args(args, 2, None, lua)?)?.to_lua_multi(lua)
             } else {
                 Err(Error::FromLuaConversionError {
                     from: "missing argument",
@@ -1481,7 +3885,8 @@ impl<'lua, T: 'static + UserData> StaticUserDataMethods<'lua, T> {
                 let mut method = method
                     .try_borrow_mut()
                     .map_err(|_| Error::RecursiveMutCallback)?;
-                (&mut *method)(lua, &mut userdata, A::from_lua_multi(args, lua)?)?.to_lua_multi(lua)
+                (&mut *method)(lua, &mut userdata, A::from_lua_args(args, 2, None, lua)?)?
+                    .to_lua_multi(lua)
             } else {
                 Err(Error::FromLuaConversionError {
                     from: "missing argument",
@@ -1498,7 +3903,9 @@ impl<'lua, T: 'static + UserData> StaticUserDataMethods<'lua, T> {
         R: ToLuaMulti<'lua>,
         F: 'static + Send + Fn(&'lua Lua, A) -> Result<R>,
     {
-        Box::new(move |lua, args| function(lua, A::from_lua_multi(args, lua)?)?.to_lua_multi(lua))
+        Box::new(move |lua, args| {
+            function(lua, A::from_lua_args(args, 1, None, lua)?)?.to_lua_multi(lua)
+        })
     }
 
     fn box_function_mut<A, R, F>(function: F) -> Callback<'lua, 'static>
@@ -1512,7 +3919,7 @@ impl<'lua, T: 'static + UserData> StaticUserDataMethods<'lua, T> {
             let function = &mut *function
                 .try_borrow_mut()
                 .map_err(|_| Error::RecursiveMutCallback)?;
-            function(lua, A::from_lua_multi(args, lua)?)?.to_lua_multi(lua)
+            function(lua, A::from_lua_args(args, 1, None, lua)?)?.to_lua_multi(lua)
         })
     }
 }
@@ -1,10 +1,12 @@
 use std::iter::FromIterator;
 use std::ops::{Deref, DerefMut};
 use std::result::Result as StdResult;
+use std::sync::Arc;
 
-use crate::error::Result;
+use crate::error::{Error, Result};
 use crate::lua::Lua;
-use crate::value::{FromLua, FromLuaMulti, MultiValue, Nil, ToLua, ToLuaMulti};
+use crate::types::Integer;
+use crate::value::{FromLua, FromLuaMulti, MultiValue, Nil, ToLua, ToLuaMulti, Value};
 
 /// Result is convertible to `MultiValue` following the common Lua idiom of returning the result
 /// on success, or in the case of an error, returning `nil` and an error message.
@@ -36,6 +38,23 @@ impl<'lua, T: FromLua<'lua>> FromLuaMulti<'lua> for T {
     fn from_lua_multi(mut values: MultiValue<'lua>, lua: &'lua Lua) -> Result<Self> {
         Ok(T::from_lua(values.pop_front().unwrap_or(Nil), lua)?)
     }
+
+    fn from_lua_args(
+        mut args: MultiValue<'lua>,
+        i: usize,
+        to: Option<&str>,
+        lua: &'lua Lua,
+    ) -> Result<Self> {
+        T::from_lua(args.pop_front().unwrap_or(Nil), lua).map_err(|err| bad_argument(to, i, err))
+    }
+
+    fn from_lua_multi_with_rest(
+        mut values: MultiValue<'lua>,
+        lua: &'lua Lua,
+    ) -> Result<(Self, MultiValue<'lua>)> {
+        let value = T::from_lua(values.pop_front().unwrap_or(Nil), lua)?;
+        Ok((value, values))
+    }
 }
 
 impl<'lua> ToLuaMulti<'lua> for MultiValue<'lua> {
@@ -135,8 +154,145 @@ impl<'lua, T: FromLua<'lua>> FromLuaMulti<'lua> for Variadic<T> {
             .collect::<Result<Vec<T>>>()
             .map(Variadic)
     }
+
+    fn from_lua_args(
+        args: MultiValue<'lua>,
+        i: usize,
+        to: Option<&str>,
+        lua: &'lua Lua,
+    ) -> Result<Self> {
+        args.into_iter()
+            .enumerate()
+            .map(|(n, e)| T::from_lua(e, lua).map_err(|err| bad_argument(to, i + n, err)))
+            .collect::<Result<Vec<T>>>()
+            .map(Variadic)
+    }
 }
 
+/// Spreads the elements of an array into multiple values.
+///
+/// This is useful for returning a fixed number of values of the same type from a callback, e.g.
+/// `[x, y, z]` is returned as three values. In contrast, a `Vec<T>` is converted with [`ToLua`] to
+/// a single table. Use [`Variadic`] to return a variable number of values.
+///
+/// # Examples
+///
+/// ```
+/// # use mlua::{Lua, Result};
+/// # fn main() -> Result<()> {
+/// # let lua = Lua::new();
+/// let bounds = lua.create_function(|_, ()| Ok([0.0, 0.0, 640.0, 480.0]))?;
+/// lua.globals().set("bounds", bounds)?;
+/// assert_eq!(lua.load("select('#', bounds())").eval::<i64>()?, 4);
+/// # Ok(())
+/// # }
+/// ```
+///
+/// [`ToLua`]: trait.ToLua.html
+/// [`Variadic`]: struct.Variadic.html
+impl<'lua, T: ToLua<'lua>, const N: usize> ToLuaMulti<'lua> for [T; N] {
+    fn to_lua_multi(self, lua: &'lua Lua) -> Result<MultiValue<'lua>> {
+        IntoIterator::into_iter(self)
+            .map(|e| e.to_lua(lua))
+            .collect()
+    }
+}
+
+/// Converts a tuple to and from a single Lua table with positional elements.
+///
+/// A tuple is normally converted to multiple values, `AsTable` instead maps `(A, B, ...)` to the
+/// sequence `{a, b, ...}`. When converting from Lua, the table must have exactly as many elements
+/// as the tuple (as given by the raw length of the table), otherwise a [`FromLuaConversionError`]
+/// with the message `expected N elements, got M` is returned.
+///
+/// # Examples
+///
+/// ```
+/// # use mlua::{AsTable, Lua, Result};
+/// # fn main() -> Result<()> {
+/// # let lua = Lua::new();
+/// let AsTable((host, port)) = lua
+///     .load(r#"{"localhost", 8080}"#)
+///     .eval::<AsTable<(String, u16)>>()?;
+/// assert_eq!(host, "localhost");
+/// assert_eq!(port, 8080);
+///
+/// assert!(lua.load(r#"{"localhost"}"#).eval::<AsTable<(String, u16)>>().is_err());
+/// # Ok(())
+/// # }
+/// ```
+///
+/// [`FromLuaConversionError`]: enum.Error.html#variant.FromLuaConversionError
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub struct AsTable<T>(pub T);
+
+macro_rules! impl_as_table {
+    ($($name:ident)+) => (
+        impl<'lua, $($name,)+> ToLua<'lua> for AsTable<($($name,)+)>
+            where $($name: ToLua<'lua>,)+
+        {
+            #[allow(non_snake_case)]
+            fn to_lua(self, lua: &'lua Lua) -> Result<Value<'lua>> {
+                let ($($name,)+) = self.0;
+                let values = vec![$($name.to_lua(lua)?,)+];
+                Ok(Value::Table(lua.create_sequence_from(values)?))
+            }
+        }
+
+        impl<'lua, $($name,)+> FromLua<'lua> for AsTable<($($name,)+)>
+            where $($name: FromLua<'lua>,)+
+        {
+            #[allow(non_snake_case)]
+            fn from_lua(value: Value<'lua>, _: &'lua Lua) -> Result<Self> {
+                let expected = [$(stringify!($name),)+].len();
+                let table = match value {
+                    Value::Table(table) => table,
+                    value => {
+                        return Err(Error::FromLuaConversionError {
+                            from: value.type_name(),
+                            to: "tuple",
+                            message: Some(format!("expected a table with {} elements", expected)),
+                        })
+                    }
+                };
+
+                let len = table.raw_len();
+                if len != expected as Integer {
+                    return Err(Error::FromLuaConversionError {
+                        from: "table",
+                        to: "tuple",
+                        message: Some(format!("expected {} elements, got {}", expected, len)),
+                    });
+                }
+
+                let mut i: Integer = 0;
+                $(
+                    i += 1;
+                    let $name = table.raw_get(i)?;
+                )+
+                Ok(AsTable(($($name,)+)))
+            }
+        }
+    );
+}
+
+impl_as_table!(A);
+impl_as_table!(A B);
+impl_as_table!(A B C);
+impl_as_table!(A B C D);
+impl_as_table!(A B C D E);
+impl_as_table!(A B C D E F);
+impl_as_table!(A B C D E F G);
+impl_as_table!(A B C D E F G H);
+impl_as_table!(A B C D E F G H I);
+impl_as_table!(A B C D E F G H I J);
+impl_as_table!(A B C D E F G H I J K);
+impl_as_table!(A B C D E F G H I J K L);
+impl_as_table!(A B C D E F G H I J K L M);
+impl_as_table!(A B C D E F G H I J K L M N);
+impl_as_table!(A B C D E F G H I J K L M N O);
+impl_as_table!(A B C D E F G H I J K L M N O P);
+
 macro_rules! impl_tuple {
     () => (
         impl<'lua> ToLuaMulti<'lua> for () {
@@ -149,6 +305,13 @@ macro_rules! impl_tuple {
             fn from_lua_multi(_: MultiValue, _: &'lua Lua) -> Result<Self> {
                 Ok(())
             }
+
+            fn from_lua_multi_with_rest(
+                values: MultiValue<'lua>,
+                _: &'lua Lua,
+            ) -> Result<(Self, MultiValue<'lua>)> {
+                Ok(((), values))
+            }
         }
     );
 
@@ -179,6 +342,34 @@ macro_rules! impl_tuple {
                 let $last = FromLuaMulti::from_lua_multi(values, lua)?;
                 Ok(($(FromLua::from_lua($name, lua)?,)* $last,))
             }
+
+            #[allow(unused_mut)]
+            #[allow(non_snake_case)]
+            fn from_lua_args(
+                mut args: MultiValue<'lua>,
+                mut i: usize,
+                to: Option<&str>,
+                lua: &'lua Lua,
+            ) -> Result<Self> {
+                $(
+                    let $name = FromLua::from_lua(args.pop_front().unwrap_or(Nil), lua)
+                        .map_err(|err| bad_argument(to, i, err))?;
+                    i += 1;
+                )*
+                let $last = FromLuaMulti::from_lua_args(args, i, to, lua)?;
+                Ok(($($name,)* $last,))
+            }
+
+            #[allow(unused_mut)]
+            #[allow(non_snake_case)]
+            fn from_lua_multi_with_rest(
+                mut values: MultiValue<'lua>,
+                lua: &'lua Lua,
+            ) -> Result<(Self, MultiValue<'lua>)> {
+                $(let $name = values.pop_front().unwrap_or(Nil);)*
+                let ($last, rest) = FromLuaMulti::from_lua_multi_with_rest(values, lua)?;
+                Ok((($(FromLua::from_lua($name, lua)?,)* $last,), rest))
+            }
         }
     );
 }
@@ -213,3 +404,12 @@ impl_tuple!(A B C D E F G H I J K L M);
 impl_tuple!(A B C D E F G H I J K L M N);
 impl_tuple!(A B C D E F G H I J K L M N O);
 impl_tuple!(A B C D E F G H I J K L M N O P);
+
+fn bad_argument(to: Option<&str>, pos: usize, err: Error) -> Error {
+    Error::BadArgument {
+        to: to.map(str::to_owned),
+        pos,
+        name: None,
+        cause: Arc::new(err),
+    }
+}
@@ -1,12 +1,22 @@
 //! Re-exports most types with an extra `Lua*` prefix to prevent name clashes.
 
 pub use crate::{
-    AnyUserData as LuaAnyUserData, Chunk as LuaChunk, Error as LuaError,
+    AnyUserData as LuaAnyUserData, AsTable as LuaAsTable, BorrowedStr as LuaBorrowedStr,
+    Breakpoint as LuaBreakpoint, Buffer as LuaBuffer, Chunk as LuaChunk, ChunkMode as LuaChunkMode,
+    CompileHandle as LuaCompileHandle, Debug as LuaDebug, DebugEvent as LuaDebugEvent,
+    DebugNames as LuaDebugNames, DebugSource as LuaDebugSource, DebugStack as LuaDebugStack,
+    Error as LuaError, ErrorChain as LuaErrorChain, ErrorKind as LuaErrorKind,
     ExternalError as LuaExternalError, ExternalResult as LuaExternalResult, FromLua, FromLuaMulti,
-    Function as LuaFunction, Integer as LuaInteger, LightUserData as LuaLightUserData, Lua,
-    MetaMethod as LuaMetaMethod, MultiValue as LuaMultiValue, Nil as LuaNil, Number as LuaNumber,
-    RegistryKey as LuaRegistryKey, Result as LuaResult, Scope as LuaScope, String as LuaString,
-    Table as LuaTable, TablePairs as LuaTablePairs, TableSequence as LuaTableSequence,
-    Thread as LuaThread, ThreadStatus as LuaThreadStatus, ToLua, ToLuaMulti,
-    UserData as LuaUserData, UserDataMethods as LuaUserDataMethods, Value as LuaValue,
+    Function as LuaFunction, FunctionProfile as LuaFunctionProfile, HookMask as LuaHookMask,
+    IndexPriority as LuaIndexPriority, Integer as LuaInteger, LightUserData as LuaLightUserData,
+    Lua, MetaMethod as LuaMetaMethod, MultiValue as LuaMultiValue, Nil as LuaNil,
+    Number as LuaNumber, NumberFormat as LuaNumberFormat, NumberKind as LuaNumberKind,
+    RateLimiter as LuaRateLimiter, RegistryKey as LuaRegistryKey, Result as LuaResult,
+    SandboxConfig as LuaSandboxConfig, Scope as LuaScope,
+    StaticUserDataMethods as LuaStaticUserDataMethods, String as LuaString, Table as LuaTable,
+    TableKeys as LuaTableKeys, TablePairs as LuaTablePairs, TableSequence as LuaTableSequence,
+    TableValues as LuaTableValues, Thread as LuaThread, ThreadIterator as LuaThreadIterator,
+    ThreadStatus as LuaThreadStatus, ToLua, ToLuaMulti, UserData as LuaUserData,
+    UserDataMetatable as LuaUserDataMetatable, UserDataMethods as LuaUserDataMethods,
+    Value as LuaValue, VariantName as LuaVariantName, Version as LuaVersion,
 };
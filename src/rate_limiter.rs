@@ -0,0 +1,147 @@
+use std::time::{Duration, Instant};
+
+use crate::userdata::{UserData, UserDataMethods};
+
+/// A token bucket that Lua scripts can use to throttle themselves.
+///
+/// The bucket holds up to `capacity` tokens and starts full. A new token is added every
+/// `interval` of wall-clock time, until the bucket is full again. Scripts take tokens with
+/// `try_acquire`, which never blocks: it returns `false` if there are not enough tokens, and the
+/// script decides whether to skip the work, yield or retry later. This makes it suitable for
+/// cooperative throttling in sandboxes and plugin systems, e.g. to limit how often a script may
+/// send messages.
+///
+/// A `RateLimiter` is created from Rust and exposes the following methods to Lua:
+///
+/// * `limiter:try_acquire([n])` takes `n` tokens (1 by default) and returns `true`, or returns
+///   `false` without taking any tokens if fewer than `n` are available.
+/// * `limiter:available()` returns the number of tokens currently available.
+///
+/// Both methods only read the clock and do some integer arithmetic, so they are cheap enough to
+/// be called in tight loops.
+///
+/// # Examples
+///
+/// ```
+/// # use mlua::{Lua, RateLimiter, Result};
+/// # use std::time::Duration;
+/// # fn main() -> Result<()> {
+/// # let lua = Lua::new();
+/// lua.globals().set("limiter", RateLimiter::new(3, Duration::from_secs(60)))?;
+///
+/// let sent = lua
+///     .load(
+///         r#"
+///             local sent = 0
+///             for i = 1, 10 do
+///                 if limiter:try_acquire() then
+///                     sent = sent + 1
+///                 end
+///             end
+///             return sent
+///         "#,
+///     )
+///     .eval::<u32>()?;
+/// assert_eq!(sent, 3);
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Debug, Clone)]
+pub struct RateLimiter {
+    capacity: u32,
+    interval: Duration,
+    tokens: u32,
+    last_refill: Instant,
+}
+
+impl RateLimiter {
+    /// Creates a full bucket holding up to `capacity` tokens, adding a token every `interval`.
+    ///
+    /// An `interval` of zero refills the bucket immediately, so only the `capacity` limits a
+    /// single acquisition.
+    pub fn new(capacity: u32, interval: Duration) -> RateLimiter {
+        RateLimiter {
+            capacity,
+            interval,
+            tokens: capacity,
+            last_refill: Instant::now(),
+        }
+    }
+
+    /// Returns the maximum number of tokens in the bucket.
+    pub fn capacity(&self) -> u32 {
+        self.capacity
+    }
+
+    /// Returns the number of tokens currently available.
+    pub fn available(&mut self) -> u32 {
+        self.available_at(Instant::now())
+    }
+
+    /// Returns the number of tokens available at the time `now`.
+    ///
+    /// This is [`available`] with an explicit clock, e.g. to share one clock reading between
+    /// several limiters or to test throttling without waiting. Times before the last refill add no
+    /// tokens.
+    ///
+    /// [`available`]: #method.available
+    pub fn available_at(&mut self, now: Instant) -> u32 {
+        self.refill(now);
+        self.tokens
+    }
+
+    /// Takes `n` tokens and returns `true`, or returns `false` if fewer than `n` tokens are
+    /// available.
+    pub fn try_acquire(&mut self, n: u32) -> bool {
+        self.try_acquire_at(n, Instant::now())
+    }
+
+    /// Takes `n` tokens available at the time `now`, like [`try_acquire`] with an explicit clock.
+    ///
+    /// [`try_acquire`]: #method.try_acquire
+    pub fn try_acquire_at(&mut self, n: u32, now: Instant) -> bool {
+        self.refill(now);
+        if n > self.tokens {
+            return false;
+        }
+        self.tokens -= n;
+        true
+    }
+
+    // Adds the tokens accumulated since the last refill. Only whole intervals are accounted for,
+    // the remainder is kept for the next refill.
+    fn refill(&mut self, now: Instant) {
+        if self.tokens >= self.capacity || self.interval == Duration::from_secs(0) {
+            self.tokens = self.capacity;
+            self.last_refill = now;
+            return;
+        }
+
+        let elapsed = now.saturating_duration_since(self.last_refill);
+        let intervals = elapsed.as_nanos() / self.interval.as_nanos();
+        if intervals == 0 {
+            return;
+        }
+        let missing = self.capacity - self.tokens;
+        if intervals >= u128::from(missing) {
+            self.tokens = self.capacity;
+            self.last_refill = now;
+        } else {
+            self.tokens += intervals as u32;
+            self.last_refill += self.interval * intervals as u32;
+        }
+    }
+}
+
+impl UserData for RateLimiter {
+    fn add_methods<'lua, M: UserDataMethods<'lua, Self>>(methods: &mut M) {
+        methods.add_method_mut("try_acquire", |_, this, n: Option<u32>| {
+            Ok(this.try_acquire(n.unwrap_or(1)))
+        });
+        methods.add_method_mut("available", |_, this, ()| Ok(this.available()));
+    }
+
+    fn name() -> Option<&'static str> {
+        Some("RateLimiter")
+    }
+}
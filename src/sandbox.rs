@@ -0,0 +1,94 @@
+use crate::stdlib::StdLib;
+
+/// Configuration of the restrictions applied by [`Lua::sandbox`].
+///
+/// The [`Default`] implementation provides a curated safe configuration, individual fields can be
+/// adjusted as needed:
+///
+/// ```
+/// # use mlua::{Lua, Result, SandboxConfig, StdLib};
+/// # fn main() -> Result<()> {
+/// let lua = Lua::new();
+/// lua.sandbox(SandboxConfig {
+///     remove_libs: StdLib::IO | StdLib::DEBUG,
+///     instruction_limit: Some(1_000_000),
+///     ..SandboxConfig::default()
+/// })?;
+/// # Ok(())
+/// # }
+/// ```
+///
+/// [`Lua::sandbox`]: struct.Lua.html#method.sandbox
+/// [`Default`]: #impl-Default
+#[derive(Copy, Clone, Debug)]
+pub struct SandboxConfig {
+    /// Standard libraries to remove from the global environment.
+    ///
+    /// Removing [`StdLib::IO`] also removes the `dofile` and `loadfile` functions, removing
+    /// [`StdLib::PACKAGE`] also removes `require`.
+    ///
+    /// Defaults to `IO | OS | DEBUG | PACKAGE`.
+    ///
+    /// [`StdLib::IO`]: struct.StdLib.html#associatedconstant.IO
+    /// [`StdLib::PACKAGE`]: struct.StdLib.html#associatedconstant.PACKAGE
+    pub remove_libs: StdLib,
+    /// Maximum amount of memory (in bytes) the Lua state is allowed to use, or `None` for no
+    /// limit.
+    ///
+    /// A limit can only be set on Lua states created by mlua, and not with LuaJIT, see
+    /// [`Lua::set_memory_limit`]. Only memory allocated by Lua is counted, memory owned by Rust
+    /// values such as the contents of a [`Buffer`] is not.
+    ///
+    /// Defaults to `None`.
+    ///
+    /// [`Lua::set_memory_limit`]: struct.Lua.html#method.set_memory_limit
+    /// [`Buffer`]: struct.Buffer.html
+    pub memory_limit: Option<usize>,
+    /// Maximum number of VM instructions executed by Lua code after the sandbox is applied, or
+    /// `None` for no limit.
+    ///
+    /// The limit is cumulative over the lifetime of the Lua state and is enforced with a hook, so
+    /// [`Lua::sandbox`] returns an error if a hook was already set with [`Lua::set_hook`], and a
+    /// hook set afterwards replaces the limit. Once the limit is exceeded, running Lua code fails
+    /// with [`Error::InstructionLimitExceeded`].
+    ///
+    /// With LuaJIT, code compiled by the JIT does not call hooks, so the JIT compiler is turned off
+    /// when a limit is set. Remove [`StdLib::JIT`] as well to prevent scripts from turning it back
+    /// on.
+    ///
+    /// Defaults to `None`.
+    ///
+    /// [`Lua::sandbox`]: struct.Lua.html#method.sandbox
+    /// [`Lua::set_hook`]: struct.Lua.html#method.set_hook
+    /// [`Error::InstructionLimitExceeded`]: enum.Error.html#variant.InstructionLimitExceeded
+    /// [`StdLib::JIT`]: struct.StdLib.html#associatedconstant.JIT
+    pub instruction_limit: Option<u64>,
+    /// Makes the global environment read-only for scripts.
+    ///
+    /// Chunks loaded after the sandbox is applied get a read-only view of the globals as their
+    /// environment, unless another one is given with [`Chunk::set_environment`]. They can not add
+    /// or modify globals, neither directly nor through `_G`, `rawset` or (with Lua 5.1 and LuaJIT)
+    /// `getfenv`. Functions loaded before keep their environment.
+    ///
+    /// The globals table itself is left as is, [`Lua::globals`] returns it as before and the host
+    /// can still modify it from Rust. This is a shallow protection: the standard library tables
+    /// stay writable, and the globals table can be reached through `package.loaded._G` and the
+    /// debug library if those are kept.
+    ///
+    /// Defaults to `true`.
+    ///
+    /// [`Chunk::set_environment`]: struct.Chunk.html#method.set_environment
+    /// [`Lua::globals`]: struct.Lua.html#method.globals
+    pub freeze_globals: bool,
+}
+
+impl Default for SandboxConfig {
+    fn default() -> SandboxConfig {
+        SandboxConfig {
+            remove_libs: StdLib::IO | StdLib::OS | StdLib::DEBUG | StdLib::PACKAGE,
+            memory_limit: None,
+            instruction_limit: None,
+            freeze_globals: true,
+        }
+    }
+}
@@ -10,13 +10,14 @@ use crate::error::{Error, Result};
 use crate::ffi;
 use crate::function::Function;
 use crate::lua::Lua;
-use crate::types::{Callback, LuaRef};
-use crate::userdata::{AnyUserData, MetaMethod, UserData, UserDataMethods};
+use crate::table::Table;
+use crate::types::{Callback, IndexTableCallback, LuaRef, MetatableProtectionCallback};
+use crate::userdata::{AnyUserData, IndexPriority, MetaMethod, UserData, UserDataMethods};
 use crate::util::{
-    assert_stack, init_userdata_metatable, protect_lua_closure, push_string, push_userdata,
-    take_userdata, StackGuard,
+    assert_stack, enable_userdata_method_cache, init_userdata_metatable, protect_lua_closure,
+    push_string, push_userdata, take_userdata, StackGuard,
 };
-use crate::value::{FromLuaMulti, MultiValue, ToLuaMulti, Value};
+use crate::value::{FromLuaMulti, MultiValue, ToLua, ToLuaMulti, Value};
 
 /// Constructed by the [`Lua::scope`] method, allows temporarily creating Lua userdata and
 /// callbacks that are not required to be Send or 'static.
@@ -63,7 +64,7 @@ impl<'lua, 'scope> Scope<'lua, 'scope> {
         // scope, and owned inside the callback itself.
         unsafe {
             self.create_callback(Box::new(move |lua, args| {
-                func(lua, A::from_lua_multi(args, lua)?)?.to_lua_multi(lua)
+                func(lua, A::from_lua_args(args, 1, None, lua)?)?.to_lua_multi(lua)
             }))
         }
     }
@@ -242,7 +243,7 @@ impl<'lua, 'scope> Scope<'lua, 'scope> {
         unsafe {
             let lua = self.lua;
             let _sg = StackGuard::new(lua.state);
-            assert_stack(lua.state, 6);
+            assert_stack(lua.state, 8);
 
             push_userdata(lua.state, ())?;
             #[cfg(feature = "lua53")]
@@ -270,8 +271,17 @@ impl<'lua, 'scope> Scope<'lua, 'scope> {
                 })?;
             }
 
-            if ud_methods.methods.is_empty() {
-                init_userdata_metatable::<()>(lua.state, -1, None)?;
+            if ud_methods.methods.is_empty()
+                && ud_methods.index_table.is_none()
+                && ud_methods.index_priority == IndexPriority::MethodsFirst
+            {
+                init_userdata_metatable::<()>(
+                    lua.state,
+                    -1,
+                    None,
+                    ud_methods.index_priority,
+                    T::name(),
+                )?;
             } else {
                 protect_lua_closure(lua.state, 0, 1, |state| {
                     ffi::lua_newtable(state);
@@ -283,11 +293,31 @@ impl<'lua, 'scope> Scope<'lua, 'scope> {
                         ffi::lua_rawset(state, -3);
                     })?;
                 }
+                if let Some(index_table) = ud_methods.index_table {
+                    // The index table callback also uses the 'callback lifetime, see `wrap_method`
+                    let lua = &*(lua as *const Lua);
+                    lua.set_methods_index_table(index_table(lua)?)?;
+                }
 
-                init_userdata_metatable::<()>(lua.state, -2, Some(-1))?;
+                init_userdata_metatable::<()>(
+                    lua.state,
+                    -2,
+                    Some(-1),
+                    ud_methods.index_priority,
+                    T::name(),
+                )?;
+                if ud_methods.method_cache {
+                    enable_userdata_method_cache(lua.state, -2)?;
+                }
                 ffi::lua_pop(lua.state, 1);
             }
 
+            if let Some(protection) = ud_methods.metatable_protection {
+                // Like the index table callback, this uses the 'callback lifetime
+                let lua = &*(lua as *const Lua);
+                lua.set_metatable_protection(protection(lua)?)?;
+            }
+
             ffi::lua_setmetatable(lua.state, -2);
 
             Ok(AnyUserData(lua.pop_ref()))
@@ -354,6 +384,10 @@ enum NonStaticMethod<'lua, T> {
 struct NonStaticUserDataMethods<'lua, T: UserData> {
     methods: Vec<(Vec<u8>, NonStaticMethod<'lua, T>)>,
     meta_methods: Vec<(MetaMethod, NonStaticMethod<'lua, T>)>,
+    index_table: Option<IndexTableCallback<'lua>>,
+    index_priority: IndexPriority,
+    method_cache: bool,
+    metatable_protection: Option<MetatableProtectionCallback<'lua>>,
 }
 
 impl<'lua, T: UserData> Default for NonStaticUserDataMethods<'lua, T> {
@@ -361,6 +395,10 @@ impl<'lua, T: UserData> Default for NonStaticUserDataMethods<'lua, T> {
         NonStaticUserDataMethods {
             methods: Vec::new(),
             meta_methods: Vec::new(),
+            index_table: None,
+            index_priority: IndexPriority::default(),
+            method_cache: false,
+            metatable_protection: None,
         }
     }
 }
@@ -376,7 +414,7 @@ impl<'lua, T: UserData> UserDataMethods<'lua, T> for NonStaticUserDataMethods<'l
         self.methods.push((
             name.as_ref().to_vec(),
             NonStaticMethod::Method(Box::new(move |lua, ud, args| {
-                method(lua, ud, A::from_lua_multi(args, lua)?)?.to_lua_multi(lua)
+                method(lua, ud, A::from_lua_args(args, 2, None, lua)?)?.to_lua_multi(lua)
             })),
         ));
     }
@@ -391,7 +429,7 @@ impl<'lua, T: UserData> UserDataMethods<'lua, T> for NonStaticUserDataMethods<'l
         self.methods.push((
             name.as_ref().to_vec(),
             NonStaticMethod::MethodMut(Box::new(move |lua, ud, args| {
-                method(lua, ud, A::from_lua_multi(args, lua)?)?.to_lua_multi(lua)
+                method(lua, ud, A::from_lua_args(args, 2, None, lua)?)?.to_lua_multi(lua)
             })),
         ));
     }
@@ -406,7 +444,7 @@ impl<'lua, T: UserData> UserDataMethods<'lua, T> for NonStaticUserDataMethods<'l
         self.methods.push((
             name.as_ref().to_vec(),
             NonStaticMethod::Function(Box::new(move |lua, args| {
-                function(lua, A::from_lua_multi(args, lua)?)?.to_lua_multi(lua)
+                function(lua, A::from_lua_args(args, 1, None, lua)?)?.to_lua_multi(lua)
             })),
         ));
     }
@@ -421,7 +459,7 @@ impl<'lua, T: UserData> UserDataMethods<'lua, T> for NonStaticUserDataMethods<'l
         self.methods.push((
             name.as_ref().to_vec(),
             NonStaticMethod::FunctionMut(Box::new(move |lua, args| {
-                function(lua, A::from_lua_multi(args, lua)?)?.to_lua_multi(lua)
+                function(lua, A::from_lua_args(args, 1, None, lua)?)?.to_lua_multi(lua)
             })),
         ));
     }
@@ -435,7 +473,10 @@ impl<'lua, T: UserData> UserDataMethods<'lua, T> for NonStaticUserDataMethods<'l
         self.meta_methods.push((
             meta,
             NonStaticMethod::Method(Box::new(move |lua, ud, args| {
-                method(lua, ud, A::from_lua_multi(args, lua)?)?.to_lua_multi(lua)
+                meta.validate_result(
+                    lua,
+                    method(lua, ud, A::from_lua_args(args, 2, None, lua)?)?.to_lua_multi(lua)?,
+                )
             })),
         ));
     }
@@ -449,7 +490,10 @@ impl<'lua, T: UserData> UserDataMethods<'lua, T> for NonStaticUserDataMethods<'l
         self.meta_methods.push((
             meta,
             NonStaticMethod::MethodMut(Box::new(move |lua, ud, args| {
-                method(lua, ud, A::from_lua_multi(args, lua)?)?.to_lua_multi(lua)
+                meta.validate_result(
+                    lua,
+                    method(lua, ud, A::from_lua_args(args, 2, None, lua)?)?.to_lua_multi(lua)?,
+                )
             })),
         ));
     }
@@ -463,7 +507,10 @@ impl<'lua, T: UserData> UserDataMethods<'lua, T> for NonStaticUserDataMethods<'l
         self.meta_methods.push((
             meta,
             NonStaticMethod::Function(Box::new(move |lua, args| {
-                function(lua, A::from_lua_multi(args, lua)?)?.to_lua_multi(lua)
+                meta.validate_result(
+                    lua,
+                    function(lua, A::from_lua_args(args, 1, None, lua)?)?.to_lua_multi(lua)?,
+                )
             })),
         ));
     }
@@ -477,8 +524,32 @@ impl<'lua, T: UserData> UserDataMethods<'lua, T> for NonStaticUserDataMethods<'l
         self.meta_methods.push((
             meta,
             NonStaticMethod::FunctionMut(Box::new(move |lua, args| {
-                function(lua, A::from_lua_multi(args, lua)?)?.to_lua_multi(lua)
+                meta.validate_result(
+                    lua,
+                    function(lua, A::from_lua_args(args, 1, None, lua)?)?.to_lua_multi(lua)?,
+                )
             })),
         ));
     }
+    fn set_index_table_with<F>(&mut self, f: F)
+    where
+        F: 'lua + FnOnce(&'lua Lua) -> Result<Table<'lua>>,
+    {
+        self.index_table = Some(Box::new(f));
+    }
+
+    fn set_index_priority(&mut self, priority: IndexPriority) {
+        self.index_priority = priority;
+    }
+
+    fn enable_method_cache(&mut self) {
+        self.method_cache = true;
+    }
+
+    fn set_metatable_protection<V>(&mut self, value: V)
+    where
+        V: 'lua + ToLua<'lua>,
+    {
+        self.metatable_protection = Some(Box::new(move |lua| value.to_lua(lua)));
+    }
 }
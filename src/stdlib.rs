@@ -24,6 +24,9 @@ impl StdLib {
     pub const FFI: StdLib = StdLib(1 << 10);
     pub const DEBUG: StdLib = StdLib(1 << 31); // always highest bit
 
+    // No standard libraries besides the base library
+    pub(crate) const NONE: StdLib = StdLib(0);
+
     pub const ALL: StdLib = StdLib(u32::MAX);
     pub const ALL_NO_DEBUG: StdLib = StdLib((1 << 31) - 1);
 
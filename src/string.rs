@@ -1,9 +1,13 @@
+use std::fmt;
+use std::ops::Deref;
 use std::{slice, str};
 
 use crate::error::{Error, Result};
 use crate::ffi;
+use crate::lua::Lua;
 use crate::types::LuaRef;
 use crate::util::{assert_stack, StackGuard};
+use crate::value::{FromLua, Value};
 
 /// Handle to an internal Lua string.
 ///
@@ -105,3 +109,79 @@ where
         self.as_bytes() == other.as_ref()
     }
 }
+
+/// A UTF-8 Lua string borrowed without copying.
+///
+/// Converting a Lua value to `BorrowedStr` validates it as UTF-8 once and then gives access to the
+/// string contents as a `&str` pointing directly into Lua memory, avoiding the allocation made by
+/// converting to a Rust `String`. This is useful for callbacks that only need to read their
+/// string arguments, e.g. to parse or hash them.
+///
+/// The `BorrowedStr` holds a reference to the Lua string, so its contents remain valid for as long
+/// as the `BorrowedStr` itself is alive. The `&str` obtained through `Deref` can not outlive it.
+///
+/// Numbers are converted to strings like with [`String`].
+///
+/// # Examples
+///
+/// ```
+/// # use mlua::{BorrowedStr, Lua, Result};
+/// # fn main() -> Result<()> {
+/// # let lua = Lua::new();
+/// let count_words = lua.create_function(|_, text: BorrowedStr| {
+///     Ok(text.split_whitespace().count())
+/// })?;
+/// assert_eq!(count_words.call::<_, usize>("no allocation here")?, 3);
+/// # Ok(())
+/// # }
+/// ```
+///
+/// [`String`]: struct.String.html
+pub struct BorrowedStr<'lua> {
+    // Points into the string kept alive by `_string`
+    buf: *const str,
+    _string: String<'lua>,
+}
+
+impl<'lua> Deref for BorrowedStr<'lua> {
+    type Target = str;
+
+    fn deref(&self) -> &str {
+        unsafe { &*self.buf }
+    }
+}
+
+impl<'lua> AsRef<str> for BorrowedStr<'lua> {
+    fn as_ref(&self) -> &str {
+        self
+    }
+}
+
+impl<'lua> fmt::Debug for BorrowedStr<'lua> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt::Debug::fmt(&**self, f)
+    }
+}
+
+impl<'lua> fmt::Display for BorrowedStr<'lua> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt::Display::fmt(&**self, f)
+    }
+}
+
+impl<'lua, T: AsRef<str>> PartialEq<T> for BorrowedStr<'lua> {
+    fn eq(&self, other: &T) -> bool {
+        **self == *other.as_ref()
+    }
+}
+
+impl<'lua> FromLua<'lua> for BorrowedStr<'lua> {
+    fn from_lua(value: Value<'lua>, lua: &'lua Lua) -> Result<Self> {
+        let string = String::from_lua(value, lua)?;
+        let buf = string.to_str()? as *const str;
+        Ok(BorrowedStr {
+            buf,
+            _string: string,
+        })
+    }
+}
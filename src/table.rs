@@ -1,9 +1,12 @@
+use std::collections::HashMap;
+use std::hash::Hash;
 use std::marker::PhantomData;
 use std::os::raw::c_int;
 
 use crate::error::{Error, Result};
 use crate::ffi;
 use crate::function::Function;
+use crate::lua::Lua;
 use crate::types::{Integer, LuaRef};
 use crate::util::{assert_stack, protect_lua, protect_lua_closure, StackGuard};
 use crate::value::{FromLua, FromLuaMulti, Nil, ToLua, ToLuaMulti, Value};
@@ -15,7 +18,9 @@ pub struct Table<'lua>(pub(crate) LuaRef<'lua>);
 impl<'lua> Table<'lua> {
     /// Sets a key-value pair in the table.
     ///
-    /// If the value is `nil`, this will effectively remove the pair.
+    /// If the value is `nil`, this will effectively remove the pair. Returns an error if the key
+    /// is `nil` or NaN, unless the table has a metatable, since its `__newindex` metamethod may
+    /// accept such keys.
     ///
     /// This might invoke the `__newindex` metamethod. Use the [`raw_set`] method if that is not
     /// desired.
@@ -55,6 +60,11 @@ impl<'lua> Table<'lua> {
             assert_stack(lua.state, 6);
 
             lua.push_ref(&self.0);
+            if ffi::lua_getmetatable(lua.state, -1) == 0 {
+                check_key(&key)?;
+            } else {
+                ffi::lua_pop(lua.state, 1);
+            }
             lua.push_value(key)?;
             lua.push_value(value)?;
 
@@ -110,7 +120,105 @@ impl<'lua> Table<'lua> {
         V::from_lua(value, lua)
     }
 
+    /// Gets the value associated to `key` from the table, or `default` if there is none.
+    ///
+    /// The default is only used if the value is `nil`. A value that is present but can not be
+    /// converted to `V` is still an error.
+    ///
+    /// This might invoke the `__index` metamethod, like [`get`].
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use mlua::{Lua, Result, Table};
+    /// # fn main() -> Result<()> {
+    /// # let lua = Lua::new();
+    /// let config: Table = lua.load("{ port = 8080 }").eval()?;
+    ///
+    /// assert_eq!(config.get_or("port", 80)?, 8080);
+    /// assert_eq!(config.get_or("workers", 4)?, 4);
+    /// # Ok(())
+    /// # }
+    /// ```
+    ///
+    /// [`get`]: #method.get
+    pub fn get_or<K: ToLua<'lua>, V: FromLua<'lua>>(&self, key: K, default: V) -> Result<V> {
+        Ok(self.get::<_, Option<V>>(key)?.unwrap_or(default))
+    }
+
+    /// Removes the value associated to `key` by setting it to `nil`.
+    ///
+    /// This is a shorthand for `set(key, Nil)` and may trigger the `__newindex` metamethod. Integer
+    /// keys are not treated specially: removing a value from the middle of a sequence leaves a
+    /// hole rather than shifting the following elements, use [`raw_remove`] for that.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use mlua::{Lua, Result};
+    /// # fn main() -> Result<()> {
+    /// # let lua = Lua::new();
+    /// let table = lua.create_table()?;
+    /// table.set("key", "value")?;
+    /// table.remove("key")?;
+    /// assert!(!table.contains_key("key")?);
+    /// # Ok(())
+    /// # }
+    /// ```
+    ///
+    /// [`raw_remove`]: #method.raw_remove
+    pub fn remove<K: ToLua<'lua>>(&self, key: K) -> Result<()> {
+        self.set(key, Nil)
+    }
+
+    /// Creates a Rust function with [`Lua::create_function`] and stores it at `name`.
+    ///
+    /// This is a shortcut for building module tables of functions.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use mlua::{Lua, Result};
+    /// # fn main() -> Result<()> {
+    /// # let lua = Lua::new();
+    /// let vec2 = lua.create_table()?;
+    /// vec2.set_function("length", |_, (x, y): (f64, f64)| Ok((x * x + y * y).sqrt()))?;
+    /// lua.globals().set("vec2", vec2)?;
+    /// assert_eq!(lua.load("vec2.length(3, 4)").eval::<f64>()?, 5.0);
+    /// # Ok(())
+    /// # }
+    /// ```
+    ///
+    /// [`Lua::create_function`]: struct.Lua.html#method.create_function
+    pub fn set_function<'callback, A, R, F>(&self, name: &str, func: F) -> Result<()>
+    where
+        A: FromLuaMulti<'callback>,
+        R: ToLuaMulti<'callback>,
+        F: 'static + Send + Fn(&'callback Lua, A) -> Result<R>,
+    {
+        self.set(name, self.0.lua.create_function(func)?)
+    }
+
     /// Checks whether the table contains a non-nil value for `key`.
+    ///
+    /// This might invoke the `__index` metamethod. Use [`raw_contains_key`] if that is not
+    /// desired. A key mapped to `false` is present.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use mlua::{Lua, Result};
+    /// # fn main() -> Result<()> {
+    /// # let lua = Lua::new();
+    /// let table = lua.create_table()?;
+    /// table.set("enabled", false)?;
+    /// assert!(table.contains_key("enabled")?);
+    /// assert!(!table.contains_key("missing")?);
+    /// # Ok(())
+    /// # }
+    /// ```
+    ///
+    /// [`raw_contains_key`]: #method.raw_contains_key
     pub fn contains_key<K: ToLua<'lua>>(&self, key: K) -> Result<bool> {
         let lua = self.0.lua;
         let key = key.to_lua(lua)?;
@@ -133,6 +241,82 @@ impl<'lua> Table<'lua> {
         }
     }
 
+    /// Gets the value at a dotted `path` of nested tables, e.g. `"server.http.port"`.
+    ///
+    /// Each segment of the path is looked up with [`get`], so metamethods are invoked. Returns
+    /// `None` if the value or any intermediate table is `nil`, and an error if an intermediate
+    /// value is not a table.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use mlua::{Lua, Result, Table};
+    /// # fn main() -> Result<()> {
+    /// # let lua = Lua::new();
+    /// let config: Table = lua.load("{ server = { http = { port = 8080 } } }").eval()?;
+    /// assert_eq!(config.get_path::<u16>("server.http.port")?, Some(8080));
+    /// assert_eq!(config.get_path::<u16>("server.https.port")?, None);
+    /// # Ok(())
+    /// # }
+    /// ```
+    ///
+    /// [`get`]: #method.get
+    pub fn get_path<V: FromLua<'lua>>(&self, path: &str) -> Result<Option<V>> {
+        let (parents, last) = split_path(path);
+        let mut table = self.clone();
+        for (i, key) in parents.iter().enumerate() {
+            table = match table.get::<_, Value>(*key)? {
+                Value::Nil => return Ok(None),
+                Value::Table(t) => t,
+                value => return Err(not_a_table(&parents[..=i], &value)),
+            };
+        }
+        match table.get::<_, Value>(last)? {
+            Value::Nil => Ok(None),
+            value => V::from_lua(value, self.0.lua).map(Some),
+        }
+    }
+
+    /// Sets the value at a dotted `path` of nested tables, creating missing intermediate tables.
+    ///
+    /// Each segment of the path is accessed with [`get`] and [`set`], so metamethods are invoked.
+    /// Returns an error if an intermediate value exists and is not a table.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use mlua::{Lua, Result, Table};
+    /// # fn main() -> Result<()> {
+    /// # let lua = Lua::new();
+    /// let config: Table = lua.load("{ server = { name = 'example' } }").eval()?;
+    /// config.set_path("server.https.port", 8443)?;
+    /// assert_eq!(config.get_path::<u16>("server.https.port")?, Some(8443));
+    ///
+    /// // `server.name` is a string, so it can not hold other values
+    /// assert!(config.set_path("server.name.first", "x").is_err());
+    /// # Ok(())
+    /// # }
+    /// ```
+    ///
+    /// [`get`]: #method.get
+    /// [`set`]: #method.set
+    pub fn set_path<V: ToLua<'lua>>(&self, path: &str, value: V) -> Result<()> {
+        let (parents, last) = split_path(path);
+        let mut table = self.clone();
+        for (i, key) in parents.iter().enumerate() {
+            table = match table.get::<_, Value>(*key)? {
+                Value::Nil => {
+                    let t = self.0.lua.create_table()?;
+                    table.set(*key, t.clone())?;
+                    t
+                }
+                Value::Table(t) => t,
+                value => return Err(not_a_table(&parents[..=i], &value)),
+            };
+        }
+        table.set(last, value)
+    }
+
     /// Gets the function associated to `key` from the table and executes it,
     /// passing the table itself as the first argument.
     ///
@@ -223,9 +407,12 @@ impl<'lua> Table<'lua> {
     }
 
     /// Sets a key-value pair without invoking metamethods.
+    ///
+    /// Returns an error if the key is `nil` or NaN.
     pub fn raw_set<K: ToLua<'lua>, V: ToLua<'lua>>(&self, key: K, value: V) -> Result<()> {
         let lua = self.0.lua;
         let key = key.to_lua(lua)?;
+        check_key(&key)?;
         let value = value.to_lua(lua)?;
 
         unsafe {
@@ -262,6 +449,21 @@ impl<'lua> Table<'lua> {
         V::from_lua(value, lua)
     }
 
+    /// Checks whether the table contains a non-nil value for `key`, without invoking metamethods.
+    pub fn raw_contains_key<K: ToLua<'lua>>(&self, key: K) -> Result<bool> {
+        let lua = self.0.lua;
+        let key = key.to_lua(lua)?;
+        unsafe {
+            let _sg = StackGuard::new(lua.state);
+            assert_stack(lua.state, 3);
+
+            lua.push_ref(&self.0);
+            lua.push_value(key)?;
+            ffi::lua_rawget(lua.state, -2);
+            Ok(ffi::lua_isnil(lua.state, -1) == 0)
+        }
+    }
+
     /// Inserts element value at position idx to the table, shifting up the elements from table[idx].
     /// The worst case complexity is O(n), where n is the table length.
     pub fn raw_insert<V: ToLua<'lua>>(&self, idx: Integer, value: V) -> Result<()> {
@@ -290,14 +492,33 @@ impl<'lua> Table<'lua> {
         }
     }
 
-    /// Removes a key from the table.
+    /// Removes a key from the table, returning the removed value.
     ///
     /// If `key` is an integer, mlua shifts down the elements from table[key+1],
     /// and erases element table[key]. The complexity is O(n) in worst case,
     /// where n is the table length.
     ///
     /// For othey key types this is equivalent to setting table[key] = nil.
-    pub fn raw_remove<K: ToLua<'lua>>(&self, key: K) -> Result<()> {
+    ///
+    /// The removed value is converted to `V` before the table is modified, so the table is left
+    /// unchanged if the conversion fails.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use mlua::{Lua, Result, Table};
+    /// # fn main() -> Result<()> {
+    /// # let lua = Lua::new();
+    /// let table: Table = lua.load("{'a', 'b', 'c'}").eval()?;
+    ///
+    /// let removed: String = table.raw_remove(2)?;
+    /// assert_eq!(removed, "b");
+    /// assert_eq!(table.raw_len(), 2);
+    /// assert_eq!(table.raw_get::<_, String>(2)?, "c");
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn raw_remove<K: ToLua<'lua>, V: FromLua<'lua>>(&self, key: K) -> Result<V> {
         let lua = self.0.lua;
         let key = key.to_lua(lua)?;
         match key {
@@ -306,6 +527,7 @@ impl<'lua> Table<'lua> {
                 if idx < 1 || idx > size {
                     return Err(Error::RuntimeError("index out of bounds".to_string()));
                 }
+                let value = V::from_lua(self.raw_get(idx)?, lua)?;
                 unsafe {
                     let _sg = StackGuard::new(lua.state);
                     assert_stack(lua.state, 6);
@@ -319,11 +541,114 @@ impl<'lua> Table<'lua> {
                         }
                         ffi::lua_pushnil(state);
                         ffi::lua_rawseti(state, -2, size);
-                    })
+                    })?;
+                }
+                Ok(value)
+            }
+            _ => {
+                let value = V::from_lua(self.raw_get(key.clone())?, lua)?;
+                self.raw_set(key, Nil)?;
+                Ok(value)
+            }
+        }
+    }
+
+    /// Copies every key-value pair from `other` into this table.
+    ///
+    /// If `overwrite` is `true`, values already present in this table are replaced by the ones
+    /// from `other`, otherwise existing keys are left untouched.
+    ///
+    /// Both reading and writing use raw access, so no metamethods are invoked. Use
+    /// [`deep_merge`] to recursively merge nested tables instead of replacing them.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use mlua::{Lua, Result, Table};
+    /// # fn main() -> Result<()> {
+    /// # let lua = Lua::new();
+    /// let config: Table = lua.load(r#"{ width = 800, height = 600 }"#).eval()?;
+    /// let overrides: Table = lua.load(r#"{ width = 1024, title = "mlua" }"#).eval()?;
+    ///
+    /// config.merge(&overrides, true)?;
+    /// assert_eq!(config.get::<_, u32>("width")?, 1024);
+    /// assert_eq!(config.get::<_, u32>("height")?, 600);
+    /// assert_eq!(config.get::<_, String>("title")?, "mlua");
+    /// # Ok(())
+    /// # }
+    /// ```
+    ///
+    /// [`deep_merge`]: #method.deep_merge
+    pub fn merge(&self, other: &Table<'lua>, overwrite: bool) -> Result<()> {
+        self.merge_impl(other, overwrite, None)
+    }
+
+    /// Copies every key-value pair from `other` into this table, recursively merging nested
+    /// tables.
+    ///
+    /// When both tables contain a table under the same key, the nested tables are merged with
+    /// the same rules instead of one replacing the other. Otherwise this behaves like [`merge`],
+    /// except that nested tables inserted from `other` are copied, so that this table never shares
+    /// them with `other`. The copies keep the metatables of the original tables, and tables that
+    /// `other` references several times (including cyclic references) are copied only once.
+    ///
+    /// [`merge`]: #method.merge
+    pub fn deep_merge(&self, other: &Table<'lua>, overwrite: bool) -> Result<()> {
+        self.merge_impl(other, overwrite, Some(&mut DeepMerge::default()))
+    }
+
+    fn merge_impl(
+        &self,
+        other: &Table<'lua>,
+        overwrite: bool,
+        mut deep: Option<&mut DeepMerge<'lua>>,
+    ) -> Result<()> {
+        if let Some(deep) = deep.as_mut() {
+            if deep
+                .visited
+                .iter()
+                .any(|(dst, src)| dst == self && src == other)
+            {
+                return Ok(());
+            }
+            deep.visited.push((self.clone(), other.clone()));
+        }
+
+        for pair in other.clone().pairs::<Value, Value>() {
+            let (key, value) = pair?;
+            match (
+                self.raw_get::<_, Value>(key.clone())?,
+                value,
+                deep.as_deref_mut(),
+            ) {
+                (Value::Table(dst), Value::Table(src), Some(deep)) => {
+                    dst.merge_impl(&src, overwrite, Some(deep))?;
+                }
+                (Value::Nil, Value::Table(src), Some(deep)) => {
+                    self.raw_set(key, src.deep_copy(deep)?)?
                 }
+                (_, Value::Table(src), Some(deep)) if overwrite => {
+                    self.raw_set(key, src.deep_copy(deep)?)?
+                }
+                (Value::Nil, value, _) => self.raw_set(key, value)?,
+                (_, value, _) if overwrite => self.raw_set(key, value)?,
+                _ => {}
             }
-            _ => self.raw_set(key, Nil),
         }
+
+        Ok(())
+    }
+
+    // Returns a copy of this table for `deep_merge`, reusing the copy if it was already made.
+    fn deep_copy(&self, deep: &mut DeepMerge<'lua>) -> Result<Table<'lua>> {
+        if let Some((_, copy)) = deep.copies.iter().find(|(src, _)| src == self) {
+            return Ok(copy.clone());
+        }
+        let copy = self.0.lua.create_table()?;
+        copy.set_metatable(self.get_metatable());
+        deep.copies.push((self.clone(), copy.clone()));
+        copy.merge_impl(self, true, Some(deep))?;
+        Ok(copy)
     }
 
     /// Returns the result of the Lua `#` operator.
@@ -375,6 +700,9 @@ impl<'lua> Table<'lua> {
     ///
     /// If `metatable` is `None`, the metatable is removed (if no metatable is set, this does
     /// nothing).
+    ///
+    /// Unlike the `setmetatable` Lua function, this method ignores the `__metatable` field, so it
+    /// also replaces a protected metatable.
     pub fn set_metatable(&self, metatable: Option<Table<'lua>>) {
         let lua = self.0.lua;
         unsafe {
@@ -390,6 +718,44 @@ impl<'lua> Table<'lua> {
         }
     }
 
+    /// Protects the metatable of this table from scripts by setting its `__metatable` field.
+    ///
+    /// Afterwards, `getmetatable` in Lua returns `value` instead of the metatable and
+    /// `setmetatable` raises an error. If the table has no metatable, an empty one is set first.
+    /// Passing `nil` removes the protection. [`get_metatable`] and [`set_metatable`] are not
+    /// affected.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use mlua::{Lua, Result};
+    /// # fn main() -> Result<()> {
+    /// # let lua = Lua::new();
+    /// let config = lua.create_table()?;
+    /// config.protect_metatable("locked")?;
+    /// lua.globals().set("config", config)?;
+    ///
+    /// assert_eq!(lua.load("getmetatable(config)").eval::<String>()?, "locked");
+    /// assert!(lua.load("setmetatable(config, {})").exec().is_err());
+    /// # Ok(())
+    /// # }
+    /// ```
+    ///
+    /// [`get_metatable`]: #method.get_metatable
+    /// [`set_metatable`]: #method.set_metatable
+    pub fn protect_metatable<V: ToLua<'lua>>(&self, value: V) -> Result<()> {
+        let lua = self.0.lua;
+        let metatable = match self.get_metatable() {
+            Some(metatable) => metatable,
+            None => {
+                let metatable = lua.create_table()?;
+                self.set_metatable(Some(metatable.clone()));
+                metatable
+            }
+        };
+        metatable.raw_set("__metatable", value)
+    }
+
     /// Consume this table and return an iterator over the pairs of the table.
     ///
     /// This works like the Lua `pairs` function, but does not invoke the `__pairs` metamethod.
@@ -431,6 +797,50 @@ impl<'lua> Table<'lua> {
         }
     }
 
+    /// Returns an iterator over the keys of the table.
+    ///
+    /// This works like [`pairs`], but only converts the keys, the values are never read into
+    /// Rust. As with `pairs`, the iteration order is unspecified and the `__pairs` metamethod is
+    /// not invoked.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use mlua::{Lua, Result, Table};
+    /// # fn main() -> Result<()> {
+    /// # let lua = Lua::new();
+    /// let table: Table = lua.load("{ a = 1, b = 2 }").eval()?;
+    ///
+    /// let mut keys = table.keys::<String>().collect::<Result<Vec<_>>>()?;
+    /// keys.sort();
+    /// assert_eq!(keys, vec!["a", "b"]);
+    /// # Ok(())
+    /// # }
+    /// ```
+    ///
+    /// [`pairs`]: #method.pairs
+    pub fn keys<K: FromLua<'lua>>(&self) -> TableKeys<'lua, K> {
+        TableKeys {
+            table: self.0.clone(),
+            next_key: Some(Nil),
+            _phantom: PhantomData,
+        }
+    }
+
+    /// Returns an iterator over the values of the table.
+    ///
+    /// This works like [`pairs`], but only converts the values. As with `pairs`, the iteration
+    /// order is unspecified and the `__pairs` metamethod is not invoked.
+    ///
+    /// [`pairs`]: #method.pairs
+    pub fn values<V: FromLua<'lua>>(&self) -> TableValues<'lua, V> {
+        TableValues {
+            table: self.0.clone(),
+            next_key: Some(Nil),
+            _phantom: PhantomData,
+        }
+    }
+
     /// Consume this table and return an iterator over all values in the sequence part of the table.
     ///
     /// The iterator will yield all values `t[1]`, `t[2]`, and so on, until a `nil` value is
@@ -479,6 +889,101 @@ impl<'lua> Table<'lua> {
             _phantom: PhantomData,
         }
     }
+
+    /// Consumes the table and collects all of its key-value pairs into a `HashMap`.
+    ///
+    /// The pairs are traversed like with [`pairs`], so the `__pairs` metamethod is not invoked.
+    /// Fails on the first key or value that can not be converted.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use std::collections::HashMap;
+    /// # use mlua::{Lua, Result, Table};
+    /// # fn main() -> Result<()> {
+    /// # let lua = Lua::new();
+    /// let table: Table = lua.load("{ a = 1, b = 2 }").eval()?;
+    /// let map: HashMap<String, i64> = table.into_hashmap()?;
+    /// assert_eq!(map["b"], 2);
+    /// # Ok(())
+    /// # }
+    /// ```
+    ///
+    /// [`pairs`]: #method.pairs
+    pub fn into_hashmap<K, V>(self) -> Result<HashMap<K, V>>
+    where
+        K: FromLua<'lua> + Eq + Hash,
+        V: FromLua<'lua>,
+    {
+        self.pairs().collect()
+    }
+
+    /// Consumes the table and collects its sequence values `t[1]`, `t[2]`, ... into a `Vec`.
+    ///
+    /// The values are read like with [`sequence_values`], stopping at the first `nil`. Fails on the
+    /// first value that can not be converted.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use mlua::{Lua, Result, Table};
+    /// # fn main() -> Result<()> {
+    /// # let lua = Lua::new();
+    /// let table: Table = lua.load("{ 1, 2, 3, nil, 5 }").eval()?;
+    /// assert_eq!(table.into_vec::<i64>()?, vec![1, 2, 3]);
+    /// # Ok(())
+    /// # }
+    /// ```
+    ///
+    /// [`sequence_values`]: #method.sequence_values
+    pub fn into_vec<V: FromLua<'lua>>(self) -> Result<Vec<V>> {
+        self.sequence_values().collect()
+    }
+
+    /// Collects the sequence values of the table into a `Vec`, letting `f` decide what to do with
+    /// each element.
+    ///
+    /// Every value `t[1]`, `t[2]`, ... (see [`sequence_values`]) is converted to `V` and the
+    /// conversion result is passed to `f`. Returning `Ok(Some(value))` keeps the value, `Ok(None)`
+    /// skips the element and returning an error aborts the collection with that error. This allows
+    /// lenient reading of partially typed data, where a single bad element would otherwise fail
+    /// the whole conversion.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use mlua::{Lua, Result, Table};
+    /// # fn main() -> Result<()> {
+    /// # let lua = Lua::new();
+    /// let table: Table = lua.load(r#"{1, "two", 3, {}, 5}"#).eval()?;
+    ///
+    /// let numbers = table.filter_collect::<i64, _>(|v| Ok(v.ok()))?;
+    /// assert_eq!(numbers, vec![1, 3, 5]);
+    /// # Ok(())
+    /// # }
+    /// ```
+    ///
+    /// [`sequence_values`]: #method.sequence_values
+    pub fn filter_collect<V, F>(&self, mut f: F) -> Result<Vec<V>>
+    where
+        V: FromLua<'lua>,
+        F: FnMut(Result<V>) -> Result<Option<V>>,
+    {
+        let mut values = Vec::new();
+        for value in self.clone().sequence_values() {
+            if let Some(value) = f(value)? {
+                values.push(value);
+            }
+        }
+        Ok(values)
+    }
+}
+
+// Tables already merged (destination and source) and copied (source and copy) by `deep_merge`.
+#[derive(Default)]
+struct DeepMerge<'lua> {
+    visited: Vec<(Table<'lua>, Table<'lua>)>,
+    copies: Vec<(Table<'lua>, Table<'lua>)>,
 }
 
 impl<'lua> PartialEq for Table<'lua> {
@@ -513,45 +1018,106 @@ where
     type Item = Result<(K, V)>;
 
     fn next(&mut self) -> Option<Self::Item> {
-        if let Some(next_key) = self.next_key.take() {
-            let lua = self.table.lua;
+        let next_key = self.next_key.take()?;
+        let lua = self.table.lua;
+        let res = (|| match table_next(&self.table, next_key, true, true)? {
+            Some((key, value, next_key)) => {
+                self.next_key = Some(next_key);
+                Ok(Some((K::from_lua(key, lua)?, V::from_lua(value, lua)?)))
+            }
+            None => Ok(None),
+        })();
+        res.transpose()
+    }
+}
 
-            let res = (|| {
-                let res = unsafe {
-                    let _sg = StackGuard::new(lua.state);
-                    assert_stack(lua.state, 6);
+/// An iterator over the keys of a Lua table.
+///
+/// This struct is created by the [`Table::keys`] method.
+///
+/// [`Table::keys`]: struct.Table.html#method.keys
+pub struct TableKeys<'lua, K> {
+    table: LuaRef<'lua>,
+    next_key: Option<Value<'lua>>,
+    _phantom: PhantomData<K>,
+}
 
-                    lua.push_ref(&self.table);
-                    lua.push_value(next_key)?;
+impl<'lua, K: FromLua<'lua>> Iterator for TableKeys<'lua, K> {
+    type Item = Result<K>;
 
-                    if protect_lua_closure(lua.state, 2, ffi::LUA_MULTRET, |state| {
-                        ffi::lua_next(state, -2) != 0
-                    })? {
-                        ffi::lua_pushvalue(lua.state, -2);
-                        let key = lua.pop_value();
-                        let value = lua.pop_value();
-                        self.next_key = Some(lua.pop_value());
+    fn next(&mut self) -> Option<Self::Item> {
+        let next_key = self.next_key.take()?;
+        let lua = self.table.lua;
+        let res = (|| match table_next(&self.table, next_key, true, false)? {
+            Some((key, _, next_key)) => {
+                self.next_key = Some(next_key);
+                Ok(Some(K::from_lua(key, lua)?))
+            }
+            None => Ok(None),
+        })();
+        res.transpose()
+    }
+}
 
-                        Some((key, value))
-                    } else {
-                        None
-                    }
-                };
+/// An iterator over the values of a Lua table.
+///
+/// This struct is created by the [`Table::values`] method.
+///
+/// [`Table::values`]: struct.Table.html#method.values
+pub struct TableValues<'lua, V> {
+    table: LuaRef<'lua>,
+    next_key: Option<Value<'lua>>,
+    _phantom: PhantomData<V>,
+}
 
-                Ok(if let Some((key, value)) = res {
-                    Some((K::from_lua(key, lua)?, V::from_lua(value, lua)?))
-                } else {
-                    None
-                })
-            })();
+impl<'lua, V: FromLua<'lua>> Iterator for TableValues<'lua, V> {
+    type Item = Result<V>;
 
-            match res {
-                Ok(Some((key, value))) => Some(Ok((key, value))),
-                Ok(None) => None,
-                Err(e) => Some(Err(e)),
+    fn next(&mut self) -> Option<Self::Item> {
+        let next_key = self.next_key.take()?;
+        let lua = self.table.lua;
+        let res = (|| match table_next(&self.table, next_key, false, true)? {
+            Some((_, value, next_key)) => {
+                self.next_key = Some(next_key);
+                Ok(Some(V::from_lua(value, lua)?))
             }
+            None => Ok(None),
+        })();
+        res.transpose()
+    }
+}
+
+// Calls `lua_next` on `table` with `key`, returning the next key and value along with the key to
+// continue from. A key or value which is not needed is returned as `nil`, to avoid creating a
+// reference for it.
+fn table_next<'lua>(
+    table: &LuaRef<'lua>,
+    key: Value<'lua>,
+    want_key: bool,
+    want_value: bool,
+) -> Result<Option<(Value<'lua>, Value<'lua>, Value<'lua>)>> {
+    let lua = table.lua;
+    unsafe {
+        let _sg = StackGuard::new(lua.state);
+        assert_stack(lua.state, 6);
+
+        lua.push_ref(table);
+        lua.push_value(key)?;
+
+        if protect_lua_closure(lua.state, 2, ffi::LUA_MULTRET, |state| {
+            ffi::lua_next(state, -2) != 0
+        })? {
+            let value = if want_value {
+                lua.pop_value()
+            } else {
+                ffi::lua_pop(lua.state, 1);
+                Nil
+            };
+            let next_key = lua.pop_value();
+            let key = if want_key { next_key.clone() } else { Nil };
+            Ok(Some((key, value, next_key)))
         } else {
-            None
+            Ok(None)
         }
     }
 }
@@ -604,3 +1170,29 @@ where
         }
     }
 }
+
+// Lua does not allow `nil` and NaN as table keys, reject them before calling into Lua.
+fn check_key(key: &Value) -> Result<()> {
+    match *key {
+        Value::Nil => Err(Error::RuntimeError("table index is nil".to_string())),
+        Value::Number(n) if n.is_nan() => {
+            Err(Error::RuntimeError("table index is NaN".to_string()))
+        }
+        _ => Ok(()),
+    }
+}
+
+// Splits a dotted path into the keys of the intermediate tables and the final key
+fn split_path(path: &str) -> (Vec<&str>, &str) {
+    let mut keys = path.split('.').collect::<Vec<_>>();
+    let last = keys.pop().unwrap_or_default();
+    (keys, last)
+}
+
+fn not_a_table(path: &[&str], value: &Value) -> Error {
+    Error::RuntimeError(format!(
+        "'{}' is not a table (got {})",
+        path.join("."),
+        value.type_name()
+    ))
+}
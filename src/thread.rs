@@ -1,7 +1,11 @@
+use std::marker::PhantomData;
+use std::mem;
 use std::os::raw::c_int;
+use std::string::String as StdString;
 
 use crate::error::{Error, Result};
 use crate::ffi;
+use crate::function::Function;
 use crate::types::LuaRef;
 use crate::util::{
     assert_stack, check_stack, error_traceback, pop_error, protect_lua_closure, StackGuard,
@@ -105,7 +109,11 @@ impl<'lua> Thread<'lua> {
                     error_traceback(thread_state);
                     0
                 })?;
-                return Err(pop_error(thread_state, ret));
+                let err = pop_error(thread_state, ret);
+                return Err(match self.name() {
+                    Some(name) => name_traceback(err, &name),
+                    None => err,
+                });
             }
 
             let nresults = ffi::lua_gettop(thread_state);
@@ -121,6 +129,88 @@ impl<'lua> Thread<'lua> {
         R::from_lua_multi(results, lua)
     }
 
+    /// Calls `func` with `args` as the main function of this thread.
+    ///
+    /// This starts `func` as if the thread had been created with it and then resumed with `args`:
+    /// if `func` calls `coroutine.yield`, this returns the yielded values and the thread can be
+    /// continued with [`resume`], otherwise the values returned by `func` are returned. Each thread
+    /// has its own call stack, so the same function can be suspended in several threads at once
+    /// without the calls affecting each other.
+    ///
+    /// Once its main function has returned, a thread can be reused with this method. Returns an
+    /// error if the thread is running or suspended, and [`CoroutineInactive`] if it has raised an
+    /// error before.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use mlua::{Function, Lua, Result};
+    /// # fn main() -> Result<()> {
+    /// # let lua = Lua::new();
+    /// let greet: Function = lua.load(r#"
+    ///     function(name)
+    ///         local greeting = coroutine.yield("ready")
+    ///         return greeting .. " " .. name
+    ///     end
+    /// "#).eval()?;
+    ///
+    /// let thread = lua.create_thread(lua.load("function() end").eval()?)?;
+    /// thread.resume::<_, ()>(())?;
+    ///
+    /// assert_eq!(thread.call::<_, String>(greet.clone(), "lua")?, "ready");
+    /// assert_eq!(thread.resume::<_, String>("hello")?, "hello lua");
+    ///
+    /// // The thread can be reused for another call
+    /// assert_eq!(thread.call::<_, String>(greet, "rust")?, "ready");
+    /// # Ok(())
+    /// # }
+    /// ```
+    ///
+    /// [`resume`]: #method.resume
+    /// [`CoroutineInactive`]: enum.Error.html#variant.CoroutineInactive
+    pub fn call<A, R>(&self, func: Function<'lua>, args: A) -> Result<R>
+    where
+        A: ToLuaMulti<'lua>,
+        R: FromLuaMulti<'lua>,
+    {
+        let lua = self.0.lua;
+        let args = args.to_lua_multi(lua)?;
+        unsafe {
+            let _sg = StackGuard::new(lua.state);
+            assert_stack(lua.state, 2);
+
+            lua.push_ref(&self.0);
+            let thread_state = ffi::lua_tothread(lua.state, -1);
+
+            let status = ffi::lua_status(thread_state);
+            if status != ffi::LUA_OK && status != ffi::LUA_YIELD {
+                return Err(Error::CoroutineInactive);
+            }
+            let mut ar: ffi::lua_Debug = mem::zeroed();
+            if status == ffi::LUA_YIELD
+                || ffi::lua_gettop(thread_state) != 0
+                || ffi::lua_getstack(thread_state, 0, &mut ar) != 0
+            {
+                return Err(Error::RuntimeError(
+                    "cannot call a function in a running or suspended thread".to_string(),
+                ));
+            }
+
+            check_stack(thread_state, 1)?;
+            lua.push_ref_checked(&func.0)?;
+            ffi::lua_xmove(lua.state, thread_state, 1);
+        }
+        self.resume(args)
+    }
+
+    /// Returns the name given to the thread by [`Lua::create_thread_with_name`], if any.
+    ///
+    /// [`Lua::create_thread_with_name`]: struct.Lua.html#method.create_thread_with_name
+    pub fn name(&self) -> Option<StdString> {
+        let names = self.0.lua.thread_names().ok()?;
+        names.raw_get(self.clone()).ok()?
+    }
+
     /// Gets the status of the thread.
     pub fn status(&self) -> ThreadStatus {
         let lua = self.0.lua;
@@ -142,6 +232,116 @@ impl<'lua> Thread<'lua> {
             }
         }
     }
+
+    /// Consumes the thread, returning an iterator over the values it yields.
+    ///
+    /// Each call to `next` resumes the thread without arguments and converts the yielded values to
+    /// `V`. Iteration stops once the thread returns from its main function, the returned values are
+    /// then available through [`ThreadIterator::take_return_values`]. An error raised by the
+    /// thread is yielded once and ends the iteration.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use mlua::{Lua, Result, Thread};
+    /// # fn main() -> Result<()> {
+    /// # let lua = Lua::new();
+    /// let thread: Thread = lua.load(r#"
+    ///     coroutine.create(function()
+    ///         for i = 1, 3 do
+    ///             coroutine.yield(i * 10)
+    ///         end
+    ///         return "done"
+    ///     end)
+    /// "#).eval()?;
+    ///
+    /// let mut iter = thread.into_iter::<u32>();
+    /// let values = iter.by_ref().collect::<Result<Vec<_>>>()?;
+    /// assert_eq!(values, vec![10, 20, 30]);
+    ///
+    /// let ret = iter.take_return_values().unwrap();
+    /// assert_eq!(lua.unpack_multi::<String>(ret)?, "done");
+    /// # Ok(())
+    /// # }
+    /// ```
+    ///
+    /// [`ThreadIterator::take_return_values`]: struct.ThreadIterator.html#method.take_return_values
+    #[allow(clippy::should_implement_trait)]
+    pub fn into_iter<V: FromLuaMulti<'lua>>(self) -> ThreadIterator<'lua, V> {
+        ThreadIterator {
+            thread: self,
+            return_values: None,
+            done: false,
+            _phantom: PhantomData,
+        }
+    }
+}
+
+// Adds the name of a thread to the traceback of an error raised inside it.
+fn name_traceback(err: Error, name: &str) -> Error {
+    let header = format!("stack traceback (coroutine '{}'):", name);
+    match err {
+        Error::CallbackError { traceback, cause } => Error::CallbackError {
+            traceback: traceback.replacen("stack traceback:", &header, 1),
+            cause,
+        },
+        Error::RuntimeError(message) => {
+            Error::RuntimeError(message.replacen("stack traceback:", &header, 1))
+        }
+        err => err,
+    }
+}
+
+/// An iterator over the values yielded by a Lua thread.
+///
+/// This struct is created by the [`Thread::into_iter`] method.
+///
+/// [`Thread::into_iter`]: struct.Thread.html#method.into_iter
+pub struct ThreadIterator<'lua, V> {
+    thread: Thread<'lua>,
+    return_values: Option<MultiValue<'lua>>,
+    done: bool,
+    _phantom: PhantomData<V>,
+}
+
+impl<'lua, V> ThreadIterator<'lua, V> {
+    /// Takes the values returned by the main function of the thread.
+    ///
+    /// Returns `None` if the thread has not finished yet, has failed with an error, or if the
+    /// values were already taken.
+    pub fn take_return_values(&mut self) -> Option<MultiValue<'lua>> {
+        self.return_values.take()
+    }
+}
+
+impl<'lua, V> Iterator for ThreadIterator<'lua, V>
+where
+    V: FromLuaMulti<'lua>,
+{
+    type Item = Result<V>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.done || self.thread.status() != ThreadStatus::Resumable {
+            return None;
+        }
+
+        let lua = self.thread.0.lua;
+        match self.thread.resume::<_, MultiValue>(()) {
+            Ok(values) => {
+                if self.thread.status() == ThreadStatus::Resumable {
+                    Some(V::from_lua_multi(values, lua))
+                } else {
+                    self.done = true;
+                    self.return_values = Some(values);
+                    None
+                }
+            }
+            Err(err) => {
+                self.done = true;
+                Some(Err(err))
+            }
+        }
+    }
 }
 
 impl<'lua> PartialEq for Thread<'lua> {
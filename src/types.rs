@@ -1,25 +1,96 @@
+use std::cell::RefCell;
+use std::mem::ManuallyDrop;
 use std::os::raw::{c_int, c_void};
 use std::sync::{Arc, Mutex};
+use std::thread::{self, ThreadId};
 use std::{fmt, mem, ptr};
 
 use crate::error::Result;
 use crate::ffi;
+use crate::hook::Debug;
 use crate::lua::Lua;
+use crate::table::Table;
 use crate::util::{assert_stack, StackGuard};
-use crate::value::MultiValue;
+use crate::value::{MultiValue, Value};
 
 /// Type of Lua integer numbers.
 pub type Integer = ffi::lua_Integer;
 /// Type of Lua floating point numbers.
 pub type Number = ffi::lua_Number;
 
+/// Version of the Lua runtime, returned by [`Lua::version`].
+///
+/// [`Lua::version`]: struct.Lua.html#method.version
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Hash)]
+pub enum Version {
+    /// Lua 5.1
+    Lua51,
+    /// Lua 5.2
+    Lua52,
+    /// Lua 5.3
+    Lua53,
+    /// LuaJIT, which implements Lua 5.1
+    LuaJIT,
+}
+
 /// A "light" userdata value. Equivalent to an unmanaged raw pointer.
+///
+/// Light userdata is a plain value: it has no metatable of its own (all light userdata share one
+/// global metatable, if any), is never collected and is compared by address. This makes it
+/// suitable for passing pointers to C libraries, e.g. as registry keys or callback payloads.
+///
+/// Neither Lua nor mlua track what the pointer refers to. Creating a `LightUserData` is safe, but
+/// nothing prevents the pointee from being freed or moved while Lua still holds the pointer, so
+/// dereferencing a pointer obtained back from Lua is only sound if its owner guarantees that it is
+/// still valid. Any Lua code can also pass around and compare the pointer, although it can not
+/// dereference it.
 #[derive(Debug, Copy, Clone, Eq, PartialEq)]
 pub struct LightUserData(pub *mut c_void);
 
 pub(crate) type Callback<'lua, 'a> =
     Box<dyn Fn(&'lua Lua, MultiValue<'lua>) -> Result<MultiValue<'lua>> + 'a>;
 
+pub(crate) type IndexTableCallback<'lua> = Box<dyn FnOnce(&'lua Lua) -> Result<Table<'lua>> + 'lua>;
+
+pub(crate) type MetatableProtectionCallback<'lua> =
+    Box<dyn FnOnce(&'lua Lua) -> Result<Value<'lua>> + 'lua>;
+
+pub(crate) type HookCallback = Arc<RefCell<dyn FnMut(&Lua, Debug) -> Result<()> + Send>>;
+
+// A value that may only be used on the thread that created it, so that `Send` is not required.
+//
+// The value can not be accessed from other threads, and if it is dropped on another thread it is
+// leaked instead.
+pub(crate) struct ThreadBound<T> {
+    thread: ThreadId,
+    value: ManuallyDrop<T>,
+}
+
+impl<T> ThreadBound<T> {
+    pub(crate) fn new(value: T) -> ThreadBound<T> {
+        ThreadBound {
+            thread: thread::current().id(),
+            value: ManuallyDrop::new(value),
+        }
+    }
+
+    pub(crate) fn get(&self) -> Option<&T> {
+        if thread::current().id() == self.thread {
+            Some(&self.value)
+        } else {
+            None
+        }
+    }
+}
+
+impl<T> Drop for ThreadBound<T> {
+    fn drop(&mut self) {
+        if thread::current().id() == self.thread {
+            unsafe { ManuallyDrop::drop(&mut self.value) }
+        }
+    }
+}
+
 /// An auto generated key into the Lua registry.
 ///
 /// This is a handle to a value stored inside the Lua registry.  It is not directly usable like the
@@ -94,6 +165,19 @@ impl<'lua> Drop for LuaRef<'lua> {
     }
 }
 
+impl<'lua> LuaRef<'lua> {
+    // Returns the address of the referenced object, which identifies it for its whole lifetime.
+    pub(crate) fn to_pointer(&self) -> *const c_void {
+        let lua = self.lua;
+        unsafe {
+            let _sg = StackGuard::new(lua.state);
+            assert_stack(lua.state, 1);
+            lua.push_ref(self);
+            ffi::lua_topointer(lua.state, -1)
+        }
+    }
+}
+
 impl<'lua> PartialEq for LuaRef<'lua> {
     fn eq(&self, other: &Self) -> bool {
         let lua = self.lua;
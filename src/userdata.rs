@@ -1,4 +1,8 @@
 use std::cell::{Ref, RefCell, RefMut};
+use std::cmp::Ordering;
+use std::hash::{Hash, Hasher};
+use std::os::raw::c_int;
+use std::string::String as StdString;
 
 use crate::error::{Error, Result};
 use crate::ffi;
@@ -6,8 +10,11 @@ use crate::function::Function;
 use crate::lua::Lua;
 use crate::table::Table;
 use crate::types::LuaRef;
-use crate::util::{assert_stack, get_userdata, StackGuard};
-use crate::value::{FromLua, FromLuaMulti, ToLua, ToLuaMulti};
+use crate::util::{
+    assert_stack, check_stack, get_userdata, protect_lua, push_existing_userdata_methods,
+    push_userdata_methods, StackGuard,
+};
+use crate::value::{FromLua, FromLuaMulti, MultiValue, Nil, ToLua, ToLuaMulti, Value};
 
 /// Kinds of metamethods that can be overridden.
 ///
@@ -71,6 +78,13 @@ pub enum MetaMethod {
     /// The `__tostring` metamethod.
     ///
     /// This is not an operator, but will be called by methods such as `tostring` and `print`.
+    ///
+    /// The handler must return a string (a Lua [`String`] can be used for binary data) or a number,
+    /// which is converted to a string. Returning any other value results in a
+    /// [`ToLuaConversionError`].
+    ///
+    /// [`String`]: struct.String.html
+    /// [`ToLuaConversionError`]: enum.Error.html#variant.ToLuaConversionError
     ToString,
     #[cfg(any(feature = "lua53", feature = "lua52"))]
     /// The `__pairs` metamethod.
@@ -123,12 +137,53 @@ impl MetaMethod {
             MetaMethod::IPairs => b"__ipairs",
         }
     }
+
+    // Validates the values returned by a metamethod handler before passing them to Lua.
+    //
+    // `__tostring` must produce a string, so numbers are coerced and any other value is reported
+    // as a conversion error instead of the generic Lua error raised by `tostring`.
+    pub(crate) fn validate_result<'lua>(
+        self,
+        lua: &'lua Lua,
+        mut results: MultiValue<'lua>,
+    ) -> Result<MultiValue<'lua>> {
+        match self {
+            MetaMethod::ToString => {
+                let value = results.pop_front().unwrap_or(Nil);
+                let ty = value.type_name();
+                match lua.coerce_string(value)? {
+                    Some(s) => Ok(MultiValue::from_vec(vec![Value::String(s)])),
+                    None => Err(Error::ToLuaConversionError {
+                        from: ty,
+                        to: "string",
+                        message: Some("__tostring must return a string or a number".to_string()),
+                    }),
+                }
+            }
+            _ => Ok(results),
+        }
+    }
+}
+
+/// Lookup order of the `__index` metamethod of userdata with both methods and an `Index` handler.
+///
+/// Set with [`UserDataMethods::set_index_priority`].
+///
+/// [`UserDataMethods::set_index_priority`]: trait.UserDataMethods.html#tymethod.set_index_priority
+#[derive(Debug, Copy, Clone, Default, Eq, PartialEq, Hash)]
+pub enum IndexPriority {
+    /// Look up registered methods (and the index table, if any) first, then call the handler if
+    /// no method is found. This is the default.
+    #[default]
+    MethodsFirst,
+    /// Call the handler first, then look up registered methods if the handler returns `nil`.
+    HandlerFirst,
 }
 
 /// Method registry for [`UserData`] implementors.
 ///
 /// [`UserData`]: trait.UserData.html
-pub trait UserDataMethods<'lua, T: UserData> {
+pub trait UserDataMethods<'lua, T> {
     /// Add a method which accepts a `&T` as the first parameter.
     ///
     /// Regular methods are implemented by overriding the `__index` metamethod and returning the
@@ -147,6 +202,11 @@ pub trait UserDataMethods<'lua, T: UserData> {
     ///
     /// Refer to [`add_method`] for more information about the implementation.
     ///
+    /// If the userdata is already borrowed when the method is called, for example because the
+    /// method calls back into Lua which then calls another method on the same userdata, the call
+    /// fails with [`Error::UserDataBorrowMutError`] instead of panicking.
+    ///
+    /// [`Error::UserDataBorrowMutError`]: enum.Error.html#variant.UserDataBorrowMutError
     /// [`add_method`]: #method.add_method
     fn add_method_mut<S, A, R, M>(&mut self, name: &S, method: M)
     where
@@ -232,6 +292,350 @@ pub trait UserDataMethods<'lua, T: UserData> {
         A: FromLuaMulti<'lua>,
         R: ToLuaMulti<'lua>,
         F: 'static + Send + FnMut(&'lua Lua, A) -> Result<R>;
+
+    /// Sets a table to look up keys which are not registered methods.
+    ///
+    /// Indexing the userdata first checks the methods added with [`add_method`] and friends, then
+    /// the given table (including its own `__index` chain), and finally a custom
+    /// [`MetaMethod::Index`] metamethod, if one was added. This allows sharing a table of constants
+    /// or Lua-defined methods between userdata types without wrapping each key in a closure.
+    ///
+    /// The table can only be created with a `Lua` handle, so this is mostly useful with
+    /// [`Lua::register_userdata_with`]. Use [`set_index_table_with`] to create the table when the
+    /// metatable is built instead. Setting an index table again replaces the previous one.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use mlua::{Lua, Result, UserDataMethods};
+    /// # fn main() -> Result<()> {
+    /// # let lua = Lua::new();
+    /// struct Circle(f64);
+    ///
+    /// let shared = lua.load("{ kind = 'shape', describe = function(self) return self.kind end }").eval()?;
+    /// lua.register_userdata_with::<Circle, _>(|methods| {
+    ///     methods.add_method("area", |_, c, ()| Ok(3.0 * c.0 * c.0));
+    ///     methods.set_index_table(shared);
+    /// })?;
+    ///
+    /// lua.globals().set("circle", lua.create_registered_userdata(Circle(1.0))?)?;
+    /// assert_eq!(lua.load("circle:area()").eval::<f64>()?, 3.0);
+    /// assert_eq!(lua.load("circle:describe()").eval::<String>()?, "shape");
+    /// # Ok(())
+    /// # }
+    /// ```
+    ///
+    /// [`add_method`]: #tymethod.add_method
+    /// [`set_index_table_with`]: #tymethod.set_index_table_with
+    /// [`MetaMethod::Index`]: enum.MetaMethod.html#variant.Index
+    /// [`Lua::register_userdata_with`]: struct.Lua.html#method.register_userdata_with
+    fn set_index_table(&mut self, table: Table<'lua>) {
+        self.set_index_table_with(move |_| Ok(table))
+    }
+
+    /// Sets a table to look up keys which are not registered methods, creating it lazily.
+    ///
+    /// The function is called once, when the userdata metatable is built, e.g. to fetch a
+    /// class table defined in Lua from within [`UserData::add_methods`]. An error returned by it
+    /// is returned from the operation which built the metatable. Refer to [`set_index_table`] for
+    /// the lookup order.
+    ///
+    /// [`UserData::add_methods`]: trait.UserData.html#method.add_methods
+    /// [`set_index_table`]: #method.set_index_table
+    fn set_index_table_with<F>(&mut self, f: F)
+    where
+        F: 'lua + FnOnce(&'lua Lua) -> Result<Table<'lua>>;
+
+    /// Sets whether registered methods or the [`MetaMethod::Index`] handler are consulted first.
+    ///
+    /// This only matters if both methods (or an index table, see [`set_index_table`]) and an
+    /// `Index` handler are added. The generated `__index` function then behaves as follows for
+    /// `obj[key]`:
+    ///
+    /// - [`IndexPriority::MethodsFirst`] (the default): if `key` is a registered method, or is found
+    ///   in the index table, that value is returned. Otherwise the handler is called with
+    ///   `(obj, key)` and its first result is returned.
+    /// - [`IndexPriority::HandlerFirst`]: the handler is called with `(obj, key)` first. If its
+    ///   first result is not `nil` it is returned, otherwise `key` is looked up in the registered
+    ///   methods and the index table.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use mlua::{IndexPriority, Lua, MetaMethod, Result, UserData, UserDataMethods};
+    /// # fn main() -> Result<()> {
+    /// # let lua = Lua::new();
+    /// struct Proxy;
+    ///
+    /// impl UserData for Proxy {
+    ///     fn add_methods<'lua, M: UserDataMethods<'lua, Self>>(methods: &mut M) {
+    ///         methods.add_method("len", |_, _, ()| Ok(0));
+    ///         methods.add_meta_method(MetaMethod::Index, |_, _, key: String| {
+    ///             Ok(if key == "len" { Some(42) } else { None })
+    ///         });
+    ///         methods.set_index_priority(IndexPriority::HandlerFirst);
+    ///     }
+    /// }
+    ///
+    /// lua.globals().set("proxy", Proxy)?;
+    /// assert_eq!(lua.load("proxy.len").eval::<i64>()?, 42);
+    /// assert_eq!(lua.load("type(proxy.other)").eval::<String>()?, "nil");
+    /// # Ok(())
+    /// # }
+    /// ```
+    ///
+    /// [`MetaMethod::Index`]: enum.MetaMethod.html#variant.Index
+    /// [`set_index_table`]: #method.set_index_table
+    /// [`IndexPriority::MethodsFirst`]: enum.IndexPriority.html#variant.MethodsFirst
+    /// [`IndexPriority::HandlerFirst`]: enum.IndexPriority.html#variant.HandlerFirst
+    fn set_index_priority(&mut self, priority: IndexPriority);
+
+    /// Caches which keys resolve to registered methods in the generated `__index` function.
+    ///
+    /// With [`IndexPriority::HandlerFirst`], every method lookup such as `obj:method()` first calls
+    /// the [`MetaMethod::Index`] handler and only falls back to the methods once it returned
+    /// `nil`. With the cache enabled, a key that was resolved to a method once is looked up in the
+    /// methods directly on later accesses, without calling the handler. This trades a little memory
+    /// for faster method calls, but the handler is then no longer consulted for such keys, so it
+    /// should not start returning values for keys that name methods.
+    ///
+    /// With the default [`IndexPriority::MethodsFirst`] methods are always found without calling
+    /// the handler, so this has no effect.
+    ///
+    /// [`IndexPriority::HandlerFirst`]: enum.IndexPriority.html#variant.HandlerFirst
+    /// [`IndexPriority::MethodsFirst`]: enum.IndexPriority.html#variant.MethodsFirst
+    /// [`MetaMethod::Index`]: enum.MetaMethod.html#variant.Index
+    fn enable_method_cache(&mut self);
+
+    /// Sets the value of the `__metatable` field of the userdata metatable.
+    ///
+    /// The metatable of userdata is always protected from scripts: by default `getmetatable`
+    /// returns `false` for userdata values and `setmetatable` can't be used on them. This method
+    /// changes the value returned by `getmetatable`, for example to a string describing the type.
+    /// Setting it to `nil` keeps the default.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use mlua::{Lua, Result, UserData, UserDataMethods};
+    /// # fn main() -> Result<()> {
+    /// # let lua = Lua::new();
+    /// struct Handle;
+    ///
+    /// impl UserData for Handle {
+    ///     fn add_methods<'lua, M: UserDataMethods<'lua, Self>>(methods: &mut M) {
+    ///         methods.set_metatable_protection("Handle");
+    ///     }
+    /// }
+    ///
+    /// lua.globals().set("handle", Handle)?;
+    /// assert_eq!(lua.load("getmetatable(handle)").eval::<String>()?, "Handle");
+    /// # Ok(())
+    /// # }
+    /// ```
+    fn set_metatable_protection<V>(&mut self, value: V)
+    where
+        V: 'lua + ToLua<'lua>;
+
+    /// Add a method which returns an iterator for use in a generic `for` loop.
+    ///
+    /// Calling the method from Lua returns an iterator function that yields the items of the
+    /// iterator created by `method`, converting each item with [`ToLuaMulti`], so scripts can
+    /// write `for k, v in obj:entries() do ... end`. The loop ends when the iterator is exhausted
+    /// or when the first value of an item is `nil`.
+    ///
+    /// The iterator can not borrow the userdata, clone or collect the data it needs instead.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use mlua::{Lua, Result, UserData, UserDataMethods};
+    /// # fn main() -> Result<()> {
+    /// # let lua = Lua::new();
+    /// struct Inventory(Vec<(String, u32)>);
+    ///
+    /// impl UserData for Inventory {
+    ///     fn add_methods<'lua, M: UserDataMethods<'lua, Self>>(methods: &mut M) {
+    ///         methods.add_iterator_method("entries", |_, inv| Ok(inv.0.clone().into_iter()));
+    ///     }
+    /// }
+    ///
+    /// let inv = Inventory(vec![("apple".to_owned(), 3), ("pear".to_owned(), 2)]);
+    /// lua.globals().set("inv", inv)?;
+    /// let total: u32 = lua.load(r#"
+    ///     local total = 0
+    ///     for name, count in inv:entries() do total = total + count end
+    ///     return total
+    /// "#).eval()?;
+    /// assert_eq!(total, 5);
+    /// # Ok(())
+    /// # }
+    /// ```
+    ///
+    /// [`ToLuaMulti`]: trait.ToLuaMulti.html
+    fn add_iterator_method<S, I, M>(&mut self, name: &S, method: M)
+    where
+        S: ?Sized + AsRef<[u8]>,
+        I: 'static + Send + Iterator,
+        I::Item: ToLuaMulti<'lua>,
+        M: 'static + Send + Fn(&'lua Lua, &T) -> Result<I>,
+    {
+        self.add_method(name, move |lua, data, ()| {
+            let mut iter = method(lua, data)?;
+            lua.create_function_mut(move |lua: &'lua Lua, ()| match iter.next() {
+                Some(item) => item.to_lua_multi(lua),
+                None => Ok(MultiValue::new()),
+            })
+        })
+    }
+
+    /// Add a `variant_name` method returning the name of the active enum variant.
+    ///
+    /// This lets scripts branch on the variant of a tagged union without writing a getter for
+    /// every variant. [`VariantName`] can be implemented by hand or, with the `macros` feature,
+    /// derived with `#[derive(mlua::VariantName)]`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use mlua::{Lua, Result, UserData, UserDataMethods, VariantName};
+    /// # fn main() -> Result<()> {
+    /// # let lua = Lua::new();
+    /// enum Shape {
+    ///     Circle(f64),
+    ///     Square(f64),
+    /// }
+    ///
+    /// impl VariantName for Shape {
+    ///     fn variant_name(&self) -> &'static str {
+    ///         match self {
+    ///             Shape::Circle(_) => "Circle",
+    ///             Shape::Square(_) => "Square",
+    ///         }
+    ///     }
+    /// }
+    ///
+    /// impl UserData for Shape {
+    ///     fn add_methods<'lua, M: UserDataMethods<'lua, Self>>(methods: &mut M) {
+    ///         methods.add_variant_name_method();
+    ///         methods.add_method("size", |_, shape, ()| match shape {
+    ///             Shape::Circle(size) | Shape::Square(size) => Ok(*size),
+    ///         });
+    ///     }
+    /// }
+    ///
+    /// lua.globals().set("circle", Shape::Circle(1.0))?;
+    /// lua.globals().set("square", Shape::Square(2.0))?;
+    /// lua.load(r#"
+    ///     assert(circle:variant_name() == "Circle")
+    ///     assert(square:variant_name() == "Square" and square:size() == 2)
+    /// "#).exec()?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    ///
+    /// [`VariantName`]: trait.VariantName.html
+    fn add_variant_name_method(&mut self)
+    where
+        T: VariantName,
+    {
+        self.add_method("variant_name", |_, data, ()| Ok(data.variant_name()));
+    }
+
+    /// Add a method like [`add_method`], naming its arguments in conversion error messages.
+    ///
+    /// `arg_names` lists the names of the arguments following `self`. When an argument can not be
+    /// converted, the resulting [`Error::BadArgument`] refers to it by name, e.g.
+    /// `bad argument 'count' to 'times' (i64 expected, got string)`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use mlua::{Lua, Result, UserData, UserDataMethods};
+    /// # fn main() -> Result<()> {
+    /// # let lua = Lua::new();
+    /// struct Text(String);
+    ///
+    /// impl UserData for Text {
+    ///     fn add_methods<'lua, M: UserDataMethods<'lua, Self>>(methods: &mut M) {
+    ///         methods.add_method_named_args(
+    ///             "times",
+    ///             &["count", "sep"],
+    ///             |_, text, (count, sep): (usize, String)| Ok(vec![text.0.as_str(); count].join(&sep)),
+    ///         );
+    ///     }
+    /// }
+    ///
+    /// lua.globals().set("text", Text("ab".to_owned()))?;
+    /// let err: String = lua.load(r#"
+    ///     local ok, err = pcall(function() return text:times("x", "-") end)
+    ///     return tostring(err)
+    /// "#).eval()?;
+    /// assert_eq!(err, "bad argument 'count' to 'times' (usize expected, got string)");
+    /// # Ok(())
+    /// # }
+    /// ```
+    ///
+    /// [`add_method`]: #method.add_method
+    /// [`Error::BadArgument`]: enum.Error.html#variant.BadArgument
+    fn add_method_named_args<S, A, R, M>(&mut self, name: &S, arg_names: &[&str], method: M)
+    where
+        S: ?Sized + AsRef<[u8]>,
+        A: FromLuaMulti<'lua>,
+        R: ToLuaMulti<'lua>,
+        M: 'static + Send + Fn(&'lua Lua, &T, A) -> Result<R>,
+    {
+        let arg_names = arg_names.iter().map(|&n| n.to_owned()).collect::<Vec<_>>();
+        self.add_method(name, move |lua, data, args: MultiValue<'lua>| {
+            let args = A::from_lua_args(args, 2, None, lua)
+                .map_err(|err| name_argument(err, 2, &arg_names))?;
+            method(lua, data, args)
+        })
+    }
+
+    /// Add a function like [`add_function`], naming its arguments in conversion error messages.
+    ///
+    /// `arg_names` lists the names of all arguments, including the userdata itself when the
+    /// function is called with method syntax. Refer to [`add_method_named_args`] for details.
+    ///
+    /// [`add_function`]: #method.add_function
+    /// [`add_method_named_args`]: #method.add_method_named_args
+    fn add_function_named_args<S, A, R, F>(&mut self, name: &S, arg_names: &[&str], function: F)
+    where
+        S: ?Sized + AsRef<[u8]>,
+        A: FromLuaMulti<'lua>,
+        R: ToLuaMulti<'lua>,
+        F: 'static + Send + Fn(&'lua Lua, A) -> Result<R>,
+    {
+        let arg_names = arg_names.iter().map(|&n| n.to_owned()).collect::<Vec<_>>();
+        self.add_function(name, move |lua, args: MultiValue<'lua>| {
+            let args = A::from_lua_args(args, 1, None, lua)
+                .map_err(|err| name_argument(err, 1, &arg_names))?;
+            function(lua, args)
+        })
+    }
+}
+
+// Sets the name of the argument referred to by a `BadArgument` error, `first` is the position of
+// the first named argument.
+fn name_argument(err: Error, first: usize, arg_names: &[StdString]) -> Error {
+    match err {
+        Error::BadArgument {
+            to,
+            pos,
+            name: None,
+            cause,
+        } => Error::BadArgument {
+            to,
+            pos,
+            name: pos
+                .checked_sub(first)
+                .and_then(|i| arg_names.get(i))
+                .cloned(),
+            cause,
+        },
+        err => err,
+    }
 }
 
 /// Trait for custom userdata types.
@@ -301,6 +705,28 @@ pub trait UserDataMethods<'lua, T: UserData> {
 pub trait UserData: Sized {
     /// Adds custom methods and operators specific to this userdata.
     fn add_methods<'lua, T: UserDataMethods<'lua, Self>>(_methods: &mut T) {}
+
+    /// Returns the name stored as `__name` in the metatable of this userdata.
+    ///
+    /// Lua 5.3 uses `__name` in error messages and, when there is no `__tostring` metamethod, in
+    /// the default string conversion, which then looks like `name: 0x55d0c8a1b2c8`. It can be read
+    /// back with [`AnyUserData::type_name`].
+    ///
+    /// [`AnyUserData::type_name`]: struct.AnyUserData.html#method.type_name
+    fn name() -> Option<&'static str> {
+        None
+    }
+}
+
+/// Trait for enums that can report the name of their active variant.
+///
+/// Used by [`UserDataMethods::add_variant_name_method`]. With the `macros` feature enabled it can
+/// be derived for enums with `#[derive(mlua::VariantName)]`.
+///
+/// [`UserDataMethods::add_variant_name_method`]: trait.UserDataMethods.html#method.add_variant_name_method
+pub trait VariantName {
+    /// Returns the name of the active variant.
+    fn variant_name(&self) -> &'static str;
 }
 
 /// Handle to an internal Lua userdata for any type that implements [`UserData`].
@@ -324,7 +750,7 @@ pub struct AnyUserData<'lua>(pub(crate) LuaRef<'lua>);
 
 impl<'lua> AnyUserData<'lua> {
     /// Checks whether the type of this userdata is `T`.
-    pub fn is<T: 'static + UserData>(&self) -> bool {
+    pub fn is<T: 'static>(&self) -> bool {
         match self.inspect(|_: &RefCell<T>| Ok(())) {
             Ok(()) => true,
             Err(Error::UserDataTypeMismatch) => false,
@@ -338,7 +764,7 @@ impl<'lua> AnyUserData<'lua> {
     ///
     /// Returns a `UserDataBorrowError` if the userdata is already mutably borrowed. Returns a
     /// `UserDataTypeMismatch` if the userdata is not of type `T`.
-    pub fn borrow<T: 'static + UserData>(&self) -> Result<Ref<T>> {
+    pub fn borrow<T: 'static>(&self) -> Result<Ref<T>> {
         self.inspect(|cell| Ok(cell.try_borrow().map_err(|_| Error::UserDataBorrowError)?))
     }
 
@@ -348,7 +774,7 @@ impl<'lua> AnyUserData<'lua> {
     ///
     /// Returns a `UserDataBorrowMutError` if the userdata is already borrowed. Returns a
     /// `UserDataTypeMismatch` if the userdata is not of type `T`.
-    pub fn borrow_mut<T: 'static + UserData>(&self) -> Result<RefMut<T>> {
+    pub fn borrow_mut<T: 'static>(&self) -> Result<RefMut<T>> {
         self.inspect(|cell| {
             Ok(cell
                 .try_borrow_mut()
@@ -400,6 +826,141 @@ impl<'lua> AnyUserData<'lua> {
         V::from_lua(res, lua)
     }
 
+    /// Looks up the method `name` of this userdata and returns it as a [`Function`].
+    ///
+    /// The lookup goes through the `__index` metamethod, exactly like `ud.name` in Lua. The
+    /// returned function is *unbound*: it expects the userdata as its first argument, the same way
+    /// `ud.name(ud, ...)` would be called from Lua. Use [`get_bound_method`] to get a function that
+    /// already carries `self`.
+    ///
+    /// Returns an error if the method does not exist or is not a function.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use mlua::{Lua, Result, UserData, UserDataMethods};
+    /// # fn main() -> Result<()> {
+    /// # let lua = Lua::new();
+    /// struct Counter(i64);
+    ///
+    /// impl UserData for Counter {
+    ///     fn add_methods<'lua, M: UserDataMethods<'lua, Self>>(methods: &mut M) {
+    ///         methods.add_method("get", |_, this, ()| Ok(this.0));
+    ///     }
+    /// }
+    ///
+    /// let ud = lua.create_userdata(Counter(7))?;
+    /// let get = ud.get_function("get")?;
+    /// assert_eq!(get.call::<_, i64>(ud.clone())?, 7);
+    /// # Ok(())
+    /// # }
+    /// ```
+    ///
+    /// [`Function`]: struct.Function.html
+    /// [`get_bound_method`]: #method.get_bound_method
+    pub fn get_function(&self, name: &str) -> Result<Function<'lua>> {
+        let lua = self.0.lua;
+        let value = unsafe {
+            let _sg = StackGuard::new(lua.state);
+            assert_stack(lua.state, 5);
+
+            lua.push_ref(&self.0);
+            lua.push_value(Value::String(lua.create_string(name)?))?;
+
+            unsafe extern "C" fn get_field(state: *mut ffi::lua_State) -> c_int {
+                ffi::lua_gettable(state, -2);
+                1
+            }
+            protect_lua(lua.state, 2, get_field)?;
+            lua.pop_value()
+        };
+        match value {
+            Value::Function(func) => Ok(func),
+            value => Err(Error::FromLuaConversionError {
+                from: value.type_name(),
+                to: "Function",
+                message: Some(format!("userdata has no method '{}'", name)),
+            }),
+        }
+    }
+
+    /// Looks up the method `name` of this userdata and returns it bound to `self`.
+    ///
+    /// This is like [`get_function`], but the returned function already has this userdata as its
+    /// first argument, so it behaves like `function(...) return ud:name(...) end` in Lua.
+    ///
+    /// [`get_function`]: #method.get_function
+    pub fn get_bound_method(&self, name: &str) -> Result<Function<'lua>> {
+        self.get_function(name)?.bind(self.clone())
+    }
+
+    /// Returns the `__name` field of the userdata metatable, if any.
+    ///
+    /// For userdata created from a [`UserData`] type, this is the value returned by
+    /// [`UserData::name`].
+    ///
+    /// [`UserData`]: trait.UserData.html
+    /// [`UserData::name`]: trait.UserData.html#method.name
+    pub fn type_name(&self) -> Option<StdString> {
+        let metatable = self.get_metatable().ok()?;
+        metatable.raw_get("__name").ok()?
+    }
+
+    /// Returns the names of the methods registered for this userdata, sorted alphabetically.
+    ///
+    /// The names are read from the methods table backing the `__index` metamethod, which holds the
+    /// methods added with [`UserDataMethods`] or [`UserDataMetatable::set_method`]. Metamethods,
+    /// fields served by an `__index` handler and keys of an index table are not included.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use mlua::{Lua, Result, UserData, UserDataMethods};
+    /// # fn main() -> Result<()> {
+    /// # let lua = Lua::new();
+    /// struct Player;
+    ///
+    /// impl UserData for Player {
+    ///     fn add_methods<'lua, M: UserDataMethods<'lua, Self>>(methods: &mut M) {
+    ///         methods.add_method("name", |_, _, ()| Ok("player"));
+    ///         methods.add_method("jump", |_, _, ()| Ok(()));
+    ///     }
+    /// }
+    ///
+    /// let player = lua.create_userdata(Player)?;
+    /// assert_eq!(player.method_names()?, vec!["jump", "name"]);
+    /// # Ok(())
+    /// # }
+    /// ```
+    ///
+    /// [`UserDataMethods`]: trait.UserDataMethods.html
+    /// [`UserDataMetatable::set_method`]: struct.UserDataMetatable.html#method.set_method
+    pub fn method_names(&self) -> Result<Vec<StdString>> {
+        let lua = self.0.lua;
+        let methods = unsafe {
+            let _sg = StackGuard::new(lua.state);
+            assert_stack(lua.state, 6);
+
+            lua.push_ref(&self.0);
+            if ffi::lua_getmetatable(lua.state, -1) == 0 {
+                return Err(Error::UserDataTypeMismatch);
+            }
+            if !push_existing_userdata_methods(lua.state, -1)? {
+                return Ok(Vec::new());
+            }
+            Table(lua.pop_ref())
+        };
+
+        let mut names = Vec::new();
+        for pair in methods.pairs::<Value, Value>() {
+            if let (Value::String(name), _) = pair? {
+                names.push(StdString::from_utf8_lossy(name.as_bytes()).into_owned());
+            }
+        }
+        names.sort();
+        Ok(names)
+    }
+
     fn get_metatable(&self) -> Result<Table<'lua>> {
         unsafe {
             let lua = self.0.lua;
@@ -438,7 +999,7 @@ impl<'lua> AnyUserData<'lua> {
 
     fn inspect<'a, T, R, F>(&'a self, func: F) -> Result<R>
     where
-        T: 'static + UserData,
+        T: 'static,
         F: FnOnce(&'a RefCell<T>) -> Result<R>,
     {
         unsafe {
@@ -448,13 +1009,19 @@ impl<'lua> AnyUserData<'lua> {
 
             lua.push_ref(&self.0);
 
+            // Userdata of a type without a registered metatable cannot exist
+            let table_id = match lua.registered_userdata_metatable::<T>() {
+                Some(table_id) => table_id,
+                None => return Err(Error::UserDataTypeMismatch),
+            };
+
             if ffi::lua_getmetatable(lua.state, -1) == 0 {
                 Err(Error::UserDataTypeMismatch)
             } else {
                 ffi::lua_rawgeti(
                     lua.state,
                     ffi::LUA_REGISTRYINDEX,
-                    lua.userdata_metatable::<T>()? as ffi::lua_Integer,
+                    table_id as ffi::lua_Integer,
                 );
 
                 if ffi::lua_rawequal(lua.state, -1, -2) == 0 {
@@ -473,9 +1040,101 @@ impl<'lua> PartialEq for AnyUserData<'lua> {
     }
 }
 
+impl<'lua> Eq for AnyUserData<'lua> {}
+
+/// Hashes the userdata by identity (its address), consistent with `PartialEq`.
+impl<'lua> Hash for AnyUserData<'lua> {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.0.to_pointer().hash(state)
+    }
+}
+
+/// Orders userdata by their address.
+///
+/// The ordering is arbitrary, but it is stable for as long as the userdata is alive, which makes
+/// `AnyUserData` usable as a `BTreeMap` key.
+impl<'lua> PartialOrd for AnyUserData<'lua> {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl<'lua> Ord for AnyUserData<'lua> {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.0.to_pointer().cmp(&other.0.to_pointer())
+    }
+}
+
 impl<'lua> AsRef<AnyUserData<'lua>> for AnyUserData<'lua> {
     #[inline]
     fn as_ref(&self) -> &Self {
         self
     }
 }
+
+/// Handle to the metatable shared by all userdata of a given type.
+///
+/// Obtained from [`Lua::userdata_type_metatable`]. Changes made through this handle apply to all
+/// instances of the type, including already existing ones.
+///
+/// The `__gc`, `__metatable` and `__index` fields are managed by mlua and cannot be modified with
+/// [`set`]. Methods (the values looked up through `__index`) are changed with [`set_method`].
+///
+/// [`Lua::userdata_type_metatable`]: struct.Lua.html#method.userdata_type_metatable
+/// [`set`]: #method.set
+/// [`set_method`]: #method.set_method
+#[derive(Clone, Debug)]
+pub struct UserDataMetatable<'lua>(pub(crate) Table<'lua>);
+
+impl<'lua> UserDataMetatable<'lua> {
+    /// Gets the value associated to `key` in the metatable.
+    pub fn get<K: ToLua<'lua>, V: FromLua<'lua>>(&self, key: K) -> Result<V> {
+        self.0.raw_get(key)
+    }
+
+    /// Sets a metatable field, such as a metamethod.
+    ///
+    /// Setting a field to `nil` removes it.
+    ///
+    /// # Errors
+    ///
+    /// Returns a `MetaMethodRestricted` error if `key` is one of the reserved fields `__gc`,
+    /// `__metatable` or `__index`.
+    pub fn set<K: ToLua<'lua>, V: ToLua<'lua>>(&self, key: K, value: V) -> Result<()> {
+        let lua = self.0 .0.lua;
+        let key = key.to_lua(lua)?;
+        if let Value::String(ref name) = key {
+            let name = name.as_bytes();
+            if name == b"__gc" || name == b"__metatable" || name == b"__index" {
+                return Err(Error::MetaMethodRestricted(
+                    StdString::from_utf8_lossy(name).into_owned(),
+                ));
+            }
+        }
+        self.0.raw_set(key, value)
+    }
+
+    /// Gets the method `name` shared by all instances.
+    pub fn get_method<V: FromLua<'lua>>(&self, name: &str) -> Result<V> {
+        self.methods()?.raw_get(name)
+    }
+
+    /// Adds or replaces the method `name` for all instances.
+    ///
+    /// Setting a method to `nil` removes it.
+    pub fn set_method<V: ToLua<'lua>>(&self, name: &str, value: V) -> Result<()> {
+        self.methods()?.raw_set(name, value)
+    }
+
+    fn methods(&self) -> Result<Table<'lua>> {
+        let lua = self.0 .0.lua;
+        unsafe {
+            let _sg = StackGuard::new(lua.state);
+            check_stack(lua.state, 10)?;
+
+            lua.push_ref(&self.0 .0);
+            push_userdata_methods(lua.state, -1)?;
+            Ok(Table(lua.pop_ref()))
+        }
+    }
+}
@@ -1,5 +1,6 @@
 use std::any::Any;
 use std::borrow::Cow;
+use std::ffi::CStr;
 use std::fmt::Write;
 use std::os::raw::{c_char, c_int, c_void};
 use std::panic::{catch_unwind, resume_unwind, AssertUnwindSafe};
@@ -8,6 +9,8 @@ use std::{mem, ptr, slice};
 
 use crate::error::{Error, Result};
 use crate::ffi;
+use crate::lua::{pop_error_value, push_error_value};
+use crate::userdata::IndexPriority;
 
 // Checks that Lua has enough free stack space for future stack operations.  On failure, this will
 // panic with an internal error message.
@@ -174,6 +177,13 @@ pub unsafe fn pop_error(state: *mut ffi::lua_State, err_code: c_int) -> Error {
 
     if let Some(err) = get_wrapped_error(state, -1).as_ref() {
         ffi::lua_pop(state, 1);
+        // The instruction limit is enforced by a hook, but the error should not look like it
+        // came from a callback
+        if let Error::CallbackError { ref cause, .. } = *err {
+            if let Error::InstructionLimitExceeded = **cause {
+                return Error::InstructionLimitExceeded;
+            }
+        }
         err.clone()
     } else if is_wrapped_panic(state, -1) {
         let panic = get_userdata::<WrappedPanic>(state, -1);
@@ -184,6 +194,15 @@ pub unsafe fn pop_error(state: *mut ffi::lua_State, err_code: c_int) -> Error {
         }
     } else {
         let err_string = to_string(state, -1).into_owned();
+
+        if err_code == ffi::LUA_ERRRUN && !is_error_message(state, -1) {
+            if let Some(value) = pop_error_value(state) {
+                return Error::RuntimeErrorValue {
+                    message: err_string,
+                    value: Arc::new(value),
+                };
+            }
+        }
         ffi::lua_pop(state, 1);
 
         match err_code {
@@ -267,60 +286,200 @@ pub unsafe fn init_userdata_metatable<T>(
     state: *mut ffi::lua_State,
     metatable: c_int,
     members: Option<c_int>,
+    index_priority: IndexPriority,
+    name: Option<&str>,
 ) -> Result<()> {
-    // Used if both an __index metamethod is set and regular methods, checks methods table
-    // first, then __index metamethod.
-    unsafe extern "C" fn meta_index_impl(state: *mut ffi::lua_State) -> c_int {
-        ffi::luaL_checkstack(state, 2, ptr::null());
+    let metatable = ffi::lua_absindex(state, metatable);
 
-        ffi::lua_pushvalue(state, -1);
-        ffi::lua_gettable(state, ffi::lua_upvalueindex(2));
-        if ffi::lua_isnil(state, -1) == 0 {
-            ffi::lua_insert(state, -3);
-            ffi::lua_pop(state, 2);
-            1
-        } else {
-            ffi::lua_pop(state, 1);
-            ffi::lua_pushvalue(state, ffi::lua_upvalueindex(1));
-            ffi::lua_insert(state, -3);
-            ffi::lua_call(state, 2, 1);
-            1
-        }
+    if let Some(members) = members {
+        init_userdata_metatable_index(state, metatable, members, index_priority)?;
     }
 
-    let members = members.map(|i| ffi::lua_absindex(state, i));
     ffi::lua_pushvalue(state, metatable);
 
-    if let Some(members) = members {
-        push_string(state, "__index")?;
-        ffi::lua_pushvalue(state, -1);
+    push_string(state, "__gc")?;
+    ffi::lua_pushcfunction(state, userdata_destructor::<T>);
+    protect_lua_closure(state, 3, 1, |state| {
+        ffi::lua_rawset(state, -3);
+    })?;
 
-        let index_type = ffi::lua_rawget(state, -3);
-        if index_type == ffi::LUA_TNIL {
-            ffi::lua_pop(state, 1);
-            ffi::lua_pushvalue(state, members);
-        } else if index_type == ffi::LUA_TFUNCTION {
-            ffi::lua_pushvalue(state, members);
-            protect_lua_closure(state, 2, 1, |state| {
-                ffi::lua_pushcclosure(state, meta_index_impl, 2);
-            })?;
-        } else {
-            mlua_panic!("improper __index type {}", index_type);
-        }
+    push_string(state, "__metatable")?;
+    ffi::lua_pushboolean(state, 0);
+    protect_lua_closure(state, 3, 1, |state| {
+        ffi::lua_rawset(state, -3);
+    })?;
 
+    if let Some(name) = name {
+        push_string(state, "__name")?;
+        push_string(state, name)?;
         protect_lua_closure(state, 3, 1, |state| {
             ffi::lua_rawset(state, -3);
         })?;
     }
 
-    push_string(state, "__gc")?;
-    ffi::lua_pushcfunction(state, userdata_destructor::<T>);
+    ffi::lua_pop(state, 1);
+
+    Ok(())
+}
+
+// Used if both an __index metamethod is set and regular methods, checks methods table
+// first, then __index metamethod.
+unsafe extern "C" fn meta_index_impl(state: *mut ffi::lua_State) -> c_int {
+    ffi::luaL_checkstack(state, 2, ptr::null());
+
+    ffi::lua_pushvalue(state, -1);
+    ffi::lua_gettable(state, ffi::lua_upvalueindex(2));
+    if ffi::lua_isnil(state, -1) == 0 {
+        ffi::lua_insert(state, -3);
+        ffi::lua_pop(state, 2);
+        1
+    } else {
+        ffi::lua_pop(state, 1);
+        ffi::lua_pushvalue(state, ffi::lua_upvalueindex(1));
+        ffi::lua_insert(state, -3);
+        ffi::lua_call(state, 2, 1);
+        1
+    }
+}
+
+// Used if both an __index metamethod is set and regular methods with `IndexPriority::HandlerFirst`,
+// calls the __index metamethod first, then checks the methods table if it returned nil.
+unsafe extern "C" fn meta_index_handler_first_impl(state: *mut ffi::lua_State) -> c_int {
+    ffi::luaL_checkstack(state, 3, ptr::null());
+
+    ffi::lua_pushvalue(state, ffi::lua_upvalueindex(1));
+    ffi::lua_pushvalue(state, -3);
+    ffi::lua_pushvalue(state, -3);
+    ffi::lua_call(state, 2, 1);
+    if ffi::lua_isnil(state, -1) == 0 {
+        1
+    } else {
+        ffi::lua_pop(state, 1);
+        ffi::lua_gettable(state, ffi::lua_upvalueindex(2));
+        1
+    }
+}
+
+// Like `meta_index_handler_first_impl`, but remembers the keys that resolved to methods in the
+// cache table kept in the third upvalue. For these keys the methods table is checked before calling
+// the handler.
+unsafe extern "C" fn meta_index_handler_first_cached_impl(state: *mut ffi::lua_State) -> c_int {
+    ffi::luaL_checkstack(state, 3, ptr::null());
+
+    ffi::lua_pushvalue(state, -1);
+    ffi::lua_rawget(state, ffi::lua_upvalueindex(3));
+    let cached = ffi::lua_toboolean(state, -1) != 0;
+    ffi::lua_pop(state, 1);
+    if cached {
+        ffi::lua_pushvalue(state, -1);
+        ffi::lua_gettable(state, ffi::lua_upvalueindex(2));
+        if ffi::lua_isnil(state, -1) == 0 {
+            return 1;
+        }
+        ffi::lua_pop(state, 1);
+    }
+
+    ffi::lua_pushvalue(state, ffi::lua_upvalueindex(1));
+    ffi::lua_pushvalue(state, -3);
+    ffi::lua_pushvalue(state, -3);
+    ffi::lua_call(state, 2, 1);
+    if ffi::lua_isnil(state, -1) == 0 {
+        return 1;
+    }
+    ffi::lua_pop(state, 1);
+
+    ffi::lua_pushvalue(state, -1);
+    ffi::lua_gettable(state, ffi::lua_upvalueindex(2));
+    if !cached && ffi::lua_isnil(state, -1) == 0 {
+        ffi::lua_pushvalue(state, -2);
+        ffi::lua_pushboolean(state, 1);
+        ffi::lua_rawset(state, ffi::lua_upvalueindex(3));
+    }
+    1
+}
+
+// Returns true if the given function is one of the generated __index functions, which keep the
+// methods table in their second upvalue.
+unsafe fn is_meta_index_impl(state: *mut ffi::lua_State, index: c_int) -> bool {
+    if ffi::lua_iscfunction(state, index) == 0 {
+        return false;
+    }
+    let f = ffi::lua_tocfunction(state, index) as *const c_void;
+    f == meta_index_impl as *const c_void
+        || f == meta_index_handler_first_impl as *const c_void
+        || f == meta_index_handler_first_cached_impl as *const c_void
+}
+
+// Replaces the generated `__index` function of the given userdata metatable with one that caches
+// which keys are methods, so that the `Index` handler is not called again for them. This only
+// affects `IndexPriority::HandlerFirst`, with any other `__index` the methods table is already
+// checked before the handler, so it is left unchanged.
+// Uses 5 stack spaces, does not call checkstack
+pub unsafe fn enable_userdata_method_cache(
+    state: *mut ffi::lua_State,
+    metatable: c_int,
+) -> Result<()> {
+    ffi::lua_pushvalue(state, metatable);
+    push_string(state, "__index")?;
+    ffi::lua_pushvalue(state, -1);
+    ffi::lua_rawget(state, -3);
+
+    if ffi::lua_iscfunction(state, -1) == 0
+        || ffi::lua_tocfunction(state, -1) as *const c_void
+            != meta_index_handler_first_impl as *const c_void
+    {
+        ffi::lua_pop(state, 3);
+        return Ok(());
+    }
+
+    ffi::lua_getupvalue(state, -1, 1);
+    ffi::lua_getupvalue(state, -2, 2);
+    ffi::lua_remove(state, -3);
+    protect_lua_closure(state, 0, 1, |state| {
+        ffi::lua_newtable(state);
+    })?;
+    protect_lua_closure(state, 3, 1, |state| {
+        ffi::lua_pushcclosure(state, meta_index_handler_first_cached_impl, 3);
+    })?;
     protect_lua_closure(state, 3, 1, |state| {
         ffi::lua_rawset(state, -3);
     })?;
+    ffi::lua_pop(state, 1);
+
+    Ok(())
+}
+
+// Sets the `__index` field of the given userdata metatable to look up the given methods table,
+// combined with the existing `__index` metamethod in the given order if there is one.
+unsafe fn init_userdata_metatable_index(
+    state: *mut ffi::lua_State,
+    metatable: c_int,
+    members: c_int,
+    index_priority: IndexPriority,
+) -> Result<()> {
+    let members = ffi::lua_absindex(state, members);
+    ffi::lua_pushvalue(state, metatable);
+
+    push_string(state, "__index")?;
+    ffi::lua_pushvalue(state, -1);
+
+    let index_type = ffi::lua_rawget(state, -3);
+    if index_type == ffi::LUA_TNIL {
+        ffi::lua_pop(state, 1);
+        ffi::lua_pushvalue(state, members);
+    } else if index_type == ffi::LUA_TFUNCTION {
+        ffi::lua_pushvalue(state, members);
+        let index_impl = match index_priority {
+            IndexPriority::MethodsFirst => meta_index_impl as ffi::lua_CFunction,
+            IndexPriority::HandlerFirst => meta_index_handler_first_impl,
+        };
+        protect_lua_closure(state, 2, 1, |state| {
+            ffi::lua_pushcclosure(state, index_impl, 2);
+        })?;
+    } else {
+        mlua_panic!("improper __index type {}", index_type);
+    }
 
-    push_string(state, "__metatable")?;
-    ffi::lua_pushboolean(state, 0);
     protect_lua_closure(state, 3, 1, |state| {
         ffi::lua_rawset(state, -3);
     })?;
@@ -330,6 +489,46 @@ pub unsafe fn init_userdata_metatable<T>(
     Ok(())
 }
 
+// Pushes the methods table of the given userdata metatable onto the stack, creating it first if the
+// userdata type was registered without any methods.
+// Uses 4 stack spaces, does not call checkstack
+pub unsafe fn push_userdata_methods(state: *mut ffi::lua_State, metatable: c_int) -> Result<()> {
+    let metatable = ffi::lua_absindex(state, metatable);
+
+    if push_existing_userdata_methods(state, metatable)? {
+        return Ok(());
+    }
+
+    protect_lua_closure(state, 0, 1, |state| {
+        ffi::lua_newtable(state);
+    })?;
+    init_userdata_metatable_index(state, metatable, -1, IndexPriority::MethodsFirst)
+}
+
+// Pushes the methods table of the given userdata metatable onto the stack and returns true, or
+// pushes nothing and returns false if the userdata type has no methods table.
+// Uses 4 stack spaces, does not call checkstack
+pub unsafe fn push_existing_userdata_methods(
+    state: *mut ffi::lua_State,
+    metatable: c_int,
+) -> Result<bool> {
+    let metatable = ffi::lua_absindex(state, metatable);
+
+    push_string(state, "__index")?;
+    match ffi::lua_rawget(state, metatable) {
+        ffi::LUA_TTABLE => Ok(true),
+        ffi::LUA_TFUNCTION if is_meta_index_impl(state, -1) => {
+            ffi::lua_getupvalue(state, -1, 2);
+            ffi::lua_remove(state, -2);
+            Ok(true)
+        }
+        _ => {
+            ffi::lua_pop(state, 1);
+            Ok(false)
+        }
+    }
+}
+
 pub unsafe extern "C" fn userdata_destructor<T>(state: *mut ffi::lua_State) -> c_int {
     callback_error(state, |_| {
         check_stack(state, 1)?;
@@ -379,6 +578,13 @@ where
         }
         Ok(Err(err)) => {
             ffi::lua_settop(state, 1);
+            if let Error::RuntimeErrorValue { ref value, .. } = err {
+                // Raise the original error value
+                if push_error_value(state, value) {
+                    drop(err);
+                    ffi::lua_error(state)
+                }
+            }
             ptr::write(ud as *mut WrappedError, WrappedError(err));
             get_error_metatable(state);
             ffi::lua_setmetatable(state, -2);
@@ -433,7 +639,8 @@ pub unsafe extern "C" fn error_traceback(state: *mut ffi::lua_State) -> c_int {
         );
         get_error_metatable(state);
         ffi::lua_setmetatable(state, -2);
-    } else if !is_wrapped_panic(state, -1) {
+    } else if !is_wrapped_panic(state, -1) && is_error_message(state, -1) {
+        // Error values that are not messages are passed through unchanged
         if ffi::lua_checkstack(state, LUA_TRACEBACK_STACK) != 0 {
             let s = ffi::luaL_tolstring(state, -1, ptr::null_mut());
             ffi::luaL_traceback(state, state, s, 0);
@@ -679,7 +886,7 @@ struct WrappedPanic(pub Option<Box<dyn Any + Send + 'static>>);
 
 // Converts the given lua value to a string in a reasonable format without causing a Lua error or
 // panicking.
-unsafe fn to_string<'a>(state: *mut ffi::lua_State, index: c_int) -> Cow<'a, str> {
+pub unsafe fn to_string<'a>(state: *mut ffi::lua_State, index: c_int) -> Cow<'a, str> {
     match ffi::lua_type(state, index) {
         ffi::LUA_TNONE => "<none>".into(),
         ffi::LUA_TNIL => "<nil>".into(),
@@ -709,6 +916,50 @@ unsafe fn to_string<'a>(state: *mut ffi::lua_State, index: c_int) -> Cow<'a, str
     }
 }
 
+// Checks if the value at the given index is an error message (a string, a number or nil) rather
+// than an error object.
+unsafe fn is_error_message(state: *mut ffi::lua_State, index: c_int) -> bool {
+    let t = ffi::lua_type(state, index);
+    t == ffi::LUA_TSTRING || t == ffi::LUA_TNUMBER || t == ffi::LUA_TNIL
+}
+
+// Fills in the name of the currently running function for a `BadArgument` error without one,
+// following the rules of `luaL_argerror`: when called as a method, the implicit `self` argument is
+// not counted.
+pub unsafe fn name_bad_argument(state: *mut ffi::lua_State, err: Error) -> Error {
+    match err {
+        Error::BadArgument {
+            to: None,
+            mut pos,
+            name,
+            cause,
+        } => {
+            let mut to = None;
+            let mut ar: ffi::lua_Debug = mem::zeroed();
+            if ffi::lua_getstack(state, 0, &mut ar) != 0
+                && ffi::lua_getinfo(state, cstr!("n"), &mut ar) != 0
+            {
+                if !ar.namewhat.is_null()
+                    && CStr::from_ptr(ar.namewhat).to_bytes() == b"method"
+                    && pos > 1
+                {
+                    pos -= 1;
+                }
+                if !ar.name.is_null() {
+                    to = Some(CStr::from_ptr(ar.name).to_string_lossy().into_owned());
+                }
+            }
+            Error::BadArgument {
+                to,
+                pos,
+                name,
+                cause,
+            }
+        }
+        err => err,
+    }
+}
+
 // Checks if the value at the given index is a WrappedPanic.  Uses 2 stack spaces and does not call
 // lua_checkstack.
 unsafe fn is_wrapped_panic(state: *mut ffi::lua_State, index: c_int) -> bool {
@@ -1,5 +1,7 @@
 use std::iter::{self, FromIterator};
-use std::{slice, str, vec};
+use std::os::raw::c_int;
+use std::string::String as StdString;
+use std::{ptr, slice, str, vec};
 
 use crate::error::{Error, Result};
 use crate::ffi;
@@ -10,6 +12,7 @@ use crate::table::Table;
 use crate::thread::Thread;
 use crate::types::{Integer, LightUserData, Number};
 use crate::userdata::AnyUserData;
+use crate::util::{check_stack, protect_lua_closure, StackGuard};
 
 /// A dynamically typed Lua value.  The `String`, `Table`, `Function`, `Thread`, and `UserData`
 /// variants contain handle types into the internal Lua state.  It is a logic error to mix handle
@@ -47,7 +50,117 @@ pub enum Value<'lua> {
 }
 pub use self::Value::Nil;
 
+/// Subtype of a Lua number.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum NumberKind {
+    /// An integer number (Lua 5.3 and later only).
+    Integer,
+    /// A floating point number.
+    Float,
+}
+
+/// Options for rendering numbers with [`Value::format_number`].
+///
+/// The [`Default`] implementation mimics Lua 5.3, except that floats use the shortest
+/// representation that round-trips instead of 14 significant digits.
+///
+/// [`Value::format_number`]: enum.Value.html#method.format_number
+/// [`Default`]: #impl-Default
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub struct NumberFormat {
+    /// Number of digits after the decimal point of floats, or `None` for the shortest
+    /// representation that round-trips.
+    ///
+    /// Defaults to `None`.
+    pub precision: Option<usize>,
+    /// Renders floats in scientific notation, e.g. `1.5e3`.
+    ///
+    /// Defaults to `false`.
+    pub scientific: bool,
+    /// Appends `.0` to floats with an integral value, so they can be told apart from integers.
+    /// Only used when `precision` is `None` and `scientific` is `false`.
+    ///
+    /// Defaults to `true`.
+    pub float_suffix: bool,
+}
+
+impl Default for NumberFormat {
+    fn default() -> NumberFormat {
+        NumberFormat {
+            precision: None,
+            scientific: false,
+            float_suffix: true,
+        }
+    }
+}
+
+impl NumberFormat {
+    fn format_float(&self, n: Number) -> StdString {
+        if n.is_nan() {
+            return "nan".to_owned();
+        } else if n.is_infinite() {
+            return if n > 0.0 { "inf" } else { "-inf" }.to_owned();
+        }
+
+        match (self.scientific, self.precision) {
+            (true, Some(precision)) => format!("{:.*e}", precision, n),
+            (true, None) => format!("{:e}", n),
+            (false, Some(precision)) => format!("{:.*}", precision, n),
+            (false, None) => {
+                let mut s = n.to_string();
+                if self.float_suffix && !s.contains('.') {
+                    s.push_str(".0");
+                }
+                s
+            }
+        }
+    }
+}
+
 impl<'lua> Value<'lua> {
+    /// A sentinel value that can be used in place of `nil`, e.g. to represent a JSON `null`.
+    ///
+    /// Assigning `nil` to a table field removes the field, so `nil` can not be stored in a table.
+    /// In particular a `nil` element in a sequence leaves a hole, after which the length operator
+    /// and functions like `ipairs` may stop. `NULL` is a light userdata holding a null pointer,
+    /// which is a regular value: it is stored in tables like any other value and keeps sequences
+    /// intact. Unlike `nil` it is truthy in conditions, so scripts have to compare against it
+    /// explicitly, e.g. after exposing it as a global.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use mlua::{Lua, Result, Table, Value};
+    /// # fn main() -> Result<()> {
+    /// # let lua = Lua::new();
+    /// lua.globals().set("null", Value::NULL)?;
+    ///
+    /// let list: Table = lua.load("{ 1, null, 3 }").eval()?;
+    /// assert_eq!(list.raw_len(), 3);
+    /// assert!(list.get::<_, Value>(2)?.is_null());
+    /// assert!(lua.load("return ... == null").call::<_, bool>(list.get::<_, Value>(2)?)?);
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub const NULL: Value<'lua> = Value::LightUserData(LightUserData(ptr::null_mut()));
+
+    /// Returns `true` if the value is `nil`.
+    ///
+    /// The [`NULL`] sentinel is not `nil`.
+    ///
+    /// [`NULL`]: #associatedconstant.NULL
+    pub fn is_nil(&self) -> bool {
+        matches!(self, Value::Nil)
+    }
+
+    /// Returns `true` if the value is the [`NULL`] sentinel, a light userdata holding a null
+    /// pointer.
+    ///
+    /// [`NULL`]: #associatedconstant.NULL
+    pub fn is_null(&self) -> bool {
+        matches!(self, Value::LightUserData(LightUserData(p)) if p.is_null())
+    }
+
     pub(crate) fn type_name(&self) -> &'static str {
         match *self {
             Value::Nil => "nil",
@@ -63,6 +176,51 @@ impl<'lua> Value<'lua> {
         }
     }
 
+    /// Returns the subtype of a number value, or `None` if the value is not a number.
+    ///
+    /// This matches the result of the `math.type` Lua function. Before Lua 5.3 all numbers are
+    /// floats, so [`NumberKind::Float`] is returned even for the `Integer` variant.
+    ///
+    /// [`NumberKind::Float`]: enum.NumberKind.html#variant.Float
+    pub fn number_subtype(&self) -> Option<NumberKind> {
+        match *self {
+            #[cfg(feature = "lua53")]
+            Value::Integer(_) => Some(NumberKind::Integer),
+            #[cfg(not(feature = "lua53"))]
+            Value::Integer(_) => Some(NumberKind::Float),
+            Value::Number(_) => Some(NumberKind::Float),
+            _ => None,
+        }
+    }
+
+    /// Renders a number value as a string using the given format, or returns `None` if the value
+    /// is not a number.
+    ///
+    /// This only affects strings produced from Rust: Lua's own `tostring`, `print` and string
+    /// coercion keep using the format Lua was compiled with (`LUAI_NUMFMT`).
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use mlua::{NumberFormat, Value};
+    /// let format = NumberFormat::default();
+    /// assert_eq!(Value::Number(2.0).format_number(&format).unwrap(), "2.0");
+    /// assert_eq!(Value::Integer(2).format_number(&format).unwrap(), "2");
+    ///
+    /// let format = NumberFormat {
+    ///     precision: Some(2),
+    ///     ..NumberFormat::default()
+    /// };
+    /// assert_eq!(Value::Number(1.0 / 3.0).format_number(&format).unwrap(), "0.33");
+    /// ```
+    pub fn format_number(&self, format: &NumberFormat) -> Option<StdString> {
+        match *self {
+            Value::Integer(i) => Some(i.to_string()),
+            Value::Number(n) => Some(format.format_float(n)),
+            _ => None,
+        }
+    }
+
     /// Compares two values for equality.
     ///
     /// Equality comparisons do not convert strings to numbers or vice versa.
@@ -80,6 +238,111 @@ impl<'lua> Value<'lua> {
             _ => Ok(self == other.as_ref()),
         }
     }
+
+    /// Adds two values the way the Lua `+` operator does.
+    ///
+    /// Strings are converted to numbers, integers are promoted to floats when mixed with them and
+    /// the `__add` metamethod is called for other values, so the result is always the same as
+    /// evaluating `a + b` in Lua. Errors raised along the way, such as adding a table without a
+    /// metamethod, are returned as `Err`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use mlua::{Lua, Result, Value};
+    /// # fn main() -> Result<()> {
+    /// # let lua = Lua::new();
+    /// let a = Value::Integer(1);
+    /// let b = Value::String(lua.create_string("2.5")?);
+    /// assert_eq!(a.arith_add(&b, &lua)?, Value::Number(3.5));
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn arith_add(&self, other: &Value<'lua>, lua: &'lua Lua) -> Result<Value<'lua>> {
+        self.arith(Some(other), ffi::LUA_OPADD, lua)
+    }
+
+    /// Subtracts `other` from this value the way the Lua `-` operator does.
+    ///
+    /// See [`arith_add`] for the coercion rules.
+    ///
+    /// [`arith_add`]: #method.arith_add
+    pub fn arith_sub(&self, other: &Value<'lua>, lua: &'lua Lua) -> Result<Value<'lua>> {
+        self.arith(Some(other), ffi::LUA_OPSUB, lua)
+    }
+
+    /// Multiplies two values the way the Lua `*` operator does.
+    ///
+    /// See [`arith_add`] for the coercion rules.
+    ///
+    /// [`arith_add`]: #method.arith_add
+    pub fn arith_mul(&self, other: &Value<'lua>, lua: &'lua Lua) -> Result<Value<'lua>> {
+        self.arith(Some(other), ffi::LUA_OPMUL, lua)
+    }
+
+    /// Divides this value by `other` the way the Lua `/` operator does.
+    ///
+    /// See [`arith_add`] for the coercion rules.
+    ///
+    /// [`arith_add`]: #method.arith_add
+    pub fn arith_div(&self, other: &Value<'lua>, lua: &'lua Lua) -> Result<Value<'lua>> {
+        self.arith(Some(other), ffi::LUA_OPDIV, lua)
+    }
+
+    /// Computes the floor division of this value by `other`, like the Lua `//` operator.
+    ///
+    /// See [`arith_add`] for the coercion rules.
+    ///
+    /// [`arith_add`]: #method.arith_add
+    #[cfg(feature = "lua53")]
+    pub fn arith_idiv(&self, other: &Value<'lua>, lua: &'lua Lua) -> Result<Value<'lua>> {
+        self.arith(Some(other), ffi::LUA_OPIDIV, lua)
+    }
+
+    /// Computes the remainder of the division by `other`, like the Lua `%` operator.
+    ///
+    /// See [`arith_add`] for the coercion rules.
+    ///
+    /// [`arith_add`]: #method.arith_add
+    pub fn arith_mod(&self, other: &Value<'lua>, lua: &'lua Lua) -> Result<Value<'lua>> {
+        self.arith(Some(other), ffi::LUA_OPMOD, lua)
+    }
+
+    /// Raises this value to the power of `other`, like the Lua `^` operator.
+    ///
+    /// See [`arith_add`] for the coercion rules.
+    ///
+    /// [`arith_add`]: #method.arith_add
+    pub fn arith_pow(&self, other: &Value<'lua>, lua: &'lua Lua) -> Result<Value<'lua>> {
+        self.arith(Some(other), ffi::LUA_OPPOW, lua)
+    }
+
+    /// Negates this value, like the Lua unary `-` operator.
+    ///
+    /// See [`arith_add`] for the coercion rules.
+    ///
+    /// [`arith_add`]: #method.arith_add
+    pub fn arith_unm(&self, lua: &'lua Lua) -> Result<Value<'lua>> {
+        self.arith(None, ffi::LUA_OPUNM, lua)
+    }
+
+    fn arith(&self, other: Option<&Value<'lua>>, op: c_int, lua: &'lua Lua) -> Result<Value<'lua>> {
+        unsafe {
+            let _sg = StackGuard::new(lua.state);
+            check_stack(lua.state, 5)?;
+
+            lua.push_value(self.clone())?;
+            let nargs = match other {
+                Some(other) => {
+                    lua.push_value(other.clone())?;
+                    2
+                }
+                None => 1,
+            };
+            protect_lua_closure(lua.state, nargs, 1, |state| ffi::lua_arith(state, op))?;
+            Ok(lua.pop_value())
+        }
+    }
 }
 
 impl<'lua> PartialEq for Value<'lua> {
@@ -197,6 +460,47 @@ impl<'lua> MultiValue<'lua> {
     pub fn iter(&self) -> iter::Rev<slice::Iter<Value<'lua>>> {
         self.0.iter().rev()
     }
+
+    /// Removes the first value and converts it to `T`, or returns `None` if there are no values
+    /// left or the value is `nil`.
+    ///
+    /// Together with [`pop_or`] this allows parsing arguments one by one, for functions with
+    /// trailing optional arguments that do not fit a fixed tuple.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use mlua::{Lua, MultiValue, Result};
+    /// # fn main() -> Result<()> {
+    /// # let lua = Lua::new();
+    /// let range = lua.create_function(|lua, mut args: MultiValue| {
+    ///     let start: i64 = args.pop_or(1, lua)?;
+    ///     let end: Option<i64> = args.pop_opt(lua)?;
+    ///     let step: i64 = args.pop_or(1, lua)?;
+    ///     Ok((start, end.unwrap_or(start), step))
+    /// })?;
+    /// assert_eq!(range.call::<_, (i64, i64, i64)>((5, 10))?, (5, 10, 1));
+    /// # Ok(())
+    /// # }
+    /// ```
+    ///
+    /// [`pop_or`]: #method.pop_or
+    pub fn pop_opt<T: FromLua<'lua>>(&mut self, lua: &'lua Lua) -> Result<Option<T>> {
+        match self.pop_front() {
+            Some(Value::Nil) | None => Ok(None),
+            Some(value) => T::from_lua(value, lua).map(Some),
+        }
+    }
+
+    /// Removes the first value and converts it to `T`, or returns `default` if there are no
+    /// values left or the value is `nil`.
+    ///
+    /// See [`pop_opt`] for an example.
+    ///
+    /// [`pop_opt`]: #method.pop_opt
+    pub fn pop_or<T: FromLua<'lua>>(&mut self, default: T, lua: &'lua Lua) -> Result<T> {
+        Ok(self.pop_opt(lua)?.unwrap_or(default))
+    }
 }
 
 /// Trait for types convertible to any number of Lua values.
@@ -212,6 +516,30 @@ pub trait ToLuaMulti<'lua> {
 ///
 /// This is a generalization of `FromLua`, allowing an arbitrary number of Lua values to participate
 /// in the conversion. Any type that implements `FromLua` will automatically implement this trait.
+///
+/// # Optional arguments
+///
+/// Missing trailing values are treated as nil, and `Option<T>` converts nil to `None`, so optional
+/// function parameters can be declared as `Option<T>` and given a default with `unwrap_or`:
+///
+/// ```
+/// # use mlua::{Lua, Result};
+/// # fn main() -> Result<()> {
+/// # let lua = Lua::new();
+/// let greet = lua.create_function(|_, name: Option<String>| {
+///     Ok(format!("hello {}", name.as_deref().unwrap_or("world")))
+/// })?;
+/// lua.globals().set("greet", greet)?;
+///
+/// assert_eq!(lua.load("greet()").eval::<String>()?, "hello world");
+/// assert_eq!(lua.load("greet(nil)").eval::<String>()?, "hello world");
+/// assert_eq!(lua.load("greet('lua')").eval::<String>()?, "hello lua");
+/// # Ok(())
+/// # }
+/// ```
+///
+/// Note that only nil maps to `None`: passing `false` to an `Option<String>` parameter is still a
+/// conversion error.
 pub trait FromLuaMulti<'lua>: Sized {
     /// Performs the conversion.
     ///
@@ -220,4 +548,42 @@ pub trait FromLuaMulti<'lua>: Sized {
     /// assigning values. Similarly, if not enough values are given, conversions should assume that
     /// any missing values are nil.
     fn from_lua_multi(values: MultiValue<'lua>, lua: &'lua Lua) -> Result<Self>;
+
+    /// Performs the conversion of function arguments.
+    ///
+    /// Works like [`from_lua_multi`], but conversion errors are reported as
+    /// [`Error::BadArgument`] with the position of the offending argument. `i` is the position of
+    /// the first value in `args` and `to` is the name of the called function, if known.
+    ///
+    /// The default implementation does not track positions and simply calls [`from_lua_multi`].
+    ///
+    /// [`from_lua_multi`]: #tymethod.from_lua_multi
+    /// [`Error::BadArgument`]: enum.Error.html#variant.BadArgument
+    fn from_lua_args(
+        args: MultiValue<'lua>,
+        i: usize,
+        to: Option<&str>,
+        lua: &'lua Lua,
+    ) -> Result<Self> {
+        let _ = (i, to);
+        Self::from_lua_multi(args, lua)
+    }
+
+    /// Performs the conversion, returning the values that were not consumed.
+    ///
+    /// Types with a fixed number of values, such as any `FromLua` type and tuples of them, consume
+    /// that many values from the front of `values` and return the rest. Types which accept any
+    /// number of values, such as [`MultiValue`] and [`Variadic`], consume everything.
+    ///
+    /// The default implementation calls [`from_lua_multi`] and returns no remaining values.
+    ///
+    /// [`MultiValue`]: struct.MultiValue.html
+    /// [`Variadic`]: struct.Variadic.html
+    /// [`from_lua_multi`]: #tymethod.from_lua_multi
+    fn from_lua_multi_with_rest(
+        values: MultiValue<'lua>,
+        lua: &'lua Lua,
+    ) -> Result<(Self, MultiValue<'lua>)> {
+        Ok((Self::from_lua_multi(values, lua)?, MultiValue::new()))
+    }
 }
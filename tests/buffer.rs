@@ -0,0 +1,82 @@
+#![cfg_attr(
+    all(feature = "luajit", target_os = "macos", target_arch = "x86_64"),
+    feature(link_args)
+)]
+
+#[cfg_attr(
+    all(feature = "luajit", target_os = "macos", target_arch = "x86_64"),
+    link_args = "-pagezero_size 10000 -image_base 100000000"
+)]
+extern "system" {}
+
+use mlua::{Buffer, Error, Integer, Lua, Result, String};
+
+#[test]
+fn test_buffer() -> Result<()> {
+    let lua = Lua::new();
+
+    let buf = lua.create_buffer(16)?;
+    lua.globals().set("buf", buf.clone())?;
+
+    lua.load(
+        r#"
+        buf:append("abc")
+        buf:append("\0\255")
+        buf:append(42)
+        assert(buf:len() == 7 and #buf == 7)
+        assert(buf:read(1, 3) == "abc")
+        assert(buf:read(4, 2) == "\0\255")
+        assert(buf:read(6) == "42")
+        assert(buf:read(8) == "")
+        assert(buf:read(3, 0) == "")
+        assert(buf:to_string() == "abc\0\25542")
+        assert(tostring(buf) == buf:to_string())
+    "#,
+    )
+    .exec()?;
+
+    assert_eq!(buf.borrow::<Buffer>()?.as_bytes(), b"abc\0\xff42");
+
+    lua.load("buf:clear()").exec()?;
+    assert!(buf.borrow::<Buffer>()?.as_bytes().is_empty());
+
+    Ok(())
+}
+
+#[test]
+fn test_buffer_out_of_bounds() -> Result<()> {
+    let lua = Lua::new();
+
+    let buf = lua.create_userdata(Buffer::from(b"hello".to_vec()))?;
+    lua.globals().set("buf", buf)?;
+    lua.globals().set("min_int", Integer::MIN)?;
+    lua.globals().set("max_int", Integer::MAX)?;
+    assert_eq!(lua.load("buf:read(2, 4)").eval::<String>()?, "ello");
+
+    for chunk in &[
+        "buf:read(0, 1)",
+        "buf:read(5, 2)",
+        "buf:read(7)",
+        "buf:read(1, -1)",
+        "buf:read(min_int)",
+        "buf:read(min_int, 1)",
+        "buf:read(min_int, max_int)",
+        "buf:read(max_int)",
+        "buf:read(max_int, max_int)",
+        "buf:read(1, max_int)",
+        "buf:read(2, min_int)",
+        "buf:read(6, max_int)",
+    ] {
+        match lua.load(*chunk).exec() {
+            Err(Error::CallbackError { ref cause, .. }) => match **cause {
+                Error::RuntimeError(ref msg) => {
+                    assert!(msg.contains("out of bounds"), "unexpected message {}", msg)
+                }
+                ref err => panic!("unexpected error {:?} for {}", err, chunk),
+            },
+            r => panic!("expected out of bounds error for {}, got {:?}", chunk, r),
+        }
+    }
+
+    Ok(())
+}
@@ -9,8 +9,8 @@
 )]
 extern "system" {}
 
-use bstr::{BStr, BString};
-use mlua::{Lua, Result};
+use bstr::{BStr, BString, ByteSlice};
+use mlua::{Error, Lua, Result};
 
 #[test]
 fn byte_string_round_trip() -> Result<()> {
@@ -105,3 +105,53 @@ fn byte_string_round_trip() -> Result<()> {
 
     Ok(())
 }
+
+#[test]
+fn byte_string_binary_data() -> Result<()> {
+    let lua = Lua::new();
+
+    let data = BString::from((0..=255u8).chain((0..=255u8).rev()).collect::<Vec<_>>());
+    lua.globals().set("data", data.clone())?;
+    assert_eq!(lua.load("#data").eval::<usize>()?, 512);
+    assert_eq!(lua.load("data:byte(1)").eval::<u8>()?, 0);
+    assert_eq!(lua.load("data:byte(256)").eval::<u8>()?, 255);
+
+    let round_trip: BString = lua.globals().get("data")?;
+    assert_eq!(round_trip, data);
+
+    let slice: &BStr = data.as_ref();
+    lua.globals().set("slice", &slice[250..260])?;
+    let round_trip: BString = lua.globals().get("slice")?;
+    assert_eq!(round_trip, &data[250..260]);
+
+    match lua.load("{}").eval::<BString>() {
+        Err(Error::FromLuaConversionError { to: "BString", .. }) => {}
+        r => panic!("expected FromLuaConversionError, got {:?}", r),
+    }
+
+    Ok(())
+}
+
+#[test]
+fn byte_string_static_slice() -> Result<()> {
+    static BLOB: &[u8] = b"\x00binary\xffblob";
+
+    let lua = Lua::new();
+
+    let f = lua.create_function(|_, ()| Ok(BLOB.as_bstr()))?;
+    lua.globals().set("blob", f)?;
+    assert!(lua
+        .load(r#"return type(blob()) == "string" and #blob() == 12"#)
+        .eval::<bool>()?);
+
+    let round_trip: BString = lua.load("blob()").eval()?;
+    assert_eq!(round_trip, BLOB);
+
+    // Byte vectors are sequences like any other `Vec`
+    lua.globals().set("bytes", BLOB.to_vec())?;
+    assert!(lua
+        .load(r#"return type(bytes) == "table" and bytes[1] == 0"#)
+        .eval::<bool>()?);
+
+    Ok(())
+}
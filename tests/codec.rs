@@ -0,0 +1,215 @@
+#![cfg_attr(
+    all(feature = "luajit", target_os = "macos", target_arch = "x86_64"),
+    feature(link_args)
+)]
+
+#[cfg_attr(
+    all(feature = "luajit", target_os = "macos", target_arch = "x86_64"),
+    link_args = "-pagezero_size 10000 -image_base 100000000"
+)]
+extern "system" {}
+
+use mlua::{Error, Lua, Result, SandboxConfig, Table, Value};
+
+#[test]
+fn test_encode_decode_value() -> Result<()> {
+    let lua = Lua::new();
+
+    let value: Value = lua
+        .load(
+            r#"
+            local shared = { "shared" }
+            local t = {
+                int = 1,
+                float = 1.0,
+                str = "a\0b",
+                yes = true,
+                no = false,
+                list = { 10, 20, 30 },
+                a = shared,
+                b = shared,
+                [2.5] = "float key",
+            }
+            t.self = t
+            return t
+        "#,
+        )
+        .eval()?;
+
+    let bytes = lua.encode_value(&value)?;
+    let decoded = lua.decode_value(&bytes)?;
+    lua.globals().set("t", decoded)?;
+
+    lua.load(
+        r#"
+        assert(math.type(t.int) == "integer" and t.int == 1)
+        assert(math.type(t.float) == "float" and t.float == 1.0)
+        assert(t.str == "a\0b")
+        assert(t.yes == true and t.no == false)
+        assert(#t.list == 3 and t.list[3] == 30)
+        assert(t.a == t.b and t.a[1] == "shared")
+        assert(t.self == t)
+        assert(t[2.5] == "float key")
+    "#,
+    )
+    .exec()?;
+
+    for value in &[Value::Nil, Value::Integer(-5), Value::Boolean(true)] {
+        let bytes = lua.encode_value(value)?;
+        assert_eq!(lua.decode_value(&bytes)?, *value);
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_encode_decode_errors() -> Result<()> {
+    let lua = Lua::new();
+
+    let table: Table = lua.load("{ f = print }").eval()?;
+    match lua.encode_value(&Value::Table(table)) {
+        Err(Error::FromLuaConversionError {
+            from: "function", ..
+        }) => {}
+        r => panic!("expected FromLuaConversionError, got {:?}", r),
+    }
+
+    let bytes = lua.encode_value(&lua.load("{ 1, 2, 3 }").eval()?)?;
+    for bytes in &[
+        &b"invalid"[..],
+        &bytes[..bytes.len() - 1],
+        &[&bytes[..], &[0]].concat(),
+    ] {
+        match lua.decode_value(bytes) {
+            Err(Error::ToLuaConversionError { .. }) => {}
+            r => panic!("expected ToLuaConversionError, got {:?}", r),
+        }
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_encode_decode_depth_limit() -> Result<()> {
+    let lua = Lua::new();
+
+    // Hostile input opening millions of nested tables
+    let header = lua.encode_value(&Value::Nil)?;
+    let mut bytes = header[..header.len() - 1].to_vec();
+    bytes.resize(bytes.len() + 2_000_000, 6);
+    match lua.decode_value(&bytes) {
+        Err(Error::ToLuaConversionError { .. }) => {}
+        r => panic!("expected ToLuaConversionError, got {:?}", r),
+    }
+
+    let nested = |depth: usize| -> Result<Value> {
+        lua.load("local t = {}; for i = 2, ... do t = { t } end; return t")
+            .call(depth)
+    };
+
+    let value = nested(200)?;
+    let decoded = lua.decode_value(&lua.encode_value(&value)?)?;
+    let depth: usize = lua
+        .load("local t, n = ..., 0; while t do n = n + 1; t = t[1] end; return n")
+        .call(decoded)?;
+    assert_eq!(depth, 200);
+
+    match lua.encode_value(&nested(100_000)?) {
+        Err(Error::FromLuaConversionError { .. }) => {}
+        r => panic!("expected FromLuaConversionError, got {:?}", r),
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_snapshot_restore_data() -> Result<()> {
+    let lua = Lua::new();
+    lua.load(
+        r#"
+        score = 42
+        name = "save"
+        world = { size = 3.5, tiles = { 1, 2, 3 }, update = function() end }
+        world.self = world
+        alias = world.tiles
+        handler = function() end
+        string.extra = "module field"
+    "#,
+    )
+    .exec()?;
+    lua.globals().set(
+        "co",
+        lua.create_thread(lua.create_function(|_, ()| Ok(()))?)?,
+    )?;
+
+    let snapshot = lua.snapshot_data()?;
+
+    let fresh = Lua::new();
+    fresh.load("score = 0; untouched = true").exec()?;
+    fresh.restore_data(&snapshot)?;
+
+    assert_eq!(fresh.load("score").eval::<i64>()?, 42);
+    assert_eq!(fresh.load("name").eval::<std::string::String>()?, "save");
+    assert_eq!(fresh.load("world.size").eval::<f64>()?, 3.5);
+    assert_eq!(fresh.load("#world.tiles").eval::<i64>()?, 3);
+    assert!(fresh.load("world.self == world").eval::<bool>()?);
+    assert!(fresh.load("alias == world.tiles").eval::<bool>()?);
+    assert!(fresh.load("world.update == nil").eval::<bool>()?);
+    assert!(fresh.load("handler == nil and co == nil").eval::<bool>()?);
+    assert!(fresh.load("untouched").eval::<bool>()?);
+    // Standard libraries are not part of the snapshot
+    assert!(fresh.load("string.extra == nil").eval::<bool>()?);
+    assert_eq!(
+        fresh
+            .load("string.rep('a', 2)")
+            .eval::<std::string::String>()?,
+        "aa"
+    );
+
+    // Non-data values in saved tables are errors in strict mode, non-data globals are skipped
+    match lua.snapshot_data_with(false) {
+        Err(Error::FromLuaConversionError { from, .. }) => assert_eq!(from, "function"),
+        r => panic!("expected FromLuaConversionError, got {:?}", r),
+    }
+    lua.load("world.update = nil").exec()?;
+    let strict = lua.snapshot_data_with(false)?;
+    let restored = Lua::new();
+    restored.restore_data(&strict)?;
+    assert!(restored
+        .load("world.self == world and handler == nil")
+        .eval::<bool>()?);
+
+    let value = lua.encode_value(&Value::Integer(1))?;
+    match fresh.restore_data(&value) {
+        Err(Error::ToLuaConversionError { .. }) => {}
+        r => panic!("expected ToLuaConversionError, got {:?}", r),
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_snapshot_restore_frozen_globals() -> Result<()> {
+    let lua = Lua::new();
+    lua.load("player = { name = 'Ann', level = 3 }").exec()?;
+    lua.sandbox(SandboxConfig::default())?;
+    let snapshot = lua.snapshot_data()?;
+
+    let fresh = Lua::new();
+    fresh.restore_data(&snapshot)?;
+    assert_eq!(
+        fresh
+            .load("player.name .. player.level")
+            .eval::<std::string::String>()?,
+        "Ann3"
+    );
+
+    // Restoring into a frozen sandbox replaces the frozen globals
+    fresh.load("player = nil").exec()?;
+    fresh.sandbox(SandboxConfig::default())?;
+    fresh.restore_data(&snapshot)?;
+    assert_eq!(fresh.load("player.level").eval::<i64>()?, 3);
+    assert!(fresh.load("player = 1").exec().is_err());
+
+    Ok(())
+}
@@ -9,7 +9,13 @@
 )]
 extern "system" {}
 
-use mlua::{Function, Lua, Result, String};
+use std::cell::Cell;
+use std::rc::Rc;
+use std::thread;
+
+use mlua::{
+    Error, Function, Lua, MultiValue, Nil, Result, String, UserData, UserDataMethods, Value,
+};
 
 #[test]
 fn test_function() -> Result<()> {
@@ -86,3 +92,436 @@ fn test_rust_function() -> Result<()> {
 
     Ok(())
 }
+
+#[test]
+fn test_call_multi() -> Result<()> {
+    let lua = Lua::new();
+
+    let globals = lua.globals();
+    lua.load(
+        r#"
+        function split(...)
+            return ...
+        end
+    "#,
+    )
+    .exec()?;
+
+    let split = globals.get::<_, Function>("split")?;
+
+    let results = split.call_multi((1, "two", true))?;
+    assert_eq!(results.len(), 3);
+    match results.as_slice() {
+        [Value::Integer(1), Value::String(s), Value::Boolean(true)] => {
+            assert_eq!(s.to_str()?, "two")
+        }
+        r => panic!("unexpected results: {:?}", r),
+    }
+
+    assert!(split.call_multi(())?.is_empty());
+
+    Ok(())
+}
+
+#[test]
+fn test_optional_arguments() -> Result<()> {
+    let lua = Lua::new();
+
+    let globals = lua.globals();
+    let add = lua.create_function(|_, (x, y): (i32, Option<i32>)| Ok(x + y.unwrap_or(0)))?;
+    globals.set("add", add)?;
+
+    assert_eq!(lua.load("return add(1)").eval::<i32>()?, 1);
+    assert_eq!(lua.load("return add(1, nil)").eval::<i32>()?, 1);
+    assert_eq!(lua.load("return add(1, 2)").eval::<i32>()?, 3);
+
+    let is_none = lua.create_function(|_, (_, y): (i32, Option<i32>)| Ok(y.is_none()))?;
+    assert!(is_none.call::<_, bool>(1)?);
+    assert!(is_none.call::<_, bool>((1, Value::Nil))?);
+    assert!(!is_none.call::<_, bool>((1, 2))?);
+
+    Ok(())
+}
+
+#[test]
+fn test_optional_string_arguments() -> Result<()> {
+    let lua = Lua::new();
+
+    let greet = lua.create_function(|_, (greeting, name): (String, Option<String>)| {
+        let name = match name {
+            Some(name) => name.to_str()?.to_owned(),
+            None => "world".to_owned(),
+        };
+        Ok(format!("{} {}", greeting.to_str()?, name))
+    })?;
+    lua.globals().set("greet", greet)?;
+
+    let greet = |code| lua.load(code).eval::<std::string::String>();
+    assert_eq!(greet("greet('hello')")?, "hello world");
+    assert_eq!(greet("greet('hello', nil)")?, "hello world");
+    assert_eq!(greet("greet('hi', 'lua')")?, "hi lua");
+    assert_eq!(greet("greet('hi', 42)")?, "hi 42");
+    assert!(greet("greet('hi', false)").is_err());
+
+    let is_none = lua.create_function(|_, name: Option<std::string::String>| Ok(name.is_none()))?;
+    assert!(is_none.call::<_, bool>(())?);
+    assert!(is_none.call::<_, bool>(Nil)?);
+    assert!(!is_none.call::<_, bool>("")?);
+
+    Ok(())
+}
+
+#[test]
+fn test_caller_source() -> Result<()> {
+    let lua = Lua::new();
+
+    assert_eq!(lua.caller_source(), None);
+
+    let where_am_i = lua.create_function(|lua, ()| match lua.caller_source() {
+        Some((source, line)) => Ok((Some(source), Some(line))),
+        None => Ok((None, None)),
+    })?;
+    lua.globals().set("where_am_i", where_am_i.clone())?;
+
+    let (source, line) = lua
+        .load(
+            r#"
+            local source, line = where_am_i()
+            return source, line
+        "#,
+        )
+        .set_name("caller")?
+        .eval::<(String, u32)>()?;
+    assert_eq!(source, "caller");
+    assert_eq!(line, 2);
+
+    // Called directly from Rust, there is no Lua caller
+    let (source, line) = where_am_i.call::<_, (Option<String>, Option<u32>)>(())?;
+    assert!(source.is_none() && line.is_none());
+
+    Ok(())
+}
+
+#[test]
+fn test_bad_argument() -> Result<()> {
+    struct Counter;
+
+    impl UserData for Counter {
+        fn add_methods<'lua, M: UserDataMethods<'lua, Self>>(methods: &mut M) {
+            methods.add_method("add", |_, _, (_a, _b): (i64, i64)| Ok(()));
+        }
+    }
+
+    let lua = Lua::new();
+    let globals = lua.globals();
+
+    globals.set(
+        "foo",
+        lua.create_function(|_, (_a, _b): (i64, i64)| Ok(()))?,
+    )?;
+    globals.set("counter", Counter)?;
+
+    let message = |code: &str| -> Result<std::string::String> {
+        lua.load(&format!(
+            "local ok, err = pcall({}); return tostring(err)",
+            code
+        ))
+        .eval()
+    };
+    assert_eq!(
+        message("function() foo(1, 'x') end")?,
+        "bad argument #2 to 'foo' (i64 expected, got string)"
+    );
+    assert_eq!(
+        message("function() counter:add({}) end")?,
+        "bad argument #1 to 'add' (i64 expected, got table)"
+    );
+
+    let foo: Function = globals.get("foo")?;
+    match foo.call::<_, ()>((true, 2)) {
+        Err(Error::CallbackError { cause, .. }) => match *cause {
+            Error::BadArgument { pos: 1, .. } => {}
+            ref err => panic!("expected BadArgument, got {:?}", err),
+        },
+        r => panic!("expected CallbackError, got {:?}", r),
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_call_unprotected() -> Result<()> {
+    let lua = Lua::new();
+
+    let sum: Function = lua
+        .load("function(...) local s = 0 for _, v in ipairs({...}) do s = s + v end return s, select('#', ...) end")
+        .eval()?;
+    let (s, n): (i64, usize) = unsafe { sum.call_unprotected((1, 2, 3, 4))? };
+    assert_eq!((s, n), (10, 4));
+
+    let callback = lua.create_function(|_, (a, b): (i64, i64)| Ok(a * b))?;
+    assert_eq!(unsafe { callback.call_unprotected::<_, i64>((6, 7))? }, 42);
+
+    // Conversion errors of the results are still reported
+    match unsafe { sum.call_unprotected::<_, Function>(()) } {
+        Err(Error::FromLuaConversionError { .. }) => {}
+        r => panic!("expected FromLuaConversionError, got {:?}", r),
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_function_line_info() -> Result<()> {
+    let lua = Lua::new();
+
+    lua.load(
+        r#"
+        local function inner()
+        end
+
+        function outer()
+            return inner
+        end
+    "#,
+    )
+    .set_name("@script.lua")?
+    .exec()?;
+
+    let outer: Function = lua.globals().get("outer")?;
+    assert_eq!(outer.line_defined(), Some((5, 7)));
+    assert_eq!(outer.source().as_deref(), Some("@script.lua"));
+
+    let inner: Function = outer.call(())?;
+    assert_eq!(inner.line_defined(), Some((2, 3)));
+
+    let main = lua.load("return 1").set_name("=main")?.into_function()?;
+    assert_eq!(main.line_defined().map(|(first, _)| first), Some(0));
+    assert_eq!(main.source().as_deref(), Some("=main"));
+
+    let callback = lua.create_function(|_, ()| Ok(()))?;
+    assert_eq!(callback.line_defined(), None);
+    assert_eq!(callback.source(), None);
+
+    Ok(())
+}
+
+#[test]
+fn test_array_multi_return() -> Result<()> {
+    let lua = Lua::new();
+
+    let triple = lua.create_function(|_, n: i64| Ok([n, n * 2, n * 3]))?;
+    assert_eq!(triple.call::<_, (i64, i64, i64)>(1)?, (1, 2, 3));
+    lua.globals().set("triple", triple)?;
+    assert_eq!(lua.load("select('#', triple(2))").eval::<i64>()?, 3);
+    assert_eq!(lua.load("select(3, triple(2))").eval::<i64>()?, 6);
+
+    // A `Vec` is returned as a single table
+    let list = lua.create_function(|_, ()| Ok(vec![1, 2, 3]))?;
+    lua.globals().set("list", list)?;
+    assert_eq!(lua.load("select('#', list())").eval::<i64>()?, 1);
+    assert_eq!(lua.load("#list()").eval::<i64>()?, 3);
+
+    let empty = lua.create_function(|_, ()| Ok([0u8; 0]))?;
+    assert_eq!(empty.call::<_, mlua::MultiValue>(())?.len(), 0);
+
+    Ok(())
+}
+
+#[test]
+fn test_set_function() -> Result<()> {
+    let lua = Lua::new();
+
+    lua.set_function("greet", |_, name: String| {
+        Ok(format!("hello, {}", name.to_str()?))
+    })?;
+    assert_eq!(
+        lua.load("greet('lua')").eval::<std::string::String>()?,
+        "hello, lua"
+    );
+
+    let module = lua.create_table()?;
+    module.set_function("double", |_, n: i64| Ok(n * 2))?;
+    module.set_function("pair", |_, ()| Ok((1, 2)))?;
+    lua.globals().set("module", module)?;
+    assert_eq!(lua.load("module.double(21)").eval::<i64>()?, 42);
+    assert_eq!(lua.load("select('#', module.pair())").eval::<i64>()?, 2);
+
+    Ok(())
+}
+
+#[test]
+fn test_function_into_thread() -> Result<()> {
+    let lua = Lua::new();
+
+    let add = lua.create_function(|_, (a, b): (i64, i64)| Ok(a + b))?;
+    let thread = add.into_thread()?;
+    assert_eq!(thread.resume::<_, i64>((1, 2))?, 3);
+    match thread.resume::<_, i64>((1, 2)) {
+        Err(Error::CoroutineInactive) => {}
+        r => panic!("expected CoroutineInactive, got {:?}", r),
+    }
+
+    let fail = lua
+        .create_function(|_, ()| -> Result<()> { Err(Error::RuntimeError("failed".to_owned())) })?;
+    match fail.into_thread()?.resume::<_, ()>(()) {
+        Err(Error::CallbackError { .. }) => {}
+        r => panic!("expected CallbackError, got {:?}", r),
+    }
+
+    let gen: Function = lua
+        .load("function(a) local b = coroutine.yield(a * 2) return a + b end")
+        .eval()?;
+    let thread = gen.into_thread()?;
+    assert_eq!(thread.resume::<_, i64>(4)?, 8);
+    assert_eq!(thread.resume::<_, i64>(3)?, 7);
+
+    Ok(())
+}
+
+#[test]
+fn test_multi_value_pop() -> Result<()> {
+    let lua = Lua::new();
+
+    let f = lua.create_function(|lua, mut args: MultiValue| {
+        let a: i64 = args.pop_or(0, lua)?;
+        let b: i64 = args.pop_or(20, lua)?;
+        let c: Option<i64> = args.pop_opt(lua)?;
+        Ok((a, b, c.unwrap_or(300)))
+    })?;
+    assert_eq!(f.call::<_, (i64, i64, i64)>(1)?, (1, 20, 300));
+    assert_eq!(f.call::<_, (i64, i64, i64)>((1, 2))?, (1, 2, 300));
+    assert_eq!(f.call::<_, (i64, i64, i64)>((1, 2, 3))?, (1, 2, 3));
+    assert_eq!(f.call::<_, (i64, i64, i64)>((1, Nil, 3))?, (1, 20, 3));
+    assert!(f.call::<_, (i64, i64, i64)>((1, "x")).is_err());
+
+    Ok(())
+}
+
+#[test]
+fn test_call_with_rest() -> Result<()> {
+    let lua = Lua::new();
+
+    let f: Function = lua.load("function() return true, 1, 2, 3 end").eval()?;
+
+    let (ok, rest) = f.call_with_rest::<_, bool>(())?;
+    assert!(ok);
+    assert_eq!(lua.convert_all::<i64>(rest.into_vec())?, vec![1, 2, 3]);
+
+    let ((ok, first), rest) = f.call_with_rest::<_, (bool, i64)>(())?;
+    assert!(ok);
+    assert_eq!(first, 1);
+    assert_eq!(lua.convert_all::<i64>(rest.into_vec())?, vec![2, 3]);
+
+    let ((), rest) = f.call_with_rest::<_, ()>(())?;
+    assert_eq!(rest.len(), 4);
+
+    let (all, rest) = f.call_with_rest::<_, MultiValue>(())?;
+    assert_eq!(all.len(), 4);
+    assert!(rest.is_empty());
+
+    // Missing values are nil and nothing remains
+    let ((ok, a, b, c, d), rest) = f.call_with_rest::<_, (bool, i64, i64, i64, Value)>(())?;
+    assert!(ok && a == 1 && b == 2 && c == 3 && d == Nil);
+    assert!(rest.is_empty());
+
+    Ok(())
+}
+
+#[test]
+fn test_function_upvalues() -> Result<()> {
+    let lua = Lua::new();
+
+    let (get, set): (Function, Function) = lua
+        .load(
+            r#"
+                local prefix, count = "n", 0
+                local function get()
+                    count = count + 1
+                    return prefix .. count
+                end
+                local function set(v) count = v end
+                return get, set
+            "#,
+        )
+        .eval()?;
+
+    assert_eq!(get.call::<_, std::string::String>(())?, "n1");
+    let (name, value) = get.get_upvalue::<i64>(1)?.unwrap();
+    assert_eq!((name.as_str(), value), ("count", 1));
+    let (name, value) = get.get_upvalue::<String>(2)?.unwrap();
+    assert_eq!((name.as_str(), value.to_str()?), ("prefix", "n"));
+    assert!(get.get_upvalue::<Value>(3)?.is_none());
+    assert!(get.get_upvalue::<Value>(0)?.is_none());
+
+    // Upvalues are shared between closures
+    get.set_upvalue(1, 10)?;
+    get.set_upvalue(2, "m")?;
+    assert_eq!(get.call::<_, std::string::String>(())?, "m11");
+    set.call::<_, ()>(20)?;
+    assert_eq!(get.get_upvalue::<i64>(1)?.unwrap().1, 20);
+
+    match get.get_upvalue::<i64>(2) {
+        Err(Error::FromLuaConversionError { .. }) => {}
+        r => panic!("expected FromLuaConversionError, got {:?}", r),
+    }
+    match get.set_upvalue(3, 0) {
+        Err(Error::RuntimeError(_)) => {}
+        r => panic!("expected RuntimeError, got {:?}", r),
+    }
+
+    // Upvalues of C functions are not accessible
+    let rust_func = lua.create_function(|_, ()| Ok(()))?;
+    assert!(rust_func.get_upvalue::<Value>(1)?.is_none());
+    match rust_func.set_upvalue(1, Nil) {
+        Err(Error::RuntimeError(_)) => {}
+        r => panic!("expected RuntimeError, got {:?}", r),
+    }
+    rust_func.call::<_, ()>(())?;
+
+    Ok(())
+}
+
+#[test]
+fn test_create_function_local() -> Result<()> {
+    let count = Rc::new(Cell::new(0));
+
+    let lua = Lua::new();
+    let counter = count.clone();
+    let add = lua.create_function_local(move |_, n: i64| {
+        counter.set(counter.get() + n);
+        Ok(counter.get())
+    })?;
+    lua.globals().set("add", add)?;
+
+    assert_eq!(lua.load("add(1); return add(2)").eval::<i64>()?, 3);
+
+    // The callback only runs on the thread that created it
+    let lua = thread::spawn(move || {
+        match lua.load("add(1)").exec() {
+            Err(Error::CallbackError { ref cause, .. }) => match **cause {
+                Error::RuntimeError(_) => {}
+                ref err => panic!("expected RuntimeError, got {:?}", err),
+            },
+            r => panic!("expected CallbackError, got {:?}", r),
+        }
+        lua
+    })
+    .join()
+    .unwrap();
+    assert_eq!(lua.load("return add(4)").eval::<i64>()?, 7);
+    assert_eq!(Rc::strong_count(&count), 2);
+
+    // Dropping the Lua instance on another thread leaks the callback
+    thread::spawn(move || drop(lua)).join().unwrap();
+    assert_eq!(Rc::strong_count(&count), 2);
+
+    let lua = Lua::new();
+    let counter = count.clone();
+    lua.create_function_local(move |_, ()| Ok(counter.get()))?;
+    assert_eq!(Rc::strong_count(&count), 3);
+    drop(lua);
+    assert_eq!(Rc::strong_count(&count), 2);
+
+    Ok(())
+}
@@ -0,0 +1,396 @@
+#![cfg_attr(
+    all(feature = "luajit", target_os = "macos", target_arch = "x86_64"),
+    feature(link_args)
+)]
+
+#[cfg_attr(
+    all(feature = "luajit", target_os = "macos", target_arch = "x86_64"),
+    link_args = "-pagezero_size 10000 -image_base 100000000"
+)]
+extern "system" {}
+
+use std::ops::Deref;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use mlua::{DebugEvent, Error, HookMask, Lua, Result, StdLib, Value, Variadic};
+
+#[test]
+fn test_line_counts() -> Result<()> {
+    let output = Arc::new(Mutex::new(Vec::new()));
+    let hook_output = output.clone();
+
+    let lua = Lua::new();
+    lua.set_hook(HookMask::LINE, 0, move |_lua, debug| {
+        assert_eq!(debug.event(), DebugEvent::Line);
+        hook_output.lock().unwrap().push(debug.curr_line());
+        Ok(())
+    })?;
+    lua.load(
+        r#"
+            local x = 2 + 3
+            local y = x * 63
+            local z = string.len(x..", "..y)
+        "#,
+    )
+    .exec()?;
+
+    let output = output.lock().unwrap();
+    assert_eq!(*output, vec![2, 3, 4]);
+
+    Ok(())
+}
+
+#[test]
+fn test_function_calls() -> Result<()> {
+    let output = Arc::new(Mutex::new(Vec::new()));
+    let hook_output = output.clone();
+
+    let lua = Lua::new();
+    lua.set_hook(HookMask::CALL, 0, move |_lua, debug| {
+        assert_eq!(debug.event(), DebugEvent::Call);
+        let names = debug.names();
+        let source = debug.source();
+        let name = names
+            .name
+            .map(|s| std::str::from_utf8(s).unwrap().to_owned());
+        let what = source
+            .what
+            .map(|s| std::str::from_utf8(s).unwrap().to_owned());
+        hook_output.lock().unwrap().push((name, what));
+        Ok(())
+    })?;
+
+    lua.load(
+        r#"
+            local v = string.len("Hello World")
+        "#,
+    )
+    .exec()?;
+
+    let output = output.lock().unwrap();
+    assert_eq!(
+        *output,
+        vec![
+            (None, Some("main".to_string())),
+            (Some("len".to_string()), Some("C".to_string()))
+        ]
+    );
+
+    Ok(())
+}
+
+#[test]
+fn test_combined_mask() -> Result<()> {
+    let output = Arc::new(Mutex::new(Vec::new()));
+    let hook_output = output.clone();
+
+    let lua = Lua::new();
+    lua.set_hook(
+        HookMask::CALL | HookMask::RET | HookMask::LINE,
+        0,
+        move |_lua, debug| {
+            hook_output.lock().unwrap().push(debug.event());
+            Ok(())
+        },
+    )?;
+
+    lua.load("local x = 1").exec()?;
+
+    let output = output.lock().unwrap();
+    assert_eq!(
+        *output,
+        vec![DebugEvent::Call, DebugEvent::Line, DebugEvent::Ret]
+    );
+
+    Ok(())
+}
+
+#[test]
+fn test_error_within_hook() -> Result<()> {
+    let lua = Lua::new();
+    lua.set_hook(HookMask::COUNT, 1, |_lua, _debug| {
+        Err(Error::RuntimeError(
+            "Something happened in there!".to_string(),
+        ))
+    })?;
+
+    let err = lua
+        .load("x = 1")
+        .exec()
+        .expect_err("panic didn't propagate");
+
+    match err {
+        Error::CallbackError { cause, .. } => match cause.deref() {
+            Error::RuntimeError(s) => assert_eq!(s, "Something happened in there!"),
+            _ => panic!("wrong callback error kind caught"),
+        },
+        _ => panic!("wrong error kind caught"),
+    };
+
+    Ok(())
+}
+
+#[test]
+fn test_hook_reentrancy() -> Result<()> {
+    let lua = Lua::new();
+    lua.globals().set("hits", 0)?;
+    lua.set_hook(HookMask::LINE, 0, |lua, _debug| {
+        // Calling into Lua must not trigger the hook recursively
+        lua.load("hits = hits + 1").exec()
+    })?;
+
+    lua.load(
+        r#"
+            local a = 1
+            local b = 2
+        "#,
+    )
+    .exec()?;
+
+    assert_eq!(lua.globals().get::<_, i64>("hits")?, 2);
+
+    Ok(())
+}
+
+#[test]
+fn test_remove_hook() -> Result<()> {
+    let output = Arc::new(Mutex::new(0));
+    let hook_output = output.clone();
+
+    let lua = Lua::new();
+    lua.set_hook(HookMask::LINE, 0, move |lua, _debug| {
+        lua.remove_hook();
+        *hook_output.lock().unwrap() += 1;
+        Ok(())
+    })?;
+
+    lua.load(
+        r#"
+            local x = 1
+            local y = 2
+        "#,
+    )
+    .exec()?;
+    assert_eq!(*output.lock().unwrap(), 1);
+
+    lua.load("local z = 3").exec()?;
+    assert_eq!(*output.lock().unwrap(), 1);
+
+    Ok(())
+}
+
+#[test]
+fn test_exec_with_timeout() -> Result<()> {
+    let lua = Lua::new();
+
+    let start = Instant::now();
+    match lua.exec_with_timeout(lua.load("while true do end"), Duration::from_millis(50)) {
+        Err(Error::Timeout) => {}
+        r => panic!("expected Timeout, got {:?}", r),
+    }
+    assert!(start.elapsed() >= Duration::from_millis(50));
+    assert!(start.elapsed() < Duration::from_secs(5));
+
+    lua.exec_with_timeout(lua.load("x = 1 + 1"), Duration::from_secs(5))?;
+    assert_eq!(lua.globals().get::<_, i64>("x")?, 2);
+
+    // A previously installed hook keeps running and is restored afterwards
+    let lines = Arc::new(Mutex::new(0));
+    let hook_lines = lines.clone();
+    lua.set_hook(HookMask::LINE, 0, move |_, _| {
+        *hook_lines.lock().unwrap() += 1;
+        Ok(())
+    })?;
+    lua.exec_with_timeout(lua.load("local y = 1"), Duration::from_secs(5))?;
+    assert_eq!(*lines.lock().unwrap(), 1);
+    lua.load("local z = 1").exec()?;
+    assert_eq!(*lines.lock().unwrap(), 2);
+
+    // The timeout still fires when the previous hook counts instructions
+    let counts = Arc::new(Mutex::new(0));
+    let hook_counts = counts.clone();
+    lua.set_hook(HookMask::COUNT, 100, move |_, _| {
+        *hook_counts.lock().unwrap() += 1;
+        Ok(())
+    })?;
+    match lua.exec_with_timeout(lua.load("while true do end"), Duration::from_millis(50)) {
+        Err(Error::Timeout) => {}
+        r => panic!("expected Timeout, got {:?}", r),
+    }
+    assert!(*counts.lock().unwrap() > 0);
+
+    Ok(())
+}
+
+#[test]
+fn test_without_debug_library() -> Result<()> {
+    let lua = Lua::new_with(StdLib::STRING);
+    assert_eq!(lua.globals().get::<_, Value>("debug")?, Value::Nil);
+
+    let lines = Arc::new(Mutex::new(Vec::new()));
+    let hook_lines = lines.clone();
+    lua.set_hook(HookMask::LINE, 0, move |_lua, debug| {
+        hook_lines.lock().unwrap().push(debug.curr_line());
+        let _ = (debug.names(), debug.source(), debug.stack());
+        Ok(())
+    })?;
+
+    let caller = lua.create_function(|lua, ()| Ok(lua.caller_source().map(|(_, line)| line)))?;
+    lua.globals().set("caller", caller)?;
+    let fail = lua
+        .create_function(|_, ()| -> Result<()> { Err(Error::RuntimeError("fail".to_string())) })?;
+    lua.globals().set("fail", fail)?;
+
+    let line: Option<u32> = lua
+        .load(
+            r#"
+            local x = 1
+            return caller()
+        "#,
+        )
+        .eval()?;
+    assert_eq!(line, Some(3));
+    assert!(!lines.lock().unwrap().is_empty());
+
+    lua.remove_hook();
+    match lua.load("fail()").exec() {
+        Err(Error::CallbackError { traceback, .. }) => {
+            assert!(traceback.contains("stack traceback"));
+        }
+        r => panic!("expected CallbackError, got {:?}", r),
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_profile() -> Result<()> {
+    let lua = Lua::new();
+
+    lua.globals().set(
+        "rust_fn",
+        lua.create_function(|_, ()| {
+            std::thread::sleep(Duration::from_millis(5));
+            Ok(())
+        })?,
+    )?;
+
+    lua.profile(true)?;
+    lua.load(
+        r#"
+        local function fib(n)
+            if n < 2 then return n end
+            return fib(n - 1) + fib(n - 2)
+        end
+        local function fails() error("boom") end
+        fib(10)
+        for i = 1, 3 do rust_fn() end
+        pcall(function() fails() end)
+    "#,
+    )
+    .set_name("profiled")?
+    .exec()?;
+    lua.profile(false)?;
+
+    let results = lua.profile_results();
+    let find = |name: &str| {
+        results
+            .iter()
+            .find(|p| p.name.as_deref() == Some(name))
+            .unwrap_or_else(|| panic!("no profile for {}", name))
+    };
+
+    let fib = find("fib");
+    assert_eq!(fib.calls, 177);
+    assert_eq!(fib.source.as_deref(), Some("[string \"profiled\"]"));
+    assert_eq!(fib.line_defined, 2);
+
+    let rust_fn = find("rust_fn");
+    assert_eq!(rust_fn.calls, 3);
+    assert!(rust_fn.total_time >= Duration::from_millis(15));
+    assert_eq!(find("fails").calls, 1);
+
+    // Nothing is collected once disabled
+    lua.load("rust_fn()").exec()?;
+    assert_eq!(lua.profile_results().len(), results.len());
+    assert_eq!(find("rust_fn").calls, 3);
+
+    Ok(())
+}
+
+#[test]
+fn test_breakpoint() -> Result<()> {
+    let lua = Lua::new();
+    lua.load(
+        r#"
+        function add(a, b) local c = a + b return c end
+        function sum(...) local s = 0 for _, v in ipairs({...}) do s = s + v end return s end
+        function other(x) return x end
+    "#,
+    )
+    .exec()?;
+    let globals = lua.globals();
+    let mul = lua.create_function(|_, (a, b): (i64, i64)| Ok(a * b))?;
+    globals.set("mul", mul.clone())?;
+
+    let hits = Arc::new(Mutex::new(Vec::new()));
+
+    let add: mlua::Function = globals.get("add")?;
+    let hook_hits = hits.clone();
+    let breakpoint = add.set_breakpoint(move |lua, _debug, args| {
+        let (a, b): (i64, i64) = lua.unpack_multi(args)?;
+        hook_hits.lock().unwrap().push(vec![a, b]);
+        Ok(())
+    })?;
+    lua.load("other(1); add(1, 2); other(add(3, 4))").exec()?;
+    assert_eq!(*hits.lock().unwrap(), vec![vec![1, 2], vec![3, 4]]);
+
+    drop(breakpoint);
+    lua.load("add(5, 6)").exec()?;
+    assert_eq!(hits.lock().unwrap().len(), 2);
+
+    // Variable arguments and Rust functions
+    hits.lock().unwrap().clear();
+    lua.load("function mixed(a, ...) return a + select('#', ...) end")
+        .exec()?;
+    let calls = [
+        ("sum", "assert(sum(1, 2, 3) == 6)"),
+        ("mul", "assert(mul(4, 5) == 20)"),
+        ("mixed", "assert(mixed(7, 8, 9) == 9)"),
+        ("mixed", "assert(mixed(10) == 10)"),
+    ];
+    for (name, chunk) in IntoIterator::into_iter(calls) {
+        let f: mlua::Function = globals.get(name)?;
+        let hook_hits = hits.clone();
+        let _breakpoint = f.set_breakpoint(move |lua, _debug, args| {
+            let args: Variadic<i64> = lua.unpack_multi(args)?;
+            hook_hits.lock().unwrap().push(args.to_vec());
+            Ok(())
+        })?;
+        lua.load(chunk).exec()?;
+    }
+    #[cfg(any(feature = "lua53", feature = "lua52"))]
+    assert_eq!(
+        *hits.lock().unwrap(),
+        vec![vec![1, 2, 3], vec![4, 5], vec![7, 8, 9], vec![10]]
+    );
+
+    // Errors abort the call
+    let _breakpoint = mul.set_breakpoint(|_, _, _| Err(Error::RuntimeError("break".to_owned())))?;
+    assert!(lua.load("mul(1, 2)").exec().is_err());
+
+    Ok(())
+}
+
+#[test]
+fn test_hook_mask() {
+    let mask = HookMask::CALL | HookMask::RET;
+    assert!(mask.contains(HookMask::CALL));
+    assert!(mask.contains(HookMask::CALL | HookMask::RET));
+    assert!(!mask.contains(HookMask::CALL | HookMask::LINE));
+    assert!(!mask.contains(HookMask::ALL));
+    assert!(mask.contains(HookMask::NONE));
+    assert!(HookMask::ALL.contains(mask | HookMask::LINE | HookMask::COUNT));
+    assert!(!HookMask::NONE.contains(HookMask::COUNT));
+}
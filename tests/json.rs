@@ -0,0 +1,144 @@
+#![cfg(feature = "json")]
+#![cfg_attr(
+    all(feature = "luajit", target_os = "macos", target_arch = "x86_64"),
+    feature(link_args)
+)]
+
+#[cfg_attr(
+    all(feature = "luajit", target_os = "macos", target_arch = "x86_64"),
+    link_args = "-pagezero_size 10000 -image_base 100000000"
+)]
+extern "system" {}
+
+use mlua::{Error, Lua, Result, Table, Value};
+use serde_json::json;
+
+#[test]
+fn test_from_json_value() -> Result<()> {
+    let lua = Lua::new();
+
+    let json = json!({
+        "name": "mlua",
+        "int": 42,
+        "float": 1.5,
+        "flag": true,
+        "none": null,
+        "list": [1, "two", [3]],
+    });
+    let table: Table = lua.unpack(lua.from_json_value(&json)?)?;
+
+    assert_eq!(table.get::<_, String>("name")?, "mlua");
+    assert_eq!(table.get::<_, Value>("int")?, Value::Integer(42));
+    assert_eq!(table.get::<_, Value>("float")?, Value::Number(1.5));
+    assert!(table.get::<_, bool>("flag")?);
+    assert_eq!(table.get::<_, Value>("none")?, Value::Nil);
+
+    let list: Table = table.get("list")?;
+    assert_eq!(list.raw_len(), 3);
+    assert_eq!(list.get::<_, i64>(1)?, 1);
+    assert_eq!(list.get::<_, String>(2)?, "two");
+    assert_eq!(list.get::<_, Table>(3)?.get::<_, i64>(1)?, 3);
+
+    Ok(())
+}
+
+#[test]
+fn test_to_json_value() -> Result<()> {
+    let lua = Lua::new();
+
+    let value = lua
+        .load(
+            r#"
+            {
+                name = "mlua",
+                int = 42,
+                float = 1.5,
+                flag = false,
+                list = { 1, 2.5, "three" },
+                empty = {},
+                [7] = "seven",
+            }
+        "#,
+        )
+        .eval::<Value>()?;
+
+    assert_eq!(
+        lua.to_json_value(value)?,
+        json!({
+            "name": "mlua",
+            "int": 42,
+            "float": 1.5,
+            "flag": false,
+            "list": [1, 2.5, "three"],
+            "empty": {},
+            "7": "seven",
+        })
+    );
+    assert_eq!(lua.to_json_value(Value::Nil)?, json!(null));
+
+    Ok(())
+}
+
+#[test]
+fn test_json_round_trip() -> Result<()> {
+    let lua = Lua::new();
+
+    let json = json!({ "a": [1, 2, { "b": null, "c": -3.25 }], "d": "e" });
+    let value = lua.from_json_value(&json)?;
+    // `null` object members are dropped since Lua tables cannot store `nil`
+    assert_eq!(
+        lua.to_json_value(value)?,
+        json!({ "a": [1, 2, { "c": -3.25 }], "d": "e" })
+    );
+
+    // `null` array elements are kept as `Value::NULL`
+    let json = json!([1, null, null, 4]);
+    let list: Table = lua.unpack(lua.from_json_value(&json)?)?;
+    assert_eq!(list.raw_len(), 4);
+    assert!(list.get::<_, Value>(2)?.is_null());
+    assert_eq!(lua.to_json_value(Value::Table(list))?, json);
+
+    Ok(())
+}
+
+#[test]
+fn test_to_json_value_errors() -> Result<()> {
+    let lua = Lua::new();
+
+    let recursive = lua.load("local t = {} t.t = t return t").eval::<Value>()?;
+    match lua.to_json_value(recursive) {
+        Err(Error::FromLuaConversionError { .. }) => {}
+        r => panic!("expected FromLuaConversionError, got {:?}", r),
+    }
+
+    let func = lua.load("function() end").eval::<Value>()?;
+    match lua.to_json_value(func) {
+        Err(Error::FromLuaConversionError {
+            from: "function", ..
+        }) => {}
+        r => panic!("expected FromLuaConversionError, got {:?}", r),
+    }
+
+    match lua.to_json_value(Value::Number(f64::NAN)) {
+        Err(Error::FromLuaConversionError { .. }) => {}
+        r => panic!("expected FromLuaConversionError, got {:?}", r),
+    }
+
+    let bad_key = lua.load("{ [true] = 1 }").eval::<Value>()?;
+    assert!(lua.to_json_value(bad_key).is_err());
+
+    // Nothing is silently dropped
+    for chunk in &["{ 1, nil, 3 }", "{ [1] = 'a', ['1'] = 'b' }"] {
+        let value = lua.load(*chunk).eval::<Value>()?;
+        match lua.to_json_value(value) {
+            Err(Error::FromLuaConversionError { from: "table", .. }) => {}
+            r => panic!("expected FromLuaConversionError for {}, got {:?}", chunk, r),
+        }
+    }
+
+    // Shared (non-recursive) references are fine
+    let shared = lua.load("local t = {1} return {t, t}").eval::<Value>()?;
+    assert_eq!(lua.to_json_value(shared)?, json!([[1], [1]]));
+
+    Ok(())
+}
@@ -0,0 +1,150 @@
+#![cfg(feature = "macros")]
+#![cfg_attr(
+    all(feature = "luajit", target_os = "macos", target_arch = "x86_64"),
+    feature(link_args)
+)]
+
+#[cfg_attr(
+    all(feature = "luajit", target_os = "macos", target_arch = "x86_64"),
+    link_args = "-pagezero_size 10000 -image_base 100000000"
+)]
+extern "system" {}
+
+use mlua::{Error, Lua, Result};
+
+struct Counter {
+    value: i64,
+}
+
+#[mlua::methods]
+impl Counter {
+    pub fn new(value: i64) -> Counter {
+        Counter { value }
+    }
+
+    pub fn get(&self) -> i64 {
+        self.value
+    }
+
+    #[lua(name = "inc")]
+    pub fn increment(&mut self, by: Option<i64>) {
+        self.value += by.unwrap_or(1);
+    }
+
+    pub fn set(&mut self, value: i64) -> Result<()> {
+        if value < 0 {
+            return Err(Error::RuntimeError("negative value".to_owned()));
+        }
+        self.value = value;
+        Ok(())
+    }
+
+    pub fn add_global(&self, lua: &Lua, name: String) -> Result<i64> {
+        Ok(self.value + lua.globals().get::<_, i64>(name)?)
+    }
+
+    #[lua(meta = "ToString")]
+    pub fn describe(&self) -> String {
+        format!("Counter({})", self.value)
+    }
+
+    #[lua(meta = "Add")]
+    pub fn sum(a: mlua::AnyUserData, b: mlua::AnyUserData) -> Result<i64> {
+        Ok(a.borrow::<Counter>()?.value + b.borrow::<Counter>()?.value)
+    }
+
+    #[allow(dead_code)]
+    fn hidden(&self) -> i64 {
+        self.value
+    }
+}
+
+#[test]
+fn test_methods_macro() -> Result<()> {
+    let lua = Lua::new();
+    let globals = lua.globals();
+
+    globals.set("counter", Counter::new(1))?;
+    globals.set("other", Counter::new(10))?;
+    globals.set("offset", 100)?;
+
+    lua.load(
+        r#"
+        assert(counter:get() == 1)
+        counter:inc()
+        counter:inc(5)
+        assert(counter:get() == 7)
+        assert(counter:add_global("offset") == 107)
+        assert(tostring(counter) == "Counter(7)")
+        assert(counter + other == 17)
+        assert(counter.hidden == nil)
+        assert(counter.increment == nil)
+        counter:set(3)
+    "#,
+    )
+    .exec()?;
+    assert_eq!(
+        globals
+            .get::<_, mlua::AnyUserData>("counter")?
+            .borrow::<Counter>()?
+            .value,
+        3
+    );
+
+    assert!(lua.load("counter:set(-1)").exec().is_err());
+    // Associated functions are registered as plain functions
+    assert_eq!(lua.load("counter.new(5):get()").eval::<i64>()?, 5);
+
+    Ok(())
+}
+
+#[derive(mlua::VariantName)]
+enum Shape {
+    Circle { radius: f64 },
+    Rect(f64, f64),
+    Empty,
+}
+
+impl mlua::UserData for Shape {
+    fn add_methods<'lua, M: mlua::UserDataMethods<'lua, Self>>(methods: &mut M) {
+        methods.add_variant_name_method();
+        methods.add_method("area", |_, shape, ()| {
+            Ok(match shape {
+                Shape::Circle { radius } => std::f64::consts::PI * radius * radius,
+                Shape::Rect(w, h) => w * h,
+                Shape::Empty => 0.0,
+            })
+        });
+    }
+}
+
+#[test]
+fn test_variant_name_derive() -> Result<()> {
+    use mlua::VariantName;
+
+    assert_eq!(Shape::Circle { radius: 1.0 }.variant_name(), "Circle");
+    assert_eq!(Shape::Rect(1.0, 2.0).variant_name(), "Rect");
+    assert_eq!(Shape::Empty.variant_name(), "Empty");
+
+    let lua = Lua::new();
+    let globals = lua.globals();
+    globals.set("rect", Shape::Rect(2.0, 3.0))?;
+    globals.set("empty", Shape::Empty)?;
+
+    lua.load(
+        r#"
+        local function describe(shape)
+            local name = shape:variant_name()
+            if name == "Rect" then
+                return "rect " .. shape:area()
+            end
+            return name
+        end
+        assert(describe(rect) == "rect 6.0")
+        assert(describe(empty) == "Empty")
+    "#,
+    )
+    .exec()?;
+
+    Ok(())
+}
@@ -11,7 +11,32 @@ extern "system" {}
 
 use std::sync::Arc;
 
-use mlua::{Lua, Result, UserData};
+use mlua::{Error, Lua, Result, UserData, Value};
+
+#[cfg(not(feature = "luajit"))]
+#[test]
+fn test_memory_limit() -> Result<()> {
+    let lua = Lua::new();
+
+    let initial_memory = lua.used_memory();
+    assert!(initial_memory > 0);
+
+    let f = lua
+        .load("local t = {}; for i = 1,10000 do t[i] = i end")
+        .into_function()?;
+    f.call::<_, ()>(())?;
+
+    assert_eq!(lua.set_memory_limit(initial_memory + 10000)?, 0);
+    match f.call::<_, ()>(()) {
+        Err(Error::MemoryError(_)) => {}
+        something_else => panic!("did not trigger memory error: {:?}", something_else),
+    };
+
+    assert_eq!(lua.set_memory_limit(0)?, initial_memory + 10000);
+    f.call::<_, ()>(())?;
+
+    Ok(())
+}
 
 #[test]
 fn test_gc_control() -> Result<()> {
@@ -32,7 +57,7 @@ fn test_gc_control() -> Result<()> {
 
     let rc = Arc::new(());
     globals.set("userdata", lua.create_userdata(MyUserdata(rc.clone()))?)?;
-    globals.raw_remove("userdata")?;
+    globals.raw_remove::<_, Value>("userdata")?;
 
     assert_eq!(Arc::strong_count(&rc), 2);
     lua.gc_collect()?;
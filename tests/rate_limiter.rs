@@ -0,0 +1,96 @@
+#![cfg_attr(
+    all(feature = "luajit", target_os = "macos", target_arch = "x86_64"),
+    feature(link_args)
+)]
+
+#[cfg_attr(
+    all(feature = "luajit", target_os = "macos", target_arch = "x86_64"),
+    link_args = "-pagezero_size 10000 -image_base 100000000"
+)]
+extern "system" {}
+
+use std::time::{Duration, Instant};
+
+use mlua::{Lua, RateLimiter, Result};
+
+#[test]
+fn test_rate_limiter() -> Result<()> {
+    let lua = Lua::new();
+
+    let limiter = lua.create_userdata(RateLimiter::new(3, Duration::from_secs(3600)))?;
+    lua.globals().set("limiter", limiter.clone())?;
+
+    lua.load(
+        r#"
+        assert(limiter:available() == 3)
+        assert(limiter:try_acquire())
+        assert(limiter:try_acquire(2))
+        assert(not limiter:try_acquire())
+        assert(limiter:available() == 0)
+        assert(limiter:try_acquire(0))
+    "#,
+    )
+    .exec()?;
+
+    Ok(())
+}
+
+#[test]
+fn test_rate_limiter_refill() -> Result<()> {
+    let interval = Duration::from_secs(1);
+    let mut limiter = RateLimiter::new(3, interval);
+
+    // A full bucket starts counting from the first acquisition
+    let start = Instant::now();
+    assert!(limiter.try_acquire_at(3, start));
+    assert_eq!(limiter.available_at(start), 0);
+
+    // Tokens are added for whole intervals only, the remainder is kept
+    assert_eq!(limiter.available_at(start + interval / 2), 0);
+    assert_eq!(limiter.available_at(start + interval * 3 / 2), 1);
+    assert!(limiter.try_acquire_at(1, start + interval * 3 / 2));
+    assert_eq!(limiter.available_at(start + interval * 2), 1);
+
+    // Times before the last refill add nothing
+    assert_eq!(limiter.available_at(start), 1);
+
+    // The bucket refills up to its capacity
+    assert_eq!(limiter.available_at(start + interval * 100), 3);
+
+    // Acquiring more than available takes nothing
+    let later = start + interval * 100;
+    assert!(!limiter.try_acquire_at(4, later));
+    assert_eq!(limiter.available_at(later), 3);
+
+    Ok(())
+}
+
+#[test]
+fn test_rate_limiter_throttle() -> Result<()> {
+    let lua = Lua::new();
+    lua.globals()
+        .set("limiter", RateLimiter::new(5, Duration::from_secs(3600)))?;
+
+    let acquired = lua
+        .load(
+            r#"
+            local acquired = 0
+            for i = 1, 100000 do
+                if limiter:try_acquire() then
+                    acquired = acquired + 1
+                end
+            end
+            return acquired
+        "#,
+        )
+        .eval::<u32>()?;
+    assert_eq!(acquired, 5);
+
+    let mut unlimited = RateLimiter::new(2, Duration::from_secs(0));
+    assert_eq!(unlimited.capacity(), 2);
+    assert!(unlimited.try_acquire(2));
+    assert!(unlimited.try_acquire(2));
+    assert!(!unlimited.try_acquire(3));
+
+    Ok(())
+}
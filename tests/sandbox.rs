@@ -0,0 +1,339 @@
+#![cfg_attr(
+    all(feature = "luajit", target_os = "macos", target_arch = "x86_64"),
+    feature(link_args)
+)]
+
+#[cfg_attr(
+    all(feature = "luajit", target_os = "macos", target_arch = "x86_64"),
+    link_args = "-pagezero_size 10000 -image_base 100000000"
+)]
+extern "system" {}
+
+use std::time::Duration;
+
+use mlua::{Error, ErrorKind, HookMask, Lua, Nil, Result, SandboxConfig, StdLib, Value};
+
+#[test]
+fn test_sandbox_libs() -> Result<()> {
+    let lua = Lua::new();
+    lua.sandbox(SandboxConfig {
+        ..SandboxConfig::default()
+    })?;
+
+    let globals = lua.globals();
+    for name in &[
+        "io", "os", "debug", "package", "require", "dofile", "loadfile",
+    ] {
+        assert_eq!(
+            globals.get::<_, Value>(*name)?,
+            Nil,
+            "`{}` is available",
+            name
+        );
+    }
+    assert_eq!(lua.load("string.len('abc')").eval::<i64>()?, 3);
+    assert_eq!(lua.load("math.max(1, 2)").eval::<i64>()?, 2);
+
+    Ok(())
+}
+
+#[test]
+fn test_sandbox_bytecode() -> Result<()> {
+    let lua = Lua::new();
+    let bytecode = lua
+        .load("return string.dump(function() return 1 end)")
+        .eval::<mlua::String>()?;
+    lua.globals().set("bytecode", bytecode)?;
+    lua.sandbox(SandboxConfig::default())?;
+
+    assert_eq!(lua.load("string.dump").eval::<Value>()?, Nil);
+    assert!(lua
+        .load("load(string.dump(function() end))")
+        .exec()
+        .is_err());
+
+    let (f, err) = lua
+        .load("return load(bytecode)")
+        .eval::<(Value, Option<String>)>()?;
+    assert_eq!(f, Nil);
+    assert!(err.unwrap().contains("binary"));
+
+    #[cfg(any(feature = "lua51", feature = "luajit"))]
+    assert!(lua.load("loadstring(bytecode) == nil").eval::<bool>()?);
+
+    // Source code still loads, from strings and reader functions
+    assert_eq!(lua.load("load('return 1 + 1')()").eval::<i64>()?, 2);
+    assert_eq!(
+        lua.load(
+            r#"
+            local parts = {"return ", "40 + 2"}
+            local i = 0
+            return load(function() i = i + 1; return parts[i] end)()
+        "#
+        )
+        .eval::<i64>()?,
+        42
+    );
+    #[cfg(any(feature = "lua53", feature = "lua52"))]
+    assert_eq!(
+        lua.load("load('return x', 'chunk', 't', {x = 7})()")
+            .eval::<i64>()?,
+        7
+    );
+
+    Ok(())
+}
+
+#[test]
+fn test_sandbox_frozen_globals() -> Result<()> {
+    let lua = Lua::new();
+    lua.globals().set("answer", 42)?;
+    lua.sandbox(SandboxConfig {
+        ..SandboxConfig::default()
+    })?;
+
+    assert_eq!(lua.load("answer").eval::<i64>()?, 42);
+    assert_eq!(lua.globals().get::<_, i64>("answer")?, 42);
+
+    // Locals and tables still work
+    assert_eq!(
+        lua.load("local t = {}; t.x = 1; return t.x")
+            .eval::<i64>()?,
+        1
+    );
+
+    for code in &[
+        "answer = 1",
+        "new_global = 1",
+        "_G.answer = 1",
+        "rawset(_G, 'answer', 1)",
+        "setmetatable(_G, nil)",
+        "load('answer = 1')()",
+    ] {
+        match lua.load(*code).exec() {
+            Err(Error::RuntimeError(_)) | Err(Error::CallbackError { .. }) => {}
+            r => panic!("`{}` did not fail: {:?}", code, r),
+        }
+    }
+    #[cfg(any(feature = "lua51", feature = "luajit"))]
+    for code in &["getfenv().answer = 1", "getfenv(0).answer = 1"] {
+        assert!(lua.load(*code).exec().is_err(), "`{}` did not fail", code);
+    }
+    assert_eq!(lua.load("answer").eval::<i64>()?, 42);
+
+    // The writable globals table is not reachable through iteration either
+    #[cfg(any(feature = "lua53", feature = "lua52"))]
+    assert!(lua
+        .load(
+            r#"
+            local found
+            for k, v in pairs(_G) do
+                if k == "answer" then found = v end
+                assert(k ~= "_G" or rawequal(v, _G))
+            end
+            return found == 42
+        "#
+        )
+        .eval::<bool>()?);
+
+    // The globals table itself is unchanged and can still be modified by the host
+    let globals = lua.globals();
+    assert_eq!(globals.raw_get::<_, i64>("answer")?, 42);
+    assert!(globals
+        .clone()
+        .pairs::<String, Value>()
+        .any(|pair| pair.map(|(k, _)| k == "answer").unwrap_or(false)));
+    globals.set("answer", 43)?;
+    globals.raw_set("added", true)?;
+    assert_eq!(lua.load("answer").eval::<i64>()?, 43);
+    assert!(lua.load("added").eval::<bool>()?);
+
+    Ok(())
+}
+
+#[test]
+fn test_sandbox_keep_globals() -> Result<()> {
+    let lua = Lua::new();
+    lua.sandbox(SandboxConfig {
+        remove_libs: StdLib::OS,
+        freeze_globals: false,
+        ..SandboxConfig::default()
+    })?;
+
+    lua.load("x = 1").exec()?;
+    assert_eq!(lua.globals().get::<_, i64>("x")?, 1);
+    assert_eq!(lua.load("os").eval::<Value>()?, Nil);
+    assert!(lua.load("require('os')").exec().is_err());
+    assert!(lua.load("io ~= nil").eval::<bool>()?);
+
+    Ok(())
+}
+
+#[test]
+fn test_sandbox_instruction_limit() -> Result<()> {
+    let lua = Lua::new();
+
+    // An existing hook is not replaced
+    lua.set_hook(HookMask::LINE, 0, |_, _| Ok(()))?;
+    assert!(lua
+        .sandbox(SandboxConfig {
+            instruction_limit: Some(100_000),
+            ..SandboxConfig::default()
+        })
+        .is_err());
+    assert!(lua.load("os ~= nil").eval::<bool>()?);
+    lua.remove_hook();
+    lua.sandbox(SandboxConfig {
+        instruction_limit: Some(100_000),
+        ..SandboxConfig::default()
+    })?;
+
+    match lua.load("while true do end").exec() {
+        Err(err @ Error::InstructionLimitExceeded) => assert_eq!(err.kind(), ErrorKind::Limit),
+        r => panic!("infinite loop was not interrupted: {:?}", r),
+    }
+
+    // Also when the limit is hit below a Rust callback
+    let run = lua.create_function(|lua, code: String| lua.load(&code).exec())?;
+    match run.call::<_, ()>("while true do end") {
+        Err(Error::InstructionLimitExceeded) => {}
+        r => panic!("expected InstructionLimitExceeded, got {:?}", r),
+    }
+
+    // And when running with a timeout
+    let chunk = lua.load("while true do end");
+    match lua.exec_with_timeout(chunk, Duration::from_secs(60)) {
+        Err(Error::InstructionLimitExceeded) => {}
+        r => panic!("expected InstructionLimitExceeded, got {:?}", r),
+    }
+    assert!(lua.load("while true do end").exec().is_err());
+
+    Ok(())
+}
+
+#[cfg(not(feature = "luajit"))]
+#[test]
+fn test_sandbox_memory_limit() -> Result<()> {
+    let lua = Lua::new();
+    lua.sandbox(SandboxConfig {
+        memory_limit: Some(lua.used_memory() + 100 * 1024),
+        ..SandboxConfig::default()
+    })?;
+
+    match lua
+        .load("local t = {}; for i = 1, 1000000 do t[i] = i end")
+        .exec()
+    {
+        Err(Error::MemoryError(_)) => {}
+        r => panic!("memory limit was not enforced: {:?}", r),
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_sandbox_default() -> Result<()> {
+    let lua = Lua::new();
+    lua.sandbox(SandboxConfig::default())?;
+    assert_eq!(lua.load("os").eval::<Value>()?, Nil);
+
+    Ok(())
+}
+
+#[test]
+fn test_sandbox_failure_leaves_state_unchanged() -> Result<()> {
+    let lua = Lua::new();
+
+    lua.set_hook(HookMask::LINE, 0, |_, _| Ok(()))?;
+    assert!(lua
+        .sandbox(SandboxConfig {
+            instruction_limit: Some(100_000),
+            ..SandboxConfig::default()
+        })
+        .is_err());
+    assert!(lua.load("os ~= nil and io ~= nil").eval::<bool>()?);
+    lua.load("x = 1").exec()?;
+    lua.remove_hook();
+
+    #[cfg(feature = "luajit")]
+    {
+        match lua.sandbox(SandboxConfig {
+            memory_limit: Some(1024 * 1024),
+            ..SandboxConfig::default()
+        }) {
+            Err(Error::MemoryLimitNotAvailable) => {}
+            r => panic!("expected MemoryLimitNotAvailable, got {:?}", r),
+        }
+        assert!(lua.load("os ~= nil and io ~= nil").eval::<bool>()?);
+        lua.load("x = 2").exec()?;
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_sandbox_globals_metatable() -> Result<()> {
+    let lua = Lua::new();
+    let fallback = lua.create_table()?;
+    fallback.set("inherited", 1)?;
+    let mt = lua.create_table()?;
+    mt.set("__index", fallback)?;
+    lua.globals().set_metatable(Some(mt));
+
+    lua.sandbox(SandboxConfig::default())?;
+    assert_eq!(lua.load("inherited").eval::<i64>()?, 1);
+    assert!(lua.load("inherited = 2").exec().is_err());
+
+    Ok(())
+}
+
+#[test]
+fn test_remove_globals() -> Result<()> {
+    let lua = Lua::new();
+
+    lua.remove_globals(&["string", "print"])?;
+    let globals = lua.globals();
+    assert_eq!(globals.get::<_, Value>("string")?, Nil);
+    assert_eq!(globals.get::<_, Value>("print")?, Nil);
+    assert!(lua.load(r#"require("string")"#).exec().is_err());
+    assert_eq!(lua.load("return math.abs(-1)").eval::<i64>()?, 1);
+
+    // Frozen globals are removed as well, and `require` can not bring them back
+    lua.sandbox(SandboxConfig {
+        remove_libs: StdLib::IO,
+        ..SandboxConfig::default()
+    })?;
+    assert!(lua.load("package.loaded.math ~= nil").eval::<bool>()?);
+    lua.remove_globals(&["math"])?;
+    assert_eq!(lua.load("math").eval::<Value>()?, Nil);
+    assert_eq!(lua.load("package.loaded.math").eval::<Value>()?, Nil);
+    assert!(lua.load(r#"require("math")"#).exec().is_err());
+
+    Ok(())
+}
+
+#[cfg(feature = "luajit")]
+#[test]
+fn test_disable_ffi() -> Result<()> {
+    let lua = Lua::new();
+    lua.load_from_std_lib(StdLib::FFI)?;
+    assert!(lua.load(r#"require("ffi")"#).exec().is_ok());
+
+    lua.disable_ffi()?;
+    assert_eq!(lua.globals().get::<_, Value>("ffi")?, Nil);
+    assert!(lua.load(r#"require("ffi")"#).exec().is_err());
+
+    // Frozen globals
+    let lua = Lua::new();
+    lua.load_from_std_lib(StdLib::FFI)?;
+    lua.sandbox(SandboxConfig {
+        remove_libs: StdLib::IO,
+        ..SandboxConfig::default()
+    })?;
+    assert!(lua.load("ffi ~= nil").eval::<bool>()?);
+    lua.disable_ffi()?;
+    assert_eq!(lua.load("ffi").eval::<Value>()?, Nil);
+    assert!(lua.load(r#"require("ffi")"#).exec().is_err());
+
+    Ok(())
+}
@@ -10,8 +10,11 @@
 extern "system" {}
 
 use std::borrow::Cow;
+use std::ffi::{OsStr, OsString};
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+use std::path::{Path, PathBuf};
 
-use mlua::{Lua, Result, String};
+use mlua::{BorrowedStr, Error, Lua, Result, StdLib, String, Value};
 
 #[test]
 fn compare() {
@@ -60,7 +63,7 @@ fn string_views() -> Result<()> {
 
     assert_eq!(empty.to_str()?, "");
     assert_eq!(empty.as_bytes_with_nul(), &[0]);
-    assert_eq!(empty.as_bytes(), &[]);
+    assert_eq!(empty.as_bytes(), &[] as &[u8]);
 
     Ok(())
 }
@@ -74,3 +77,208 @@ fn raw_string() -> Result<()> {
 
     Ok(())
 }
+
+#[test]
+fn test_path_conversion() -> Result<()> {
+    let lua = Lua::new();
+    let globals = lua.globals();
+
+    globals.set("path", Path::new("/tmp/mlua.txt"))?;
+    assert_eq!(
+        lua.load(r#"path .. ".bak""#).eval::<PathBuf>()?,
+        PathBuf::from("/tmp/mlua.txt.bak")
+    );
+
+    globals.set("os_str", OsStr::new("hello"))?;
+    assert_eq!(
+        globals.get::<_, OsString>("os_str")?,
+        OsString::from("hello")
+    );
+
+    Ok(())
+}
+
+#[cfg(unix)]
+#[test]
+fn test_non_utf8_path_round_trip() -> Result<()> {
+    use std::os::unix::ffi::OsStrExt;
+
+    let lua = Lua::new();
+    let globals = lua.globals();
+
+    let path = PathBuf::from(OsStr::from_bytes(b"/tmp/\xff\xfe.txt"));
+    globals.set("path", path.clone())?;
+    assert_eq!(
+        globals.get::<_, String>("path")?.as_bytes(),
+        b"/tmp/\xff\xfe.txt"
+    );
+    assert_eq!(globals.get::<_, PathBuf>("path")?, path);
+
+    let os_string = OsString::from(OsStr::from_bytes(b"\xc3\x28"));
+    globals.set("os_string", os_string.as_os_str())?;
+    assert_eq!(globals.get::<_, OsString>("os_string")?, os_string);
+
+    Ok(())
+}
+
+#[test]
+fn test_addr_conversion() -> Result<()> {
+    let lua = Lua::new();
+    let globals = lua.globals();
+
+    let v4 = Ipv4Addr::new(127, 0, 0, 1);
+    let v6: Ipv6Addr = "::1".parse().unwrap();
+    let socket: SocketAddr = "[2001:db8::1]:8080".parse().unwrap();
+
+    globals.set("v4", v4)?;
+    globals.set("v6", IpAddr::V6(v6))?;
+    globals.set("socket", socket)?;
+    assert_eq!(globals.get::<_, std::string::String>("v4")?, "127.0.0.1");
+    assert_eq!(globals.get::<_, std::string::String>("v6")?, "::1");
+    assert_eq!(
+        globals.get::<_, std::string::String>("socket")?,
+        "[2001:db8::1]:8080"
+    );
+
+    assert_eq!(globals.get::<_, Ipv4Addr>("v4")?, v4);
+    assert_eq!(globals.get::<_, IpAddr>("v4")?, IpAddr::V4(v4));
+    assert_eq!(globals.get::<_, Ipv6Addr>("v6")?, v6);
+    assert_eq!(globals.get::<_, SocketAddr>("socket")?, socket);
+    assert_eq!(
+        lua.load(r#""10.0.0.1:53""#).eval::<SocketAddr>()?,
+        "10.0.0.1:53".parse::<SocketAddr>().unwrap()
+    );
+
+    match globals.get::<_, Ipv4Addr>("v6") {
+        Err(Error::FromLuaConversionError { to: "Ipv4Addr", .. }) => {}
+        r => panic!("expected FromLuaConversionError, got {:?}", r),
+    }
+    assert!(lua.load(r#""300.1.1.1""#).eval::<IpAddr>().is_err());
+    assert!(lua.load("{}").eval::<SocketAddr>().is_err());
+
+    Ok(())
+}
+
+#[test]
+fn test_borrowed_str() -> Result<()> {
+    let lua = Lua::new();
+
+    let s: BorrowedStr = lua.unpack(lua.pack("hello")?)?;
+    assert_eq!(&*s, "hello");
+    assert_eq!(s, "hello");
+    assert_eq!(s.to_string(), "hello");
+
+    let n: BorrowedStr = lua.load("42").eval()?;
+    assert_eq!(n, "42");
+
+    let len = lua.create_function(|_, (a, b): (BorrowedStr, BorrowedStr)| Ok(a.len() + b.len()))?;
+    assert_eq!(len.call::<_, usize>(("abc", "de"))?, 5);
+
+    match lua.load(r#""\xff""#).eval::<BorrowedStr>() {
+        Err(Error::FromLuaConversionError { .. }) => {}
+        r => panic!("expected FromLuaConversionError, got {:?}", r),
+    }
+    assert!(lua.unpack::<BorrowedStr>(mlua::Value::Nil).is_err());
+
+    Ok(())
+}
+
+#[test]
+fn test_char_conversion() -> Result<()> {
+    let lua = Lua::new();
+
+    for &c in &['a', 'é', '€', '🦀'] {
+        let s: String = lua.unpack(lua.pack(c)?)?;
+        assert_eq!(s.as_bytes(), c.to_string().as_bytes());
+        assert_eq!(lua.unpack::<char>(Value::String(s))?, c);
+    }
+    lua.globals().set("c", 'é')?;
+    assert_eq!(lua.load("#c").eval::<i64>()?, 2);
+    assert_eq!(lua.load("'x'").eval::<char>()?, 'x');
+    assert_eq!(lua.load("7").eval::<char>()?, '7');
+
+    for source in &["''", "'ab'", "'é!'", r#""\xff""#, "{}"] {
+        match lua.load(source).eval::<char>() {
+            Err(Error::FromLuaConversionError { .. }) => {}
+            r => panic!(
+                "expected FromLuaConversionError for {}, got {:?}",
+                source, r
+            ),
+        }
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_intern_string() -> Result<()> {
+    let lua = Lua::new();
+
+    let long = "x".repeat(100);
+    let a = lua.intern_string(&long)?;
+    let b = lua.intern_string(&long)?;
+    assert_eq!(a.as_bytes().as_ptr(), b.as_bytes().as_ptr());
+    assert_eq!(a, long.as_str());
+
+    // Lua 5.1 and LuaJIT intern every string themselves
+    #[cfg(any(feature = "lua53", feature = "lua52"))]
+    {
+        let fresh = lua.create_string(&long)?;
+        assert!(fresh.as_bytes().as_ptr() != a.as_bytes().as_ptr());
+    }
+
+    let other = lua.intern_string("other")?;
+    assert_eq!(other, "other");
+
+    // Hits do not add entries
+    let slots = lua.registry_slot_count();
+    for _ in 0..10 {
+        lua.intern_string(&long)?;
+        lua.intern_string("other")?;
+    }
+    assert_eq!(lua.registry_slot_count(), slots);
+
+    let before = lua.registry_slot_count();
+    lua.clear_interned_strings();
+    assert_eq!(lua.registry_slot_count(), before - 2);
+    let c = lua.intern_string(&long)?;
+    assert_eq!(c, long.as_str());
+
+    Ok(())
+}
+
+#[test]
+fn test_string_format() -> Result<()> {
+    let lua = Lua::new();
+
+    assert_eq!(lua.string_format("%d", 42)?, "42");
+    assert_eq!(lua.string_format("%5.2f|%-3s|", (1.5, "a"))?, " 1.50|a  |");
+    assert_eq!(lua.string_format("%s %s", (true, Value::Nil))?, "true nil");
+    assert_eq!(lua.string_format("100%%", ())?, "100%");
+
+    // The same output as from a script
+    let quoted = lua.string_format("%q", "line\n\"quoted\"\0")?;
+    let expected: String = lua
+        .load(r#"string.format("%q", "line\n\"quoted\"\0")"#)
+        .eval()?;
+    assert_eq!(quoted, expected);
+    let unquoted: String = lua.load(quoted.as_bytes()).eval()?;
+    assert_eq!(unquoted, "line\n\"quoted\"\0");
+
+    // `__tostring` is respected by `%s`
+    let t = lua
+        .load("setmetatable({}, { __tostring = function() return 'custom' end })")
+        .eval::<Value>()?;
+    assert_eq!(lua.string_format("<%s>", t)?, "<custom>");
+
+    // Errors from `string.format` are returned
+    assert!(lua.string_format("%d", "not a number").is_err());
+    assert!(lua.string_format("%d", ()).is_err());
+
+    match Lua::new_with(StdLib::TABLE).string_format("%d", 1) {
+        Err(Error::RuntimeError(_)) => {}
+        r => panic!("expected RuntimeError, got {:?}", r),
+    }
+
+    Ok(())
+}
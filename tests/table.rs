@@ -9,7 +9,9 @@
 )]
 extern "system" {}
 
-use mlua::{Lua, Nil, Result, Table, Value};
+use std::collections::HashMap;
+
+use mlua::{Error, Lua, Nil, Result, Table, Value};
 
 #[test]
 fn test_set_get() -> Result<()> {
@@ -79,7 +81,7 @@ fn test_table() -> Result<()> {
     );
     assert_eq!(
         table2.sequence_values().collect::<Result<Vec<i64>>>()?,
-        vec![]
+        Vec::<i64>::new()
     );
 
     // sequence_values should only iterate until the first border
@@ -108,7 +110,7 @@ fn test_table() -> Result<()> {
         vec![(1, 1), (2, 2), (3, 3), (4, 35), (5, 4), (6, 5), (7, 7)]
     );
 
-    table4.raw_remove(1)?;
+    assert_eq!(table4.raw_remove::<_, i64>(1)?, 1);
     assert_eq!(
         table4
             .clone()
@@ -120,6 +122,40 @@ fn test_table() -> Result<()> {
     Ok(())
 }
 
+#[test]
+fn test_table_raw_insert_remove() -> Result<()> {
+    let lua = Lua::new();
+
+    let table: Table = lua.load(r#"{"a", "b", "c", "d"}"#).eval()?;
+    let values =
+        |table: &Table| -> Result<Vec<String>> { table.clone().sequence_values().collect() };
+
+    assert_eq!(table.raw_remove::<_, String>(2)?, "b");
+    assert_eq!(values(&table)?, vec!["a", "c", "d"]);
+
+    table.raw_insert(2, "x")?;
+    table.raw_insert(4, "y")?;
+    assert_eq!(values(&table)?, vec!["a", "x", "c", "y", "d"]);
+
+    assert_eq!(table.raw_remove::<_, String>(5)?, "d");
+    assert_eq!(values(&table)?, vec!["a", "x", "c", "y"]);
+
+    // A failed conversion leaves the table untouched
+    assert!(table.raw_remove::<_, i64>(1).is_err());
+    assert_eq!(values(&table)?, vec!["a", "x", "c", "y"]);
+
+    assert!(table.raw_remove::<_, Value>(0).is_err());
+    assert!(table.raw_remove::<_, Value>(5).is_err());
+    assert!(table.raw_insert(6, "z").is_err());
+
+    table.raw_set("key", 42)?;
+    assert_eq!(table.raw_remove::<_, i64>("key")?, 42);
+    assert_eq!(table.raw_get::<_, Value>("key")?, Nil);
+    assert_eq!(table.raw_remove::<_, Value>("missing")?, Nil);
+
+    Ok(())
+}
+
 #[test]
 fn test_table_scope() -> Result<()> {
     let lua = Lua::new();
@@ -237,3 +273,401 @@ fn test_table_error() -> Result<()> {
 
     Ok(())
 }
+
+#[test]
+fn test_table_merge() -> Result<()> {
+    let lua = Lua::new();
+
+    let defaults: Table = lua
+        .load(r#"{ a = 1, b = 2, nested = { x = 1, y = 2 } }"#)
+        .eval()?;
+    let overrides: Table = lua.load(r#"{ b = 3, c = 4, nested = { y = 3 } }"#).eval()?;
+
+    let config = lua.create_table()?;
+    config.merge(&defaults, false)?;
+    config.merge(&overrides, false)?;
+    assert_eq!(config.get::<_, i64>("a")?, 1);
+    assert_eq!(config.get::<_, i64>("b")?, 2);
+    assert_eq!(config.get::<_, i64>("c")?, 4);
+
+    let config = lua.create_table()?;
+    config.merge(&defaults, true)?;
+    config.merge(&overrides, true)?;
+    assert_eq!(config.get::<_, i64>("b")?, 3);
+    let nested: Table = config.get("nested")?;
+    assert_eq!(nested.get::<_, Option<i64>>("x")?, None);
+    assert_eq!(nested.get::<_, i64>("y")?, 3);
+
+    // Merging uses raw access
+    let proxy: Table = lua
+        .load(r#"setmetatable({}, { __newindex = function() error("metamethod called") end })"#)
+        .eval()?;
+    proxy.merge(&defaults, true)?;
+    assert_eq!(proxy.raw_get::<_, i64>("a")?, 1);
+
+    Ok(())
+}
+
+#[test]
+fn test_table_deep_merge() -> Result<()> {
+    let lua = Lua::new();
+
+    let config: Table = lua
+        .load(r#"{ a = 1, nested = { x = 1, y = 2, deeper = { z = 1 } } }"#)
+        .eval()?;
+    let overrides: Table = lua
+        .load(r#"{ a = 2, nested = { y = 3, deeper = { w = 2 } } }"#)
+        .eval()?;
+
+    config.deep_merge(&overrides, true)?;
+    assert_eq!(config.get::<_, i64>("a")?, 2);
+    let nested: Table = config.get("nested")?;
+    assert_eq!(nested.get::<_, i64>("x")?, 1);
+    assert_eq!(nested.get::<_, i64>("y")?, 3);
+    let deeper: Table = nested.get("deeper")?;
+    assert_eq!(deeper.get::<_, i64>("z")?, 1);
+    assert_eq!(deeper.get::<_, i64>("w")?, 2);
+
+    // Cyclic tables must not recurse forever
+    let cyclic: Table = lua
+        .load(r#"local t = { v = 1 }; t.t = t; return t"#)
+        .eval()?;
+    cyclic.deep_merge(&cyclic.clone(), false)?;
+    let other: Table = lua
+        .load(r#"local t = { v = 2 }; t.t = t; return t"#)
+        .eval()?;
+    cyclic.deep_merge(&other, true)?;
+    assert_eq!(cyclic.get::<_, i64>("v")?, 2);
+
+    // Inserted tables are copied instead of shared with the source
+    let target = lua.create_table()?;
+    let source: Table = lua
+        .load(
+            r#"
+            local shared = setmetatable({ x = 1 }, { __index = { y = 2 } })
+            local cycle = {}
+            cycle.self = cycle
+            return { a = shared, b = shared, cycle = cycle }
+        "#,
+        )
+        .eval()?;
+    target.deep_merge(&source, false)?;
+    let a: Table = target.get("a")?;
+    assert!(a != source.get::<_, Table>("a")?);
+    assert!(a == target.get::<_, Table>("b")?);
+    assert_eq!(a.get::<_, i64>("y")?, 2);
+    a.set("x", 2)?;
+    assert_eq!(source.get::<_, Table>("a")?.get::<_, i64>("x")?, 1);
+    let cycle: Table = target.get("cycle")?;
+    assert!(cycle != source.get::<_, Table>("cycle")?);
+    assert!(cycle == cycle.get::<_, Table>("self")?);
+
+    Ok(())
+}
+
+#[test]
+fn test_table_filter_collect() -> Result<()> {
+    let lua = Lua::new();
+
+    let table: Table = lua.load(r#"{1, "2", "three", 4.0, {}, 6}"#).eval()?;
+
+    let lenient = table.filter_collect::<i64, _>(|v| Ok(v.ok()))?;
+    assert_eq!(lenient, vec![1, 2, 4, 6]);
+
+    let strict = table.filter_collect::<i64, _>(|v| v.map(Some));
+    assert!(strict.is_err());
+
+    let evens = table.filter_collect::<i64, _>(|v| Ok(v.ok().filter(|n| n % 2 == 0)))?;
+    assert_eq!(evens, vec![2, 4, 6]);
+
+    Ok(())
+}
+
+#[test]
+fn test_table_remove() -> Result<()> {
+    let lua = Lua::new();
+    let globals = lua.globals();
+
+    globals.set("x", 1)?;
+    lua.remove_global("x")?;
+    assert!(!globals.contains_key("x")?);
+    assert_eq!(lua.load("return x").eval::<Value>()?, Nil);
+
+    let table: Table = lua.load("{1, 2, 3, key = 'value'}").eval()?;
+    table.remove("key")?;
+    table.remove(2)?;
+    assert!(!table.contains_key("key")?);
+    assert_eq!(table.get::<_, Value>(2)?, Nil);
+    assert_eq!(table.get::<_, i64>(3)?, 3);
+
+    Ok(())
+}
+
+#[test]
+fn test_table_into_collections() -> Result<()> {
+    let lua = Lua::new();
+
+    let mut map = HashMap::new();
+    map.insert("one".to_string(), 1);
+    map.insert("two".to_string(), 2);
+    let table = lua.create_table_from(map.clone())?;
+    assert_eq!(table.into_hashmap::<std::string::String, i64>()?, map);
+
+    let vec = vec![1.5, 2.5, 3.5];
+    let table = lua.create_sequence_from(vec.clone())?;
+    table.set("key", "ignored")?;
+    assert_eq!(table.into_vec::<f64>()?, vec);
+
+    let table: Table = lua.load("{ 1, 'x' }").eval()?;
+    assert!(table.clone().into_vec::<i64>().is_err());
+    assert!(table.into_hashmap::<i64, i64>().is_err());
+
+    Ok(())
+}
+
+#[test]
+fn test_table_invalid_keys() -> Result<()> {
+    let lua = Lua::new();
+    let table = lua.create_table()?;
+
+    for key in &[Value::Nil, Value::Number(f64::NAN)] {
+        match table.set(key.clone(), 1) {
+            Err(Error::RuntimeError(_)) => {}
+            r => panic!("expected RuntimeError, got {:?}", r),
+        }
+        match table.raw_set(key.clone(), 1) {
+            Err(Error::RuntimeError(_)) => {}
+            r => panic!("expected RuntimeError, got {:?}", r),
+        }
+    }
+    assert_eq!(table.raw_len(), 0);
+
+    table.set(f64::INFINITY, "inf")?;
+    assert_eq!(table.get::<_, std::string::String>(f64::INFINITY)?, "inf");
+
+    // `__newindex` of proxy tables is still called
+    let proxy: Table = lua
+        .load(
+            r#"
+            setmetatable({}, {
+                __newindex = function(t, k, v) rawset(t, tostring(k), v) end,
+            })
+        "#,
+        )
+        .eval()?;
+    proxy.set(Nil, "nil")?;
+    proxy.set(f64::NAN, "nan")?;
+    assert_eq!(proxy.get::<_, std::string::String>("nil")?, "nil");
+    assert_eq!(proxy.raw_len(), 0);
+    assert!(proxy.raw_set(Nil, 1).is_err());
+
+    Ok(())
+}
+
+#[test]
+fn test_create_table_with_metatable() -> Result<()> {
+    let lua = Lua::new();
+
+    let class: Table = lua
+        .load(
+            r#"
+            {
+                area = function(self) return self.w * self.h end,
+                __newindex = function() error("read-only") end,
+            }
+        "#,
+        )
+        .eval()?;
+    class.set("__index", class.clone())?;
+
+    let rect = lua.create_table_from_with_metatable(vec![("w", 2), ("h", 3)], class.clone())?;
+    assert_eq!(rect.get_metatable(), Some(class.clone()));
+    lua.globals().set("rect", rect)?;
+    assert_eq!(lua.load("rect:area()").eval::<i64>()?, 6);
+    assert!(lua.load("rect.d = 1").exec().is_err());
+
+    let empty = lua.create_table_with_metatable(class.clone())?;
+    assert_eq!(empty.get_metatable(), Some(class));
+    assert!(empty.set("w", 1).is_err());
+
+    Ok(())
+}
+
+#[test]
+fn test_table_path() -> Result<()> {
+    let lua = Lua::new();
+
+    let config: Table = lua
+        .load(r#"{ server = { http = { port = 8080, host = "localhost" } }, debug = true }"#)
+        .eval()?;
+
+    assert_eq!(config.get_path::<i64>("server.http.port")?, Some(8080));
+    assert_eq!(
+        config.get_path::<std::string::String>("server.http.host")?,
+        Some("localhost".to_owned())
+    );
+    assert_eq!(config.get_path::<bool>("debug")?, Some(true));
+    assert_eq!(config.get_path::<i64>("server.http.timeout")?, None);
+    assert_eq!(config.get_path::<i64>("client.http.port")?, None);
+    match config.get_path::<i64>("debug.level") {
+        Err(Error::RuntimeError(msg)) => assert_eq!(msg, "'debug' is not a table (got boolean)"),
+        r => panic!("expected RuntimeError, got {:?}", r),
+    }
+
+    config.set_path("server.http.port", 9090)?;
+    config.set_path("server.https.tls.enabled", true)?;
+    assert_eq!(config.get_path::<i64>("server.http.port")?, Some(9090));
+    assert_eq!(
+        config.get_path::<bool>("server.https.tls.enabled")?,
+        Some(true)
+    );
+    let tls: Table = config.get_path("server.https.tls")?.unwrap();
+    assert!(tls.get::<_, bool>("enabled")?);
+
+    match config.set_path("server.http.port.value", 1) {
+        Err(Error::RuntimeError(msg)) => {
+            assert_eq!(msg, "'server.http.port' is not a table (got integer)")
+        }
+        r => panic!("expected RuntimeError, got {:?}", r),
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_table_contains_key() -> Result<()> {
+    let lua = Lua::new();
+
+    let table = lua.create_table()?;
+    table.set("off", false)?;
+    table.set(1, "one")?;
+    assert!(table.contains_key("off")?);
+    assert!(table.contains_key(1)?);
+    assert!(!table.contains_key("missing")?);
+    assert!(table.raw_contains_key("off")?);
+    assert!(!table.raw_contains_key("missing")?);
+
+    let defaults = lua.create_table()?;
+    defaults.set("inherited", 1)?;
+    let metatable = lua.create_table()?;
+    metatable.set("__index", defaults)?;
+    table.set_metatable(Some(metatable));
+    assert!(table.contains_key("inherited")?);
+    assert!(!table.raw_contains_key("inherited")?);
+
+    Ok(())
+}
+
+#[test]
+fn test_table_keys_values() -> Result<()> {
+    let lua = Lua::new();
+
+    let table: Table = lua
+        .load(r#"{ a = 1, b = 2, c = 3, [1] = 10, [2] = 20 }"#)
+        .eval()?;
+
+    let mut keys = table
+        .keys::<Value>()
+        .map(|k| {
+            Ok(match k? {
+                Value::String(s) => s.to_str()?.to_owned(),
+                Value::Integer(i) => i.to_string(),
+                k => panic!("unexpected key {:?}", k),
+            })
+        })
+        .collect::<Result<Vec<_>>>()?;
+    keys.sort();
+    assert_eq!(keys, vec!["1", "2", "a", "b", "c"]);
+
+    let mut values = table.values::<i64>().collect::<Result<Vec<_>>>()?;
+    values.sort();
+    assert_eq!(values, vec![1, 2, 3, 10, 20]);
+
+    // Conversion errors are reported per item, without stopping the iteration
+    let errors = table
+        .keys::<i64>()
+        .filter(|k| matches!(k, Err(Error::FromLuaConversionError { .. })))
+        .count();
+    assert_eq!(errors, 3);
+
+    assert_eq!(lua.create_table()?.keys::<Value>().count(), 0);
+
+    Ok(())
+}
+
+#[test]
+fn test_table_protect_metatable() -> Result<()> {
+    let lua = Lua::new();
+
+    let table = lua.create_table()?;
+    table.protect_metatable("locked")?;
+    lua.globals().set("t", table.clone())?;
+
+    assert_eq!(lua.load("getmetatable(t)").eval::<String>()?, "locked");
+    match lua.load("setmetatable(t, {})").exec() {
+        Err(Error::RuntimeError(msg)) => assert!(msg.contains("protected metatable"), "{}", msg),
+        r => panic!("expected RuntimeError, got {:?}", r),
+    }
+
+    // The Rust side bypasses the protection
+    let metatable = table.get_metatable().expect("metatable was not set");
+    assert_eq!(metatable.raw_get::<_, String>("__metatable")?, "locked");
+    table.set_metatable(Some(lua.create_table()?));
+    assert!(lua.load("getmetatable(t) ~= nil").eval::<bool>()?);
+
+    let table = lua.create_table()?;
+    table.protect_metatable("locked")?;
+    table.protect_metatable(Nil)?;
+    lua.globals().set("t", table)?;
+    lua.load("setmetatable(t, {})").exec()?;
+
+    Ok(())
+}
+
+#[test]
+fn test_create_sequence_from() -> Result<()> {
+    let lua = Lua::new();
+
+    let table = lua.create_sequence_from(1..=1000)?;
+    assert_eq!(table.raw_len(), 1000);
+    assert_eq!(table.get::<_, i64>(1000)?, 1000);
+
+    // Iterators with an inexact size hint
+    let table = lua.create_sequence_from((1..=10).filter(|i| i % 2 == 0))?;
+    assert_eq!(table.into_vec::<i64>()?, vec![2, 4, 6, 8, 10]);
+    let table = lua.create_sequence_from(vec!["a"].into_iter().chain(vec!["b", "c"]))?;
+    assert_eq!(table.into_vec::<String>()?, vec!["a", "b", "c"]);
+
+    let table = lua.create_sequence_from(Vec::<i64>::new())?;
+    assert_eq!(table.raw_len(), 0);
+
+    Ok(())
+}
+
+#[test]
+fn test_table_get_or() -> Result<()> {
+    let lua = Lua::new();
+
+    let config: Table = lua
+        .load(r#"{ name = "server", port = 8080, debug = false, workers = "many" }"#)
+        .eval()?;
+
+    assert_eq!(config.get_or("name", "default".to_string())?, "server");
+    assert_eq!(config.get_or("port", 80)?, 8080);
+    assert_eq!(config.get_or("timeout", 30)?, 30);
+    // `false` is a value, not an absent key
+    assert!(!config.get_or("debug", true)?);
+
+    match config.get_or("workers", 4) {
+        Err(Error::FromLuaConversionError { .. }) => {}
+        r => panic!("expected FromLuaConversionError, got {:?}", r),
+    }
+
+    // Defaults provided by `__index` are used
+    let defaults = lua.create_table_from(vec![("timeout", 60)])?;
+    let meta = lua.create_table_from(vec![("__index", defaults)])?;
+    config.set_metatable(Some(meta));
+    assert_eq!(config.get_or("timeout", 30)?, 60);
+
+    Ok(())
+}
@@ -10,12 +10,14 @@
 extern "system" {}
 
 use std::iter::FromIterator;
+use std::os::raw::c_int;
 use std::panic::catch_unwind;
 use std::sync::Arc;
 use std::{error, f32, f64, fmt};
 
 use mlua::{
-    Error, ExternalError, Function, Lua, Nil, Result, String, Table, UserData, Value, Variadic,
+    lua_State, ChunkMode, Error, ErrorKind, ExternalError, Function, Lua, Nil, Result,
+    SandboxConfig, StdLib, String, Table, UserData, Value, Variadic,
 };
 
 #[test]
@@ -30,6 +32,40 @@ fn test_load() -> Result<()> {
     Ok(())
 }
 
+#[test]
+fn test_load_mode() -> Result<()> {
+    let lua = Lua::new();
+
+    let bytecode: String = lua
+        .load("string.dump(function(a) return a * 2 end)")
+        .eval()?;
+    let bytecode = bytecode.as_bytes();
+    match lua.load(bytecode).into_function() {
+        Err(Error::SyntaxError { .. }) => {}
+        r => panic!("expected SyntaxError, got {:?}", r),
+    }
+    let double = lua
+        .load(&bytecode)
+        .set_mode(ChunkMode::Binary)
+        .into_function()?;
+    assert_eq!(double.call::<_, i64>(21)?, 42);
+
+    match lua.load("return 1").set_mode(ChunkMode::Binary).exec() {
+        Err(Error::SyntaxError { .. }) => {}
+        r => panic!("expected SyntaxError, got {:?}", r),
+    }
+
+    let double = lua
+        .load(&bytecode)
+        .set_mode(ChunkMode::TextOrBinary)
+        .into_function()?;
+    assert_eq!(double.call::<_, i64>(2)?, 4);
+    let three: i64 = lua.load("1 + 2").set_mode(ChunkMode::TextOrBinary).eval()?;
+    assert_eq!(three, 3);
+
+    Ok(())
+}
+
 #[test]
 fn test_exec() -> Result<()> {
     let lua = Lua::new();
@@ -86,6 +122,30 @@ fn test_eval() -> Result<()> {
     Ok(())
 }
 
+#[test]
+fn test_chunk_into_function() -> Result<()> {
+    let lua = Lua::new();
+    let globals = lua.globals();
+    globals.set("counter", 0)?;
+
+    let func = lua
+        .load(
+            r#"
+            counter = counter + 1
+            return counter
+        "#,
+        )
+        .into_function()?;
+
+    // Compiling alone must not execute the chunk
+    assert_eq!(globals.get::<_, i64>("counter")?, 0);
+
+    assert_eq!(func.call::<_, i64>(())?, 1);
+    assert_eq!(func.call::<_, i64>(())?, 2);
+
+    Ok(())
+}
+
 #[test]
 fn test_lua_multi() -> Result<()> {
     let lua = Lua::new();
@@ -337,6 +397,193 @@ fn test_error() -> Result<()> {
     Ok(())
 }
 
+#[test]
+fn test_error_chain() -> Result<()> {
+    let lua = Lua::new();
+
+    let inner = lua.create_function(|_, ()| -> Result<()> { Err("inner failure".to_lua_err()) })?;
+    lua.globals().set("inner", inner)?;
+    let outer = lua.create_function(|lua, ()| {
+        lua.load("inner()").exec()?;
+        Ok(())
+    })?;
+
+    let err = outer.call::<_, ()>(()).unwrap_err();
+    let chain = err.chain().collect::<Vec<_>>();
+    assert_eq!(chain.len(), 3);
+    assert!(matches!(chain[0], Error::CallbackError { .. }));
+    assert!(matches!(chain[1], Error::CallbackError { .. }));
+    assert!(matches!(chain[2], Error::ExternalError(_)));
+    assert_eq!(err.message(), "inner failure");
+    assert!(err.to_string().contains("stack traceback"));
+
+    // Errors wrapped with `Error::external` are followed as well
+    let err = Error::external(Error::RuntimeError("wrapped".to_string()));
+    let chain = err.chain().collect::<Vec<_>>();
+    assert_eq!(chain.len(), 2);
+    assert!(matches!(chain[1], Error::RuntimeError(_)));
+
+    match lua.load(r#"error("oops")"#).exec() {
+        Err(err @ Error::RuntimeError(_)) => {
+            assert!(err.to_string().starts_with("runtime error: "));
+            assert!(err.message().ends_with("oops"), "{:?}", err.message());
+            assert_eq!(err.chain().count(), 1);
+        }
+        r => panic!("expected RuntimeError, got {:?}", r),
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_error_kind() -> Result<()> {
+    let lua = Lua::new();
+
+    let kind = |code: &str| lua.load(code).exec().unwrap_err().kind();
+    assert_eq!(kind("x = "), ErrorKind::Syntax);
+    assert_eq!(kind("error('boom')"), ErrorKind::Runtime);
+    assert_eq!(kind("error({})"), ErrorKind::Runtime);
+
+    #[cfg(not(feature = "luajit"))]
+    {
+        let used = lua.used_memory();
+        lua.set_memory_limit(used + 10000)?;
+        assert_eq!(
+            kind("local t = {}; for i = 1, 10000 do t[i] = i end"),
+            ErrorKind::Memory
+        );
+        lua.set_memory_limit(0)?;
+    }
+
+    #[cfg(any(feature = "lua53", feature = "lua52"))]
+    assert_eq!(
+        kind("setmetatable({}, { __gc = function() error('gc') end }); collectgarbage()"),
+        ErrorKind::GarbageCollector
+    );
+
+    let err = lua.unpack::<i64>(Value::Boolean(true)).unwrap_err();
+    assert_eq!(err.kind(), ErrorKind::FromLuaConversion);
+    let err = lua.decode_value(b"garbage").unwrap_err();
+    assert_eq!(err.kind(), ErrorKind::ToLuaConversion);
+
+    let add = lua.create_function(|_, (a, b): (i64, i64)| Ok(a + b))?;
+    let err = add.call::<_, i64>((1, "x")).unwrap_err();
+    assert_eq!(err.kind(), ErrorKind::Callback);
+    let kinds = err.chain().map(Error::kind).collect::<Vec<_>>();
+    assert_eq!(
+        kinds,
+        vec![
+            ErrorKind::Callback,
+            ErrorKind::BadArgument,
+            ErrorKind::FromLuaConversion
+        ]
+    );
+
+    let external = "custom".to_lua_err();
+    assert_eq!(external.kind(), ErrorKind::External);
+
+    #[derive(Debug)]
+    struct Noop;
+    impl UserData for Noop {}
+    let ud = lua.create_userdata(Noop)?;
+    let _borrow = ud.borrow_mut::<Noop>()?;
+    assert_eq!(ud.borrow::<Noop>().unwrap_err().kind(), ErrorKind::UserData);
+
+    let thread = lua.create_thread(lua.create_function(|_, ()| Ok(()))?)?;
+    thread.resume::<_, ()>(())?;
+    let err = thread.resume::<_, ()>(()).unwrap_err();
+    assert_eq!(err.kind(), ErrorKind::Usage);
+
+    assert_eq!(Error::StackError.kind(), ErrorKind::Limit);
+    assert_eq!(Error::Timeout.kind(), ErrorKind::Limit);
+    assert_eq!(Error::InstructionLimitExceeded.kind(), ErrorKind::Limit);
+
+    Ok(())
+}
+
+#[test]
+fn test_error_value() -> Result<()> {
+    let lua = Lua::new();
+
+    match lua.load("error({code = 42})").exec() {
+        Err(Error::RuntimeErrorValue { value, .. }) => {
+            let table: Table = lua.registry_value(&value)?;
+            assert_eq!(table.get::<_, i64>("code")?, 42);
+        }
+        r => panic!("expected RuntimeErrorValue, got {:?}", r),
+    }
+
+    // String errors are unaffected
+    match lua.load("error('message')").exec() {
+        Err(Error::RuntimeError(_)) => {}
+        r => panic!("expected RuntimeError, got {:?}", r),
+    }
+
+    // The original value is raised again when the error passes through a Rust callback
+    let raise: Function = lua.load("function(err) error(err) end").eval()?;
+    lua.globals().set(
+        "call_in_rust",
+        lua.create_function(|lua, f: Function| {
+            f.call::<_, ()>(lua.create_table_from(vec![("code", 7)])?)
+        })?,
+    )?;
+    lua.globals().set("raise", raise)?;
+    let code = lua
+        .load(
+            r#"
+            local ok, err = pcall(call_in_rust, raise)
+            assert(not ok)
+            return err.code
+        "#,
+        )
+        .eval::<i64>()?;
+    assert_eq!(code, 7);
+
+    // Dropped errors do not keep their registry slots
+    lua.load("error({})").exec().unwrap_err();
+    let slots = lua.registry_slot_count();
+    for _ in 0..10 {
+        lua.load("error({})").exec().unwrap_err();
+    }
+    assert_eq!(lua.registry_slot_count(), slots);
+
+    Ok(())
+}
+
+#[test]
+fn test_create_error_object() -> Result<()> {
+    let lua = Lua::new();
+
+    let fail = lua.create_function(|lua, code: i64| -> Result<()> {
+        let err = lua.create_table()?;
+        err.set("code", code)?;
+        err.set("reason", "not found")?;
+        Err(lua.create_error_object(err)?)
+    })?;
+    lua.globals().set("fail", fail.clone())?;
+
+    lua.load(
+        r#"
+        local ok, err = pcall(fail, 404)
+        assert(not ok)
+        assert(type(err) == "table")
+        assert(err.code == 404 and err.reason == "not found")
+    "#,
+    )
+    .exec()?;
+
+    // Calling from Rust gives back the same value
+    match fail.call::<_, ()>(500) {
+        Err(Error::RuntimeErrorValue { value, .. }) => {
+            let table: Table = lua.registry_value(&value)?;
+            assert_eq!(table.get::<_, i64>("code")?, 500);
+        }
+        r => panic!("expected RuntimeErrorValue, got {:?}", r),
+    }
+
+    Ok(())
+}
+
 #[test]
 fn test_result_conversions() -> Result<()> {
     let lua = Lua::new();
@@ -504,6 +751,37 @@ fn test_pcall_xpcall() -> Result<()> {
     Ok(())
 }
 
+#[test]
+fn test_call_depth_limit() -> Result<()> {
+    let lua = Lua::new();
+    assert_eq!(lua.set_call_depth_limit(20), 0);
+
+    // An `__index` handler which indexes its own table recurses forever
+    let index = lua.create_function(|_, (t, k): (Table, Value)| t.get::<_, Value>(k))?;
+    let mt = lua.create_table()?;
+    mt.set("__index", index)?;
+    let t = lua.create_table()?;
+    t.set_metatable(Some(mt));
+    lua.globals().set("t", t)?;
+
+    match lua.load("return t.x").exec() {
+        Err(err) => assert!(
+            err.chain()
+                .any(|err| matches!(err, Error::RecursionLimitReached)),
+            "unexpected error {:?}",
+            err
+        ),
+        Ok(_) => panic!("expected RecursionLimitReached"),
+    }
+
+    // The depth is unwound after the error
+    let f = lua.create_function(|_, n: i64| Ok(n + 1))?;
+    assert_eq!(f.call::<_, i64>(1)?, 2);
+    assert_eq!(lua.set_call_depth_limit(0), 20);
+
+    Ok(())
+}
+
 #[test]
 fn test_recursive_mut_callback_error() -> Result<()> {
     let lua = Lua::new();
@@ -552,6 +830,182 @@ fn test_set_metatable_nil() -> Result<()> {
     Ok(())
 }
 
+#[test]
+fn test_preload_module() -> Result<()> {
+    let lua = Lua::new();
+
+    let opener = lua.create_function(|lua, name: String| {
+        let module = lua.create_table()?;
+        module.set("name", name)?;
+        Ok(module)
+    })?;
+    lua.preload_module("foo", opener)?;
+
+    assert_eq!(lua.load(r#"require("foo").name"#).eval::<String>()?, "foo");
+    assert!(lua
+        .load(r#"require("foo") == require("foo")"#)
+        .eval::<bool>()?);
+
+    unsafe extern "C" fn luaopen_bar(_: *mut lua_State) -> c_int {
+        0
+    }
+    unsafe { lua.register_c_module("bar", luaopen_bar)? };
+    assert!(lua.load(r#"require("bar")"#).eval::<bool>()?);
+
+    Ok(())
+}
+
+#[test]
+fn test_register_module() -> Result<()> {
+    let lua = Lua::new();
+
+    let module = lua.register_module("greeter", |lua, module| {
+        module.set(
+            "greet",
+            lua.create_function(|_, name: String| Ok(format!("hello, {}", name.to_str()?)))?,
+        )?;
+        module.set("version", 2)
+    })?;
+    assert_eq!(module.get::<_, i64>("version")?, 2);
+
+    assert_eq!(
+        lua.load(r#"require("greeter").greet("lua")"#)
+            .eval::<String>()?,
+        "hello, lua"
+    );
+    let required: Table = lua.load(r#"require("greeter")"#).eval()?;
+    assert_eq!(required, module);
+
+    // Errors from `build` are returned and nothing is registered
+    let res = lua.register_module("broken", |_, _| Err(Error::RuntimeError("oops".into())));
+    assert!(res.is_err());
+    assert!(lua.load(r#"require("broken")"#).exec().is_err());
+
+    Ok(())
+}
+
+#[test]
+fn test_unregister_function() -> Result<()> {
+    let lua = Lua::new();
+    let globals = lua.globals();
+
+    let state = Arc::new(());
+    let captured = state.clone();
+    globals.set(
+        "counter",
+        lua.create_function(move |_, ()| Ok(Arc::strong_count(&captured)))?,
+    )?;
+    assert_eq!(Arc::strong_count(&state), 2);
+
+    assert!(lua.unregister_function("counter")?);
+    assert_eq!(globals.get::<_, Value>("counter")?, Nil);
+    assert_eq!(Arc::strong_count(&state), 1);
+    assert!(!lua.unregister_function("counter")?);
+
+    // A function that is still referenced elsewhere stays alive
+    let captured = state.clone();
+    globals.set(
+        "counter",
+        lua.create_function(move |_, ()| Ok(Arc::strong_count(&captured)))?,
+    )?;
+    lua.load("saved = { counter = counter }").exec()?;
+    assert!(lua.unregister_function("counter")?);
+    assert_eq!(Arc::strong_count(&state), 2);
+    assert_eq!(lua.load("saved.counter()").eval::<usize>()?, 2);
+    lua.load("saved = nil").exec()?;
+    lua.gc_collect()?;
+    assert_eq!(Arc::strong_count(&state), 1);
+
+    // Non-function globals are rejected
+    globals.set("answer", 42)?;
+    assert!(lua.unregister_function("answer").is_err());
+    assert_eq!(globals.get::<_, i64>("answer")?, 42);
+
+    // Module functions
+    let captured = state.clone();
+    lua.register_module("stats", |lua, module| {
+        module.set(
+            "count",
+            lua.create_function(move |_, ()| Ok(Arc::strong_count(&captured)))?,
+        )
+    })?;
+    assert_eq!(Arc::strong_count(&state), 2);
+    assert!(lua.unregister_module_function("stats", "count")?);
+    assert_eq!(Arc::strong_count(&state), 1);
+    assert!(lua.load(r#"require("stats").count()"#).exec().is_err());
+    assert!(!lua.unregister_module_function("stats", "count")?);
+    assert!(!lua.unregister_module_function("missing", "count")?);
+
+    Ok(())
+}
+
+#[test]
+fn test_mismatched_lua_state() -> Result<()> {
+    let lua_a = Lua::new();
+    let lua_b = Lua::new();
+    assert!(lua_a.ptr_eq(&lua_a));
+    assert!(!lua_a.ptr_eq(&lua_b));
+
+    // The `Lua` passed to callbacks shares the state of its creator
+    let same = lua_a.scope(|scope| {
+        let f = scope.create_function(|lua, ()| Ok(lua.ptr_eq(&lua_a)))?;
+        f.call::<_, bool>(())
+    })?;
+    assert!(same);
+
+    let table = lua_a.create_table()?;
+    match lua_b.globals().set("foreign", table.clone()) {
+        Err(Error::MismatchedLuaState) => {}
+        r => panic!("expected MismatchedLuaState, got {:?}", r),
+    }
+
+    let func: Function = lua_b.load("function(t) return t end").eval()?;
+    match func.call::<_, Value>(table) {
+        Err(Error::MismatchedLuaState) => {}
+        r => panic!("expected MismatchedLuaState, got {:?}", r),
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_compile_on_thread() -> Result<()> {
+    let handle = Lua::compile_on_thread(
+        r#"
+            local base = ...
+            return function(x) return (base or answer) + x end
+        "#,
+        "bundle",
+    );
+    let broken = Lua::compile_on_thread("return +", "broken");
+
+    let lua = Lua::new();
+    lua.globals().set("answer", 40)?;
+
+    let key = handle.join(&lua)?;
+    let bundle: Function = lua.registry_value(&key)?;
+    let add: Function = bundle.call(())?;
+    assert_eq!(add.call::<_, i64>(2)?, 42);
+    let add: Function = bundle.call(10)?;
+    assert_eq!(add.call::<_, i64>(2)?, 12);
+
+    // Debug information is kept
+    let fail = Lua::compile_on_thread("error('boom')", "failing").join(&lua)?;
+    match lua.registry_value::<Function>(&fail)?.call::<_, ()>(()) {
+        Err(Error::RuntimeError(msg)) => {
+            assert!(msg.contains("[string \"failing\"]:1:"), "{}", msg)
+        }
+        r => panic!("expected RuntimeError, got {:?}", r),
+    }
+
+    match broken.join(&lua) {
+        Err(Error::SyntaxError { .. }) => {}
+        r => panic!("expected SyntaxError, got {:?}", r),
+    }
+
+    Ok(())
+}
+
 #[test]
 fn test_named_registry_value() -> Result<()> {
     let lua = Lua::new();
@@ -573,6 +1027,32 @@ fn test_named_registry_value() -> Result<()> {
     Ok(())
 }
 
+#[test]
+fn test_namespaced_registry_value() -> Result<()> {
+    let lua = Lua::new();
+
+    // `_LOADED` is used by Lua itself to track loaded modules
+    lua.set_namespaced_registry_value("_LOADED", 42)?;
+    assert_eq!(lua.namespaced_registry_value::<_, i32>("_LOADED")?, 42);
+    assert!(lua
+        .load(r#"return type(require("string").format) == "function""#)
+        .eval::<bool>()?);
+
+    // Named registry values use the name as is
+    let loaded: Table = lua.named_registry_value("_LOADED")?;
+    assert_eq!(loaded, lua.load("package.loaded").eval::<Table>()?);
+    lua.set_named_registry_value("answer", 1)?;
+    assert_eq!(lua.namespaced_registry_value::<_, Value>("answer")?, Nil);
+
+    lua.unset_namespaced_registry_value("_LOADED")?;
+    assert_eq!(lua.namespaced_registry_value::<_, Value>("_LOADED")?, Nil);
+    assert!(lua
+        .load(r#"return package.loaded.string == string"#)
+        .eval::<bool>()?);
+
+    Ok(())
+}
+
 #[test]
 fn test_registry_value() -> Result<()> {
     let lua = Lua::new();
@@ -839,3 +1319,361 @@ fn context_thread_51() -> Result<()> {
 
     Ok(())
 }
+
+#[test]
+fn test_module_searcher() -> Result<()> {
+    let lua = Lua::new();
+
+    lua.set_module_searcher(|lua, name| match name {
+        "counter" => Ok(Some(
+            lua.load("loads = (loads or 0) + 1; return {}")
+                .into_function()?,
+        )),
+        _ => Ok(None),
+    })?;
+
+    // The module is loaded only once and cached in `package.loaded`
+    assert!(lua
+        .load(r#"require("counter") == require("counter")"#)
+        .eval::<bool>()?);
+    assert_eq!(lua.globals().get::<_, i32>("loads")?, 1);
+    assert!(lua
+        .load(r#"package.loaded.counter ~= nil"#)
+        .eval::<bool>()?);
+
+    // Unknown modules fall through to the remaining searchers
+    match lua.load(r#"require("missing")"#).exec() {
+        Err(Error::RuntimeError(msg)) => {
+            assert!(msg.contains("no module 'missing' in Rust searcher"))
+        }
+        r => panic!("unexpected result: {:?}", r),
+    }
+
+    // Names that are not valid UTF-8 are left to the remaining searchers
+    lua.load(r#"package.preload["\255"] = function() return 7 end"#)
+        .exec()?;
+    assert_eq!(lua.load(r#"require("\255")"#).eval::<i32>()?, 7);
+    match lua.load(r#"require("missing\255")"#).exec() {
+        Err(Error::RuntimeError(msg)) => {
+            assert!(msg.contains("no module 'missing\u{fffd}' in Rust searcher"))
+        }
+        r => panic!("unexpected result: {:?}", r),
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_multiple_states_on_threads() -> Result<()> {
+    const THREADS: usize = 8;
+    let barrier = Arc::new(std::sync::Barrier::new(THREADS));
+
+    let workers = (0..THREADS)
+        .map(|i| {
+            let barrier = barrier.clone();
+            std::thread::spawn(move || -> Result<()> {
+                let lua = Lua::new();
+                let globals = lua.globals();
+                globals.set("id", i)?;
+                globals.set(
+                    "add",
+                    lua.create_function(move |_, (a, b): (usize, usize)| Ok(a + b + i))?,
+                )?;
+                globals.set(
+                    "boom",
+                    lua.create_function(|_, ()| -> Result<()> { panic!("boom") })?,
+                )?;
+
+                barrier.wait();
+                for _ in 0..100 {
+                    lua.load(
+                        r#"
+                        total = (total or 0) + add(id, 0)
+                        assert(id == ...)
+                    "#,
+                    )
+                    .call::<_, ()>(i)?;
+                }
+                assert_eq!(globals.get::<_, usize>("total")?, 100 * 2 * i);
+
+                // Panics are caught per state and do not affect other threads
+                let panicked =
+                    catch_unwind(std::panic::AssertUnwindSafe(|| lua.load("boom()").exec()));
+                assert!(panicked.is_err());
+                assert_eq!(lua.load("id").eval::<usize>()?, i);
+
+                Ok(())
+            })
+        })
+        .collect::<Vec<_>>();
+
+    for worker in workers {
+        worker.join().unwrap()?;
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_load_cached() -> Result<()> {
+    let lua = Lua::new();
+
+    let f1 = lua.load_cached(
+        "counter",
+        "local n = 0; return function() n = n + 1; return n end",
+    )?;
+    let f2 = lua.load_cached(
+        "counter",
+        "local n = 0; return function() n = n + 1; return n end",
+    )?;
+    assert_eq!(f1, f2);
+
+    let f3 = lua.load_cached(
+        "counter",
+        "local n = 10; return function() n = n + 1; return n end",
+    )?;
+    assert!(f1 != f3);
+    assert_eq!(f3.call::<_, Function>(())?.call::<_, i64>(())?, 11);
+
+    // The new source replaced the cached function, so the old one is compiled again
+    let f6 = lua.load_cached(
+        "counter",
+        "local n = 0; return function() n = n + 1; return n end",
+    )?;
+    assert!(f1 != f6);
+
+    // Changing the source of a name does not grow the cache
+    let slots = lua.registry_slot_count();
+    for i in 0..10 {
+        lua.load_cached("counter", &format!("return {}", i))?;
+    }
+    assert_eq!(lua.registry_slot_count(), slots);
+
+    let f4 = lua.load_cached(
+        "other",
+        "local n = 0; return function() n = n + 1; return n end",
+    )?;
+    assert!(f1 != f4);
+
+    lua.clear_chunk_cache();
+    let f5 = lua.load_cached(
+        "counter",
+        "local n = 0; return function() n = n + 1; return n end",
+    )?;
+    assert!(f1 != f5);
+
+    match lua.load_cached("broken", "return +") {
+        Err(Error::SyntaxError { .. }) => {}
+        r => panic!("expected SyntaxError, got {:?}", r),
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_global_names() -> Result<()> {
+    let lua = Lua::new();
+    lua.globals().set("custom_global", 1)?;
+
+    let names = lua.global_names()?;
+    for name in &["print", "string", "table", "_G", "custom_global"] {
+        assert!(names.iter().any(|n| n == name), "missing global {}", name);
+    }
+    assert!(names.windows(2).all(|w| w[0] < w[1]));
+
+    // Globals inherited through `__index`
+    let fallback = lua.create_table()?;
+    fallback.set("inherited_global", 1)?;
+    let mt = lua.create_table()?;
+    mt.set("__index", fallback)?;
+    lua.globals().set_metatable(Some(mt));
+    assert!(!lua.global_names()?.iter().any(|n| n == "inherited_global"));
+    let names = lua.global_names_with(true)?;
+    assert!(names.iter().any(|n| n == "inherited_global"));
+    assert!(names.iter().any(|n| n == "custom_global"));
+
+    // Frozen globals are kept in a backing table behind the globals table
+    let lua = Lua::new();
+    lua.globals().set("custom_global", 1)?;
+    let mut before = lua.global_names()?;
+    before.retain(|n| n != "os");
+    lua.sandbox(SandboxConfig {
+        remove_libs: StdLib::OS,
+        memory_limit: None,
+        instruction_limit: None,
+        freeze_globals: true,
+    })?;
+    assert_eq!(lua.global_names()?, before);
+
+    Ok(())
+}
+
+#[test]
+fn test_pointer_conversions() -> Result<()> {
+    use std::rc::Rc;
+    use std::sync::Arc;
+
+    let lua = Lua::new();
+    let globals = lua.globals();
+
+    globals.set("boxed_str", Box::<str>::from("hello"))?;
+    globals.set("boxed_slice", vec![1, 2, 3].into_boxed_slice())?;
+    globals.set("rc", Rc::new(vec![4, 5]))?;
+    globals.set("arc", Arc::new(std::string::String::from("shared")))?;
+
+    lua.load(
+        r#"
+        assert(boxed_str == "hello")
+        assert(#boxed_slice == 3 and boxed_slice[3] == 3)
+        assert(#rc == 2 and rc[1] == 4)
+        assert(arc == "shared")
+    "#,
+    )
+    .exec()?;
+
+    assert_eq!(&*globals.get::<_, Box<str>>("boxed_str")?, "hello");
+    assert_eq!(&*globals.get::<_, Box<[i64]>>("boxed_slice")?, &[1, 2, 3]);
+    assert_eq!(*globals.get::<_, Rc<Vec<i64>>>("rc")?, vec![4, 5]);
+
+    let arc = globals.get::<_, Arc<std::string::String>>("arc")?;
+    assert_eq!(Arc::strong_count(&arc), 1);
+    assert_eq!(*arc, "shared");
+
+    Ok(())
+}
+
+#[test]
+fn test_stack_diagnostics() -> Result<()> {
+    let lua = Lua::new();
+
+    let top = lua.stack_top();
+    lua.globals().set("t", lua.create_table()?)?;
+    let _: Value = lua.load("return t").eval()?;
+    assert_eq!(lua.stack_top(), top);
+
+    lua.check_stack(100)?;
+    assert_eq!(lua.stack_top(), top);
+
+    // Grow the stack until the limit is reached
+    let mut n = 1024;
+    loop {
+        match lua.check_stack(n) {
+            Ok(()) => n *= 2,
+            Err(Error::StackError) => break,
+            Err(e) => panic!("expected StackError, got {:?}", e),
+        }
+    }
+    assert!(n > 1024);
+    match lua.check_stack(-1) {
+        Err(Error::StackError) => {}
+        r => panic!("expected StackError, got {:?}", r),
+    }
+
+    // The state remains usable afterwards
+    assert_eq!(lua.load("1 + 1").eval::<i64>()?, 2);
+
+    let f = lua.create_function(|lua, ()| {
+        lua.check_stack(10)?;
+        Ok(lua.stack_top())
+    })?;
+    assert!(f.call::<_, i32>(())? >= 0);
+
+    Ok(())
+}
+
+#[test]
+fn test_nonzero_conversions() -> Result<()> {
+    use std::num::{NonZeroI32, NonZeroU32, NonZeroUsize};
+
+    let lua = Lua::new();
+
+    let n: NonZeroU32 = lua.load("7").eval()?;
+    assert_eq!(n.get(), 7);
+    let n: NonZeroI32 = lua.load("-3").eval()?;
+    assert_eq!(n.get(), -3);
+    assert_eq!(
+        lua.unpack::<i64>(lua.pack(NonZeroUsize::new(5).unwrap())?)?,
+        5
+    );
+
+    match lua.load("0").eval::<NonZeroU32>() {
+        Err(Error::FromLuaConversionError { to, message, .. }) => {
+            assert_eq!(to, "NonZeroU32");
+            assert_eq!(message.as_deref(), Some("expected non-zero"));
+        }
+        r => panic!("expected FromLuaConversionError, got {:?}", r),
+    }
+    assert!(lua.load("-1").eval::<NonZeroU32>().is_err());
+
+    let repeat = lua
+        .create_function(|_, (s, n): (std::string::String, NonZeroUsize)| Ok(s.repeat(n.get())))?;
+    assert_eq!(repeat.call::<_, std::string::String>(("ab", 2))?, "abab");
+    match repeat.call::<_, std::string::String>(("ab", 0)) {
+        Err(Error::CallbackError { cause, .. }) => match *cause {
+            Error::BadArgument { pos: 2, .. } => {}
+            ref e => panic!("expected BadArgument, got {:?}", e),
+        },
+        r => panic!("expected CallbackError, got {:?}", r),
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_ref_counts() -> Result<()> {
+    let lua = Lua::new();
+
+    let refs = lua.active_ref_count();
+    let slots = lua.registry_slot_count();
+
+    {
+        let table = lua.create_table()?;
+        let function: Function = lua.load("function() return {} end").eval()?;
+        let _: Table = function.call(())?;
+        table.set("f", function.clone())?;
+        assert!(lua.active_ref_count() >= refs + 2);
+
+        let key = lua.create_registry_value(table)?;
+        let key2 = lua.create_registry_value("value")?;
+        assert_eq!(lua.registry_slot_count(), slots + 2);
+        lua.remove_registry_value(key)?;
+        assert_eq!(lua.registry_slot_count(), slots + 1);
+        drop(key2);
+        // Dropped keys keep their slot until expired
+        assert_eq!(lua.registry_slot_count(), slots + 1);
+        lua.expire_registry_values();
+    }
+
+    assert_eq!(lua.active_ref_count(), refs);
+    assert_eq!(lua.registry_slot_count(), slots);
+
+    // Freed slots are reused
+    let key = lua.create_registry_value(1)?;
+    assert_eq!(lua.registry_slot_count(), slots + 1);
+    lua.remove_registry_value(key)?;
+    assert_eq!(lua.registry_slot_count(), slots);
+
+    Ok(())
+}
+
+#[test]
+fn test_version() {
+    use mlua::Version;
+
+    let lua = Lua::new();
+    let expected = if cfg!(feature = "lua53") {
+        Version::Lua53
+    } else if cfg!(feature = "lua52") {
+        Version::Lua52
+    } else if cfg!(feature = "lua51") {
+        Version::Lua51
+    } else {
+        Version::LuaJIT
+    };
+    assert_eq!(lua.version(), expected);
+
+    let release = lua.version_release();
+    let version: std::string::String = lua.load("_VERSION").eval().unwrap();
+    assert!(release.starts_with(&version), "{} / {}", release, version);
+    assert!(release.len() > version.len());
+}
@@ -139,3 +139,212 @@ fn coroutine_panic() {
         Err(p) => assert!(*p.downcast::<&str>().unwrap() == "test_panic"),
     }
 }
+
+#[test]
+fn test_thread_iterator() -> Result<()> {
+    let lua = Lua::new();
+
+    let thread: Thread = lua
+        .load(
+            r#"
+            coroutine.create(function()
+                for i = 1, 3 do
+                    coroutine.yield(i, i * i)
+                end
+                return "done", 4
+            end)
+        "#,
+        )
+        .eval()?;
+
+    let mut iter = thread.clone().into_iter::<(i64, i64)>();
+    let values = iter.by_ref().collect::<Result<Vec<_>>>()?;
+    assert_eq!(values, vec![(1, 1), (2, 4), (3, 9)]);
+    let (msg, n) = lua.unpack_multi::<(std::string::String, i64)>(
+        iter.take_return_values().expect("no return values"),
+    )?;
+    assert_eq!((msg.as_str(), n), ("done", 4));
+    assert!(iter.next().is_none());
+    assert_eq!(thread.status(), ThreadStatus::Unresumable);
+
+    let thread: Thread = lua
+        .load(
+            r#"
+            coroutine.create(function()
+                coroutine.yield(1)
+                error("boom")
+            end)
+        "#,
+        )
+        .eval()?;
+
+    let mut iter = thread.into_iter::<i64>();
+    assert_eq!(iter.next().unwrap()?, 1);
+    match iter.next() {
+        Some(Err(Error::RuntimeError(_))) => {}
+        r => panic!("expected RuntimeError, got {:?}", r),
+    }
+    assert!(iter.next().is_none());
+    assert!(iter.take_return_values().is_none());
+
+    Ok(())
+}
+
+#[test]
+fn test_thread_name() -> Result<()> {
+    let lua = Lua::new();
+
+    let func: Function = lua
+        .load(r#"function(fail) coroutine.yield() if fail then error("boom") end end"#)
+        .eval()?;
+    let named = lua.create_thread_with_name(func.clone(), "worker-1")?;
+    let unnamed = lua.create_thread(func)?;
+    assert_eq!(named.name().as_deref(), Some("worker-1"));
+    assert_eq!(unnamed.name(), None);
+
+    named.resume::<_, ()>(true)?;
+    match named.resume::<_, ()>(()) {
+        Err(Error::RuntimeError(msg)) => {
+            assert!(msg.contains("boom"));
+            assert!(msg.contains("stack traceback (coroutine 'worker-1'):"));
+        }
+        r => panic!("expected RuntimeError, got {:?}", r),
+    }
+
+    let fail = lua.create_function(|_, ()| -> Result<()> {
+        Err(Error::RuntimeError("rust boom".to_owned()))
+    })?;
+    let thread = lua.create_thread_with_name(fail, "rust-worker")?;
+    match thread.resume::<_, ()>(()) {
+        Err(Error::CallbackError { traceback, .. }) => {
+            assert!(traceback.starts_with("stack traceback (coroutine 'rust-worker'):"));
+        }
+        r => panic!("expected CallbackError, got {:?}", r),
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_yield_across_callback() -> Result<()> {
+    let lua = Lua::new();
+
+    let call = lua.create_function(|_, f: Function| f.call::<_, ()>(()))?;
+    lua.globals().set("call", call)?;
+
+    let thread: Thread = lua
+        .load(
+            r#"
+            coroutine.create(function()
+                call(function() coroutine.yield(1) end)
+            end)
+        "#,
+        )
+        .eval()?;
+    match thread.resume::<_, ()>(()) {
+        Err(Error::CallbackError { cause, .. }) => match *cause {
+            Error::RuntimeError(ref msg) => assert!(msg.contains("attempt to yield across")),
+            ref err => panic!("expected RuntimeError, got {:?}", err),
+        },
+        r => panic!("expected CallbackError, got {:?}", r),
+    }
+    assert_eq!(thread.status(), ThreadStatus::Error);
+
+    // The error can be caught in Lua
+    let ok: bool = lua
+        .load(
+            r#"
+            local co = coroutine.wrap(function()
+                return pcall(call, function() coroutine.yield() end)
+            end)
+            return co()
+        "#,
+        )
+        .eval()?;
+    assert!(!ok);
+
+    Ok(())
+}
+
+#[test]
+fn test_thread_call() -> Result<()> {
+    let lua = Lua::new();
+
+    let actor: Function = lua
+        .load(
+            r#"
+            function(name)
+                local total = 0
+                while true do
+                    local n = coroutine.yield(name .. ":" .. total)
+                    if n == nil then
+                        return name .. " done"
+                    end
+                    total = total + n
+                end
+            end
+        "#,
+        )
+        .eval()?;
+
+    let finished = || -> Result<Thread> {
+        let thread = lua.create_thread(lua.load("function() end").eval()?)?;
+        thread.resume::<_, ()>(())?;
+        Ok(thread)
+    };
+
+    // Both threads run the same function with independent state
+    let a = finished()?;
+    let b = finished()?;
+    assert_eq!(a.call::<_, String>(actor.clone(), "a")?, "a:0");
+    assert_eq!(b.call::<_, String>(actor.clone(), "b")?, "b:0");
+    assert_eq!(a.resume::<_, String>(1)?, "a:1");
+    assert_eq!(b.resume::<_, String>(10)?, "b:10");
+    assert_eq!(a.resume::<_, String>(2)?, "a:3");
+    assert_eq!(b.resume::<_, String>(20)?, "b:30");
+    assert_eq!(a.resume::<_, String>(())?, "a done");
+    assert_eq!(a.status(), ThreadStatus::Unresumable);
+
+    // Suspended threads can't be called
+    match b.call::<_, ()>(actor.clone(), "c") {
+        Err(Error::RuntimeError(_)) => {}
+        r => panic!("expected RuntimeError, got {:?}", r),
+    }
+    assert_eq!(b.resume::<_, String>(1)?, "b:31");
+
+    // A finished thread can be reused
+    assert_eq!(a.call::<_, String>(actor.clone(), "c")?, "c:0");
+    assert_eq!(a.resume::<_, String>(5)?, "c:5");
+
+    // Functions that don't yield just return
+    let add: Function = lua.load("function(a, b) return a + b end").eval()?;
+    let c = finished()?;
+    assert_eq!(c.call::<_, i64>(add.clone(), (1, 2))?, 3);
+    assert_eq!(c.call::<_, i64>(add, (3, 4))?, 7);
+
+    // Threads that raised an error can't be reused
+    let fail: Function = lua.load("function() error('boom') end").eval()?;
+    assert!(c.call::<_, ()>(fail.clone(), ()).is_err());
+    match c.call::<_, ()>(fail, ()) {
+        Err(Error::CoroutineInactive) => {}
+        r => panic!("expected CoroutineInactive, got {:?}", r),
+    }
+
+    // A thread can't call a function in itself while running
+    let thread = finished()?;
+    lua.globals().set("thread", thread.clone())?;
+    let call_self = lua.create_function(|lua, ()| {
+        let thread: Thread = lua.globals().get("thread")?;
+        let f: Function = lua.load("function() end").eval()?;
+        thread.call::<_, ()>(f, ())
+    })?;
+    match thread.call::<_, ()>(call_self, ()) {
+        Err(Error::CallbackError { ref cause, .. }) => match **cause {
+            Error::RuntimeError(_) => {}
+            ref err => panic!("expected RuntimeError, got {:?}", err),
+        },
+        r => panic!("expected CallbackError, got {:?}", r),
+    }
+
+    Ok(())
+}
@@ -35,3 +35,31 @@ fn test_lightuserdata() -> Result<()> {
 
     Ok(())
 }
+
+#[test]
+fn test_create_light_userdata() -> Result<()> {
+    let lua = Lua::new();
+
+    let mut a = 1u32;
+    let mut b = 2u32;
+    let pa = lua.create_light_userdata(&mut a as *mut u32);
+    let pb = lua.create_light_userdata(&mut b as *mut u32);
+
+    // Light userdata can be used as table keys and compare by address
+    let table = lua.create_table()?;
+    table.set(pa, "a")?;
+    table.set(pb, "b")?;
+    assert_eq!(table.get::<_, std::string::String>(pa)?, "a");
+    assert_eq!(table.get::<_, std::string::String>(pb)?, "b");
+
+    lua.globals().set("pa", pa)?;
+    assert_eq!(
+        lua.load("type(pa)").eval::<std::string::String>()?,
+        "userdata"
+    );
+    let back: LightUserData = lua.load("pa").eval()?;
+    assert_eq!(back, pa);
+    assert_eq!(unsafe { *(back.0 as *mut u32) }, 1);
+
+    Ok(())
+}
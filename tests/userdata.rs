@@ -9,11 +9,12 @@
 )]
 extern "system" {}
 
+use std::collections::{BTreeMap, HashMap};
 use std::sync::Arc;
 
 use mlua::{
-    AnyUserData, ExternalError, Function, Lua, MetaMethod, Result, String, UserData,
-    UserDataMethods, Value,
+    AnyUserData, Error, ExternalError, Function, IndexPriority, Lua, MetaMethod, Result, String,
+    UserData, UserDataMethods, Value,
 };
 
 #[test]
@@ -165,6 +166,64 @@ fn test_metamethods() -> Result<()> {
     Ok(())
 }
 
+#[test]
+fn test_tostring_metamethod() -> Result<()> {
+    struct Bytes(Vec<u8>);
+
+    impl UserData for Bytes {
+        fn add_methods<'lua, M: UserDataMethods<'lua, Self>>(methods: &mut M) {
+            methods.add_meta_method(MetaMethod::ToString, |lua, data, ()| {
+                lua.create_string(&data.0)
+            });
+        }
+    }
+
+    struct Number(i64);
+
+    impl UserData for Number {
+        fn add_methods<'lua, M: UserDataMethods<'lua, Self>>(methods: &mut M) {
+            methods.add_meta_method(MetaMethod::ToString, |_, data, ()| Ok(data.0));
+        }
+    }
+
+    struct Invalid;
+
+    impl UserData for Invalid {
+        fn add_methods<'lua, M: UserDataMethods<'lua, Self>>(methods: &mut M) {
+            methods.add_meta_function(MetaMethod::ToString, |_, _: AnyUserData| Ok(true));
+        }
+    }
+
+    let lua = Lua::new();
+    let globals = lua.globals();
+    globals.set("bytes", Bytes(b"\xff\x00binary".to_vec()))?;
+    globals.set("number", Number(42))?;
+    globals.set("invalid", Invalid)?;
+
+    assert_eq!(
+        lua.load("tostring(bytes)").eval::<String>()?.as_bytes(),
+        b"\xff\x00binary"
+    );
+    assert_eq!(lua.load("tostring(number)").eval::<String>()?, "42");
+    assert_eq!(
+        lua.load("type(tostring(number))").eval::<String>()?,
+        "string"
+    );
+
+    match lua.load("tostring(invalid)").exec() {
+        Err(Error::CallbackError { ref cause, .. }) => match **cause {
+            Error::ToLuaConversionError { from, to, .. } => {
+                assert_eq!(from, "boolean");
+                assert_eq!(to, "string");
+            }
+            ref other => panic!("unexpected error cause {:?}", other),
+        },
+        other => panic!("unexpected result {:?}", other),
+    }
+
+    Ok(())
+}
+
 #[test]
 fn test_gc_userdata() -> Result<()> {
     struct MyUserdata {
@@ -219,7 +278,7 @@ fn detroys_userdata() -> Result<()> {
     assert_eq!(Arc::strong_count(&rc), 2);
 
     // should destroy all objects
-    let _ = lua.globals().raw_remove("userdata")?;
+    lua.globals().raw_remove::<_, Value>("userdata")?;
     lua.gc_collect()?;
 
     assert_eq!(Arc::strong_count(&rc), 1);
@@ -290,3 +349,784 @@ fn test_functions() -> Result<()> {
 
     Ok(())
 }
+
+#[test]
+// Keys are compared by identity, which never changes
+#[allow(clippy::mutable_key_type)]
+fn test_userdata_identity_keys() -> Result<()> {
+    struct MyUserData;
+
+    impl UserData for MyUserData {}
+
+    let lua = Lua::new();
+
+    let ud1 = lua.create_userdata(MyUserData)?;
+    let ud2 = lua.create_userdata(MyUserData)?;
+    lua.globals().set("ud1", ud1.clone())?;
+    let ud1_again: AnyUserData = lua.globals().get("ud1")?;
+
+    let mut hash_map = HashMap::new();
+    hash_map.insert(ud1.clone(), "first");
+    hash_map.insert(ud2.clone(), "second");
+    assert_eq!(hash_map.len(), 2);
+    assert_eq!(hash_map[&ud1_again], "first");
+    assert_eq!(hash_map[&ud2], "second");
+
+    let mut btree_map = BTreeMap::new();
+    btree_map.insert(ud1.clone(), "first");
+    btree_map.insert(ud2.clone(), "second");
+    btree_map.insert(ud1_again.clone(), "again");
+    assert_eq!(btree_map.len(), 2);
+    assert_eq!(btree_map[&ud1], "again");
+    assert_eq!(ud1.cmp(&ud1_again), std::cmp::Ordering::Equal);
+    assert_ne!(ud1.cmp(&ud2), std::cmp::Ordering::Equal);
+
+    Ok(())
+}
+
+#[test]
+fn test_userdata_type_metatable() -> Result<()> {
+    struct NoMethods;
+    impl UserData for NoMethods {}
+
+    struct WithMethods(i64);
+    impl UserData for WithMethods {
+        fn add_methods<'lua, M: UserDataMethods<'lua, Self>>(methods: &mut M) {
+            methods.add_method("get", |_, data, ()| Ok(data.0));
+        }
+    }
+
+    struct WithIndex;
+    impl UserData for WithIndex {
+        fn add_methods<'lua, M: UserDataMethods<'lua, Self>>(methods: &mut M) {
+            methods.add_meta_method(MetaMethod::Index, |_, _, key: String| {
+                Ok(format!("index {}", key.to_str()?))
+            });
+        }
+    }
+
+    let lua = Lua::new();
+    let globals = lua.globals();
+
+    globals.set("a", lua.create_userdata(NoMethods)?)?;
+    let metatable = lua.userdata_type_metatable::<NoMethods>()?;
+    metatable.set_method("hello", lua.create_function(|_, _: AnyUserData| Ok("hi"))?)?;
+    assert_eq!(lua.load("a:hello()").eval::<String>()?, "hi");
+    metatable.set_method("hello", Value::Nil)?;
+    assert_eq!(lua.load("a.hello").eval::<Value>()?, Value::Nil);
+
+    globals.set("b", lua.create_userdata(WithMethods(5))?)?;
+    let metatable = lua.userdata_type_metatable::<WithMethods>()?;
+    assert!(metatable.get_method::<Option<Function>>("get")?.is_some());
+    let get_twice: Function = lua
+        .load("function(self) return self:get() * 2 end")
+        .eval()?;
+    metatable.set_method("get_twice", get_twice)?;
+    assert_eq!(lua.load("b:get_twice()").eval::<i64>()?, 10);
+    // New instances share the updated metatable
+    globals.set("b2", lua.create_userdata(WithMethods(7))?)?;
+    assert_eq!(lua.load("b2:get_twice()").eval::<i64>()?, 14);
+
+    globals.set("c", lua.create_userdata(WithIndex)?)?;
+    let metatable = lua.userdata_type_metatable::<WithIndex>()?;
+    metatable.set_method("one", 1)?;
+    metatable.set_method("two", 2)?;
+    assert_eq!(lua.load("c.one + c.two").eval::<i64>()?, 3);
+    assert_eq!(lua.load("c.other").eval::<String>()?, "index other");
+
+    metatable.set("__len", lua.create_function(|_, _: AnyUserData| Ok(42))?)?;
+    assert_eq!(lua.load("#c").eval::<i64>()?, 42);
+
+    for key in &["__gc", "__metatable", "__index"] {
+        match metatable.set(*key, Value::Nil) {
+            Err(Error::MetaMethodRestricted(_)) => {}
+            r => panic!("expected MetaMethodRestricted, got {:?}", r),
+        }
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_register_userdata_with() -> Result<()> {
+    // Stands in for a type from another crate
+    #[derive(Debug, PartialEq)]
+    struct Point {
+        x: i64,
+        y: i64,
+    }
+
+    let lua = Lua::new();
+
+    match lua.create_registered_userdata(Point { x: 0, y: 0 }) {
+        Err(Error::RuntimeError(_)) => {}
+        r => panic!("expected RuntimeError, got {:?}", r),
+    }
+
+    lua.register_userdata_with::<Point, _>(|methods| {
+        methods.add_method("sum", |_, point, ()| Ok(point.x + point.y));
+        methods.add_method_mut("shift", |_, point, (dx, dy): (i64, i64)| {
+            point.x += dx;
+            point.y += dy;
+            Ok(())
+        });
+        methods.add_meta_method(MetaMethod::ToString, |_, point, ()| {
+            Ok(format!("({}, {})", point.x, point.y))
+        });
+    })?;
+
+    let point = lua.create_registered_userdata(Point { x: 1, y: 2 })?;
+    lua.globals().set("point", point.clone())?;
+    lua.load("point:shift(10, 20)").exec()?;
+    assert_eq!(lua.load("point:sum()").eval::<i64>()?, 33);
+    assert_eq!(lua.load("tostring(point)").eval::<String>()?, "(11, 22)");
+
+    assert!(point.is::<Point>());
+    assert_eq!(*point.borrow::<Point>()?, Point { x: 11, y: 22 });
+    assert!(!point.is::<i64>());
+
+    match lua.register_userdata_with::<Point, _>(|_| {}) {
+        Err(Error::RuntimeError(_)) => {}
+        r => panic!("expected RuntimeError, got {:?}", r),
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_named_args() -> Result<()> {
+    struct Pad;
+
+    impl UserData for Pad {
+        fn add_methods<'lua, M: UserDataMethods<'lua, Self>>(methods: &mut M) {
+            methods.add_method_named_args(
+                "left",
+                &["text", "width"],
+                |_, _, (text, width): (std::string::String, usize)| {
+                    Ok(format!("{:>1$}", text, width))
+                },
+            );
+            methods.add_function_named_args(
+                "right",
+                &["pad", "text", "width"],
+                |_, (_, text, width): (AnyUserData, std::string::String, usize)| {
+                    Ok(format!("{:<1$}", text, width))
+                },
+            );
+        }
+    }
+
+    let lua = Lua::new();
+    lua.globals().set("pad", Pad)?;
+
+    assert_eq!(
+        lua.load("pad:left('x', 3)").eval::<std::string::String>()?,
+        "  x"
+    );
+    assert_eq!(
+        lua.load("pad:right('x', 3)")
+            .eval::<std::string::String>()?,
+        "x  "
+    );
+
+    let message = |code: &str| -> Result<std::string::String> {
+        lua.load(&format!(
+            "local ok, err = pcall(function() {} end); return tostring(err)",
+            code
+        ))
+        .eval()
+    };
+    assert_eq!(
+        message("pad:left('x', 'y')")?,
+        "bad argument 'width' to 'left' (usize expected, got string)"
+    );
+    assert_eq!(
+        message("pad:right({}, 1)")?,
+        "bad argument 'text' to 'right' (String expected, got table)"
+    );
+    assert_eq!(
+        message("pad.right(1, 'x', 1)")?,
+        "bad argument 'pad' to 'right' (userdata expected, got integer)"
+    );
+
+    Ok(())
+}
+
+#[test]
+fn test_reentrant_method_mut() -> Result<()> {
+    struct Counter(i64);
+
+    impl UserData for Counter {
+        fn add_methods<'lua, M: UserDataMethods<'lua, Self>>(methods: &mut M) {
+            methods.add_method_mut("call", |_, counter, f: Function| {
+                counter.0 += 1;
+                f.call::<_, ()>(())
+            });
+            methods.add_method("get", |_, counter, ()| Ok(counter.0));
+        }
+    }
+
+    let lua = Lua::new();
+    lua.globals().set("counter", Counter(0))?;
+
+    let err: std::string::String = lua
+        .load(
+            r#"
+            local ok, err = pcall(counter.call, counter, function()
+                counter:call(function() end)
+            end)
+            assert(not ok)
+            return tostring(err)
+        "#,
+        )
+        .eval()?;
+    assert!(err.contains("userdata already borrowed"), "{}", err);
+
+    // The userdata is usable again once the outer call has finished
+    lua.load("counter:call(function() end)").exec()?;
+    assert_eq!(lua.load("counter:get()").eval::<i64>()?, 2);
+
+    let counter: AnyUserData = lua.globals().get("counter")?;
+    let _guard = counter.borrow::<Counter>()?;
+    match lua.load("counter:call(function() end)").exec() {
+        Err(Error::CallbackError { cause, .. }) => match *cause {
+            Error::UserDataBorrowMutError => {}
+            ref err => panic!("expected UserDataBorrowMutError, got {:?}", err),
+        },
+        r => panic!("expected CallbackError, got {:?}", r),
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_index_table() -> Result<()> {
+    struct Point(i64, i64);
+
+    impl UserData for Point {
+        fn add_methods<'lua, M: UserDataMethods<'lua, Self>>(methods: &mut M) {
+            methods.add_method("x", |_, p, ()| Ok(p.0));
+            methods.set_index_table_with(|lua| lua.globals().get("PointBase"));
+            methods.add_meta_method(MetaMethod::Index, |_, p, key: String| {
+                Ok(format!("{}:{}", key.to_str()?, p.1))
+            });
+        }
+    }
+
+    let lua = Lua::new();
+    lua.load(
+        r#"
+        PointBase = { x = "shadowed", origin = 0 }
+        function PointBase:sum() return self:x() + 1 end
+    "#,
+    )
+    .exec()?;
+    lua.globals().set("p", Point(3, 4))?;
+
+    // Registered methods first, then the index table, then the `__index` metamethod
+    assert_eq!(lua.load("p:x()").eval::<i64>()?, 3);
+    assert_eq!(lua.load("p.origin").eval::<i64>()?, 0);
+    assert_eq!(lua.load("p:sum()").eval::<i64>()?, 4);
+    assert_eq!(
+        lua.load("p.missing").eval::<std::string::String>()?,
+        "missing:4"
+    );
+
+    // Changes to the index table are visible
+    lua.load("PointBase.origin = 10").exec()?;
+    assert_eq!(lua.load("p.origin").eval::<i64>()?, 10);
+
+    struct Empty;
+    impl UserData for Empty {
+        fn add_methods<'lua, M: UserDataMethods<'lua, Self>>(methods: &mut M) {
+            methods.set_index_table_with(|lua| lua.globals().get("PointBase"));
+        }
+    }
+    lua.globals().set("e", Empty)?;
+    assert_eq!(lua.load("e.origin").eval::<i64>()?, 10);
+
+    lua.scope(|scope| {
+        lua.globals()
+            .set("sp", scope.create_nonstatic_userdata(Point(5, 6))?)?;
+        assert_eq!(lua.load("sp:sum()").eval::<i64>()?, 6);
+        assert_eq!(
+            lua.load("sp.other").eval::<std::string::String>()?,
+            "other:6"
+        );
+        Ok(())
+    })?;
+
+    Ok(())
+}
+
+#[test]
+fn test_iterator_method() -> Result<()> {
+    struct Scores(BTreeMap<std::string::String, i64>);
+
+    impl UserData for Scores {
+        fn add_methods<'lua, M: UserDataMethods<'lua, Self>>(methods: &mut M) {
+            methods.add_iterator_method("entries", |_, scores| Ok(scores.0.clone().into_iter()));
+            methods.add_iterator_method("names", |_, scores| {
+                Ok(scores.0.keys().cloned().collect::<Vec<_>>().into_iter())
+            });
+        }
+    }
+
+    let lua = Lua::new();
+    let mut scores = BTreeMap::new();
+    scores.insert("alice".to_owned(), 3);
+    scores.insert("bob".to_owned(), 5);
+    lua.globals().set("scores", Scores(scores))?;
+
+    let joined: std::string::String = lua
+        .load(
+            r#"
+        local parts = {}
+        for name, score in scores:entries() do
+            parts[#parts + 1] = name .. "=" .. score
+        end
+        for name in scores:names() do
+            parts[#parts + 1] = name
+        end
+        return table.concat(parts, ",")
+    "#,
+        )
+        .eval()?;
+    assert_eq!(joined, "alice=3,bob=5,alice,bob");
+
+    // Each call creates an independent iterator
+    let count: i64 = lua
+        .load(
+            r#"
+        local iter = scores:entries()
+        iter()
+        local n = 0
+        for _ in scores:entries() do n = n + 1 end
+        return n
+    "#,
+        )
+        .eval()?;
+    assert_eq!(count, 2);
+
+    Ok(())
+}
+
+#[test]
+fn test_index_priority() -> Result<()> {
+    fn add_proxy_methods<'lua, T, M: UserDataMethods<'lua, T>>(methods: &mut M) {
+        methods.add_method("name", |_, _, ()| Ok("method"));
+        methods.add_method("size", |_, _, ()| Ok(1));
+        methods.add_meta_method(MetaMethod::Index, |_, _, key: String| {
+            Ok(match key.to_str()? {
+                "name" => Some("handler"),
+                "extra" => Some("extra"),
+                _ => None,
+            })
+        });
+    }
+
+    struct MethodsFirst;
+    impl UserData for MethodsFirst {
+        fn add_methods<'lua, M: UserDataMethods<'lua, Self>>(methods: &mut M) {
+            add_proxy_methods(methods);
+        }
+    }
+
+    struct HandlerFirst;
+    impl UserData for HandlerFirst {
+        fn add_methods<'lua, M: UserDataMethods<'lua, Self>>(methods: &mut M) {
+            add_proxy_methods(methods);
+            methods.set_index_priority(IndexPriority::HandlerFirst);
+        }
+    }
+
+    let lua = Lua::new();
+    let globals = lua.globals();
+    globals.set("m", MethodsFirst)?;
+    globals.set("h", HandlerFirst)?;
+
+    assert_eq!(
+        lua.load("type(m.name)").eval::<std::string::String>()?,
+        "function"
+    );
+    assert_eq!(lua.load("m.extra").eval::<std::string::String>()?, "extra");
+    assert_eq!(lua.load("m:size()").eval::<i64>()?, 1);
+
+    assert_eq!(lua.load("h.name").eval::<std::string::String>()?, "handler");
+    assert_eq!(lua.load("h.extra").eval::<std::string::String>()?, "extra");
+    assert_eq!(lua.load("h:size()").eval::<i64>()?, 1);
+    assert_eq!(lua.load("h.missing").eval::<Value>()?, Value::Nil);
+
+    lua.scope(|scope| {
+        globals.set("sh", scope.create_nonstatic_userdata(HandlerFirst)?)?;
+        assert_eq!(
+            lua.load("sh.name").eval::<std::string::String>()?,
+            "handler"
+        );
+        assert_eq!(lua.load("sh:size()").eval::<i64>()?, 1);
+        Ok(())
+    })?;
+
+    Ok(())
+}
+
+#[test]
+fn test_method_cache() -> Result<()> {
+    fn add_proxy_methods<'lua, T, M: UserDataMethods<'lua, T>>(methods: &mut M) {
+        methods.add_method("size", |_, _, ()| Ok(1));
+        methods.add_meta_method(MetaMethod::Index, |lua, _, key: String| {
+            let globals = lua.globals();
+            globals.set("lookups", globals.get::<_, i64>("lookups")? + 1)?;
+            Ok(match key.to_str()? {
+                "extra" => Some("extra"),
+                _ => None,
+            })
+        });
+        methods.set_index_priority(IndexPriority::HandlerFirst);
+    }
+
+    struct Cached;
+    impl UserData for Cached {
+        fn add_methods<'lua, M: UserDataMethods<'lua, Self>>(methods: &mut M) {
+            add_proxy_methods(methods);
+            methods.enable_method_cache();
+        }
+    }
+
+    struct Uncached;
+    impl UserData for Uncached {
+        fn add_methods<'lua, M: UserDataMethods<'lua, Self>>(methods: &mut M) {
+            add_proxy_methods(methods);
+        }
+    }
+
+    let lua = Lua::new();
+    let globals = lua.globals();
+    let count_lookups = |obj: AnyUserData| -> Result<i64> {
+        globals.set("lookups", 0)?;
+        let n: i64 = lua
+            .load("local obj = ...; local n = 0; for i = 1, 10 do n = n + obj:size() end; return n")
+            .call(obj)?;
+        assert_eq!(n, 10);
+        globals.get("lookups")
+    };
+
+    // Only the first lookup of a method calls the handler
+    let cached = lua.create_userdata(Cached)?;
+    assert_eq!(count_lookups(cached.clone())?, 1);
+    assert_eq!(count_lookups(cached.clone())?, 0);
+    assert_eq!(count_lookups(lua.create_userdata(Cached)?)?, 0);
+    assert_eq!(count_lookups(lua.create_userdata(Uncached)?)?, 10);
+
+    // Other keys still go through the handler
+    globals.set("cached", cached.clone())?;
+    assert_eq!(
+        lua.load("cached.extra").eval::<std::string::String>()?,
+        "extra"
+    );
+    assert_eq!(lua.load("cached.missing").eval::<Value>()?, Value::Nil);
+    assert_eq!(cached.method_names()?, vec!["size"]);
+
+    lua.scope(|scope| {
+        let cached = scope.create_nonstatic_userdata(Cached)?;
+        assert_eq!(count_lookups(cached.clone())?, 1);
+        assert_eq!(count_lookups(cached)?, 0);
+        Ok(())
+    })?;
+
+    Ok(())
+}
+
+#[test]
+fn test_index_multiple_returns() -> Result<()> {
+    // The `Index` handler resolves `triple` to a function, which is then called as a method
+    fn add_proxy_methods<'lua, T, M: UserDataMethods<'lua, T>>(methods: &mut M) {
+        methods.add_method("pair", |_, _, ()| Ok((1, 2)));
+        methods.add_meta_method(MetaMethod::Index, |lua, _, key: String| {
+            match key.to_str()? {
+                "triple" => {
+                    Ok(Some(lua.create_function(|_, _: AnyUserData| {
+                        Ok((1, "two", 3.0))
+                    })?))
+                }
+                "script" => lua.load("return function(self) return 4, 5, 6 end").eval(),
+                _ => Ok(None),
+            }
+        });
+    }
+
+    struct MethodsFirst;
+    impl UserData for MethodsFirst {
+        fn add_methods<'lua, M: UserDataMethods<'lua, Self>>(methods: &mut M) {
+            add_proxy_methods(methods);
+        }
+    }
+
+    struct HandlerFirst;
+    impl UserData for HandlerFirst {
+        fn add_methods<'lua, M: UserDataMethods<'lua, Self>>(methods: &mut M) {
+            add_proxy_methods(methods);
+            methods.set_index_priority(IndexPriority::HandlerFirst);
+        }
+    }
+
+    struct Cached;
+    impl UserData for Cached {
+        fn add_methods<'lua, M: UserDataMethods<'lua, Self>>(methods: &mut M) {
+            add_proxy_methods(methods);
+            methods.set_index_priority(IndexPriority::HandlerFirst);
+            methods.enable_method_cache();
+        }
+    }
+
+    struct HandlerOnly;
+    impl UserData for HandlerOnly {
+        fn add_methods<'lua, M: UserDataMethods<'lua, Self>>(methods: &mut M) {
+            methods.add_meta_method(MetaMethod::Index, |lua, _, key: String| {
+                match key.to_str()? {
+                    "triple" => {
+                        Ok(Some(lua.create_function(|_, _: AnyUserData| {
+                            Ok((1, "two", 3.0))
+                        })?))
+                    }
+                    _ => Ok(None),
+                }
+            });
+        }
+    }
+
+    let lua = Lua::new();
+    let check = |obj: AnyUserData| -> Result<()> {
+        let (a, b, c, n): (i64, std::string::String, f64, i64) = lua
+            .load("local obj = ...; local a, b, c = obj:triple(); return a, b, c, select('#', obj:triple())")
+            .call(obj.clone())?;
+        assert_eq!((a, b.as_str(), c, n), (1, "two", 3.0, 3));
+
+        if !obj.is::<HandlerOnly>() {
+            let values: Vec<i64> = lua
+                .load("local obj = ...; return { obj:script() }")
+                .call(obj.clone())?;
+            assert_eq!(values, vec![4, 5, 6]);
+            let values: Vec<i64> = lua
+                .load("local obj = ...; return { obj:pair() }")
+                .call(obj.clone())?;
+            assert_eq!(values, vec![1, 2]);
+        }
+
+        // Indexing itself still yields a single value
+        let n: i64 = lua
+            .load("local obj = ...; return select('#', obj.triple)")
+            .call(obj)?;
+        assert_eq!(n, 1);
+        Ok(())
+    };
+
+    check(lua.create_userdata(MethodsFirst)?)?;
+    check(lua.create_userdata(HandlerFirst)?)?;
+    check(lua.create_userdata(Cached)?)?;
+    check(lua.create_userdata(HandlerOnly)?)?;
+
+    lua.scope(|scope| {
+        check(scope.create_nonstatic_userdata(MethodsFirst)?)?;
+        check(scope.create_nonstatic_userdata(HandlerFirst)?)?;
+        check(scope.create_nonstatic_userdata(Cached)?)
+    })?;
+
+    Ok(())
+}
+
+#[test]
+fn test_gc_during_borrow() -> Result<()> {
+    struct Counter(i64);
+
+    impl UserData for Counter {
+        fn add_methods<'lua, M: UserDataMethods<'lua, Self>>(methods: &mut M) {
+            methods.add_method_mut("collect", |lua, this, ()| {
+                this.0 += 1;
+                lua.gc_collect()?;
+                lua.gc_collect()?;
+                lua.gc_step()?;
+                this.0 += 1;
+                Ok(this.0)
+            });
+            methods.add_method("get", |_, this, ()| Ok(this.0));
+        }
+    }
+
+    let lua = Lua::new();
+    lua.globals().set("counter", Counter(0))?;
+
+    // A finalizer which accesses the mutably borrowed userdata gets a borrow error instead of
+    // aliasing it
+    let result: Value = lua
+        .load(
+            r#"
+        local log = {}
+        do
+            setmetatable({}, { __gc = function()
+                log[#log + 1] = tostring(select(2, pcall(counter.get, counter)))
+            end })
+            setmetatable({}, { __gc = function() log[#log + 1] = "other" end })
+        end
+        local n = counter:collect()
+        return { n = n, log = log }
+    "#,
+        )
+        .eval()?;
+    let result = match result {
+        Value::Table(t) => t,
+        v => panic!("expected table, got {:?}", v),
+    };
+    assert_eq!(result.get::<_, i64>("n")?, 2);
+    let log: Vec<std::string::String> = result.get("log")?;
+    assert_eq!(log.len(), 2);
+    assert!(log.iter().any(|l| l == "userdata already mutably borrowed"));
+    assert!(log.iter().any(|l| l == "other"));
+
+    Ok(())
+}
+
+#[test]
+fn test_userdata_name() -> Result<()> {
+    struct Named;
+    impl UserData for Named {
+        fn name() -> Option<&'static str> {
+            Some("Named")
+        }
+    }
+
+    struct Unnamed;
+    impl UserData for Unnamed {}
+
+    let lua = Lua::new();
+    let named = lua.create_userdata(Named)?;
+    let unnamed = lua.create_userdata(Unnamed)?;
+    assert_eq!(named.type_name().as_deref(), Some("Named"));
+    assert_eq!(unnamed.type_name(), None);
+
+    #[cfg(feature = "lua53")]
+    {
+        let tostring: Function = lua.globals().get("tostring")?;
+        let s = tostring.call::<_, std::string::String>(named)?;
+        assert!(s.starts_with("Named: "), "unexpected string {:?}", s);
+        let s = tostring.call::<_, std::string::String>(unnamed)?;
+        assert!(s.starts_with("userdata: "), "unexpected string {:?}", s);
+    }
+
+    lua.scope(|scope| {
+        let named = scope.create_nonstatic_userdata(Named)?;
+        assert_eq!(named.type_name().as_deref(), Some("Named"));
+        Ok(())
+    })?;
+
+    Ok(())
+}
+
+#[test]
+fn test_userdata_get_function() -> Result<()> {
+    struct Counter(i64);
+
+    impl UserData for Counter {
+        fn add_methods<'lua, M: UserDataMethods<'lua, Self>>(methods: &mut M) {
+            methods.add_method("get", |_, this, ()| Ok(this.0));
+            methods.add_method_mut("add", |_, this, n: i64| {
+                this.0 += n;
+                Ok(this.0)
+            });
+        }
+    }
+
+    let lua = Lua::new();
+    let ud = lua.create_userdata(Counter(1))?;
+
+    let add = ud.get_function("add")?;
+    assert_eq!(add.call::<_, i64>((ud.clone(), 2))?, 3);
+
+    let bound_add = ud.get_bound_method("add")?;
+    assert_eq!(bound_add.call::<_, i64>(4)?, 7);
+
+    let bound_get = ud.get_bound_method("get")?;
+    assert_eq!(bound_get.call::<_, i64>(())?, 7);
+
+    match ud.get_function("missing") {
+        Err(Error::FromLuaConversionError { .. }) => {}
+        r => panic!("expected FromLuaConversionError, got {:?}", r),
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_userdata_method_names() -> Result<()> {
+    struct Player;
+
+    impl UserData for Player {
+        fn add_methods<'lua, M: UserDataMethods<'lua, Self>>(methods: &mut M) {
+            methods.add_method("name", |_, _, ()| Ok("player"));
+            methods.add_method_mut("jump", |_, _, ()| Ok(()));
+            methods.add_function("spawn", |_, ()| Ok(()));
+            methods.add_meta_method(MetaMethod::Index, |_, _, key: String| Ok(key));
+            methods.add_meta_method(MetaMethod::ToString, |_, _, ()| Ok("player"));
+        }
+    }
+
+    struct Empty;
+    impl UserData for Empty {}
+
+    let lua = Lua::new();
+    let player = lua.create_userdata(Player)?;
+    assert_eq!(player.method_names()?, vec!["jump", "name", "spawn"]);
+
+    lua.userdata_type_metatable::<Player>()?
+        .set_method("greet", lua.create_function(|_, _: AnyUserData| Ok("hi"))?)?;
+    assert_eq!(
+        player.method_names()?,
+        vec!["greet", "jump", "name", "spawn"]
+    );
+
+    let empty = lua.create_userdata(Empty)?;
+    assert!(empty.method_names()?.is_empty());
+
+    Ok(())
+}
+
+#[test]
+fn test_userdata_metatable_protection() -> Result<()> {
+    struct Default;
+    impl UserData for Default {}
+
+    struct Protected;
+    impl UserData for Protected {
+        fn add_methods<'lua, M: UserDataMethods<'lua, Self>>(methods: &mut M) {
+            methods.add_method("ping", |_, _, ()| Ok("pong"));
+            methods.set_metatable_protection("Protected");
+        }
+    }
+
+    let lua = Lua::new();
+    lua.globals().set("default", Default)?;
+    lua.globals().set("protected", Protected)?;
+
+    assert_eq!(
+        lua.load("getmetatable(default)").eval::<Value>()?,
+        Value::Boolean(false)
+    );
+    assert_eq!(
+        lua.load("getmetatable(protected)").eval::<String>()?,
+        "Protected"
+    );
+    assert_eq!(lua.load("protected:ping()").eval::<String>()?, "pong");
+    assert!(lua.load("setmetatable(protected, {})").exec().is_err());
+
+    lua.scope(|scope| {
+        let ud = scope.create_nonstatic_userdata(Protected)?;
+        lua.globals().set("scoped", ud)?;
+        assert_eq!(
+            lua.load("getmetatable(scoped)").eval::<String>()?,
+            "Protected"
+        );
+        Ok(())
+    })?;
+
+    Ok(())
+}
@@ -0,0 +1,62 @@
+#![cfg(feature = "uuid")]
+#![cfg_attr(
+    all(feature = "luajit", target_os = "macos", target_arch = "x86_64"),
+    feature(link_args)
+)]
+
+#[cfg_attr(
+    all(feature = "luajit", target_os = "macos", target_arch = "x86_64"),
+    link_args = "-pagezero_size 10000 -image_base 100000000"
+)]
+extern "system" {}
+
+use mlua::{Error, Lua, Result, Value};
+use uuid::Uuid;
+
+#[test]
+fn test_uuid_round_trip() -> Result<()> {
+    let lua = Lua::new();
+
+    let id = Uuid::parse_str("67e55044-10b1-426f-9247-bb680e5fe0c8").unwrap();
+    lua.globals().set("id", id)?;
+    assert_eq!(
+        lua.load("id").eval::<String>()?,
+        "67e55044-10b1-426f-9247-bb680e5fe0c8"
+    );
+    assert_eq!(lua.globals().get::<_, Uuid>("id")?, id);
+
+    // Other formats are accepted as well
+    assert_eq!(
+        lua.load("'67E55044-10B1-426F-9247-BB680E5FE0C8'")
+            .eval::<Uuid>()?,
+        id
+    );
+    assert_eq!(
+        lua.load("'67e5504410b1426f9247bb680e5fe0c8'")
+            .eval::<Uuid>()?,
+        id
+    );
+    assert_eq!(lua.unpack::<Option<Uuid>>(Value::Nil)?, None);
+
+    Ok(())
+}
+
+#[test]
+fn test_uuid_malformed() -> Result<()> {
+    let lua = Lua::new();
+
+    for chunk in &[
+        "'67e55044-10b1-426f-9247'",
+        "'67e55044-10b1-426f-9247-bb680e5fe0cz'",
+        "'not a uuid'",
+        "42",
+        "{}",
+    ] {
+        match lua.load(*chunk).eval::<Uuid>() {
+            Err(Error::FromLuaConversionError { to: "Uuid", .. }) => {}
+            r => panic!("expected conversion error for {}, got {:?}", chunk, r),
+        }
+    }
+
+    Ok(())
+}
@@ -1,4 +1,4 @@
-use mlua::{Lua, Result, Value};
+use mlua::{AsTable, Error, Function, Lua, NumberFormat, NumberKind, Result, Value};
 
 #[test]
 fn test_value_eq() -> Result<()> {
@@ -55,3 +55,231 @@ fn test_value_eq() -> Result<()> {
 
     Ok(())
 }
+
+#[test]
+fn test_number_subtype() -> Result<()> {
+    let lua = Lua::new();
+
+    let int: Value = lua.load("3").eval()?;
+    let float: Value = lua.load("3.0").eval()?;
+    let string: Value = lua.load("'3'").eval()?;
+
+    #[cfg(feature = "lua53")]
+    assert_eq!(int.number_subtype(), Some(NumberKind::Integer));
+    #[cfg(not(feature = "lua53"))]
+    assert_eq!(int.number_subtype(), Some(NumberKind::Float));
+    assert_eq!(float.number_subtype(), Some(NumberKind::Float));
+    assert_eq!(string.number_subtype(), None);
+
+    Ok(())
+}
+
+#[test]
+fn test_format_number() -> Result<()> {
+    let lua = Lua::new();
+
+    let format = NumberFormat::default();
+    let render = |code: &str, format: &NumberFormat| -> Result<Option<String>> {
+        Ok(lua.load(code).eval::<Value>()?.format_number(format))
+    };
+
+    assert_eq!(render("10", &format)?.as_deref(), Some("10"));
+    assert_eq!(render("10.0", &format)?.as_deref(), Some("10.0"));
+    assert_eq!(
+        render("0.1 + 0.2", &format)?.as_deref(),
+        Some("0.30000000000000004")
+    );
+    assert_eq!(render("1/0", &format)?.as_deref(), Some("inf"));
+    assert_eq!(render("-1/0", &format)?.as_deref(), Some("-inf"));
+    assert_eq!(render("'10'", &format)?, None);
+
+    let no_suffix = NumberFormat {
+        float_suffix: false,
+        ..NumberFormat::default()
+    };
+    assert_eq!(render("10.0", &no_suffix)?.as_deref(), Some("10"));
+
+    let fixed = NumberFormat {
+        precision: Some(3),
+        ..NumberFormat::default()
+    };
+    assert_eq!(render("2/3", &fixed)?.as_deref(), Some("0.667"));
+    assert_eq!(render("2", &fixed)?.as_deref(), Some("2"));
+
+    let scientific = NumberFormat {
+        scientific: true,
+        precision: Some(2),
+        ..NumberFormat::default()
+    };
+    assert_eq!(render("1234.5", &scientific)?.as_deref(), Some("1.23e3"));
+
+    Ok(())
+}
+
+#[test]
+fn test_convert_all() -> Result<()> {
+    let lua = Lua::new();
+
+    let f: Function = lua.load(r#"function() return 1, "2", 3.0 end"#).eval()?;
+    let values = f.call_multi(())?;
+    assert_eq!(lua.convert_all::<i64>(values.clone())?, vec![1, 2, 3]);
+
+    let mixed = lua.to_values(vec![Value::Integer(1), Value::Boolean(true)])?;
+    match lua.convert_all::<i64>(mixed) {
+        Err(Error::FromLuaConversionError { from, message, .. }) => {
+            assert_eq!(from, "boolean");
+            assert!(message.unwrap().starts_with("at index 1: "));
+        }
+        r => panic!("expected FromLuaConversionError, got {:?}", r),
+    }
+
+    let values = lua.to_values(vec!["a", "b"])?;
+    assert_eq!(
+        lua.convert_all::<std::string::String>(values)?,
+        vec!["a", "b"]
+    );
+
+    Ok(())
+}
+
+#[test]
+fn test_value_arith() -> Result<()> {
+    let lua = Lua::new();
+
+    let vector: Value = lua
+        .load(
+            r#"
+            setmetatable({x = 1}, {
+                __add = function(a, b) return "add" end,
+                __unm = function(a) return "unm" end,
+            })
+        "#,
+        )
+        .eval()?;
+    let values = vec![
+        Value::Integer(7),
+        Value::Integer(-2),
+        Value::Number(2.5),
+        Value::String(lua.create_string("3")?),
+        Value::String(lua.create_string("0x10")?),
+        vector,
+    ];
+
+    type BinaryOp<'lua> = fn(&Value<'lua>, &Value<'lua>, &'lua Lua) -> Result<Value<'lua>>;
+    let mut ops: Vec<(&str, BinaryOp)> = vec![
+        ("+", Value::arith_add),
+        ("-", Value::arith_sub),
+        ("*", Value::arith_mul),
+        ("/", Value::arith_div),
+        ("%", Value::arith_mod),
+        ("^", Value::arith_pow),
+    ];
+    #[cfg(feature = "lua53")]
+    ops.push(("//", Value::arith_idiv));
+
+    for (op, arith) in ops {
+        let expected: Function = lua
+            .load(&format!("function(a, b) return a {} b end", op))
+            .eval()?;
+        for a in &values {
+            for b in &values {
+                match (
+                    arith(a, b, &lua),
+                    expected.call::<_, Value>((a.clone(), b.clone())),
+                ) {
+                    (Ok(r), Ok(e)) => assert!(
+                        r == e || r != r && e != e,
+                        "{:?} {} {:?}: got {:?}, expected {:?}",
+                        a,
+                        op,
+                        b,
+                        r,
+                        e
+                    ),
+                    (Err(_), Err(_)) => {}
+                    (r, e) => panic!("{:?} {} {:?}: got {:?}, expected {:?}", a, op, b, r, e),
+                }
+            }
+        }
+    }
+
+    let unm: Function = lua.load("function(a) return -a end").eval()?;
+    for a in &values {
+        assert_eq!(a.arith_unm(&lua)?, unm.call::<_, Value>(a.clone())?);
+    }
+
+    assert!(Value::Boolean(true)
+        .arith_add(&Value::Integer(1), &lua)
+        .is_err());
+
+    Ok(())
+}
+
+#[test]
+fn test_as_table() -> Result<()> {
+    let lua = Lua::new();
+
+    let AsTable((host, port)) = lua
+        .load(r#"{"localhost", 8080}"#)
+        .eval::<AsTable<(std::string::String, u16)>>()?;
+    assert_eq!(host, "localhost");
+    assert_eq!(port, 8080);
+
+    for chunk in &[r#"{"localhost"}"#, r#"{"localhost", 8080, true}"#] {
+        match lua
+            .load(*chunk)
+            .eval::<AsTable<(std::string::String, u16)>>()
+        {
+            Err(Error::FromLuaConversionError {
+                to: "tuple",
+                message: Some(ref message),
+                ..
+            }) => assert!(message.starts_with("expected 2 elements, got ")),
+            r => panic!("expected FromLuaConversionError, got {:?}", r),
+        }
+    }
+    match lua
+        .load(r#""localhost""#)
+        .eval::<AsTable<(std::string::String, u16)>>()
+    {
+        Err(Error::FromLuaConversionError { from: "string", .. }) => {}
+        r => panic!("expected FromLuaConversionError, got {:?}", r),
+    }
+
+    lua.globals().set("pair", AsTable(("x", 1)))?;
+    assert!(lua
+        .load(r#"return #pair == 2 and pair[1] == "x" and pair[2] == 1"#)
+        .eval::<bool>()?);
+
+    Ok(())
+}
+
+#[test]
+fn test_null_sentinel() -> Result<()> {
+    let lua = Lua::new();
+    lua.globals().set("null", Value::NULL)?;
+
+    assert!(Value::Nil.is_nil());
+    assert!(!Value::Nil.is_null());
+    assert!(Value::NULL.is_null());
+    assert!(!Value::NULL.is_nil());
+    assert!(!Value::Boolean(false).is_nil());
+
+    // `nil` leaves a hole in a sequence, `NULL` keeps the length
+    let list = lua.create_sequence_from(vec![Value::Integer(1), Value::NULL, Value::Integer(3)])?;
+    assert_eq!(list.raw_len(), 3);
+    assert!(list.get::<_, Value>(2)?.is_null());
+    assert_eq!(list.clone().sequence_values::<Value>().count(), 3);
+    lua.globals().set("list", list)?;
+    assert_eq!(lua.load("#list").eval::<i64>()?, 3);
+    assert!(lua.load("list[2] == null").eval::<bool>()?);
+    assert!(lua.load("list[2] ~= nil").eval::<bool>()?);
+
+    // Absent keys are nil, not `NULL`
+    let table = lua.create_table()?;
+    table.set("present", Value::NULL)?;
+    assert!(table.contains_key("present")?);
+    assert!(table.get::<_, Value>("absent")?.is_nil());
+
+    Ok(())
+}